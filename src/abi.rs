@@ -19,6 +19,12 @@ abigen!(
         event Sync(uint112 reserve0, uint112 reserve1)
     ]"#;
 
+    IUniswapV2Router,
+    r#"[
+        function getAmountsOut(uint256 amountIn, address[] path) external view returns (uint256[] amounts)
+        function getAmountsIn(uint256 amountOut, address[] path) external view returns (uint256[] amounts)
+    ]"#;
+
     IUniswapV3Factory,
     r#"[
         function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)
@@ -37,6 +43,9 @@ abigen!(
         function tickBitmap(int16 wordPosition) external view returns (uint256)
         function swap(address recipient, bool zeroForOne, int256 amountSpecified, uint160 sqrtPriceLimitX96, bytes calldata data) external returns (int256, int256)
         event Swap( address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)
+        event Mint(address sender, address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1)
+        event Burn(address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1)
+        event Collect(address indexed owner, address recipient, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount0, uint128 amount1)
     ]"#;
 
     IUniswapV3Quoter,
@@ -44,6 +53,64 @@ abigen!(
         function quoteExactInputSingle(address tokenIn, address tokenOut, uint24 fee, uint256 amountIn, uint160 sqrtPriceLimitX96) external returns (uint256 amountOut)
     ]"#;
 
+    ISolidlyFactory,
+    r#"[
+        function getPair(address tokenA, address tokenB, bool stable) external view returns (address)
+        function getFee(address pair, bool stable) external view returns (uint256)
+        event PairCreated(address indexed token0, address indexed token1, bool stable, address pair, uint256)
+    ]"#;
+
+    ISolidlyPair,
+    r#"[
+        function metadata() external view returns (uint256 dec0, uint256 dec1, uint256 r0, uint256 r1, bool st, address t0, address t1)
+        function getReserves() external view returns (uint256 reserve0, uint256 reserve1, uint256 blockTimestampLast)
+        function factory() external view returns (address)
+        function stable() external view returns (bool)
+        event Sync(uint256 reserve0, uint256 reserve1)
+    ]"#;
+
+    ICurvePool,
+    r#"[
+        function coins(uint256 i) external view returns (address)
+        function balances(uint256 i) external view returns (uint256)
+        function fee() external view returns (uint256)
+        function admin_fee() external view returns (uint256)
+        function A() external view returns (uint256)
+        function A_precise() external view returns (uint256)
+        function initial_A() external view returns (uint256)
+        function future_A() external view returns (uint256)
+        function initial_A_time() external view returns (uint256)
+        function future_A_time() external view returns (uint256)
+        function get_dy(int128 i, int128 j, uint256 dx) external view returns (uint256)
+        function stored_rates() external view returns (uint256[])
+        function base_pool() external view returns (address)
+        function underlying_coins(uint256 i) external view returns (address)
+        event TokenExchange(address indexed buyer, int128 sold_id, uint256 tokens_sold, int128 bought_id, uint256 tokens_bought)
+    ]"#;
+
+    ICurveRegistry,
+    r#"[
+        function find_pool_for_coins(address from, address to, uint256 i) external view returns (address)
+        event PoolAdded(address indexed pool, bytes rate_method_id)
+    ]"#;
+
+    IBalancerV2Vault,
+    r#"[
+        struct BatchSwapStep { bytes32 poolId; uint256 assetInIndex; uint256 assetOutIndex; uint256 amount; bytes userData; }
+        struct FundManagement { address sender; bool fromInternalBalance; address recipient; bool toInternalBalance; }
+        function getPoolTokens(bytes32 poolId) external view returns (address[] tokens, uint256[] balances, uint256 lastChangeBlock)
+        function queryBatchSwap(uint8 kind, BatchSwapStep[] swaps, address[] assets, FundManagement funds) external returns (int256[] assetDeltas)
+        event PoolRegistered(bytes32 indexed poolId, address indexed poolAddress, uint8 specialization)
+    ]"#;
+
+    IBalancerV2WeightedPool,
+    r#"[
+        function getPoolId() external view returns (bytes32)
+        function getVault() external view returns (address)
+        function getNormalizedWeights() external view returns (uint256[])
+        function getSwapFeePercentage() external view returns (uint256)
+    ]"#;
+
     IErc20,
     r#"[
         function balanceOf(address account) external view returns (uint256)
@@ -0,0 +1,314 @@
+use std::sync::Arc;
+
+use ethers::{
+    abi::Token,
+    prelude::{Multicall, MULTICALL_ADDRESS},
+    providers::Middleware,
+    types::{H160, U256},
+};
+
+use crate::{abi, errors::CFMMError, pool::Pool};
+
+//Gets the pool data of each pool with Multicall3 calls. The tokens and balances come from the Vault's `getPoolTokens`,
+//and the weights and swap fee from the pool. Pools registered with the Vault that are not weighted pools do not have
+//`getNormalizedWeights`, so they are left empty. A pool id that the Vault does not know fails the whole batch so that it can be split.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pools = pools.len()))
+)]
+pub async fn get_pool_data_batch_request<M: Middleware>(
+    pools: &mut [Pool],
+    middleware: Arc<M>,
+) -> Result<(), CFMMError<M>> {
+    get_pool_state_batch_request(pools, middleware.clone()).await?;
+    get_decimals_batch_request(pools, middleware).await
+}
+
+async fn get_pool_state_batch_request<M: Middleware>(
+    pools: &mut [Pool],
+    middleware: Arc<M>,
+) -> Result<(), CFMMError<M>> {
+    let mut multicall = Multicall::new(middleware.clone(), Some(MULTICALL_ADDRESS)).await?;
+
+    let mut pool_indices = vec![];
+    for (pool_idx, pool) in pools.iter().enumerate() {
+        if let Pool::BalancerV2(balancer_v2_pool) = pool {
+            let vault = abi::IBalancerV2Vault::new(balancer_v2_pool.vault, middleware.clone());
+            let weighted_pool =
+                abi::IBalancerV2WeightedPool::new(balancer_v2_pool.address, middleware.clone());
+
+            multicall
+                .add_call(vault.get_pool_tokens(balancer_v2_pool.pool_id.0), false)
+                .add_call(weighted_pool.get_normalized_weights(), true)
+                .add_call(weighted_pool.get_swap_fee_percentage(), true);
+            pool_indices.push(pool_idx);
+        }
+    }
+
+    if pool_indices.is_empty() {
+        return Ok(());
+    }
+
+    let results = multicall.call_raw().await?;
+
+    for (pool_idx, results) in pool_indices.into_iter().zip(results.chunks(3)) {
+        let (pool_tokens, weights, swap_fee) = match results {
+            [Ok(Token::Tuple(pool_tokens)), Ok(Token::Array(weights)), Ok(Token::Uint(swap_fee))] => {
+                (pool_tokens, weights, swap_fee)
+            }
+            _ => continue,
+        };
+
+        let tokens = pool_tokens[0]
+            .to_owned()
+            .into_array()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(Token::into_address)
+            .collect::<Vec<H160>>();
+        let balances = uints(&pool_tokens[1]);
+        let weights = uints(&Token::Array(weights.to_owned()));
+
+        if tokens.len() != balances.len() || tokens.len() != weights.len() {
+            continue;
+        }
+
+        if let Pool::BalancerV2(balancer_v2_pool) = &mut pools[pool_idx] {
+            //Decimals are requested again if the tokens of the pool changed
+            if balancer_v2_pool.tokens != tokens {
+                balancer_v2_pool.decimals = vec![];
+            }
+
+            balancer_v2_pool.tokens = tokens;
+            balancer_v2_pool.balances = balances;
+            balancer_v2_pool.weights = weights;
+            balancer_v2_pool.swap_fee = *swap_fee;
+        }
+    }
+
+    Ok(())
+}
+
+//Gets the decimals of the tokens of each pool that does not have them yet. A pool with a token whose decimals can not be
+//read is left without decimals, so that it stays unpopulated instead of scaling the token's balance as if it had 0 decimals.
+async fn get_decimals_batch_request<M: Middleware>(
+    pools: &mut [Pool],
+    middleware: Arc<M>,
+) -> Result<(), CFMMError<M>> {
+    let mut multicall = Multicall::new(middleware.clone(), Some(MULTICALL_ADDRESS)).await?;
+
+    let mut pool_indices = vec![];
+    for (pool_idx, pool) in pools.iter().enumerate() {
+        if let Pool::BalancerV2(balancer_v2_pool) = pool {
+            if !balancer_v2_pool.tokens.is_empty()
+                && balancer_v2_pool.decimals.len() != balancer_v2_pool.tokens.len()
+            {
+                for token in balancer_v2_pool.tokens.iter() {
+                    let token = abi::IErc20::new(*token, middleware.clone());
+                    multicall.add_call(token.decimals(), true);
+                }
+                pool_indices.push(pool_idx);
+            }
+        }
+    }
+
+    if pool_indices.is_empty() {
+        return Ok(());
+    }
+
+    let mut results = multicall.call_raw().await?.into_iter();
+
+    for pool_idx in pool_indices {
+        if let Pool::BalancerV2(balancer_v2_pool) = &mut pools[pool_idx] {
+            let decimals = results
+                .by_ref()
+                .take(balancer_v2_pool.tokens.len())
+                .map(|result| match result {
+                    Ok(Token::Uint(decimals)) => u8::try_from(decimals).ok(),
+                    _ => None,
+                })
+                .collect::<Vec<Option<u8>>>();
+
+            balancer_v2_pool.decimals = decimals
+                .into_iter()
+                .collect::<Option<Vec<u8>>>()
+                .unwrap_or_default();
+        }
+    }
+
+    Ok(())
+}
+
+fn uints(token: &Token) -> Vec<U256> {
+    token
+        .to_owned()
+        .into_array()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(Token::into_uint)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::Token,
+        types::{Bytes, H160, H256, U256},
+    };
+
+    use super::get_pool_data_batch_request;
+    use crate::{
+        pool::{BalancerV2Pool, Pool},
+        test_utils::mock_provider,
+    };
+
+    fn uint(value: U256) -> (bool, Bytes) {
+        (true, ethers::abi::encode(&[Token::Uint(value)]).into())
+    }
+
+    //Return data of Multicall3's aggregate3, which is a list of (success, return data) results
+    fn aggregate_3_result(results: Vec<(bool, Bytes)>) -> Bytes {
+        ethers::abi::encode(&[Token::Array(
+            results
+                .into_iter()
+                .map(|(success, return_data)| {
+                    Token::Tuple(vec![
+                        Token::Bool(success),
+                        Token::Bytes(return_data.to_vec()),
+                    ])
+                })
+                .collect(),
+        )])
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_get_pool_data_batch_request() {
+        let (middleware, client) = mock_provider();
+        let (token_a, token_b) = (H160::from_low_u64_be(100), H160::from_low_u64_be(200));
+        let weights = vec![U256::exp10(17) * 8, U256::exp10(17) * 2];
+
+        let mut pools = vec![
+            Pool::BalancerV2(BalancerV2Pool {
+                address: H160::from_low_u64_be(1),
+                pool_id: H256::from_low_u64_be(1),
+                vault: H160::from_low_u64_be(10),
+                ..Default::default()
+            }),
+            Pool::BalancerV2(BalancerV2Pool {
+                address: H160::from_low_u64_be(2),
+                pool_id: H256::from_low_u64_be(2),
+                vault: H160::from_low_u64_be(10),
+                ..Default::default()
+            }),
+        ];
+
+        let pool_tokens: Bytes = ethers::abi::encode(&[
+            Token::Array(vec![Token::Address(token_a), Token::Address(token_b)]),
+            Token::Array(vec![
+                Token::Uint(U256::from(1000)),
+                Token::Uint(U256::from(2000)),
+            ]),
+            Token::Uint(U256::from(17_000_000)),
+        ])
+        .into();
+
+        //The second pool is not a weighted pool, so it does not have normalized weights
+        client.push(aggregate_3_result(vec![
+            (true, pool_tokens.clone()),
+            (
+                true,
+                ethers::abi::encode(&[Token::Array(
+                    weights.iter().copied().map(Token::Uint).collect(),
+                )])
+                .into(),
+            ),
+            uint(U256::exp10(15) * 3),
+            (true, pool_tokens),
+            (false, Bytes::new()),
+            uint(U256::exp10(15)),
+        ]));
+        client.push(aggregate_3_result(vec![
+            uint(U256::from(18)),
+            uint(U256::from(6)),
+        ]));
+
+        get_pool_data_batch_request(&mut pools, middleware)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            pools[0],
+            Pool::BalancerV2(BalancerV2Pool {
+                address: H160::from_low_u64_be(1),
+                pool_id: H256::from_low_u64_be(1),
+                vault: H160::from_low_u64_be(10),
+                tokens: vec![token_a, token_b],
+                decimals: vec![18, 6],
+                balances: vec![U256::from(1000), U256::from(2000)],
+                weights,
+                swap_fee: U256::exp10(15) * 3,
+            })
+        );
+
+        if let Pool::BalancerV2(pool) = &pools[1] {
+            assert!(pool.tokens.is_empty());
+        }
+        assert_eq!(client.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_pool_data_batch_request_decimals_failure() {
+        let (middleware, client) = mock_provider();
+        let (token_a, token_b) = (H160::from_low_u64_be(100), H160::from_low_u64_be(200));
+
+        let mut pools = vec![Pool::BalancerV2(BalancerV2Pool {
+            address: H160::from_low_u64_be(1),
+            pool_id: H256::from_low_u64_be(1),
+            vault: H160::from_low_u64_be(10),
+            tokens: vec![token_a, token_b],
+            ..Default::default()
+        })];
+
+        client.push(aggregate_3_result(vec![
+            (
+                true,
+                ethers::abi::encode(&[
+                    Token::Array(vec![Token::Address(token_a), Token::Address(token_b)]),
+                    Token::Array(vec![
+                        Token::Uint(U256::from(1000)),
+                        Token::Uint(U256::from(2000)),
+                    ]),
+                    Token::Uint(U256::from(17_000_000)),
+                ])
+                .into(),
+            ),
+            (
+                true,
+                ethers::abi::encode(&[Token::Array(vec![
+                    Token::Uint(U256::exp10(17) * 5),
+                    Token::Uint(U256::exp10(17) * 5),
+                ])])
+                .into(),
+            ),
+            uint(U256::exp10(15)),
+        ]));
+
+        //The second token does not have `decimals()`
+        client.push(aggregate_3_result(vec![
+            uint(U256::from(18)),
+            (false, Bytes::new()),
+        ]));
+
+        get_pool_data_batch_request(&mut pools, middleware)
+            .await
+            .unwrap();
+
+        if let Pool::BalancerV2(pool) = &pools[0] {
+            assert_eq!(pool.balances.len(), 2);
+            assert!(pool.decimals.is_empty());
+            assert!(!pool.data_is_populated());
+        }
+        assert_eq!(client.requests().len(), 2);
+    }
+}
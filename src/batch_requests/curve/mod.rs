@@ -0,0 +1,457 @@
+use std::sync::Arc;
+
+use ethers::{
+    abi::Token,
+    prelude::{Multicall, MULTICALL_ADDRESS},
+    providers::Middleware,
+    types::{Bytes, H160, U256},
+};
+
+use crate::{
+    abi,
+    errors::CFMMError,
+    pool::{curve::rate_from_decimals, CurvePool, Pool},
+};
+
+//Max number of coins in a Curve pool
+pub const MAX_COINS: u64 = 8;
+
+//Placeholder address that Curve pools use for ether, which has 18 decimals
+pub const ETH_ADDRESS: H160 = H160([0xee; 20]);
+
+//Gets the pool data of each pool with Multicall3 calls. The coins and decimals of a pool never change, so they are
+//only requested for pools that do not have them yet, ie. pools that were just discovered from the registry.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pools = pools.len()))
+)]
+pub async fn get_pool_data_batch_request<M: Middleware>(
+    pools: &mut [Pool],
+    middleware: Arc<M>,
+) -> Result<(), CFMMError<M>> {
+    get_coins_batch_request(pools, middleware.clone()).await?;
+    get_decimals_batch_request(pools, middleware.clone()).await?;
+    get_pool_state_batch_request(pools, middleware).await
+}
+
+//Gets the coins of each pool without coins. Pools do not expose the number of coins, so `coins(i)` is requested
+//for every possible coin and the coins end at the first call that reverts.
+async fn get_coins_batch_request<M: Middleware>(
+    pools: &mut [Pool],
+    middleware: Arc<M>,
+) -> Result<(), CFMMError<M>> {
+    let mut multicall = Multicall::new(middleware.clone(), Some(MULTICALL_ADDRESS)).await?;
+
+    let mut pool_indices = vec![];
+    for (pool_idx, pool) in pools.iter().enumerate() {
+        if let Pool::Curve(curve_pool) = pool {
+            if curve_pool.coins.is_empty() {
+                let curve_pool = abi::ICurvePool::new(curve_pool.address, middleware.clone());
+
+                for i in 0..MAX_COINS {
+                    multicall.add_call(curve_pool.coins(U256::from(i)), true);
+                }
+                pool_indices.push(pool_idx);
+            }
+        }
+    }
+
+    if pool_indices.is_empty() {
+        return Ok(());
+    }
+
+    let results = multicall.call_raw().await?;
+
+    for (pool_idx, results) in pool_indices
+        .into_iter()
+        .zip(results.chunks(MAX_COINS as usize))
+    {
+        let coins = results
+            .iter()
+            .map_while(|result| match result {
+                Ok(Token::Address(coin)) if !coin.is_zero() => Some(*coin),
+                _ => None,
+            })
+            .collect::<Vec<H160>>();
+
+        //A pool with less than two coins is not a pool that can be swapped through and stays empty
+        if let Pool::Curve(curve_pool) = &mut pools[pool_idx] {
+            if coins.len() >= 2 {
+                curve_pool.coins = coins;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//Gets the decimals of each coin and the rates that normalize the balances of the pool to 18 decimals. The rates only
+//follow from the decimals for plain pools. Metapools and lending pools, which have a `base_pool()` or `underlying_coins(0)`,
+//price their coins with rates that change over time and are left without rates, unless the state batch can read them
+//from `stored_rates()`.
+async fn get_decimals_batch_request<M: Middleware>(
+    pools: &mut [Pool],
+    middleware: Arc<M>,
+) -> Result<(), CFMMError<M>> {
+    let mut multicall = Multicall::new(middleware.clone(), Some(MULTICALL_ADDRESS)).await?;
+
+    let mut pool_indices = vec![];
+    for (pool_idx, pool) in pools.iter().enumerate() {
+        if let Pool::Curve(curve_pool) = pool {
+            if needs_decimals(curve_pool) {
+                for coin in curve_pool.coins.iter().filter(|coin| **coin != ETH_ADDRESS) {
+                    let token = abi::IErc20::new(*coin, middleware.clone());
+                    multicall.add_call(token.decimals(), false);
+                }
+
+                let contract = abi::ICurvePool::new(curve_pool.address, middleware.clone());
+                multicall
+                    .add_call(contract.base_pool(), true)
+                    .add_call(contract.underlying_coins(U256::zero()), true);
+                pool_indices.push(pool_idx);
+            }
+        }
+    }
+
+    if pool_indices.is_empty() {
+        return Ok(());
+    }
+
+    let mut results = multicall.call_raw().await?.into_iter();
+
+    for pool_idx in pool_indices {
+        if let Pool::Curve(curve_pool) = &mut pools[pool_idx] {
+            let mut decimals = vec![];
+            for coin in curve_pool.coins.iter() {
+                if *coin == ETH_ADDRESS {
+                    decimals.push(18);
+                } else if let Some(Ok(Token::Uint(coin_decimals))) = results.next() {
+                    decimals.push(coin_decimals.as_u32() as u8);
+                }
+            }
+
+            //Both results are taken before checking them so that the next pool starts at its own results
+            let is_plain_pool = results
+                .by_ref()
+                .take(2)
+                .filter(
+                    |result| matches!(result, Ok(Token::Address(address)) if !address.is_zero()),
+                )
+                .count()
+                == 0;
+
+            if decimals.len() == curve_pool.coins.len() {
+                if is_plain_pool {
+                    curve_pool.rates = decimals.iter().map(|d| rate_from_decimals(*d)).collect();
+                }
+                curve_pool.decimals = decimals;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn needs_decimals(pool: &CurvePool) -> bool {
+    !pool.coins.is_empty() && pool.decimals.len() != pool.coins.len()
+}
+
+//Gets the balances, fees, amplification coefficient and rates of each pool with coins, along with the timestamp of the
+//block that they are read at. Pools that do not ramp A, do not
+//store A with extra precision or do not expose their rates do not have all of the getters, so those calls are allowed to fail.
+//Pools without `stored_rates()` keep the rates from their decimals.
+pub async fn get_pool_state_batch_request<M: Middleware>(
+    pools: &mut [Pool],
+    middleware: Arc<M>,
+) -> Result<(), CFMMError<M>> {
+    let mut multicall = Multicall::new(middleware.clone(), Some(MULTICALL_ADDRESS)).await?;
+
+    let mut pool_indices = vec![];
+    for (pool_idx, pool) in pools.iter().enumerate() {
+        if let Pool::Curve(curve_pool) = pool {
+            if curve_pool.coins.is_empty() {
+                continue;
+            }
+
+            let contract = abi::ICurvePool::new(curve_pool.address, middleware.clone());
+            for i in 0..curve_pool.coins.len() {
+                multicall.add_call(contract.balances(U256::from(i)), false);
+            }
+
+            multicall
+                .add_call(contract.fee(), false)
+                .add_call(contract.admin_fee(), true)
+                .add_call(contract.a(), false)
+                .add_call(contract.a_precise(), true)
+                .add_call(contract.initial_a(), true)
+                .add_call(contract.future_a(), true)
+                .add_call(contract.initial_a_time(), true)
+                .add_call(contract.future_a_time(), true)
+                .add_call(contract.stored_rates(), true);
+            pool_indices.push(pool_idx);
+        }
+    }
+
+    if pool_indices.is_empty() {
+        return Ok(());
+    }
+
+    //The timestamp is the last result of the batch
+    multicall.add_get_current_block_timestamp();
+
+    let mut results = multicall.call_raw().await?;
+    let timestamp = results
+        .pop()
+        .and_then(into_uint)
+        .unwrap_or_default()
+        .as_u64();
+    let mut results = results.into_iter();
+
+    for pool_idx in pool_indices {
+        if let Pool::Curve(curve_pool) = &mut pools[pool_idx] {
+            curve_pool.balances = results
+                .by_ref()
+                .take(curve_pool.coins.len())
+                .map(|result| into_uint(result).unwrap_or_default())
+                .collect();
+
+            let mut next = || results.next().and_then(into_uint);
+            let fee = next().unwrap_or_default();
+            let admin_fee = next().unwrap_or_default();
+            let a = next().unwrap_or_default();
+            let a_precise = next();
+            let initial_a = next();
+            let future_a = next();
+            let initial_a_time = next();
+            let future_a_time = next();
+
+            if let Some(Ok(Token::Array(rates))) = results.next() {
+                let rates = rates
+                    .into_iter()
+                    .map(Token::into_uint)
+                    .collect::<Option<Vec<U256>>>();
+
+                if let Some(rates) = rates.filter(|rates| rates.len() == curve_pool.coins.len()) {
+                    curve_pool.rates = rates;
+                }
+            }
+
+            curve_pool.fee = fee.as_u64();
+            curve_pool.admin_fee = admin_fee.as_u64();
+            curve_pool.timestamp = timestamp;
+
+            //`A_precise` is `A` multiplied by the precision that the pool stores A with
+            curve_pool.a_precision = match a_precise {
+                Some(a_precise) if !a.is_zero() => (a_precise / a).as_u64().max(1),
+                _ => 1,
+            };
+
+            //Pools that can not ramp A only have the current A
+            match (initial_a, future_a) {
+                (Some(initial_a), Some(future_a)) => {
+                    curve_pool.initial_a = initial_a.as_u64();
+                    curve_pool.future_a = future_a.as_u64();
+                    curve_pool.initial_a_time = initial_a_time.unwrap_or_default().as_u64();
+                    curve_pool.future_a_time = future_a_time.unwrap_or_default().as_u64();
+                }
+                _ => {
+                    let a = (a * curve_pool.a_precision).as_u64();
+                    curve_pool.initial_a = a;
+                    curve_pool.future_a = a;
+                    curve_pool.initial_a_time = 0;
+                    curve_pool.future_a_time = 0;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn into_uint(result: Result<Token, Bytes>) -> Option<U256> {
+    match result {
+        Ok(Token::Uint(value)) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::Token,
+        types::{Bytes, H160, U256},
+    };
+
+    use super::{get_pool_data_batch_request, ETH_ADDRESS, MAX_COINS};
+    use crate::{
+        pool::{curve::rate_from_decimals, CurvePool, Pool},
+        test_utils::mock_provider,
+    };
+
+    fn uint(value: u64) -> (bool, Bytes) {
+        (
+            true,
+            ethers::abi::encode(&[Token::Uint(U256::from(value))]).into(),
+        )
+    }
+
+    //Return data of Multicall3's aggregate3, which is a list of (success, return data) results
+    fn aggregate_3_result(results: Vec<(bool, Bytes)>) -> Bytes {
+        ethers::abi::encode(&[Token::Array(
+            results
+                .into_iter()
+                .map(|(success, return_data)| {
+                    Token::Tuple(vec![
+                        Token::Bool(success),
+                        Token::Bytes(return_data.to_vec()),
+                    ])
+                })
+                .collect(),
+        )])
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_get_pool_data_batch_request() {
+        let (middleware, client) = mock_provider();
+        let token = H160::from_low_u64_be(100);
+
+        let mut pools = vec![Pool::Curve(CurvePool {
+            address: H160::from_low_u64_be(1),
+            ..Default::default()
+        })];
+
+        //The pool has two coins, the calls for the other coins revert
+        let mut coins = vec![
+            (
+                true,
+                ethers::abi::encode(&[Token::Address(ETH_ADDRESS)]).into(),
+            ),
+            (true, ethers::abi::encode(&[Token::Address(token)]).into()),
+        ];
+        coins.resize(MAX_COINS as usize, (false, Bytes::new()));
+        client.push(aggregate_3_result(coins));
+
+        //Decimals are only requested for the token, ether always has 18 decimals. The pool is a plain pool, so it does not
+        //have a base pool or underlying coins.
+        client.push(aggregate_3_result(vec![
+            uint(6),
+            (false, Bytes::new()),
+            (false, Bytes::new()),
+        ]));
+
+        //The pool stores A with a precision of 100 and can not ramp A
+        let mut state = vec![
+            uint(1000),
+            uint(2000),
+            uint(4_000_000),
+            uint(5_000_000_000),
+            uint(100),
+            uint(10000),
+        ];
+        state.resize(11, (false, Bytes::new()));
+        state.push(uint(1_700_000_000));
+        client.push(aggregate_3_result(state));
+
+        get_pool_data_batch_request(&mut pools, middleware)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            pools[0],
+            Pool::Curve(CurvePool {
+                address: H160::from_low_u64_be(1),
+                coins: vec![ETH_ADDRESS, token],
+                decimals: vec![18, 6],
+                balances: vec![U256::from(1000), U256::from(2000)],
+                rates: vec![rate_from_decimals(18), rate_from_decimals(6)],
+                fee: 4_000_000,
+                admin_fee: 5_000_000_000,
+                initial_a: 10000,
+                future_a: 10000,
+                initial_a_time: 0,
+                future_a_time: 0,
+                a_precision: 100,
+                timestamp: 1_700_000_000,
+            })
+        );
+        assert_eq!(client.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_get_pool_data_batch_request_rates() {
+        let (middleware, client) = mock_provider();
+        let (token_a, token_b) = (H160::from_low_u64_be(100), H160::from_low_u64_be(200));
+
+        let pool = |address| {
+            Pool::Curve(CurvePool {
+                address: H160::from_low_u64_be(address),
+                coins: vec![token_a, token_b],
+                ..Default::default()
+            })
+        };
+        let mut pools = vec![pool(1), pool(2)];
+
+        //Both pools are metapools, only the second pool has `stored_rates()`
+        let base_pool = (
+            true,
+            ethers::abi::encode(&[Token::Address(H160::from_low_u64_be(3))]).into(),
+        );
+        client.push(aggregate_3_result(vec![
+            uint(18),
+            uint(18),
+            base_pool.clone(),
+            (false, Bytes::new()),
+            uint(18),
+            uint(18),
+            base_pool,
+            (false, Bytes::new()),
+        ]));
+
+        let rates = vec![
+            rate_from_decimals(18),
+            U256::from(1_020_000_000_000_000_000u128),
+        ];
+        let mut state = vec![];
+        for stored_rates in [
+            (false, Bytes::new()),
+            (
+                true,
+                ethers::abi::encode(&[Token::Array(
+                    rates.iter().map(|rate| Token::Uint(*rate)).collect(),
+                )])
+                .into(),
+            ),
+        ] {
+            state.extend([
+                uint(1000),
+                uint(2000),
+                uint(4_000_000),
+                uint(5_000_000_000),
+                uint(100),
+            ]);
+            state.resize(state.len() + 5, (false, Bytes::new()));
+            state.push(stored_rates);
+        }
+        state.push(uint(1_700_000_000));
+        client.push(aggregate_3_result(state));
+
+        get_pool_data_batch_request(&mut pools, middleware)
+            .await
+            .unwrap();
+
+        //The rates of the first pool can not be read, so the pool is not populated
+        if let Pool::Curve(pool) = &pools[0] {
+            assert_eq!(pool.decimals, vec![18, 18]);
+            assert!(pool.rates.is_empty());
+            assert!(!pool.data_is_populated());
+        }
+        assert!(crate::sync::is_empty_pool(&pools[0]));
+
+        if let Pool::Curve(pool) = &pools[1] {
+            assert_eq!(pool.rates, rates);
+            assert!(pool.data_is_populated());
+        }
+        assert_eq!(client.requests().len(), 2);
+    }
+}
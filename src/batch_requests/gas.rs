@@ -0,0 +1,79 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{Middleware, MiddlewareError},
+    types::{transaction::eip2718::TypedTransaction, BlockId, Bytes, U256},
+};
+use thiserror::Error;
+
+//Middleware that estimates the gas of every eth_call before making it, so that the gas used by the batch request
+//contracts can be measured with the same requests that are used to get the pool data
+#[derive(Debug)]
+pub struct GasEstimator<M> {
+    inner: M,
+    estimates: Mutex<Vec<U256>>,
+}
+
+impl<M: Middleware> GasEstimator<M> {
+    pub fn new(inner: M) -> GasEstimator<M> {
+        GasEstimator {
+            inner,
+            estimates: Mutex::new(vec![]),
+        }
+    }
+
+    //Gas estimates of the calls that were made, in the order they were made
+    pub fn estimates(&self) -> Vec<U256> {
+        self.estimates.lock().unwrap().clone()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GasEstimatorError<M: Middleware> {
+    #[error("{0}")]
+    MiddlewareError(M::Error),
+}
+
+impl<M: Middleware> MiddlewareError for GasEstimatorError<M> {
+    type Inner = M::Error;
+
+    fn from_err(src: M::Error) -> GasEstimatorError<M> {
+        GasEstimatorError::MiddlewareError(src)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            GasEstimatorError::MiddlewareError(error) => Some(error),
+        }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware> Middleware for GasEstimator<M> {
+    type Error = GasEstimatorError<M>;
+    type Provider = M::Provider;
+    type Inner = M;
+
+    fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        let gas = self
+            .inner
+            .estimate_gas(tx, block)
+            .await
+            .map_err(MiddlewareError::from_err)?;
+        self.estimates.lock().unwrap().push(gas);
+
+        self.inner
+            .call(tx, block)
+            .await
+            .map_err(MiddlewareError::from_err)
+    }
+}
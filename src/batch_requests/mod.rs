@@ -1,2 +1,181 @@
+pub mod balancer_v2;
+pub mod curve;
+pub mod gas;
+pub mod solidly;
 pub mod uniswap_v2;
 pub mod uniswap_v3;
+
+use std::collections::HashMap;
+
+use crate::dex::DexVariant;
+
+//Gas limit that most providers apply to eth_call, the batch request contracts are deployed within a single call
+pub const DEFAULT_BATCH_REQUEST_GAS_BUDGET: u64 = 30_000_000;
+//The gas constants below are fallbacks for dexes whose batch requests have not been measured with
+//`Dex::measure_pool_data_gas`. They are conservative estimates and not measurements, a measured `PoolDataGas`
+//should be set with `BatchRequestConfig::with_pool_data_gas` when the batches need to be close to the gas budget.
+//Gas used to deploy a batch request contract and encode the return data, independent of the number of pools
+pub const BATCH_REQUEST_BASE_GAS: u64 = 100_000;
+//Estimated gas used per pool, including cold account access for the pool and both tokens and the memory expansion of the return data
+pub const UNISWAP_V2_POOL_DATA_GAS_PER_POOL: u64 = 235_000;
+pub const UNISWAP_V3_POOL_DATA_GAS_PER_POOL: u64 = 393_000;
+//Solidly pool data is read with a multicall of the pair's `metadata()` and the factory's `getFee`
+pub const SOLIDLY_POOL_DATA_GAS_PER_POOL: u64 = 60_000;
+//Curve pool data is read with a multicall of the balance of each coin, the fees and the amplification coefficient
+pub const CURVE_POOL_DATA_GAS_PER_POOL: u64 = 200_000;
+//Balancer V2 pool data is read with a multicall of the Vault's `getPoolTokens` and the pool's weights and swap fee
+pub const BALANCER_V2_POOL_DATA_GAS_PER_POOL: u64 = 120_000;
+//Number of batch requests that are in flight at the same time for each dex
+pub const DEFAULT_BATCH_REQUEST_PARALLELISM: usize = 4;
+
+//Gas used by a pool data batch request, as a fixed cost for the request and a cost for each pool in the batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolDataGas {
+    pub base: u64,
+    pub per_pool: u64,
+}
+
+impl PoolDataGas {
+    pub fn new(base: u64, per_pool: u64) -> PoolDataGas {
+        PoolDataGas {
+            base,
+            per_pool: per_pool.max(1),
+        }
+    }
+
+    //Fits the fixed and per pool cost to the measured gas of a batch with one pool and a batch with `batch_size` pools.
+    //The gas of a batch grows linearly with the number of pools, so the per pool cost is the slope between the two
+    //measurements, rounded up, and the fixed cost is what is left of the single pool batch.
+    pub fn from_measurements(single_pool_gas: u64, batch_gas: u64, batch_size: u64) -> PoolDataGas {
+        if batch_size <= 1 {
+            return PoolDataGas::new(0, single_pool_gas);
+        }
+
+        let per_pool = batch_gas
+            .saturating_sub(single_pool_gas)
+            .div_ceil(batch_size - 1)
+            .max(1);
+
+        PoolDataGas::new(single_pool_gas.saturating_sub(per_pool), per_pool)
+    }
+
+    //Fallback gas for dexes whose batch requests have not been measured
+    pub fn estimate(dex_variant: DexVariant) -> PoolDataGas {
+        let per_pool = match dex_variant {
+            DexVariant::UniswapV2 => UNISWAP_V2_POOL_DATA_GAS_PER_POOL,
+            DexVariant::UniswapV3 => UNISWAP_V3_POOL_DATA_GAS_PER_POOL,
+            DexVariant::Solidly => SOLIDLY_POOL_DATA_GAS_PER_POOL,
+            DexVariant::Curve => CURVE_POOL_DATA_GAS_PER_POOL,
+            DexVariant::BalancerV2 => BALANCER_V2_POOL_DATA_GAS_PER_POOL,
+        };
+
+        PoolDataGas::new(BATCH_REQUEST_BASE_GAS, per_pool)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchRequestConfig {
+    //Gas that a single batch request is allowed to use, which determines the number of pools in each batch
+    pub gas_budget: u64,
+    //Number of batch requests that are in flight at the same time
+    pub max_in_flight: usize,
+    //Measured gas of the pool data batch requests, dexes that are not measured use `PoolDataGas::estimate`
+    pub pool_data_gas: HashMap<DexVariant, PoolDataGas>,
+}
+
+impl Default for BatchRequestConfig {
+    fn default() -> Self {
+        BatchRequestConfig::new(
+            DEFAULT_BATCH_REQUEST_GAS_BUDGET,
+            DEFAULT_BATCH_REQUEST_PARALLELISM,
+        )
+    }
+}
+
+impl BatchRequestConfig {
+    pub fn new(gas_budget: u64, max_in_flight: usize) -> BatchRequestConfig {
+        BatchRequestConfig {
+            gas_budget,
+            max_in_flight: max_in_flight.max(1),
+            pool_data_gas: HashMap::new(),
+        }
+    }
+
+    pub fn with_pool_data_gas(
+        mut self,
+        dex_variant: DexVariant,
+        pool_data_gas: PoolDataGas,
+    ) -> BatchRequestConfig {
+        self.pool_data_gas.insert(dex_variant, pool_data_gas);
+        self
+    }
+
+    //Number of pools that fit in a single pool data batch request within the gas budget
+    pub fn pool_data_batch_size(&self, dex_variant: DexVariant) -> usize {
+        let pool_data_gas = self
+            .pool_data_gas
+            .get(&dex_variant)
+            .copied()
+            .unwrap_or_else(|| PoolDataGas::estimate(dex_variant));
+
+        (self.gas_budget.saturating_sub(pool_data_gas.base) / pool_data_gas.per_pool).max(1)
+            as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchRequestConfig, PoolDataGas};
+    use crate::dex::DexVariant;
+
+    #[test]
+    fn test_pool_data_gas_from_measurements() {
+        //A batch of 10 pools uses 9 times the per pool gas more than a batch of one pool
+        assert_eq!(
+            PoolDataGas::from_measurements(150_000, 600_000, 10),
+            PoolDataGas::new(100_000, 50_000)
+        );
+
+        //The per pool gas is rounded up so that a batch sized from it never goes over the budget
+        assert_eq!(
+            PoolDataGas::from_measurements(150_000, 600_001, 10),
+            PoolDataGas::new(99_999, 50_001)
+        );
+
+        //A single measurement can only be used as the per pool gas
+        assert_eq!(
+            PoolDataGas::from_measurements(150_000, 150_000, 1),
+            PoolDataGas::new(0, 150_000)
+        );
+    }
+
+    #[test]
+    fn test_pool_data_batch_size() {
+        let pool_data_gas = PoolDataGas::from_measurements(150_000, 600_000, 10);
+        let batch_request_config = BatchRequestConfig::new(1_100_000, 1)
+            .with_pool_data_gas(DexVariant::UniswapV2, pool_data_gas);
+
+        //The measured gas of 10 pools is 600_000, so 20 pools fit in 1_100_000 gas
+        assert_eq!(
+            batch_request_config.pool_data_batch_size(DexVariant::UniswapV2),
+            20
+        );
+        assert_eq!(
+            pool_data_gas.base + 20 * pool_data_gas.per_pool,
+            batch_request_config.gas_budget
+        );
+
+        //Dexes that were not measured fall back to the estimated gas
+        let estimate = PoolDataGas::estimate(DexVariant::UniswapV3);
+        assert_eq!(
+            batch_request_config.pool_data_batch_size(DexVariant::UniswapV3),
+            ((1_100_000 - estimate.base) / estimate.per_pool) as usize
+        );
+
+        //At least one pool is requested in each batch
+        assert_eq!(
+            BatchRequestConfig::new(0, 1).pool_data_batch_size(DexVariant::UniswapV2),
+            1
+        );
+    }
+}
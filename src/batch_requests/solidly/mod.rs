@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use ethers::{
+    abi::Token,
+    prelude::{Multicall, MULTICALL_ADDRESS},
+    providers::Middleware,
+    types::{H160, U256},
+};
+
+use crate::{
+    abi,
+    errors::CFMMError,
+    pool::{solidly::FEE_DENOMINATOR, Pool},
+    telemetry,
+};
+
+//Solidly factories report fees in basis points
+const FACTORY_FEE_DENOMINATOR: u32 = 10000;
+
+//Gets the pool data of each pair with a single Multicall3 call. The pair's `metadata()` returns the tokens, decimals,
+//reserves and stability, and the factory's `getFee(pair, stable)` returns the pair's fee. Factories without a per pair
+//fee, or that report a fee above 100%, keep the fee that the pool already has. A pair that reverts fails the whole batch so that it can be split.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(factory = ?factory, pools = pools.len()))
+)]
+pub async fn get_pool_data_batch_request<M: Middleware>(
+    factory: H160,
+    pools: &mut [Pool],
+    middleware: Arc<M>,
+) -> Result<(), CFMMError<M>> {
+    let factory = abi::ISolidlyFactory::new(factory, middleware.clone());
+    let mut multicall = Multicall::new(middleware.clone(), Some(MULTICALL_ADDRESS)).await?;
+
+    let mut pool_indices = vec![];
+    for (pool_idx, pool) in pools.iter().enumerate() {
+        if let Pool::Solidly(solidly_pool) = pool {
+            let pair = abi::ISolidlyPair::new(solidly_pool.address, middleware.clone());
+
+            multicall.add_call(pair.metadata(), false).add_call(
+                factory.get_fee(solidly_pool.address, solidly_pool.stable),
+                true,
+            );
+            pool_indices.push(pool_idx);
+        }
+    }
+
+    if pool_indices.is_empty() {
+        return Ok(());
+    }
+
+    let results = multicall.call_raw().await?;
+
+    for (pool_idx, results) in pool_indices.into_iter().zip(results.chunks(2)) {
+        let metadata = match &results[0] {
+            Ok(Token::Tuple(metadata)) => metadata,
+            _ => continue,
+        };
+
+        if let Pool::Solidly(solidly_pool) = &mut pools[pool_idx] {
+            //The pair returns the decimals as multipliers, ie. 1e18 for a token with 18 decimals
+            solidly_pool.token_a_decimals = decimals(&metadata[0]);
+            solidly_pool.token_b_decimals = decimals(&metadata[1]);
+            solidly_pool.reserve_0 = metadata[2].to_owned().into_uint().unwrap().as_u128();
+            solidly_pool.reserve_1 = metadata[3].to_owned().into_uint().unwrap().as_u128();
+            solidly_pool.stable = metadata[4].to_owned().into_bool().unwrap();
+            solidly_pool.token_a = metadata[5].to_owned().into_address().unwrap();
+            solidly_pool.token_b = metadata[6].to_owned().into_address().unwrap();
+
+            if let Ok(Token::Uint(fee)) = &results[1] {
+                if *fee <= U256::from(FACTORY_FEE_DENOMINATOR) {
+                    solidly_pool.fee = fee.as_u32() * (FEE_DENOMINATOR / FACTORY_FEE_DENOMINATOR);
+                } else {
+                    telemetry::warn_event!(
+                        pool = ?solidly_pool.address,
+                        %fee,
+                        "Factory fee is out of bounds, keeping the pool's fee"
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn decimals(multiplier: &Token) -> u8 {
+    let mut multiplier = multiplier.to_owned().into_uint().unwrap_or_default();
+    let mut decimals = 0;
+
+    while multiplier >= 10.into() {
+        multiplier /= 10;
+        decimals += 1;
+    }
+
+    decimals
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::Token,
+        types::{Bytes, H160, U256},
+    };
+
+    use super::get_pool_data_batch_request;
+    use crate::{
+        pool::{solidly::DEFAULT_VOLATILE_FEE, Pool, SolidlyPool},
+        test_utils::mock_provider,
+    };
+
+    fn metadata(stable: bool, token_a: H160, token_b: H160) -> Bytes {
+        ethers::abi::encode(&[
+            Token::Uint(U256::exp10(6)),
+            Token::Uint(U256::exp10(18)),
+            Token::Uint(U256::from(1000)),
+            Token::Uint(U256::from(2000)),
+            Token::Bool(stable),
+            Token::Address(token_a),
+            Token::Address(token_b),
+        ])
+        .into()
+    }
+
+    //Return data of Multicall3's aggregate3, which is a list of (success, return data) results
+    fn aggregate_3_result(results: Vec<(bool, Bytes)>) -> Bytes {
+        ethers::abi::encode(&[Token::Array(
+            results
+                .into_iter()
+                .map(|(success, return_data)| {
+                    Token::Tuple(vec![
+                        Token::Bool(success),
+                        Token::Bytes(return_data.to_vec()),
+                    ])
+                })
+                .collect(),
+        )])
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_get_pool_data_batch_request() {
+        let (middleware, client) = mock_provider();
+        let token_a = H160::from_low_u64_be(100);
+        let token_b = H160::from_low_u64_be(200);
+
+        let mut pools = vec![
+            Pool::Solidly(SolidlyPool {
+                address: H160::from_low_u64_be(1),
+                stable: true,
+                ..Default::default()
+            }),
+            Pool::Solidly(SolidlyPool {
+                address: H160::from_low_u64_be(2),
+                fee: DEFAULT_VOLATILE_FEE,
+                ..Default::default()
+            }),
+        ];
+
+        //The factory reports a 4 basis point fee for the first pair and does not have a fee for the second pair
+        client.push(aggregate_3_result(vec![
+            (true, metadata(true, token_a, token_b)),
+            (
+                true,
+                ethers::abi::encode(&[Token::Uint(U256::from(4))]).into(),
+            ),
+            (true, metadata(false, token_a, token_b)),
+            (false, Bytes::new()),
+        ]));
+
+        get_pool_data_batch_request(H160::from_low_u64_be(10), &mut pools, middleware)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            pools[0],
+            Pool::Solidly(SolidlyPool {
+                address: H160::from_low_u64_be(1),
+                token_a,
+                token_a_decimals: 6,
+                token_b,
+                token_b_decimals: 18,
+                stable: true,
+                reserve_0: 1000,
+                reserve_1: 2000,
+                fee: 40,
+            })
+        );
+
+        if let Pool::Solidly(pool) = &pools[1] {
+            assert!(!pool.stable);
+            assert_eq!(pool.fee, DEFAULT_VOLATILE_FEE);
+        }
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_pool_data_batch_request_fee_out_of_bounds() {
+        let (middleware, client) = mock_provider();
+        let token_a = H160::from_low_u64_be(100);
+        let token_b = H160::from_low_u64_be(200);
+
+        let mut pools = vec![
+            Pool::Solidly(SolidlyPool {
+                address: H160::from_low_u64_be(1),
+                fee: DEFAULT_VOLATILE_FEE,
+                ..Default::default()
+            }),
+            Pool::Solidly(SolidlyPool {
+                address: H160::from_low_u64_be(2),
+                fee: DEFAULT_VOLATILE_FEE,
+                ..Default::default()
+            }),
+        ];
+
+        //A fee above 100% and a fee that does not fit in a u32 are both ignored
+        client.push(aggregate_3_result(vec![
+            (true, metadata(false, token_a, token_b)),
+            (
+                true,
+                ethers::abi::encode(&[Token::Uint(U256::from(10001))]).into(),
+            ),
+            (true, metadata(false, token_a, token_b)),
+            (true, ethers::abi::encode(&[Token::Uint(U256::MAX)]).into()),
+        ]));
+
+        get_pool_data_batch_request(H160::from_low_u64_be(10), &mut pools, middleware)
+            .await
+            .unwrap();
+
+        for pool in pools {
+            if let Pool::Solidly(pool) = pool {
+                assert_eq!(pool.reserve_0, 1000);
+                assert_eq!(pool.fee, DEFAULT_VOLATILE_FEE);
+            }
+        }
+    }
+}
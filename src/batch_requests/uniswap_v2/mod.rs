@@ -18,6 +18,10 @@ abigen!(
     "src/batch_requests/uniswap_v2/GetUniswapV2PoolDataBatchRequest.json";
 );
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(factory = ?factory, from = %from, step = %step))
+)]
 pub async fn get_pairs_batch_request<M: Middleware>(
     factory: H160,
     from: U256,
@@ -55,6 +59,10 @@ pub async fn get_pairs_batch_request<M: Middleware>(
     Ok(pairs)
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pools = pools.len()))
+)]
 pub async fn get_pool_data_batch_request<M: Middleware>(
     pools: &mut [Pool],
     middleware: Arc<M>,
@@ -69,7 +77,11 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
     let deployer =
         GetUniswapV2PoolDataBatchRequest::deploy(middleware.clone(), constructor_args).unwrap();
 
-    let return_data: Bytes = deployer.call_raw().await?;
+    //Called through the middleware rather than the provider so that middleware like `GasEstimator` sees the request
+    let return_data: Bytes = middleware
+        .call(&deployer.deployer.tx, None)
+        .await
+        .map_err(CFMMError::MiddlewareError)?;
     let return_data_tokens = ethers::abi::decode(
         &[ParamType::Array(Box::new(ParamType::Tuple(vec![
             ParamType::Address,   // token a
@@ -105,7 +117,10 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
                             uniswap_v2_pool.reserve_1 =
                                 pool_data[5].to_owned().into_uint().unwrap().as_u128();
 
-                            uniswap_v2_pool.fee = 300;
+                            //If the fee has not been set by the dex, default to the standard Uniswap V2 fee
+                            if uniswap_v2_pool.fee == 0 {
+                                uniswap_v2_pool.fee = 300;
+                            }
                         }
                     }
                     pool_idx += 1;
@@ -117,6 +132,10 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
     Ok(())
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pool = ?pool.address))
+)]
 pub async fn get_v2_pool_data_batch_request<M: Middleware>(
     pool: &mut UniswapV2Pool,
     middleware: Arc<M>,
@@ -155,7 +174,10 @@ pub async fn get_v2_pool_data_batch_request<M: Middleware>(
                         pool.reserve_0 = pool_data[4].to_owned().into_uint().unwrap().as_u128();
                         pool.reserve_1 = pool_data[5].to_owned().into_uint().unwrap().as_u128();
 
-                        pool.fee = 300;
+                        //If the fee has not been set, default to the standard Uniswap V2 fee
+                        if pool.fee == 0 {
+                            pool.fee = 300;
+                        }
                     }
                 }
             }
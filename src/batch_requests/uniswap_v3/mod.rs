@@ -1,15 +1,17 @@
-use std::{sync::Arc, vec};
+use std::{collections::BTreeMap, sync::Arc, vec};
 
 use ethers::{
     abi::{ParamType, Token},
-    prelude::abigen,
+    prelude::{abigen, Multicall, MULTICALL_ADDRESS},
     providers::Middleware,
     types::{Bytes, I256, U256, U64},
 };
 
 use crate::{
+    abi,
     errors::CFMMError,
     pool::{Pool, UniswapV3Pool},
+    telemetry,
 };
 
 abigen!(
@@ -24,6 +26,10 @@ abigen!(
     "src/batch_requests/uniswap_v3/GetUniswapV3TickDataBatchRequest.json";
 );
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pools = pools.len()))
+)]
 pub async fn get_pool_data_batch_request<M: Middleware>(
     pools: &mut [Pool],
     middleware: Arc<M>,
@@ -38,7 +44,11 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
     let deployer =
         GetUniswapV3PoolDataBatchRequest::deploy(middleware.clone(), constructor_args).unwrap();
 
-    let return_data: Bytes = deployer.call_raw().await?;
+    //Called through the middleware rather than the provider so that middleware like `GasEstimator` sees the request
+    let return_data: Bytes = middleware
+        .call(&deployer.deployer.tx, None)
+        .await
+        .map_err(CFMMError::MiddlewareError)?;
 
     let return_data_tokens = ethers::abi::decode(
         &[ParamType::Array(Box::new(ParamType::Tuple(vec![
@@ -109,6 +119,10 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
     Ok(())
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pool = ?pool.address))
+)]
 pub async fn get_v3_pool_data_batch_request<M: Middleware>(
     pool: &mut UniswapV3Pool,
     middleware: Arc<M>,
@@ -182,6 +196,10 @@ pub struct UniswapV3TickData {
     pub liquidity_net: i128,
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pool = ?pool.address, tick_start, zero_for_one, num_ticks, block_number = ?block_number))
+)]
 pub async fn get_uniswap_v3_tick_data_batch_request<M: Middleware>(
     pool: &UniswapV3Pool,
     tick_start: i32,
@@ -201,8 +219,8 @@ pub async fn get_uniswap_v3_tick_data_batch_request<M: Middleware>(
     let deployer =
         GetUniswapV3TickDataBatchRequest::deploy(middleware.clone(), constructor_args).unwrap();
 
-    let return_data: Bytes = if block_number.is_some() {
-        deployer.block(block_number.unwrap()).call_raw().await?
+    let return_data: Bytes = if let Some(block_number) = block_number {
+        deployer.block(block_number).call_raw().await?
     } else {
         deployer.call_raw().await?
     };
@@ -263,9 +281,58 @@ pub async fn get_uniswap_v3_tick_data_batch_request<M: Middleware>(
         .into_uint()
         .expect("Failed to convert block_number from Token to U64");
 
+    telemetry::debug_event!(
+        ticks = tick_data.len(),
+        block_number = block_number.as_u64(),
+        "Got tick data"
+    );
+
     Ok((tick_data, U64::from(block_number.as_u64())))
 }
 
+//Gets the liquidity gross of each tick at the block that the tick data was read at. The tick data batch only returns the
+//liquidity net, which can not tell when a tick has no positions left.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pool = ?pool.address, ticks = ticks.len(), block_number = ?block_number))
+)]
+pub async fn get_uniswap_v3_liquidity_gross_batch_request<M: Middleware>(
+    pool: &UniswapV3Pool,
+    ticks: &[i32],
+    block_number: U64,
+    middleware: Arc<M>,
+) -> Result<BTreeMap<i32, u128>, CFMMError<M>> {
+    if ticks.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let contract = abi::IUniswapV3Pool::new(pool.address, middleware.clone());
+    let mut multicall = Multicall::new(middleware.clone(), Some(MULTICALL_ADDRESS))
+        .await?
+        .block(block_number);
+
+    for tick in ticks {
+        multicall.add_call(contract.ticks(*tick), false);
+    }
+
+    let mut liquidity_gross = BTreeMap::new();
+    for (tick, result) in ticks.iter().zip(multicall.call_raw().await?) {
+        if let Ok(Token::Tuple(tick_info)) = result {
+            if let Some(tick_liquidity_gross) =
+                tick_info.into_iter().next().and_then(Token::into_uint)
+            {
+                liquidity_gross.insert(*tick, tick_liquidity_gross.as_u128());
+            }
+        }
+    }
+
+    Ok(liquidity_gross)
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pool = ?pool.address))
+)]
 pub async fn sync_v3_pool_batch_request<M: Middleware>(
     pool: &mut UniswapV3Pool,
     middleware: Arc<M>,
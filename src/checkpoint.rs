@@ -1,25 +1,33 @@
-use core::panic;
 use std::{
-    fs::read_to_string,
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Write},
     panic::resume_unwind,
+    path::{Path, PathBuf},
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use ethers::{
     providers::Middleware,
-    types::{BlockNumber, H160, U256},
+    types::{BlockNumber, Filter, ValueOrArray, H160, U256},
 };
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use futures::future;
+use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use tokio::task::JoinHandle;
 
 use crate::{
     dex::{Dex, DexVariant},
-    errors::CFMMError,
-    pool::{Pool, UniswapV2Pool, UniswapV3Pool},
-    sync,
+    errors::{CFMMError, CheckpointError},
+    logs::LogFetcher,
+    pool::{self, Pool, UniswapV2Pool, UniswapV3Pool},
+    retry::RetryPolicy,
+    state_space::StateSpaceManager,
+    stats::SyncStatsCollector,
+    sync::{self, SyncConfig, SyncReport},
+    telemetry,
     throttle::RequestThrottle,
 };
 
@@ -33,28 +41,149 @@ pub async fn sync_pools_from_checkpoint<M: 'static + Middleware>(
 }
 
 //Get all pairs from last synced block and sync reserve values for each Dex in the `dexes` vec.
+//Use `sync_pools_from_checkpoint_with_config` to set the retry policy, the progress or to stop a sync part way through.
 pub async fn sync_pools_from_checkpoint_with_throttle<M: 'static + Middleware>(
     path_to_checkpoint: &str,
     step: usize,
     requests_per_second_limit: usize,
     middleware: Arc<M>,
 ) -> Result<(Vec<Dex>, Vec<Pool>), CFMMError<M>> {
-    let current_block = middleware
-        .get_block_number()
-        .await
-        .map_err(CFMMError::MiddlewareError)?;
+    let config = SyncConfig::new()
+        .with_step(step)
+        .with_requests_per_second_limit(requests_per_second_limit);
 
-    let request_throttle = Arc::new(Mutex::new(RequestThrottle::new(requests_per_second_limit)));
-    //Initialize multi progress bar
-    let multi_progress_bar = MultiProgress::new();
+    let (dexes, sync_report) =
+        sync_pools_from_checkpoint_with_config(path_to_checkpoint, false, config, middleware)
+            .await?;
 
-    //Read in checkpoint
-    let (dexes, pools, checkpoint_block_number) = deconstruct_checkpoint(path_to_checkpoint);
+    Ok((dexes, sync_report.synced_pools))
+}
+
+//Loads the pool state from the checkpoint and only refreshes the pools that emitted a state changing log since the checkpoint block.
+//The refreshed and new pools are appended to the checkpoint as a delta, use `compact_checkpoint` to fold the deltas into the snapshot.
+pub async fn sync_touched_pools_from_checkpoint<M: 'static + Middleware>(
+    path_to_checkpoint: &str,
+    step: usize,
+    middleware: Arc<M>,
+) -> Result<(Vec<Dex>, Vec<Pool>), CFMMError<M>> {
+    let config = SyncConfig::new().with_step(step);
+
+    let (dexes, sync_report) =
+        sync_pools_from_checkpoint_with_config(path_to_checkpoint, true, config, middleware)
+            .await?;
 
-    //Sort all of the pools from the checkpoint into uniswapv2 and uniswapv3 pools so we can sync them concurrently
-    let (uinswap_v2_pools, uniswap_v3_pools) = sort_pool_variants(pools);
+    Ok((dexes, sync_report.synced_pools))
+}
+
+//Loads the dexes, the fully populated pools and the block number that the pool state is valid at, without making any requests to the node.
+//Pools that could not be synced are kept in the checkpoint without their state and are left out.
+pub fn load_pools_from_checkpoint(
+    checkpoint_path: &str,
+) -> Result<(Vec<Dex>, Vec<Pool>, u64), CheckpointError> {
+    let (dexes, pools, block_number) = deconstruct_checkpoint(checkpoint_path)?;
+
+    Ok((
+        dexes,
+        pools
+            .into_iter()
+            .filter(|pool| !sync::is_empty_pool(pool))
+            .collect(),
+        block_number_from_checkpoint(block_number)?,
+    ))
+}
+
+//Syncs the pools from the checkpoint, listing the pools that could not be synced in the report instead of failing the sync.
+//The synced pools in the report include the pools that were kept from the checkpoint when `only_touched_pools` is set.
+//Checkpoint pools that fail or are skipped are written back without their state, so the checkpoint block never covers stale state
+//and the pools are requested again by the next sync.
+//
+//If the config has a cancellation token, the sync stops when it is cancelled. A cancelled sync does not update the checkpoint,
+//but saves the last completed block range of new pools for each dex as a partial checkpoint next to it, so the next sync only
+//requests the pool created logs after that range. The checkpoint pools are synced again. The report of a cancelled sync lists
+//the checkpoint pools that were being synced as unsynced pools, with their state from the checkpoint block, along with the new
+//pools that were found before the sync was cancelled.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(path_to_checkpoint, step = config.step, only_touched_pools))
+)]
+pub async fn sync_pools_from_checkpoint_with_config<M: 'static + Middleware>(
+    path_to_checkpoint: &str,
+    only_touched_pools: bool,
+    config: SyncConfig,
+    middleware: Arc<M>,
+) -> Result<(Vec<Dex>, SyncReport<M>), CFMMError<M>> {
+    //A sync without a cancellation token runs until it is done
+    let cancellation_token = config.cancellation_token.clone().unwrap_or_default();
+
+    let current_block = config
+        .retry_policy
+        .retry(|| async {
+            middleware
+                .get_block_number()
+                .await
+                .map_err(CFMMError::MiddlewareError)
+        })
+        .await?;
+
+    let sync_stats = Arc::new(SyncStatsCollector::new(config.sync_progress.clone()));
+
+    //Read in checkpoint
+    let (dexes, pools, checkpoint_block_number) = deconstruct_checkpoint(path_to_checkpoint)?;
+    let checkpoint_block_number = block_number_from_checkpoint(checkpoint_block_number)?;
+    let from_block = checkpoint_block_number + 1;
+    let partial_checkpoint = PartialCheckpoint::load(path_to_checkpoint)?;
 
     let mut aggregated_pools = vec![];
+
+    //Keep the checkpoint state for pools that have not changed since the checkpoint block
+    let pools = if only_touched_pools {
+        //The checkpoint state is valid at the end of the checkpoint block, so only logs after it are relevant
+        let touched_pools = tokio::select! {
+            biased;
+            _ = cancellation_token.cancelled() => {
+                telemetry::info_event!("Sync from checkpoint cancelled while getting touched pools");
+
+                let sync_report = SyncReport {
+                    cancelled: true,
+                    unsynced_pools: pools,
+                    ..SyncReport::default()
+                };
+                return Ok((dexes, sync_report));
+            }
+            touched_pools = get_touched_pool_addresses(
+                BlockNumber::Number(from_block.into()),
+                current_block.into(),
+                config.step,
+                config.request_throttle.clone(),
+                config.retry_policy.clone(),
+                middleware.clone(),
+            ) => touched_pools?,
+        };
+
+        //Pools without state failed a previous sync and are requested again even if they were not touched
+        let (touched, untouched): (Vec<Pool>, Vec<Pool>) = pools
+            .into_iter()
+            .partition(|pool| touched_pools.contains(&pool.address()) || sync::is_empty_pool(pool));
+
+        telemetry::info_event!(
+            touched_pools = touched.len(),
+            untouched_pools = untouched.len(),
+            "Got pools touched since the checkpoint"
+        );
+
+        aggregated_pools.extend(untouched);
+        touched
+    } else {
+        pools
+    };
+
+    let mut stale_pools = pools.iter().map(Pool::without_state).collect::<Vec<Pool>>();
+    let pools_to_sync = pools.clone();
+
+    //Sort all of the pools from the checkpoint into uniswapv2, uniswapv3, solidly, curve and balancer v2 pools so we can sync them concurrently
+    let (uinswap_v2_pools, uniswap_v3_pools, solidly_pools, curve_pools, balancer_v2_pools) =
+        sort_pool_variants(pools);
+
     let mut handles = vec![];
 
     //Sync all uniswap v2 pools from checkpoint
@@ -63,8 +192,8 @@ pub async fn sync_pools_from_checkpoint_with_throttle<M: 'static + Middleware>(
             batch_sync_pools_from_checkpoint(
                 uinswap_v2_pools,
                 DexVariant::UniswapV2,
-                multi_progress_bar.add(ProgressBar::new(0)),
-                request_throttle.clone(),
+                config.clone(),
+                sync_stats.clone(),
                 middleware.clone(),
             )
             .await,
@@ -77,31 +206,142 @@ pub async fn sync_pools_from_checkpoint_with_throttle<M: 'static + Middleware>(
             batch_sync_pools_from_checkpoint(
                 uniswap_v3_pools,
                 DexVariant::UniswapV3,
-                multi_progress_bar.add(ProgressBar::new(0)),
-                request_throttle.clone(),
+                config.clone(),
+                sync_stats.clone(),
                 middleware.clone(),
             )
             .await,
         );
     }
 
-    //Sync all pools from the since synced block
-    handles.extend(
-        get_new_pools_from_range(
-            dexes.clone(),
-            checkpoint_block_number,
-            current_block.into(),
-            step,
-            request_throttle,
-            multi_progress_bar,
+    //Sync all solidly pools from checkpoint, the pools keep the fee from the checkpoint since the factory is not known
+    if !solidly_pools.is_empty() {
+        handles.push(
+            batch_sync_pools_from_checkpoint(
+                solidly_pools,
+                DexVariant::Solidly,
+                config.clone(),
+                sync_stats.clone(),
+                middleware.clone(),
+            )
+            .await,
+        );
+    }
+
+    //Sync all curve pools from checkpoint, the coins and decimals are kept and only the pool state is requested
+    if !curve_pools.is_empty() {
+        handles.push(
+            batch_sync_pools_from_checkpoint(
+                curve_pools,
+                DexVariant::Curve,
+                config.clone(),
+                sync_stats.clone(),
+                middleware.clone(),
+            )
+            .await,
+        );
+    }
+
+    //Sync all balancer v2 pools from checkpoint, the pool id and vault are kept from the checkpoint
+    if !balancer_v2_pools.is_empty() {
+        handles.push(
+            batch_sync_pools_from_checkpoint(
+                balancer_v2_pools,
+                DexVariant::BalancerV2,
+                config.clone(),
+                sync_stats.clone(),
+                middleware.clone(),
+            )
+            .await,
+        );
+    }
+
+    //Sync all pools created since the checkpoint block, resuming each dex from the partial checkpoint if the last sync was cancelled
+    let mut dex_handles = vec![];
+    for dex in dexes.clone() {
+        let dex_sync_state = partial_checkpoint
+            .as_ref()
+            .and_then(|partial_checkpoint| partial_checkpoint.dex_sync_state(&dex))
+            .filter(|dex_sync_state| {
+                dex_sync_state.synced_to_block >= Some(checkpoint_block_number)
+            })
+            .cloned()
+            .unwrap_or_else(|| DexSyncState {
+                dex,
+                synced_to_block: Some(checkpoint_block_number),
+                pools: vec![],
+            });
+
+        dex_handles.push(tokio::spawn(sync::sync_dex_until_cancelled(
+            dex_sync_state,
+            current_block.as_u64(),
+            config.clone(),
+            cancellation_token.clone(),
+            sync_stats.clone(),
             middleware.clone(),
-        )
-        .await,
-    );
+        )));
+    }
+
+    let results = tokio::select! {
+        biased;
+        _ = cancellation_token.cancelled() => None,
+        results = future::join_all(handles.iter_mut()) => Some(results),
+    };
 
-    for handle in handles {
+    //The dex tasks stop on their own when cancelled, returning the block range that they completed
+    let mut sync_report = SyncReport::new();
+    let mut dex_sync_states = vec![];
+    for handle in dex_handles {
         match handle.await {
-            Ok(sync_result) => aggregated_pools.extend(sync_result?),
+            Ok(sync_result) => {
+                let (dex_sync_state, dex_sync_report) = sync_result?;
+                dex_sync_states.push(dex_sync_state);
+                sync_report.extend(dex_sync_report);
+            }
+            Err(err) => {
+                if err.is_panic() {
+                    // Resume the panic on the main task
+                    resume_unwind(err.into_panic());
+                }
+            }
+        }
+    }
+
+    //The checkpoint is left as it is, the untouched pools are still valid at the current block
+    let results = match results {
+        Some(results) if !sync_report.cancelled => results,
+        _ => {
+            for handle in handles.iter() {
+                handle.abort();
+            }
+
+            telemetry::info_event!("Sync from checkpoint cancelled while syncing pools");
+
+            //New pools are synced again when the sync is resumed, even if their dex finished syncing
+            let mut unsynced_pools = pools_to_sync;
+            unsynced_pools.extend(
+                dex_sync_states
+                    .iter()
+                    .flat_map(|dex_sync_state| dex_sync_state.pools.clone()),
+            );
+
+            PartialCheckpoint::new(current_block.as_u64(), dex_sync_states)
+                .save(path_to_checkpoint)?;
+
+            let sync_report = SyncReport {
+                synced_pools: aggregated_pools,
+                stats: sync_stats.finish(),
+                cancelled: true,
+                unsynced_pools,
+                ..SyncReport::default()
+            };
+            return Ok((dexes, sync_report));
+        }
+    };
+
+    for result in results {
+        match result {
+            Ok(sync_result) => sync_report.extend(sync_result?),
             Err(err) => {
                 {
                     if err.is_panic() {
@@ -113,139 +353,207 @@ pub async fn sync_pools_from_checkpoint_with_throttle<M: 'static + Middleware>(
         }
     }
 
-    //update the sync checkpoint
-    construct_checkpoint(
-        dexes.clone(),
-        &aggregated_pools,
-        current_block.as_u64(),
-        path_to_checkpoint,
+    //Pools found by a dex that was resumed from a partial checkpoint can already be in the checkpoint
+    let mut seen_pools = aggregated_pools
+        .iter()
+        .map(|pool| pool.address())
+        .collect::<HashSet<H160>>();
+    sync_report
+        .synced_pools
+        .retain(|pool| seen_pools.insert(pool.address()));
+
+    //The checkpoint pools that were not synced are failed or skipped, their old state is not valid at the current block
+    stale_pools.retain(|pool| !seen_pools.contains(&pool.address()));
+
+    //update the sync checkpoint, only appending the refreshed and new pools when the untouched pools were kept from the checkpoint
+    if only_touched_pools {
+        let mut delta_pools = sync_report.synced_pools.clone();
+        delta_pools.extend(stale_pools);
+        append_checkpoint_delta(
+            path_to_checkpoint,
+            &CheckpointDelta::new(current_block.as_u64(), delta_pools),
+        )?;
+        aggregated_pools.append(&mut sync_report.synced_pools);
+    } else {
+        aggregated_pools.append(&mut sync_report.synced_pools);
+        let mut checkpoint_pools = aggregated_pools.clone();
+        checkpoint_pools.extend(stale_pools);
+        construct_checkpoint(
+            dexes.clone(),
+            &checkpoint_pools,
+            current_block.as_u64(),
+            path_to_checkpoint,
+        )?;
+    }
+    remove_partial_checkpoint(path_to_checkpoint)?;
+    sync_report.synced_pools = aggregated_pools;
+    sync_report.stats = sync_stats.finish();
+
+    telemetry::info_event!(
+        block_number = current_block.as_u64(),
+        synced_pools = sync_report.synced_pools.len(),
+        failed_pools = sync_report.failed_pools.len(),
+        skipped_pools = sync_report.skipped_pools.len(),
+        "Synced pools from checkpoint"
     );
 
-    Ok((dexes, aggregated_pools))
+    Ok((dexes, sync_report))
+}
+
+//Gets the addresses of all pools that emitted a state changing log within the block range
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(from_block = ?from_block, to_block = ?to_block))
+)]
+pub async fn get_touched_pool_addresses<M: 'static + Middleware>(
+    from_block: BlockNumber,
+    to_block: BlockNumber,
+    step: usize,
+    request_throttle: Arc<RequestThrottle>,
+    retry_policy: Arc<RetryPolicy>,
+    middleware: Arc<M>,
+) -> Result<HashSet<H160>, CFMMError<M>> {
+    let from_block = from_block
+        .as_number()
+        .expect("Error converting from block as number")
+        .as_u64();
+    let to_block = to_block
+        .as_number()
+        .expect("Error converting to block as number")
+        .as_u64();
+
+    let filter = Filter::new().topic0(ValueOrArray::Array(
+        StateSpaceManager::<M>::state_change_event_signatures()
+            .into_iter()
+            .map(Some)
+            .collect(),
+    ));
+
+    let log_ranges = LogFetcher::new(step as u64)
+        .with_request_throttle(request_throttle)
+        .with_retry_policy(retry_policy)
+        .get_logs(&filter, from_block, to_block, middleware)
+        .await?;
+
+    let touched_pools = log_ranges
+        .into_iter()
+        .flat_map(|log_range| log_range.logs)
+        .map(|log| pool::pool_address_from_log(&log))
+        .collect();
+
+    Ok(touched_pools)
 }
 
 pub async fn batch_sync_pools_from_checkpoint<M: 'static + Middleware>(
     mut pools: Vec<Pool>,
     dex_variant: DexVariant,
-    progress_bar: ProgressBar,
-    request_throttle: Arc<Mutex<RequestThrottle>>,
+    config: SyncConfig,
+    sync_stats: Arc<SyncStatsCollector>,
     middleware: Arc<M>,
-) -> JoinHandle<Result<Vec<Pool>, CFMMError<M>>> {
+) -> JoinHandle<Result<SyncReport<M>, CFMMError<M>>> {
     let dex = Dex::new(H160::zero(), dex_variant, 0, None);
 
     //Spawn a new thread to get all pools and sync data for each dex
     tokio::spawn(async move {
-        progress_bar.set_style(
-            ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                .expect("Error when setting progress bar style")
-                .progress_chars("##-"),
-        );
-        progress_bar.set_length(pools.len() as u64);
-
-        match dex {
-            Dex::UniswapV2(_) => {
-                progress_bar.set_message("Syncing all Uniswap V2 pool variants from checkpoint");
-            }
-            Dex::UniswapV3(_) => {
-                progress_bar.set_message("Syncing all Uniswap V3 pool variants from checkpoint");
-            }
-        }
+        let pools_discovered = pools.len();
 
         //Get all pool data via batched calls
-        dex.get_all_pool_data(&mut pools, request_throttle, progress_bar, middleware)
+        let failures = dex
+            .get_all_pool_data_with_config(
+                &mut pools,
+                config.batch_config,
+                config.request_throttle,
+                config.retry_policy,
+                sync_stats.clone(),
+                middleware,
+            )
             .await?;
 
-        //Clean empty pools
-        pools = sync::remove_empty_pools(pools);
+        let sync_report = SyncReport::from_pools(pools, failures);
+        sync_stats.record_pools(&dex, pools_discovered, &sync_report);
 
-        Ok::<_, CFMMError<M>>(pools)
+        Ok::<_, CFMMError<M>>(sync_report)
     })
 }
 
-pub fn sort_pool_variants(pools: Vec<Pool>) -> (Vec<Pool>, Vec<Pool>) {
+#[allow(clippy::type_complexity)]
+pub fn sort_pool_variants(
+    pools: Vec<Pool>,
+) -> (Vec<Pool>, Vec<Pool>, Vec<Pool>, Vec<Pool>, Vec<Pool>) {
     let mut uniswap_v2_pools = vec![];
     let mut uniswap_v3_pools = vec![];
+    let mut solidly_pools = vec![];
+    let mut curve_pools = vec![];
+    let mut balancer_v2_pools = vec![];
 
     for pool in pools {
         match pool {
             Pool::UniswapV2(_) => uniswap_v2_pools.push(pool),
             Pool::UniswapV3(_) => uniswap_v3_pools.push(pool),
+            Pool::Solidly(_) => solidly_pools.push(pool),
+            Pool::Curve(_) => curve_pools.push(pool),
+            Pool::BalancerV2(_) => balancer_v2_pools.push(pool),
         }
     }
 
-    (uniswap_v2_pools, uniswap_v3_pools)
+    (
+        uniswap_v2_pools,
+        uniswap_v3_pools,
+        solidly_pools,
+        curve_pools,
+        balancer_v2_pools,
+    )
 }
 
 pub async fn get_new_pools_from_range<M: 'static + Middleware>(
     dexes: Vec<Dex>,
     from_block: BlockNumber,
     to_block: BlockNumber,
-    step: usize,
-    request_throttle: Arc<Mutex<RequestThrottle>>,
-    multi_progress_bar: MultiProgress,
+    config: SyncConfig,
+    sync_stats: Arc<SyncStatsCollector>,
     middleware: Arc<M>,
-) -> Vec<JoinHandle<Result<Vec<Pool>, CFMMError<M>>>> {
+) -> Vec<JoinHandle<Result<SyncReport<M>, CFMMError<M>>>> {
     //Create the filter with all the pair created events
     //Aggregate the populated pools from each thread
     let mut handles = vec![];
 
     for dex in dexes {
         let middleware = middleware.clone();
-        let request_throttle = request_throttle.clone();
-        let progress_bar = multi_progress_bar.add(ProgressBar::new(0));
+        let config = config.clone();
+        let sync_stats = sync_stats.clone();
 
         //Spawn a new thread to get all pools and sync data for each dex
         handles.push(tokio::spawn(async move {
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                    .expect("Error when setting progress bar style")
-                    .progress_chars("##-"),
-            );
-
             //Get all of the pools from the dex
-            progress_bar.set_message(format!(
-                "Getting new all pools from: {}",
-                dex.factory_address()
-            ));
-
             let mut pools = dex
                 .get_all_pools_from_logs_within_range(
                     from_block,
                     to_block,
-                    step,
-                    request_throttle.clone(),
-                    progress_bar.clone(),
+                    config.step,
+                    config.request_throttle.clone(),
+                    config.retry_policy.clone(),
+                    sync_stats.clone(),
                     middleware.clone(),
                 )
                 .await?;
-
-            progress_bar.reset();
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                    .expect("Error when setting progress bar style")
-                    .progress_chars("##-"),
-            );
+            let pools_discovered = pools.len();
 
             //Get all of the pool data and sync the pool
-            progress_bar.set_message(format!(
-                "Getting all pool data for new pools from: {}",
-                dex.factory_address()
-            ));
-
-            progress_bar.set_length(pools.len() as u64);
-
-            dex.get_all_pool_data(
-                &mut pools,
-                request_throttle.clone(),
-                progress_bar.clone(),
-                middleware.clone(),
-            )
-            .await?;
+            let failures = dex
+                .get_all_pool_data_with_config(
+                    &mut pools,
+                    config.batch_config,
+                    config.request_throttle,
+                    config.retry_policy,
+                    sync_stats.clone(),
+                    middleware,
+                )
+                .await?;
 
-            //Clean empty pools
-            pools = sync::remove_empty_pools(pools);
+            let sync_report = SyncReport::from_pools(pools, failures);
+            sync_stats.record_pools(&dex, pools_discovered, &sync_report);
 
-            Ok::<_, CFMMError<M>>(pools)
+            Ok::<_, CFMMError<M>>(sync_report)
         }));
     }
 
@@ -259,10 +567,19 @@ pub async fn generate_checkpoint<M: 'static + Middleware>(
     checkpoint_file_name: &str,
 ) -> Result<(), CFMMError<M>> {
     //Sync pairs with throttle but set the requests per second limit to 0, disabling the throttle.
-    generate_checkpoint_with_throttle(dexes, middleware, 100000, 0, checkpoint_file_name).await
+    generate_checkpoint_with_throttle(
+        dexes,
+        middleware,
+        sync::DEFAULT_SYNC_STEP,
+        0,
+        checkpoint_file_name,
+    )
+    .await
 }
 
 //Get all pairs and sync reserve values for each Dex in the `dexes` vec.
+//Use `sync::sync_pairs_with_config` with the checkpoint file name to set the retry policy, the progress or to stop
+//the sync part way through and resume it later.
 pub async fn generate_checkpoint_with_throttle<M: 'static + Middleware>(
     dexes: Vec<Dex>,
     middleware: Arc<M>,
@@ -270,440 +587,1490 @@ pub async fn generate_checkpoint_with_throttle<M: 'static + Middleware>(
     requests_per_second_limit: usize,
     checkpoint_file_name: &str,
 ) -> Result<(), CFMMError<M>> {
-    //Initialize a new request throttle
-    let request_throttle = Arc::new(Mutex::new(RequestThrottle::new(requests_per_second_limit)));
+    let config = SyncConfig::new()
+        .with_step(step)
+        .with_requests_per_second_limit(requests_per_second_limit);
 
-    //Aggregate the populated pools from each thread
-    let mut aggregated_pools: Vec<Pool> = vec![];
-    let mut handles = vec![];
+    sync::sync_pairs_with_config(dexes, Some(checkpoint_file_name), config, middleware).await?;
 
-    //Initialize multi progress bar
-    let multi_progress_bar = MultiProgress::new();
+    Ok(())
+}
 
-    //For each dex supplied, get all pair created events and get reserve values
-    for dex in dexes.clone() {
-        let async_provider = middleware.clone();
-        let request_throttle = request_throttle.clone();
-        let progress_bar = multi_progress_bar.add(ProgressBar::new(0));
+//Current version of the checkpoint schema. Checkpoints without a version field use the hand written layout from before the schema was versioned.
+pub const CHECKPOINT_VERSION: u32 = 2;
 
-        handles.push(tokio::spawn(async move {
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7} Blocks")
-                    .unwrap()
-                    .progress_chars("##-"),
-            );
+//Prefix of binary encoded checkpoints, used to tell them apart from json checkpoints when loading
+pub const BINARY_CHECKPOINT_MAGIC: &[u8; 8] = b"CFMMCKPT";
 
-            let mut pools = dex
-                .get_all_pools(
-                    request_throttle.clone(),
-                    step,
-                    progress_bar.clone(),
-                    async_provider.clone(),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointFormat {
+    Json,
+    Binary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    pub checkpoint_timestamp: u64,
+    pub block_number: u64,
+    pub dexes: Vec<Dex>,
+    pub pools: Vec<Pool>,
+}
+
+//Only the version is deserialized so the rest of the checkpoint can be parsed according to its schema
+#[derive(Deserialize)]
+struct CheckpointVersion {
+    version: Option<u32>,
+}
+
+impl Checkpoint {
+    pub fn new(dexes: Vec<Dex>, pools: Vec<Pool>, block_number: u64) -> Checkpoint {
+        //Fall back to zero if the system clock is before the unix epoch
+        let checkpoint_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            checkpoint_timestamp,
+            block_number,
+            dexes,
+            pools,
+        }
+    }
+
+    pub fn to_bytes(&self, format: CheckpointFormat) -> Result<Vec<u8>, CheckpointError> {
+        match format {
+            CheckpointFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            CheckpointFormat::Binary => {
+                let mut bytes = BINARY_CHECKPOINT_MAGIC.to_vec();
+                bincode::serialize_into(&mut bytes, self)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    //Decodes a checkpoint in either format, migrating checkpoints written with the unversioned layout
+    pub fn from_bytes(bytes: &[u8]) -> Result<Checkpoint, CheckpointError> {
+        let checkpoint = if let Some(bytes) = bytes.strip_prefix(BINARY_CHECKPOINT_MAGIC) {
+            bincode::deserialize::<Checkpoint>(bytes)?
+        } else {
+            match serde_json::from_slice::<CheckpointVersion>(bytes).map(|c| c.version) {
+                Ok(Some(CHECKPOINT_VERSION)) => serde_json::from_slice::<Checkpoint>(bytes)?,
+                Ok(Some(version)) => return Err(CheckpointError::UnsupportedVersion(version)),
+                //Unversioned checkpoints, and json that does not match the expected shape, are parsed with the unversioned layout so errors carry the json path
+                _ => Checkpoint::from_unversioned_json(&serde_json::from_slice(bytes)?)?,
+            }
+        };
+
+        if checkpoint.version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::UnsupportedVersion(checkpoint.version));
+        }
+
+        Ok(checkpoint)
+    }
+
+    //Migrates a checkpoint written with the hand written, unversioned layout
+    pub fn from_unversioned_json(checkpoint_json: &Value) -> Result<Checkpoint, CheckpointError> {
+        let checkpoint_map = checkpoint_json
+            .as_object()
+            .ok_or_else(|| CheckpointError::invalid_field("$", "expected an object"))?;
+
+        let block_number = get_u64(checkpoint_map, "$", "block_number")?;
+
+        let checkpoint_timestamp = match checkpoint_map.get("checkpoint_timestamp") {
+            Some(checkpoint_timestamp) => checkpoint_timestamp.as_u64().ok_or_else(|| {
+                CheckpointError::invalid_field(
+                    "$.checkpoint_timestamp",
+                    "expected an unsigned integer",
                 )
-                .await?;
+            })?,
+            None => 0,
+        };
 
-            progress_bar.reset();
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7} Pairs")
-                    .unwrap()
-                    .progress_chars("##-"),
-            );
+        let mut dexes = vec![];
+        for (i, dex_data) in get_array(checkpoint_map, "$", "dexes")?.iter().enumerate() {
+            let path = format!("$.dexes[{}]", i);
 
-            dex.get_all_pool_data(
-                &mut pools,
-                request_throttle.clone(),
-                progress_bar.clone(),
-                async_provider.clone(),
-            )
-            .await?;
+            let dex = deconstruct_dex_from_checkpoint(
+                dex_data
+                    .as_object()
+                    .ok_or_else(|| CheckpointError::invalid_field(&path, "expected an object"))?,
+                &path,
+            )?;
 
-            progress_bar.finish_and_clear();
-            progress_bar.set_message(format!(
-                "Finished syncing pools for {} ✅",
-                dex.factory_address()
-            ));
+            dexes.push(dex);
+        }
 
-            progress_bar.finish();
+        //get all pools
+        let pools = deconstruct_pools_from_checkpoint(get_array(checkpoint_map, "$", "pools")?)?;
 
-            Ok::<_, CFMMError<M>>(pools)
-        }));
+        Ok(Checkpoint {
+            version: CHECKPOINT_VERSION,
+            checkpoint_timestamp,
+            block_number,
+            dexes,
+            pools,
+        })
     }
 
-    for handle in handles {
-        match handle.await {
-            Ok(sync_result) => aggregated_pools.extend(sync_result?),
-            Err(err) => {
-                {
-                    if err.is_panic() {
-                        // Resume the panic on the main task
-                        resume_unwind(err.into_panic());
-                    }
+    //Loads the checkpoint snapshot and applies any deltas that were appended after it
+    pub fn load(checkpoint_path: &str) -> Result<Checkpoint, CheckpointError> {
+        let mut checkpoint = Checkpoint::load_snapshot(checkpoint_path)?;
+
+        for delta in read_checkpoint_deltas(checkpoint_path)? {
+            checkpoint.apply_delta(delta);
+        }
+
+        Ok(checkpoint)
+    }
+
+    //Loads the checkpoint snapshot without applying deltas
+    pub fn load_snapshot(checkpoint_path: &str) -> Result<Checkpoint, CheckpointError> {
+        Checkpoint::from_bytes(&std::fs::read(checkpoint_path)?)
+    }
+
+    //Writes the checkpoint to a temp file, syncs it to disk and renames it over the checkpoint so that a crash never leaves a partially written checkpoint
+    pub fn save(
+        &self,
+        checkpoint_path: &str,
+        format: CheckpointFormat,
+    ) -> Result<(), CheckpointError> {
+        write_atomic(Path::new(checkpoint_path), &self.to_bytes(format)?)
+    }
+
+    //Inserts or replaces the pools in the delta. Deltas at or before the checkpoint block are already included and are skipped.
+    pub fn apply_delta(&mut self, delta: CheckpointDelta) {
+        if delta.block_number <= self.block_number {
+            return;
+        }
+
+        let mut pool_indices = self
+            .pools
+            .iter()
+            .enumerate()
+            .map(|(i, pool)| (pool.address(), i))
+            .collect::<HashMap<H160, usize>>();
+
+        for pool in delta.pools {
+            match pool_indices.get(&pool.address()) {
+                Some(&i) => self.pools[i] = pool,
+                None => {
+                    pool_indices.insert(pool.address(), self.pools.len());
+                    self.pools.push(pool);
                 }
             }
         }
+
+        self.block_number = delta.block_number;
     }
+}
 
-    //Clean empty pools
-    aggregated_pools = sync::remove_empty_pools(aggregated_pools);
+//Newly discovered pools and the latest state of pools that changed, up to and including `block_number`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointDelta {
+    pub block_number: u64,
+    pub pools: Vec<Pool>,
+}
 
-    let latest_block = middleware
-        .get_block_number()
-        .await
-        .map_err(CFMMError::MiddlewareError)?;
+impl CheckpointDelta {
+    pub fn new(block_number: u64, pools: Vec<Pool>) -> CheckpointDelta {
+        CheckpointDelta {
+            block_number,
+            pools,
+        }
+    }
+}
 
-    println!("total pools :{}", aggregated_pools.len());
+//Deltas are stored as json lines in a file next to the checkpoint
+pub fn checkpoint_delta_path(checkpoint_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.delta", checkpoint_path))
+}
 
-    construct_checkpoint(
-        dexes,
-        &aggregated_pools,
-        latest_block.as_u64(),
-        checkpoint_file_name,
-    );
+//Appends a delta to the checkpoint without rewriting the snapshot
+pub fn append_checkpoint_delta(
+    checkpoint_path: &str,
+    delta: &CheckpointDelta,
+) -> Result<(), CheckpointError> {
+    let mut line = serde_json::to_vec(delta)?;
+    line.push(b'\n');
+
+    let mut delta_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(checkpoint_delta_path(checkpoint_path))?;
+
+    delta_file.write_all(&line)?;
+    delta_file.sync_all()?;
 
     Ok(())
 }
 
-pub fn deconstruct_checkpoint(checkpoint_path: &str) -> (Vec<Dex>, Vec<Pool>, BlockNumber) {
-    let mut dexes = vec![];
+//Reads all deltas appended to the checkpoint, ignoring a trailing line that was only partially written
+pub fn read_checkpoint_deltas(
+    checkpoint_path: &str,
+) -> Result<Vec<CheckpointDelta>, CheckpointError> {
+    let contents = match std::fs::read(checkpoint_delta_path(checkpoint_path)) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
 
-    let checkpoint_json: serde_json::Value = serde_json::from_str(
-        read_to_string(checkpoint_path)
-            .expect("Error when reading in checkpoint json")
-            .as_str(),
-    )
-    .expect("Error when converting checkpoint file contents to serde_json::Value");
-
-    let block_number = checkpoint_json
-        .get("block_number")
-        .expect("Could not get block_number from checkpoint")
-        .as_u64()
-        .expect("Could not convert block_number to u64");
-
-    for dex_data in checkpoint_json
-        .get("dexes")
-        .expect("Could not get checkpoint_data")
-        .as_array()
-        .expect("Could not unwrap checkpoint json into array")
-        .iter()
-    {
-        let dex = deconstruct_dex_from_checkpoint(
-            dex_data
-                .as_object()
-                .expect("Dex checkpoint is not formatted correctly"),
-        );
+    let mut deltas = vec![];
+    let mut lines = contents.split_inclusive(|byte| *byte == b'\n').peekable();
+
+    while let Some(line) = lines.next() {
+        //Every complete delta ends with a newline, so a final line without one was interrupted mid write
+        if lines.peek().is_none() && !line.ends_with(b"\n") {
+            break;
+        }
 
-        dexes.push(dex);
+        if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+            continue;
+        }
+
+        deltas.push(serde_json::from_slice(line)?);
     }
 
-    //get all pools
-    let pools_array = checkpoint_json
-        .get("pools")
-        .expect("Could not get pools from checkpoint")
-        .as_array()
-        .expect("Could not convert pools to value array");
+    Ok(deltas)
+}
 
-    let pools = deconstruct_pools_from_checkpoint(pools_array);
+//Folds the appended deltas into a new snapshot and removes the delta file
+pub fn compact_checkpoint(
+    checkpoint_path: &str,
+    format: CheckpointFormat,
+) -> Result<(), CheckpointError> {
+    Checkpoint::load(checkpoint_path)?.save(checkpoint_path, format)?;
+
+    //If the process stops before the delta file is removed, the deltas are at or before the snapshot block and are skipped on load
+    match std::fs::remove_file(checkpoint_delta_path(checkpoint_path)) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
 
-    (dexes, pools, BlockNumber::Number(block_number.into()))
+//Progress of a dex in a sync that was cancelled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DexSyncState {
+    pub dex: Dex,
+    //Last block that all pool created logs were fetched up to, `None` if no block range was completed
+    pub synced_to_block: Option<u64>,
+    //Pools created up to `synced_to_block`, their data is synced again when the sync is resumed
+    pub pools: Vec<Pool>,
 }
 
-pub fn deconstruct_dex_from_checkpoint(dex_map: &Map<String, Value>) -> Dex {
-    let dex_variant = match dex_map
-        .get("dex_variant")
-        .expect("Checkpoint formatted incorrectly, could not get dex_variant.")
-        .as_str()
-        .expect("Could not convert dex variant to string")
-        .to_lowercase()
-        .as_str()
-    {
-        "uniswapv2" => DexVariant::UniswapV2,
-        "uniswapv3" => DexVariant::UniswapV3,
-        other => {
-            panic!("Unrecognized dex variant in checkpoint: {:?}", other)
+impl DexSyncState {
+    pub fn new(dex: Dex) -> DexSyncState {
+        DexSyncState {
+            dex,
+            synced_to_block: None,
+            pools: vec![],
         }
-    };
+    }
+}
 
-    let block_number = dex_map
-        .get("block_number")
-        .expect("Checkpoint formatted incorrectly, could not get dex latest_synced_block.")
-        .as_u64()
-        .expect("Could not convert latest_synced_block to u64");
-
-    let factory_address = H160::from_str(
-        dex_map
-            .get("factory_address")
-            .expect("Checkpoint formatted incorrectly, could not get dex factory_address.")
-            .as_str()
-            .expect("Could not convert factory_address to str"),
-    )
-    .expect("Could not convert checkpoint factory_address to H160.");
+//Written when a sync is cancelled so that the next sync can resume from the last completed block range of each dex
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialCheckpoint {
+    pub checkpoint_timestamp: u64,
+    //Block that the cancelled sync was syncing up to
+    pub block_number: u64,
+    pub dexes: Vec<DexSyncState>,
+}
 
-    let fee = dex_map
-        .get("fee")
-        .map(|fee| fee.as_u64().expect("Could not convert fee to u64"));
+impl PartialCheckpoint {
+    pub fn new(block_number: u64, dexes: Vec<DexSyncState>) -> PartialCheckpoint {
+        let checkpoint_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        PartialCheckpoint {
+            checkpoint_timestamp,
+            block_number,
+            dexes,
+        }
+    }
+
+    //Loads the partial checkpoint that was written next to the checkpoint, if a sync was cancelled
+    pub fn load(checkpoint_path: &str) -> Result<Option<PartialCheckpoint>, CheckpointError> {
+        match std::fs::read(partial_checkpoint_path(checkpoint_path)) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, checkpoint_path: &str) -> Result<(), CheckpointError> {
+        write_atomic(
+            &partial_checkpoint_path(checkpoint_path),
+            &serde_json::to_vec_pretty(self)?,
+        )
+    }
+
+    pub fn dex_sync_state(&self, dex: &Dex) -> Option<&DexSyncState> {
+        self.dexes.iter().find(|dex_sync_state| {
+            dex_sync_state.dex.factory_address() == dex.factory_address()
+                && dex_sync_state.dex.dex_variant() == dex.dex_variant()
+        })
+    }
+}
 
-    Dex::new(factory_address, dex_variant, block_number, fee)
+pub fn partial_checkpoint_path(checkpoint_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.partial", checkpoint_path))
 }
 
-pub fn deconstruct_pools_from_checkpoint(pools_array: &Vec<Value>) -> Vec<Pool> {
+//Removes the partial checkpoint once the sync that it belongs to has completed
+pub fn remove_partial_checkpoint(checkpoint_path: &str) -> Result<(), CheckpointError> {
+    match std::fs::remove_file(partial_checkpoint_path(checkpoint_path)) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), CheckpointError> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let mut temp_file = File::create(&temp_path)?;
+    temp_file.write_all(bytes)?;
+    temp_file.sync_all()?;
+
+    std::fs::rename(&temp_path, path)?;
+
+    //Sync the directory so that the rename itself is durable
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+//Rewrites a checkpoint of any version or format in the current schema
+pub fn migrate_checkpoint(
+    checkpoint_path: &str,
+    format: CheckpointFormat,
+) -> Result<(), CheckpointError> {
+    Checkpoint::load(checkpoint_path)?.save(checkpoint_path, format)
+}
+
+pub fn deconstruct_checkpoint(
+    checkpoint_path: &str,
+) -> Result<(Vec<Dex>, Vec<Pool>, BlockNumber), CheckpointError> {
+    let checkpoint = Checkpoint::load(checkpoint_path)?;
+
+    Ok((
+        checkpoint.dexes,
+        checkpoint.pools,
+        BlockNumber::Number(checkpoint.block_number.into()),
+    ))
+}
+
+//Returns the block number that the checkpoint state is valid at
+fn block_number_from_checkpoint(block_number: BlockNumber) -> Result<u64, CheckpointError> {
+    block_number
+        .as_number()
+        .map(|block_number| block_number.as_u64())
+        .ok_or_else(|| CheckpointError::invalid_field("$.block_number", "expected a block number"))
+}
+
+pub fn deconstruct_dex_from_checkpoint(
+    dex_map: &Map<String, Value>,
+    path: &str,
+) -> Result<Dex, CheckpointError> {
+    let dex_variant = get_dex_variant(dex_map, path)?;
+    let block_number = get_u64(dex_map, path, "block_number")?;
+    let factory_address = get_h160(dex_map, path, "factory_address")?;
+
+    //The unversioned layout wrote the uniswap v2 dex fee as a decimal string, ie. "300"
+    let fee = match dex_map.get("fee") {
+        Some(fee) => {
+            let fee = fee
+                .as_u64()
+                .or_else(|| fee.as_str().and_then(|fee| fee.parse().ok()))
+                .ok_or_else(|| {
+                    CheckpointError::invalid_field(
+                        &format!("{}.fee", path),
+                        "expected an unsigned integer",
+                    )
+                })?;
+
+            Some(u32::try_from(fee).map_err(|_| {
+                CheckpointError::invalid_field(
+                    &format!("{}.fee", path),
+                    "fee does not fit in a u32",
+                )
+            })?)
+        }
+        None => None,
+    };
+
+    Ok(Dex::new(factory_address, dex_variant, block_number, fee))
+}
+
+pub fn deconstruct_pools_from_checkpoint(
+    pools_array: &[Value],
+) -> Result<Vec<Pool>, CheckpointError> {
     let mut pools = vec![];
 
-    for pool_value in pools_array {
+    for (i, pool_value) in pools_array.iter().enumerate() {
+        let path = format!("$.pools[{}]", i);
+
         let pool_map = pool_value
             .as_object()
-            .expect("Could not convert pool value to map");
-
-        let pool_dex_variant = match pool_map
-            .get("dex_variant")
-            .expect("Could not get pool dex_variant")
-            .as_str()
-            .expect("Could not convert dex_variant to str")
-            .to_lowercase()
-            .as_str()
-        {
-            "uniswapv2" => DexVariant::UniswapV2,
-            "uniswapv3" => DexVariant::UniswapV3,
-            _ => {
-                panic!("Unrecognized pool dex variant")
-            }
-        };
+            .ok_or_else(|| CheckpointError::invalid_field(&path, "expected an object"))?;
+
+        let pool_dex_variant = get_dex_variant(pool_map, &path)?;
+
+        let addr = get_h160(pool_map, &path, "address")?;
+        let token_a = get_h160(pool_map, &path, "token_a")?;
+        let token_a_decimals = get_u8(pool_map, &path, "token_a_decimals")?;
+        let token_b = get_h160(pool_map, &path, "token_b")?;
+        let token_b_decimals = get_u8(pool_map, &path, "token_b_decimals")?;
+
+        let fee = u32::try_from(get_u64(pool_map, &path, "fee")?).map_err(|_| {
+            CheckpointError::invalid_field(&format!("{}.fee", path), "fee does not fit in a u32")
+        })?;
 
         match pool_dex_variant {
-            DexVariant::UniswapV2 | DexVariant::UniswapV3 => {
-                let addr = H160::from_str(
-                    pool_map
-                        .get("address")
-                        .unwrap_or_else(|| panic!("Could not get pool address {:?}", pool_map))
-                        .as_str()
-                        .unwrap_or_else(|| {
-                            panic!("Could not convert pool address to str {:?}", pool_map)
-                        }),
-                )
-                .expect("Could not convert token_a to H160");
-
-                let token_a = H160::from_str(
-                    pool_map
-                        .get("token_a")
-                        .unwrap_or_else(|| panic!("Could not get token_a {:?}", pool_map))
-                        .as_str()
-                        .unwrap_or_else(|| {
-                            panic!("Could not convert token_a to str {:?}", pool_map)
-                        }),
-                )
-                .expect("Could not convert token_a to H160");
-
-                let token_a_decimals = pool_map
-                    .get("token_a_decimals")
-                    .unwrap_or_else(|| panic!("Could not get token_a_decimals {:?}", pool_map))
-                    .as_u64()
-                    .expect("Could not convert token_a_decimals to u64")
-                    as u8;
-
-                let token_b = H160::from_str(
-                    pool_map
-                        .get("token_b")
-                        .unwrap_or_else(|| panic!("Could not get token_b {:?}", pool_map))
-                        .as_str()
-                        .unwrap_or_else(|| {
-                            panic!("Could not convert token_b to str {:?}", pool_map)
-                        }),
-                )
-                .expect("Could not convert token_b to H160");
-
-                let token_b_decimals = pool_map
-                    .get("token_b_decimals")
-                    .unwrap_or_else(|| panic!("Could not get token_b_decimals {:?}", pool_map))
-                    .as_u64()
-                    .expect("Could not convert token_b_decimals to u64")
-                    as u8;
-
-                let fee = pool_map
-                    .get("fee")
-                    .unwrap_or_else(|| panic!("Could not get fee {:?}", pool_map))
-                    .as_u64()
-                    .expect("Could not convert fee to u64") as u32;
-
-                match pool_dex_variant {
-                    DexVariant::UniswapV2 => {
-                        pools.push(Pool::UniswapV2(UniswapV2Pool::new(
-                            addr,
-                            token_a,
-                            token_a_decimals,
-                            token_b,
-                            token_b_decimals,
-                            0,
-                            0,
-                            fee,
-                        )));
+            DexVariant::UniswapV2 => {
+                pools.push(Pool::UniswapV2(UniswapV2Pool::new(
+                    addr,
+                    token_a,
+                    token_a_decimals,
+                    token_b,
+                    token_b_decimals,
+                    parse_pool_state(pool_map, &path, "reserve_0")?,
+                    parse_pool_state(pool_map, &path, "reserve_1")?,
+                    fee,
+                )));
+            }
+
+            DexVariant::UniswapV3 => {
+                let tick = get_optional_i32(pool_map, &path, "tick")?;
+                let tick_spacing = get_optional_i32(pool_map, &path, "tick_spacing")?;
+
+                let sqrt_price = match pool_map.get("sqrt_price") {
+                    Some(sqrt_price) => {
+                        let field_path = format!("{}.sqrt_price", path);
+
+                        U256::from_dec_str(sqrt_price.as_str().ok_or_else(|| {
+                            CheckpointError::invalid_field(&field_path, "expected a string")
+                        })?)
+                        .map_err(|err| CheckpointError::invalid_field(&field_path, err))?
                     }
+                    None => U256::zero(),
+                };
+
+                let mut pool = UniswapV3Pool::new(
+                    addr,
+                    token_a,
+                    token_a_decimals,
+                    token_b,
+                    token_b_decimals,
+                    fee,
+                    parse_pool_state(pool_map, &path, "liquidity")?,
+                    sqrt_price,
+                    tick,
+                    tick_spacing,
+                    parse_pool_state(pool_map, &path, "liquidity_net")?,
+                );
 
-                    DexVariant::UniswapV3 => {
-                        pools.push(Pool::UniswapV3(UniswapV3Pool::new(
-                            addr,
-                            token_a,
-                            token_a_decimals,
-                            token_b,
-                            token_b_decimals,
-                            fee,
-                            0,
-                            U256::zero(),
-                            0,
-                            0,
-                            0,
-                        )));
+                pool.tick_map = match pool_map.get("tick_map") {
+                    Some(tick_map) => {
+                        Some(serde_json::from_value(tick_map.clone()).map_err(|err| {
+                            CheckpointError::invalid_field(&format!("{}.tick_map", path), err)
+                        })?)
                     }
-                }
+                    None => None,
+                };
+
+                pools.push(Pool::UniswapV3(pool));
+            }
+
+            //Solidly, curve and balancer v2 pools were added after checkpoints were versioned, so they are never in the unversioned layout
+            DexVariant::Solidly | DexVariant::Curve | DexVariant::BalancerV2 => {
+                return Err(CheckpointError::invalid_field(
+                    &format!("{}.dex_variant", path),
+                    "solidly, curve and balancer v2 pools are only supported in versioned checkpoints",
+                ))
             }
         }
     }
 
-    pools
+    Ok(pools)
+}
+
+fn get_field<'a>(
+    map: &'a Map<String, Value>,
+    path: &str,
+    key: &str,
+) -> Result<&'a Value, CheckpointError> {
+    map.get(key).ok_or_else(|| {
+        CheckpointError::invalid_field(&format!("{}.{}", path, key), "missing field")
+    })
+}
+
+fn get_str<'a>(
+    map: &'a Map<String, Value>,
+    path: &str,
+    key: &str,
+) -> Result<&'a str, CheckpointError> {
+    get_field(map, path, key)?.as_str().ok_or_else(|| {
+        CheckpointError::invalid_field(&format!("{}.{}", path, key), "expected a string")
+    })
+}
+
+fn get_u64(map: &Map<String, Value>, path: &str, key: &str) -> Result<u64, CheckpointError> {
+    get_field(map, path, key)?.as_u64().ok_or_else(|| {
+        CheckpointError::invalid_field(&format!("{}.{}", path, key), "expected an unsigned integer")
+    })
+}
+
+fn get_u8(map: &Map<String, Value>, path: &str, key: &str) -> Result<u8, CheckpointError> {
+    u8::try_from(get_u64(map, path, key)?).map_err(|_| {
+        CheckpointError::invalid_field(&format!("{}.{}", path, key), "value does not fit in a u8")
+    })
+}
+
+fn get_h160(map: &Map<String, Value>, path: &str, key: &str) -> Result<H160, CheckpointError> {
+    H160::from_str(get_str(map, path, key)?)
+        .map_err(|err| CheckpointError::invalid_field(&format!("{}.{}", path, key), err))
+}
+
+fn get_array<'a>(
+    map: &'a Map<String, Value>,
+    path: &str,
+    key: &str,
+) -> Result<&'a Vec<Value>, CheckpointError> {
+    get_field(map, path, key)?.as_array().ok_or_else(|| {
+        CheckpointError::invalid_field(&format!("{}.{}", path, key), "expected an array")
+    })
+}
+
+//Missing values default to zero for checkpoints written before pool state was persisted
+fn get_optional_i32(
+    map: &Map<String, Value>,
+    path: &str,
+    key: &str,
+) -> Result<i32, CheckpointError> {
+    match map.get(key) {
+        Some(value) => value
+            .as_i64()
+            .and_then(|value| i32::try_from(value).ok())
+            .ok_or_else(|| {
+                CheckpointError::invalid_field(&format!("{}.{}", path, key), "expected an i32")
+            }),
+        None => Ok(0),
+    }
+}
+
+fn get_dex_variant(map: &Map<String, Value>, path: &str) -> Result<DexVariant, CheckpointError> {
+    match get_str(map, path, "dex_variant")?.to_lowercase().as_str() {
+        "uniswapv2" => Ok(DexVariant::UniswapV2),
+        "uniswapv3" => Ok(DexVariant::UniswapV3),
+        other => Err(CheckpointError::invalid_field(
+            &format!("{}.dex_variant", path),
+            format!("unrecognized dex variant {:?}", other),
+        )),
+    }
 }
 
+//Parses a numeric pool state value that was written to the checkpoint as a string.
+//Checkpoints written before pool state was persisted do not have these fields, so missing values default to zero.
+fn parse_pool_state<T>(
+    map: &Map<String, Value>,
+    path: &str,
+    key: &str,
+) -> Result<T, CheckpointError>
+where
+    T: FromStr + Default,
+    <T as FromStr>::Err: ToString,
+{
+    match map.get(key) {
+        Some(_) => get_str(map, path, key)?
+            .parse()
+            .map_err(|err: <T as FromStr>::Err| {
+                CheckpointError::invalid_field(&format!("{}.{}", path, key), err)
+            }),
+        None => Ok(T::default()),
+    }
+}
+
+//Writes a pretty json checkpoint, use `Checkpoint::save` to write a binary checkpoint
 pub fn construct_checkpoint(
     dexes: Vec<Dex>,
-    pools: &Vec<Pool>,
+    pools: &[Pool],
     latest_block: u64,
     checkpoint_path: &str,
-) {
-    let mut checkpoint = Map::new();
-
-    //Insert checkpoint_timestamp
-    let checkpoint_timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs_f32() as u32;
-
-    checkpoint.insert(
-        String::from("checkpoint_timestamp"),
-        checkpoint_timestamp.into(),
-    );
+) -> Result<(), CheckpointError> {
+    Checkpoint::new(dexes, pools.to_vec(), latest_block)
+        .save(checkpoint_path, CheckpointFormat::Json)
+}
 
-    checkpoint.insert(String::from("block_number"), latest_block.into());
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
 
-    //Add dexes to checkpoint
-    let mut dexes_array: Vec<Value> = vec![];
-    for dex in dexes {
-        let mut dex_map = Map::new();
+    use ethers::{
+        abi::Token,
+        types::{BlockNumber, Bytes, Log, H160, U256, U64},
+    };
+
+    use super::{
+        append_checkpoint_delta, block_number_from_checkpoint, checkpoint_delta_path,
+        compact_checkpoint, construct_checkpoint, deconstruct_checkpoint,
+        load_pools_from_checkpoint, migrate_checkpoint, read_checkpoint_deltas,
+        remove_partial_checkpoint, sync_pools_from_checkpoint_with_config, Checkpoint,
+        CheckpointDelta, CheckpointFormat, PartialCheckpoint, BINARY_CHECKPOINT_MAGIC,
+        CHECKPOINT_VERSION,
+    };
+    use crate::{
+        dex::{Dex, DexVariant},
+        errors::CheckpointError,
+        pool::{
+            uniswap_v2::SYNC_EVENT_SIGNATURE, uniswap_v3::UniswapV3TickMap, Pool, UniswapV2Pool,
+            UniswapV3Pool,
+        },
+        progress::NoProgress,
+        retry::RetryPolicy,
+        sync::{self, CancellationToken, SyncConfig},
+        test_utils::{mock_provider, pair_created_log, CancellingProgress},
+    };
+
+    #[test]
+    fn test_checkpoint_round_trip_pool_state() {
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_checkpoint_round_trip.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+
+        let (dexes, pools) = test_dexes_and_pools();
+
+        construct_checkpoint(dexes, &pools, 17000000, checkpoint_path).unwrap();
 
-        dex_map.insert(
-            String::from("factory_address"),
-            format!("{:?}", dex.factory_address()).into(),
+        let (loaded_dexes, loaded_pools, block_number) =
+            load_pools_from_checkpoint(checkpoint_path).unwrap();
+
+        std::fs::remove_file(checkpoint_path).unwrap();
+
+        assert_eq!(block_number, 17000000);
+        assert_eq!(loaded_dexes.len(), 1);
+        assert_eq!(loaded_pools, pools);
+    }
+
+    fn test_dexes_and_pools() -> (Vec<Dex>, Vec<Pool>) {
+        let dexes = vec![Dex::new(
+            H160::from_str("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f").unwrap(),
+            DexVariant::UniswapV2,
+            2638438,
+            Some(300),
+        )];
+
+        let mut uniswap_v3_pool = UniswapV3Pool::new(
+            H160::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640").unwrap(),
+            H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap(),
+            6,
+            H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap(),
+            18,
+            500,
+            u128::MAX,
+            U256::from_dec_str("1987654321098765432109876543210987").unwrap(),
+            -201234,
+            10,
+            i128::MIN,
         );
+        uniswap_v3_pool.tick_map = Some(UniswapV3TickMap {
+            tick_bitmap: [(-79, U256::from(5)), (3, U256::MAX)].into_iter().collect(),
+            liquidity_net: [(-201240, 1000), (-201230, -1000)].into_iter().collect(),
+            liquidity_gross: [(-201240, 1000), (-201230, 1000)].into_iter().collect(),
+            lower_tick: -204800,
+            upper_tick: -197640,
+            block_number: 17000000.into(),
+        });
+
+        let pools = vec![
+            Pool::UniswapV2(UniswapV2Pool::new(
+                H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc").unwrap(),
+                H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap(),
+                6,
+                H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap(),
+                18,
+                47092140895915,
+                u128::MAX,
+                300,
+            )),
+            Pool::UniswapV3(uniswap_v3_pool),
+        ];
+
+        (dexes, pools)
+    }
 
-        dex_map.insert(String::from("block_number"), latest_block.into());
+    #[test]
+    fn test_binary_checkpoint_round_trip() {
+        let (dexes, pools) = test_dexes_and_pools();
+        let checkpoint = Checkpoint::new(dexes, pools.clone(), 17000000);
 
-        match dex {
-            Dex::UniswapV2(uniswap_v2_dex) => {
-                dex_map.insert(
-                    String::from("dex_variant"),
-                    String::from("UniswapV2").into(),
-                );
+        let binary = checkpoint.to_bytes(CheckpointFormat::Binary).unwrap();
+        let json = checkpoint.to_bytes(CheckpointFormat::Json).unwrap();
 
-                dex_map.insert(
-                    String::from("fee"),
-                    format!("{:?}", uniswap_v2_dex.fee).into(),
-                );
-            }
+        assert!(binary.starts_with(BINARY_CHECKPOINT_MAGIC));
+        assert!(binary.len() < json.len());
 
-            Dex::UniswapV3(_) => {
-                dex_map.insert(
-                    String::from("dex_variant"),
-                    String::from("UniswapV3").into(),
-                );
+        for bytes in [binary, json] {
+            let loaded = Checkpoint::from_bytes(&bytes).unwrap();
+
+            assert_eq!(loaded.version, CHECKPOINT_VERSION);
+            assert_eq!(loaded.block_number, 17000000);
+            assert_eq!(loaded.checkpoint_timestamp, checkpoint.checkpoint_timestamp);
+            assert_eq!(
+                loaded.dexes[0].factory_address(),
+                checkpoint.dexes[0].factory_address()
+            );
+            assert_eq!(loaded.pools, pools);
+        }
+    }
+
+    #[test]
+    fn test_migrate_unversioned_checkpoint() {
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_checkpoint_migrate.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+
+        std::fs::write(checkpoint_path, checkpoint_json(VALID_DEX, VALID_POOL)).unwrap();
+
+        let unversioned = Checkpoint::load(checkpoint_path).unwrap();
+
+        migrate_checkpoint(checkpoint_path, CheckpointFormat::Binary).unwrap();
+
+        let bytes = std::fs::read(checkpoint_path).unwrap();
+        std::fs::remove_file(checkpoint_path).unwrap();
+
+        assert!(bytes.starts_with(BINARY_CHECKPOINT_MAGIC));
+
+        let migrated = Checkpoint::from_bytes(&bytes).unwrap();
+
+        assert_eq!(migrated.version, CHECKPOINT_VERSION);
+        assert_eq!(migrated.block_number, 17000000);
+        assert_eq!(migrated.checkpoint_timestamp, 1681000000);
+        assert_eq!(migrated.pools, unversioned.pools);
+
+        match &migrated.pools[0] {
+            Pool::UniswapV2(pool) => {
+                assert_eq!(pool.reserve_0, 47092140895915);
+                assert_eq!(pool.reserve_1, 28396598565590008529300);
             }
+            _ => panic!("Unexpected pool variant"),
         }
+    }
+
+    #[test]
+    fn test_migrate_unversioned_checkpoint_with_dex_fee_as_string() {
+        let checkpoint_path =
+            std::env::temp_dir().join("cfmms_test_checkpoint_migrate_dex_fee.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+
+        //The unversioned layout wrote the uniswap v2 dex fee with `format!("{:?}", fee)`
+        std::fs::write(
+            checkpoint_path,
+            checkpoint_json(
+                &VALID_DEX.replace("\"fee\": 300", "\"fee\": \"300\""),
+                VALID_POOL,
+            ),
+        )
+        .unwrap();
+
+        migrate_checkpoint(checkpoint_path, CheckpointFormat::Json).unwrap();
+        let migrated = Checkpoint::load(checkpoint_path).unwrap();
+        std::fs::remove_file(checkpoint_path).unwrap();
 
-        dexes_array.push(Value::Object(dex_map));
+        assert_eq!(migrated.version, CHECKPOINT_VERSION);
+        match &migrated.dexes[0] {
+            Dex::UniswapV2(dex) => assert_eq!(dex.fee, 300),
+            _ => panic!("Unexpected dex variant"),
+        }
+        assert_eq!(migrated.pools.len(), 1);
     }
 
-    checkpoint.insert(String::from("dexes"), dexes_array.into());
+    #[test]
+    fn test_unsupported_checkpoint_version() {
+        assert!(matches!(
+            Checkpoint::from_bytes(br#"{"version": 3, "block_number": 17000000}"#),
+            Err(CheckpointError::UnsupportedVersion(3))
+        ));
 
-    //Insert pools into checkpoint
-    let mut pools_array: Vec<Value> = vec![];
-    for pool in pools {
-        let mut pool_map = Map::new();
+        let mut checkpoint = Checkpoint::new(vec![], vec![], 17000000);
+        checkpoint.version = 1;
 
-        match pool {
-            Pool::UniswapV2(uniswap_v2_pool) => {
-                pool_map.insert(
-                    String::from("dex_variant"),
-                    String::from("UniswapV2").into(),
-                );
+        assert!(matches!(
+            Checkpoint::from_bytes(&checkpoint.to_bytes(CheckpointFormat::Binary).unwrap()),
+            Err(CheckpointError::UnsupportedVersion(1))
+        ));
+    }
 
-                pool_map.insert(
-                    String::from("address"),
-                    format!("{:?}", uniswap_v2_pool.address).into(),
-                );
+    #[test]
+    fn test_load_checkpoint_without_pool_state() {
+        let checkpoint_path =
+            std::env::temp_dir().join("cfmms_test_checkpoint_without_pool_state.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+
+        //Checkpoint written before the pool state was persisted
+        std::fs::write(
+            checkpoint_path,
+            r#"{
+                "block_number": 17000000,
+                "checkpoint_timestamp": 1681000000,
+                "dexes": [],
+                "pools": [
+                    {
+                        "address": "0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc",
+                        "dex_variant": "UniswapV2",
+                        "fee": 300,
+                        "token_a": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+                        "token_a_decimals": 6,
+                        "token_b": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+                        "token_b_decimals": 18
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
 
-                pool_map.insert(
-                    String::from("token_a"),
-                    format!("{:?}", uniswap_v2_pool.token_a).into(),
-                );
+        let (_, pools, _) = load_pools_from_checkpoint(checkpoint_path).unwrap();
 
-                pool_map.insert(
-                    String::from("token_a_decimals"),
-                    uniswap_v2_pool.token_a_decimals.into(),
-                );
+        std::fs::remove_file(checkpoint_path).unwrap();
 
-                pool_map.insert(
-                    String::from("token_b"),
-                    format!("{:?}", uniswap_v2_pool.token_b).into(),
-                );
+        match &pools[0] {
+            Pool::UniswapV2(pool) => {
+                assert_eq!(pool.fee, 300);
+                assert_eq!((pool.reserve_0, pool.reserve_1), (0, 0));
+            }
+            _ => panic!("Unexpected pool variant"),
+        }
+    }
 
-                pool_map.insert(
-                    String::from("token_b_decimals"),
-                    uniswap_v2_pool.token_b_decimals.into(),
-                );
+    const VALID_POOL: &str = r#"{
+        "address": "0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc",
+        "dex_variant": "UniswapV2",
+        "fee": 300,
+        "token_a": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        "token_a_decimals": 6,
+        "token_b": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+        "token_b_decimals": 18,
+        "reserve_0": "47092140895915",
+        "reserve_1": "28396598565590008529300"
+    }"#;
+
+    const VALID_DEX: &str = r#"{
+        "block_number": 17000000,
+        "dex_variant": "UniswapV2",
+        "factory_address": "0x5c69bee701ef814a2b6a3edd4b1652cb9cc5aa6f",
+        "fee": 300
+    }"#;
+
+    fn checkpoint_json(dexes: &str, pools: &str) -> String {
+        format!(
+            r#"{{"block_number": 17000000, "checkpoint_timestamp": 1681000000, "dexes": [{}], "pools": [{}]}}"#,
+            dexes, pools
+        )
+    }
 
-                pool_map.insert(String::from("fee"), uniswap_v2_pool.fee.into());
+    fn deconstruct_fixture(name: &str, contents: &str) -> Result<(), CheckpointError> {
+        let checkpoint_path =
+            std::env::temp_dir().join(format!("cfmms_test_checkpoint_{}.json", name));
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
 
-                pools_array.push(pool_map.into());
+        std::fs::write(checkpoint_path, contents).unwrap();
+        let result = deconstruct_checkpoint(checkpoint_path);
+        std::fs::remove_file(checkpoint_path).unwrap();
+
+        result.map(|_| ())
+    }
+
+    #[test]
+    fn test_malformed_checkpoints() {
+        let valid = checkpoint_json(VALID_DEX, VALID_POOL);
+        assert!(deconstruct_fixture("valid", &valid).is_ok());
+
+        //Each fixture is paired with the json path that should be reported in the error
+        let fixtures = vec![
+            (
+                "missing_block_number",
+                r#"{"dexes": [], "pools": []}"#.to_string(),
+                "$.block_number",
+            ),
+            (
+                "block_number_not_a_number",
+                r#"{"block_number": "17000000", "dexes": [], "pools": []}"#.to_string(),
+                "$.block_number",
+            ),
+            ("root_not_an_object", r#"[]"#.to_string(), "$"),
+            (
+                "dexes_not_an_array",
+                r#"{"block_number": 17000000, "dexes": {}, "pools": []}"#.to_string(),
+                "$.dexes",
+            ),
+            (
+                "missing_pools",
+                r#"{"block_number": 17000000, "dexes": []}"#.to_string(),
+                "$.pools",
+            ),
+            (
+                "unrecognized_dex_variant",
+                checkpoint_json(&VALID_DEX.replace("UniswapV2", "SushiSwap"), ""),
+                "$.dexes[0].dex_variant",
+            ),
+            (
+                "invalid_factory_address",
+                checkpoint_json(&VALID_DEX.replace("0x5c69", "0xzz69"), ""),
+                "$.dexes[0].factory_address",
+            ),
+            (
+                "dex_fee_not_a_number",
+                checkpoint_json(&VALID_DEX.replace("\"fee\": 300", "\"fee\": \"0x12c\""), ""),
+                "$.dexes[0].fee",
+            ),
+            (
+                "dex_fee_overflow",
+                checkpoint_json(
+                    &VALID_DEX.replace("\"fee\": 300", "\"fee\": 4294967296"),
+                    "",
+                ),
+                "$.dexes[0].fee",
+            ),
+            (
+                "pool_not_an_object",
+                checkpoint_json("", &format!("{}, 1", VALID_POOL)),
+                "$.pools[1]",
+            ),
+            (
+                "missing_token_a",
+                checkpoint_json(
+                    "",
+                    &VALID_POOL.replace(
+                        r#""token_a": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48","#,
+                        "",
+                    ),
+                ),
+                "$.pools[0].token_a",
+            ),
+            (
+                "decimals_overflow",
+                checkpoint_json(
+                    "",
+                    &VALID_POOL.replace(r#""token_b_decimals": 18"#, r#""token_b_decimals": 256"#),
+                ),
+                "$.pools[0].token_b_decimals",
+            ),
+            (
+                "negative_fee",
+                checkpoint_json("", &VALID_POOL.replace(r#""fee": 300"#, r#""fee": -300"#)),
+                "$.pools[0].fee",
+            ),
+            (
+                "invalid_reserve",
+                checkpoint_json(
+                    "",
+                    &VALID_POOL.replace("\"47092140895915\"", "\"-47092140895915\""),
+                ),
+                "$.pools[0].reserve_0",
+            ),
+            (
+                "reserve_as_number",
+                checkpoint_json(
+                    "",
+                    &VALID_POOL.replace("\"47092140895915\"", "47092140895915"),
+                ),
+                "$.pools[0].reserve_0",
+            ),
+            (
+                "invalid_sqrt_price",
+                checkpoint_json(
+                    "",
+                    &VALID_POOL
+                        .replace("UniswapV2", "UniswapV3")
+                        .replace(r#""fee": 300,"#, r#""fee": 500, "sqrt_price": "0x1f","#),
+                ),
+                "$.pools[0].sqrt_price",
+            ),
+            (
+                "tick_out_of_range",
+                checkpoint_json(
+                    "",
+                    &VALID_POOL
+                        .replace("UniswapV2", "UniswapV3")
+                        .replace(r#""fee": 300,"#, r#""fee": 500, "tick": 4294967296,"#),
+                ),
+                "$.pools[0].tick",
+            ),
+            (
+                "invalid_tick_map",
+                checkpoint_json(
+                    "",
+                    &VALID_POOL
+                        .replace("UniswapV2", "UniswapV3")
+                        .replace(r#""fee": 300,"#, r#""fee": 500, "tick_map": [],"#),
+                ),
+                "$.pools[0].tick_map",
+            ),
+        ];
+
+        for (name, contents, expected_path) in fixtures {
+            match deconstruct_fixture(name, &contents) {
+                Err(CheckpointError::InvalidField { path, .. }) => {
+                    assert_eq!(path, expected_path, "Unexpected path for fixture {}", name)
+                }
+                other => panic!("Unexpected result for fixture {}: {:?}", name, other),
             }
+        }
+    }
 
-            Pool::UniswapV3(uniswap_v3_pool) => {
-                pool_map.insert(
-                    String::from("dex_variant"),
-                    String::from("UniswapV3").into(),
-                );
+    #[test]
+    fn test_truncated_checkpoint() {
+        let valid = checkpoint_json(VALID_DEX, VALID_POOL);
 
-                pool_map.insert(
-                    String::from("address"),
-                    format!("{:?}", uniswap_v3_pool.address).into(),
-                );
+        assert!(matches!(
+            deconstruct_fixture("truncated", &valid[..valid.len() / 2]),
+            Err(CheckpointError::SerdeJsonError(_))
+        ));
+    }
 
-                pool_map.insert(
-                    String::from("token_a"),
-                    format!("{:?}", uniswap_v3_pool.token_a).into(),
-                );
+    #[test]
+    fn test_block_number_from_checkpoint() {
+        assert_eq!(
+            block_number_from_checkpoint(BlockNumber::Number(16_000_000.into())).unwrap(),
+            16_000_000
+        );
+        assert!(matches!(
+            block_number_from_checkpoint(BlockNumber::Latest),
+            Err(CheckpointError::InvalidField { path, .. }) if path == "$.block_number"
+        ));
+    }
 
-                pool_map.insert(
-                    String::from("token_a_decimals"),
-                    uniswap_v3_pool.token_a_decimals.into(),
-                );
+    #[test]
+    fn test_missing_checkpoint_file() {
+        assert!(matches!(
+            deconstruct_checkpoint("/nonexistent/cfmms_checkpoint.json"),
+            Err(CheckpointError::IOError(_))
+        ));
+    }
 
-                pool_map.insert(
-                    String::from("token_b"),
-                    format!("{:?}", uniswap_v3_pool.token_b).into(),
-                );
+    #[test]
+    fn test_construct_checkpoint_write_error() {
+        assert!(matches!(
+            construct_checkpoint(vec![], &[], 0, "/nonexistent/cfmms_checkpoint.json"),
+            Err(CheckpointError::IOError(_))
+        ));
+    }
 
-                pool_map.insert(
-                    String::from("token_b_decimals"),
-                    uniswap_v3_pool.token_b_decimals.into(),
-                );
+    #[test]
+    fn test_checkpoint_deltas_and_compaction() {
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_checkpoint_deltas.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+        let delta_path = checkpoint_delta_path(checkpoint_path);
 
-                pool_map.insert(String::from("fee"), uniswap_v3_pool.fee.into());
+        let (dexes, pools) = test_dexes_and_pools();
+        let _ = std::fs::remove_file(&delta_path);
 
-                pools_array.push(pool_map.into());
-            }
+        Checkpoint::new(dexes, pools.clone(), 17000000)
+            .save(checkpoint_path, CheckpointFormat::Binary)
+            .unwrap();
+
+        //Saving goes through a temp file that is renamed over the checkpoint
+        assert!(!std::path::Path::new(&format!("{}.tmp", checkpoint_path)).exists());
+
+        let mut updated_pool = pools[0].clone();
+        if let Pool::UniswapV2(pool) = &mut updated_pool {
+            pool.reserve_0 = 1;
         }
+
+        let new_pool = Pool::UniswapV2(UniswapV2Pool {
+            address: H160::from_low_u64_be(1),
+            ..Default::default()
+        });
+
+        //A delta at the snapshot block is already included in the snapshot
+        append_checkpoint_delta(
+            checkpoint_path,
+            &CheckpointDelta::new(17000000, vec![new_pool.clone()]),
+        )
+        .unwrap();
+        append_checkpoint_delta(
+            checkpoint_path,
+            &CheckpointDelta::new(17000001, vec![updated_pool.clone()]),
+        )
+        .unwrap();
+        append_checkpoint_delta(
+            checkpoint_path,
+            &CheckpointDelta::new(17000002, vec![new_pool.clone()]),
+        )
+        .unwrap();
+
+        //Simulate a crash in the middle of appending a delta
+        let mut delta_file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&delta_path)
+            .unwrap();
+        std::io::Write::write_all(
+            &mut delta_file,
+            br#"{"block_number":17000003,"pools":[{"Uni"#,
+        )
+        .unwrap();
+
+        assert_eq!(read_checkpoint_deltas(checkpoint_path).unwrap().len(), 3);
+        assert_eq!(
+            Checkpoint::load_snapshot(checkpoint_path).unwrap().pools,
+            pools
+        );
+
+        let expected_pools = vec![updated_pool, pools[1].clone(), new_pool];
+
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        assert_eq!(checkpoint.block_number, 17000002);
+        assert_eq!(checkpoint.pools, expected_pools);
+
+        compact_checkpoint(checkpoint_path, CheckpointFormat::Json).unwrap();
+
+        assert!(!delta_path.exists());
+
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        std::fs::remove_file(checkpoint_path).unwrap();
+
+        assert_eq!(checkpoint.block_number, 17000002);
+        assert_eq!(checkpoint.pools, expected_pools);
+    }
+
+    fn uniswap_v2_pool(address: u64, reserve: u128) -> Pool {
+        Pool::UniswapV2(UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            token_a: H160::from_low_u64_be(100),
+            token_a_decimals: 18,
+            token_b: H160::from_low_u64_be(200),
+            token_b_decimals: 18,
+            reserve_0: reserve,
+            reserve_1: reserve,
+            fee: 300,
+        })
     }
 
-    checkpoint.insert(String::from("pools"), pools_array.into());
+    //Return data of the uniswap v2 pool data batch request for a single pool
+    fn uniswap_v2_pool_data(reserve: u128) -> Bytes {
+        ethers::abi::encode(&[Token::Array(vec![Token::Tuple(vec![
+            Token::Address(H160::from_low_u64_be(100)),
+            Token::Uint(U256::from(18)),
+            Token::Address(H160::from_low_u64_be(200)),
+            Token::Uint(U256::from(18)),
+            Token::Uint(U256::from(reserve)),
+            Token::Uint(U256::from(reserve)),
+        ])])])
+        .into()
+    }
 
-    std::fs::write(
-        checkpoint_path,
-        serde_json::to_string_pretty(&checkpoint).unwrap(),
-    )
-    .unwrap();
+    fn sync_log(address: u64) -> Log {
+        Log {
+            address: H160::from_low_u64_be(address),
+            topics: vec![SYNC_EVENT_SIGNATURE],
+            ..Default::default()
+        }
+    }
+
+    fn pool_addresses(pools: &[Pool]) -> Vec<H160> {
+        pools.iter().map(|pool| pool.address()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_sync_from_checkpoint_keeps_failed_pools_stale() {
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_checkpoint_failed_pools.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+        let _ = std::fs::remove_file(checkpoint_delta_path(checkpoint_path));
+
+        construct_checkpoint(
+            vec![],
+            &[
+                uniswap_v2_pool(1, 1000),
+                uniswap_v2_pool(2, 1000),
+                uniswap_v2_pool(3, 1000),
+            ],
+            100,
+            checkpoint_path,
+        )
+        .unwrap();
+
+        //Pools 2 and 3 are touched, the batch for both fails and is split so that only pool 3 fails
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(200));
+        client.push(vec![sync_log(2), sync_log(3)]);
+        client.push_revert();
+        client.push(uniswap_v2_pool_data(2000));
+        client.push_revert();
+
+        let (_, sync_report) = sync_pools_from_checkpoint_with_config(
+            checkpoint_path,
+            true,
+            SyncConfig::new()
+                .with_step(1000)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(NoProgress)),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(client.remaining_responses(), 0);
+        assert_eq!(
+            pool_addresses(&sync_report.synced_pools),
+            vec![H160::from_low_u64_be(1), H160::from_low_u64_be(2)]
+        );
+        assert_eq!(sync_report.failed_pools.len(), 1);
+        assert_eq!(
+            sync_report.failed_pools[0].address,
+            H160::from_low_u64_be(3)
+        );
+
+        //The failed pool is in the delta without its state, so its checkpoint state is not loaded as valid at block 200
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        assert_eq!(checkpoint.block_number, 200);
+        assert_eq!(checkpoint.pools[1], uniswap_v2_pool(2, 2000));
+        assert_eq!(
+            checkpoint.pools[2],
+            uniswap_v2_pool(3, 1000).without_state()
+        );
+        assert!(sync::is_empty_pool(&checkpoint.pools[2]));
+
+        let (_, pools, _) = load_pools_from_checkpoint(checkpoint_path).unwrap();
+        assert_eq!(
+            pool_addresses(&pools),
+            vec![H160::from_low_u64_be(1), H160::from_low_u64_be(2)]
+        );
+
+        //The stale pool is requested again even though no logs touched it
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(300));
+        client.push(Vec::<Log>::new());
+        client.push(uniswap_v2_pool_data(3000));
+
+        let (_, sync_report) = sync_pools_from_checkpoint_with_config(
+            checkpoint_path,
+            true,
+            SyncConfig::new()
+                .with_step(1000)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(NoProgress)),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(client.remaining_responses(), 0);
+        assert!(sync_report.is_complete());
+        assert!(sync_report.synced_pools.contains(&uniswap_v2_pool(3, 3000)));
+
+        //A full rewrite keeps the pools that failed instead of dropping them from the checkpoint
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(400));
+        for _ in 0..5 {
+            client.push_revert();
+        }
+
+        let (_, sync_report) = sync_pools_from_checkpoint_with_config(
+            checkpoint_path,
+            false,
+            SyncConfig::new()
+                .with_step(1000)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(NoProgress)),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(client.remaining_responses(), 0);
+        assert!(sync_report.synced_pools.is_empty());
+        assert_eq!(sync_report.failed_pools.len(), 3);
+
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        std::fs::remove_file(checkpoint_path).unwrap();
+        let _ = std::fs::remove_file(checkpoint_delta_path(checkpoint_path));
+
+        assert_eq!(checkpoint.block_number, 400);
+        assert_eq!(
+            checkpoint.pools,
+            vec![
+                uniswap_v2_pool(1, 1000).without_state(),
+                uniswap_v2_pool(2, 2000).without_state(),
+                uniswap_v2_pool(3, 3000).without_state(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_sync_from_checkpoint_keeps_checkpoint() {
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_checkpoint_cancellation.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+        let _ = std::fs::remove_file(checkpoint_delta_path(checkpoint_path));
+
+        let checkpoint_pools = vec![uniswap_v2_pool(1, 1000), uniswap_v2_pool(2, 1000)];
+        construct_checkpoint(vec![], &checkpoint_pools, 100, checkpoint_path).unwrap();
+
+        //The sync is cancelled once the data of the touched pool has been requested, before it is written to the checkpoint
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(200));
+        client.push(vec![sync_log(2)]);
+        client.push(uniswap_v2_pool_data(2000));
+
+        let cancellation_token = CancellationToken::new();
+
+        let (_, sync_report) = sync_pools_from_checkpoint_with_config(
+            checkpoint_path,
+            true,
+            SyncConfig::new()
+                .with_step(1000)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(CancellingProgress::new(
+                    cancellation_token.clone(),
+                    1,
+                )))
+                .with_cancellation_token(cancellation_token),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert!(sync_report.cancelled);
+        assert_eq!(
+            client.requests(),
+            vec!["eth_blockNumber", "eth_getLogs", "eth_call"]
+        );
+        assert_eq!(sync_report.synced_pools, vec![uniswap_v2_pool(1, 1000)]);
+        assert_eq!(sync_report.unsynced_pools, vec![uniswap_v2_pool(2, 1000)]);
+
+        //The checkpoint is not advanced, so the next sync requests the touched pool again
+        assert!(!checkpoint_delta_path(checkpoint_path).exists());
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        assert_eq!(checkpoint.block_number, 100);
+        assert_eq!(checkpoint.pools, checkpoint_pools);
+
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(200));
+        client.push(vec![sync_log(2)]);
+        client.push(uniswap_v2_pool_data(2000));
+
+        let (_, sync_report) = sync_pools_from_checkpoint_with_config(
+            checkpoint_path,
+            true,
+            SyncConfig::new()
+                .with_step(1000)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(NoProgress))
+                .with_cancellation_token(CancellationToken::new()),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert!(sync_report.is_complete());
+        assert_eq!(client.remaining_responses(), 0);
+
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        std::fs::remove_file(checkpoint_path).unwrap();
+        std::fs::remove_file(checkpoint_delta_path(checkpoint_path)).unwrap();
+
+        assert_eq!(checkpoint.block_number, 200);
+        assert_eq!(
+            checkpoint.pools,
+            vec![uniswap_v2_pool(1, 1000), uniswap_v2_pool(2, 2000)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_sync_from_checkpoint_resumes_new_pools() {
+        let checkpoint_path =
+            std::env::temp_dir().join("cfmms_test_checkpoint_cancellation_resume.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+        let dex = Dex::new(H160::from_low_u64_be(1), DexVariant::UniswapV2, 50, None);
+        let _ = remove_partial_checkpoint(checkpoint_path);
+
+        construct_checkpoint(vec![dex], &[], 100, checkpoint_path).unwrap();
+
+        //The sync is cancelled once the first block range after the checkpoint block is done
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(399));
+        client.push(vec![pair_created_log(10, 150)]);
+
+        let cancellation_token = CancellationToken::new();
+
+        let (_, sync_report) = sync_pools_from_checkpoint_with_config(
+            checkpoint_path,
+            false,
+            SyncConfig::new()
+                .with_step(100)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(CancellingProgress::new(
+                    cancellation_token.clone(),
+                    100,
+                )))
+                .with_cancellation_token(cancellation_token),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert!(sync_report.cancelled);
+        assert_eq!(client.requests(), vec!["eth_blockNumber", "eth_getLogs"]);
+        assert_eq!(
+            pool_addresses(&sync_report.unsynced_pools),
+            vec![H160::from_low_u64_be(10)]
+        );
+        assert_eq!(Checkpoint::load(checkpoint_path).unwrap().block_number, 100);
+
+        let partial_checkpoint = PartialCheckpoint::load(checkpoint_path).unwrap().unwrap();
+        assert_eq!(partial_checkpoint.block_number, 399);
+        assert_eq!(partial_checkpoint.dexes.len(), 1);
+        assert_eq!(partial_checkpoint.dexes[0].synced_to_block, Some(200));
+        assert_eq!(
+            partial_checkpoint.dexes[0].pools,
+            sync_report.unsynced_pools
+        );
+
+        //The next sync only requests the logs after the completed block range, then syncs the pool found before the resume
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(399));
+        client.push(Vec::<Log>::new());
+        client.push(Vec::<Log>::new());
+        client.push(uniswap_v2_pool_data(1000));
+
+        let (_, sync_report) = sync_pools_from_checkpoint_with_config(
+            checkpoint_path,
+            false,
+            SyncConfig::new()
+                .with_step(100)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(NoProgress))
+                .with_cancellation_token(CancellationToken::new()),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert!(sync_report.is_complete());
+        assert_eq!(
+            client.requests(),
+            vec!["eth_blockNumber", "eth_getLogs", "eth_getLogs", "eth_call"]
+        );
+        assert!(PartialCheckpoint::load(checkpoint_path).unwrap().is_none());
+
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        std::fs::remove_file(checkpoint_path).unwrap();
+
+        assert_eq!(checkpoint.block_number, 399);
+        assert_eq!(
+            pool_addresses(&checkpoint.pools),
+            vec![H160::from_low_u64_be(10)]
+        );
+        assert_eq!(checkpoint.pools, sync_report.synced_pools);
+    }
 }
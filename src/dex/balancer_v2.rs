@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber, Log, H160, H256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::CFMMError,
+    pool::{BalancerV2Pool, Pool},
+};
+
+//Balancer V2 pools are registered with the Vault, so the factory address of the dex is the address of the Vault
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash)]
+pub struct BalancerV2Dex {
+    pub factory_address: H160,
+    pub creation_block: BlockNumber,
+}
+
+//PoolRegistered(bytes32,address,uint8)
+pub const POOL_REGISTERED_EVENT_SIGNATURE: H256 = H256([
+    60, 19, 188, 48, 184, 232, 120, 197, 63, 210, 163, 107, 103, 148, 9, 192, 115, 175, 215, 89,
+    80, 190, 67, 216, 133, 135, 104, 233, 86, 251, 194, 14,
+]);
+
+impl BalancerV2Dex {
+    pub fn new(factory_address: H160, creation_block: BlockNumber) -> BalancerV2Dex {
+        BalancerV2Dex {
+            factory_address,
+            creation_block,
+        }
+    }
+
+    pub const fn pool_created_event_signature(&self) -> H256 {
+        POOL_REGISTERED_EVENT_SIGNATURE
+    }
+
+    pub async fn new_pool_from_event<M: Middleware>(
+        &self,
+        log: Log,
+        middleware: Arc<M>,
+    ) -> Result<Pool, CFMMError<M>> {
+        Ok(Pool::BalancerV2(
+            BalancerV2Pool::new_from_event_log(log, middleware).await?,
+        ))
+    }
+
+    pub fn new_empty_pool_from_event<M: Middleware>(&self, log: Log) -> Result<Pool, CFMMError<M>> {
+        Ok(Pool::BalancerV2(
+            BalancerV2Pool::new_empty_pool_from_event_log(log)?,
+        ))
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber, Log, H160, H256, U256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abi,
+    errors::CFMMError,
+    pool::{CurvePool, Pool},
+};
+
+//Curve pools are discovered from a registry, so the factory address of the dex is the address of the registry
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash)]
+pub struct CurveDex {
+    pub factory_address: H160,
+    pub creation_block: BlockNumber,
+}
+
+//PoolAdded(address,bytes)
+pub const POOL_ADDED_EVENT_SIGNATURE: H256 = H256([
+    228, 133, 193, 100, 121, 171, 112, 146, 192, 179, 252, 70, 73, 132, 60, 6, 190, 127, 7, 33,
+    148, 103, 82, 97, 89, 12, 132, 71, 58, 176, 174, 169,
+]);
+
+//Max number of pools that the registry is asked for when looking up the pools for a pair
+const MAX_POOLS_FOR_COINS: u64 = 8;
+
+impl CurveDex {
+    pub fn new(factory_address: H160, creation_block: BlockNumber) -> CurveDex {
+        CurveDex {
+            factory_address,
+            creation_block,
+        }
+    }
+
+    pub const fn pool_created_event_signature(&self) -> H256 {
+        POOL_ADDED_EVENT_SIGNATURE
+    }
+
+    pub async fn new_pool_from_event<M: Middleware>(
+        &self,
+        log: Log,
+        middleware: Arc<M>,
+    ) -> Result<Pool, CFMMError<M>> {
+        Ok(Pool::Curve(
+            CurvePool::new_from_event_log(log, middleware).await?,
+        ))
+    }
+
+    pub fn new_empty_pool_from_event<M: Middleware>(&self, log: Log) -> Result<Pool, CFMMError<M>> {
+        Ok(Pool::Curve(CurvePool::new_empty_pool_from_event_log(log)?))
+    }
+
+    //Returns every pool in the registry that holds both tokens
+    pub async fn get_pools_for_coins<M: Middleware>(
+        &self,
+        token_a: H160,
+        token_b: H160,
+        middleware: Arc<M>,
+    ) -> Result<Vec<Pool>, CFMMError<M>> {
+        let registry = abi::ICurveRegistry::new(self.factory_address, middleware.clone());
+
+        let mut pools = vec![];
+        for i in 0..MAX_POOLS_FOR_COINS {
+            let pool_address = registry
+                .find_pool_for_coins(token_a, token_b, U256::from(i))
+                .call()
+                .await?;
+
+            if pool_address.is_zero() {
+                break;
+            }
+
+            pools.push(Pool::Curve(
+                CurvePool::new_from_address(pool_address, middleware.clone()).await?,
+            ));
+        }
+
+        Ok(pools)
+    }
+}
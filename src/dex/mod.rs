@@ -1,22 +1,36 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use ethers::{
     providers::Middleware,
-    types::{BlockNumber, Filter, Log, ValueOrArray, H160, H256, U64},
+    types::{BlockNumber, Filter, Log, ValueOrArray, H160, H256},
 };
-use indicatif::ProgressBar;
+use futures::{stream, StreamExt, TryStreamExt};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    abi, batch_requests,
+    abi,
+    batch_requests::{self, gas::GasEstimator, BatchRequestConfig, PoolDataGas},
     errors::CFMMError,
-    pool::{Pool, UniswapV2Pool, UniswapV3Pool},
+    logs::{self, LogFetcher, DEFAULT_LOG_FETCH_PARALLELISM},
+    pool::{self, Pool, UniswapV2Pool, UniswapV3Pool},
+    progress::{PhaseProgress, SyncPhase, SyncProgress},
+    retry::{RetryPolicy, RetryableError},
+    stats::RequestKind,
+    sync::PoolSyncFailure,
+    telemetry,
     throttle::RequestThrottle,
 };
 
 use serde::{Deserialize, Serialize};
 
-use self::{uniswap_v2::UniswapV2Dex, uniswap_v3::UniswapV3Dex};
+use self::{
+    balancer_v2::BalancerV2Dex, curve::CurveDex, solidly::SolidlyDex, uniswap_v2::UniswapV2Dex,
+    uniswap_v3::UniswapV3Dex,
+};
 
+pub mod balancer_v2;
+pub mod curve;
+pub mod solidly;
 pub mod uniswap_v2;
 pub mod uniswap_v3;
 
@@ -24,6 +38,9 @@ pub mod uniswap_v3;
 pub enum Dex {
     UniswapV2(UniswapV2Dex),
     UniswapV3(UniswapV3Dex),
+    Solidly(SolidlyDex),
+    Curve(CurveDex),
+    BalancerV2(BalancerV2Dex),
 }
 
 impl Dex {
@@ -31,9 +48,9 @@ impl Dex {
         factory_address: H160,
         dex_variant: DexVariant,
         creation_block: u64,
-        fee: Option<u64>,
+        fee: Option<u32>,
     ) -> Dex {
-        let fee = if let Some(fee) = fee { fee } else { 300 };
+        let fee = fee.unwrap_or(pool::uniswap_v2::DEFAULT_FEE);
 
         match dex_variant {
             DexVariant::UniswapV2 => Dex::UniswapV2(UniswapV2Dex::new(
@@ -46,6 +63,24 @@ impl Dex {
                 factory_address,
                 BlockNumber::Number(creation_block.into()),
             )),
+
+            //Solidly pairs get their fee from the factory, so the fee is not used
+            DexVariant::Solidly => Dex::Solidly(SolidlyDex::new(
+                factory_address,
+                BlockNumber::Number(creation_block.into()),
+            )),
+
+            //Curve pools get their fee from the pool, so the fee is not used
+            DexVariant::Curve => Dex::Curve(CurveDex::new(
+                factory_address,
+                BlockNumber::Number(creation_block.into()),
+            )),
+
+            //Balancer V2 pools get their swap fee from the pool, so the fee is not used
+            DexVariant::BalancerV2 => Dex::BalancerV2(BalancerV2Dex::new(
+                factory_address,
+                BlockNumber::Number(creation_block.into()),
+            )),
         }
     }
 
@@ -53,6 +88,9 @@ impl Dex {
         match self {
             Dex::UniswapV2(uniswap_v2_dex) => uniswap_v2_dex.factory_address,
             Dex::UniswapV3(uniswap_v3_dex) => uniswap_v3_dex.factory_address,
+            Dex::Solidly(solidly_dex) => solidly_dex.factory_address,
+            Dex::Curve(curve_dex) => curve_dex.factory_address,
+            Dex::BalancerV2(balancer_v2_dex) => balancer_v2_dex.factory_address,
         }
     }
 
@@ -60,6 +98,9 @@ impl Dex {
         match self {
             Dex::UniswapV2(uniswap_v2_dex) => uniswap_v2_dex.creation_block,
             Dex::UniswapV3(uniswap_v3_dex) => uniswap_v3_dex.creation_block,
+            Dex::Solidly(solidly_dex) => solidly_dex.creation_block,
+            Dex::Curve(curve_dex) => curve_dex.creation_block,
+            Dex::BalancerV2(balancer_v2_dex) => balancer_v2_dex.creation_block,
         }
     }
 
@@ -67,6 +108,9 @@ impl Dex {
         match self {
             Dex::UniswapV2(uniswap_v2_dex) => uniswap_v2_dex.pool_created_event_signature(),
             Dex::UniswapV3(uniswap_v3_dex) => uniswap_v3_dex.pool_created_event_signature(),
+            Dex::Solidly(solidly_dex) => solidly_dex.pool_created_event_signature(),
+            Dex::Curve(curve_dex) => curve_dex.pool_created_event_signature(),
+            Dex::BalancerV2(balancer_v2_dex) => balancer_v2_dex.pool_created_event_signature(),
         }
     }
 
@@ -75,33 +119,62 @@ impl Dex {
         log: Log,
         middleware: Arc<M>,
     ) -> Result<Pool, CFMMError<M>> {
-        Pool::new_from_event_log(log, middleware).await
+        match self {
+            Dex::UniswapV2(uniswap_v2_dex) => {
+                uniswap_v2_dex.new_pool_from_event(log, middleware).await
+            }
+            Dex::UniswapV3(uniswap_v3_dex) => {
+                uniswap_v3_dex.new_pool_from_event(log, middleware).await
+            }
+            Dex::Solidly(solidly_dex) => solidly_dex.new_pool_from_event(log, middleware).await,
+            Dex::Curve(curve_dex) => curve_dex.new_pool_from_event(log, middleware).await,
+            Dex::BalancerV2(balancer_v2_dex) => {
+                balancer_v2_dex.new_pool_from_event(log, middleware).await
+            }
+        }
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(factory_address = ?self.factory_address(), dex_variant = ?self.dex_variant()))
+    )]
     pub async fn get_all_pools<M: 'static + Middleware>(
         &self,
-        request_throttle: Arc<Mutex<RequestThrottle>>,
+        request_throttle: Arc<RequestThrottle>,
+        retry_policy: Arc<RetryPolicy>,
         step: usize,
-        progress_bar: ProgressBar,
+        sync_progress: Arc<dyn SyncProgress>,
         middleware: Arc<M>,
     ) -> Result<Vec<Pool>, CFMMError<M>> {
         match self {
             Dex::UniswapV2(uniswap_v2_dex) => {
                 uniswap_v2_dex
-                    .get_all_pairs_via_batched_calls(middleware, request_throttle, progress_bar)
+                    .get_all_pairs_via_batched_calls(
+                        middleware,
+                        request_throttle,
+                        retry_policy,
+                        PhaseProgress::new(sync_progress, *self, SyncPhase::GetPools),
+                    )
                     .await
             }
-            Dex::UniswapV3(_) => {
-                let current_block = middleware
-                    .get_block_number()
-                    .await
-                    .map_err(CFMMError::MiddlewareError)?;
+            //The stability of a Solidly pair is only known from its creation log, so Solidly pairs are also found from logs.
+            //Curve registries and the Balancer V2 Vault do not index their pools by pair, so those pools are found from logs.
+            Dex::UniswapV3(_) | Dex::Solidly(_) | Dex::Curve(_) | Dex::BalancerV2(_) => {
+                let current_block = retry_policy
+                    .retry(|| async {
+                        middleware
+                            .get_block_number()
+                            .await
+                            .map_err(CFMMError::MiddlewareError)
+                    })
+                    .await?;
 
                 self.get_all_pools_from_logs(
                     current_block.into(),
                     step,
                     request_throttle,
-                    progress_bar,
+                    retry_policy,
+                    sync_progress,
                     middleware,
                 )
                 .await
@@ -109,60 +182,261 @@ impl Dex {
         }
     }
 
+    pub fn dex_variant(&self) -> DexVariant {
+        match self {
+            Dex::UniswapV2(_) => DexVariant::UniswapV2,
+            Dex::UniswapV3(_) => DexVariant::UniswapV3,
+            Dex::Solidly(_) => DexVariant::Solidly,
+            Dex::Curve(_) => DexVariant::Curve,
+            Dex::BalancerV2(_) => DexVariant::BalancerV2,
+        }
+    }
+
     //Gets all pool data and sync reserves
     pub async fn get_all_pool_data<M: Middleware>(
         &self,
         pools: &mut [Pool],
-        request_throttle: Arc<Mutex<RequestThrottle>>,
-        progress_bar: ProgressBar,
+        request_throttle: Arc<RequestThrottle>,
+        retry_policy: Arc<RetryPolicy>,
+        sync_progress: Arc<dyn SyncProgress>,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        self.get_all_pool_data_with_config(
+            pools,
+            BatchRequestConfig::default(),
+            request_throttle,
+            retry_policy,
+            sync_progress,
+            middleware,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    //Gets all pool data and sync reserves, dispatching up to `max_in_flight` batch requests at the same time.
+    //Pools that can not be synced, even in a batch of their own, are left unpopulated and returned with their errors.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(factory_address = ?self.factory_address(), dex_variant = ?self.dex_variant(), pools = pools.len()))
+    )]
+    pub async fn get_all_pool_data_with_config<M: Middleware>(
+        &self,
+        pools: &mut [Pool],
+        batch_request_config: BatchRequestConfig,
+        request_throttle: Arc<RequestThrottle>,
+        retry_policy: Arc<RetryPolicy>,
+        sync_progress: Arc<dyn SyncProgress>,
+        middleware: Arc<M>,
+    ) -> Result<Vec<PoolSyncFailure<M>>, CFMMError<M>> {
+        let step = batch_request_config.pool_data_batch_size(self.dex_variant());
+
+        let progress = PhaseProgress::new(sync_progress, *self, SyncPhase::GetPoolData);
+        progress.start(pools.len() as u64);
+
+        telemetry::debug_event!(
+            batch_size = step,
+            max_in_flight = batch_request_config.max_in_flight,
+            "Getting pool data"
+        );
+
+        let chunks = pools
+            .chunks(step)
+            .map(<[Pool]>::to_vec)
+            .enumerate()
+            .collect::<Vec<(usize, Vec<Pool>)>>();
+
+        let synced_chunks = stream::iter(chunks)
+            .map(|(chunk_idx, pools)| {
+                let request_throttle = request_throttle.clone();
+                let retry_policy = retry_policy.clone();
+                let progress = progress.clone();
+                let middleware = middleware.clone();
+
+                async move {
+                    let (pools, failures) = self
+                        .get_pool_data_splitting_failed_batches(
+                            pools,
+                            &request_throttle,
+                            &retry_policy,
+                            &progress,
+                            middleware,
+                        )
+                        .await?;
+
+                    progress.inc(pools.len() as u64);
+
+                    Ok::<_, CFMMError<M>>((chunk_idx, pools, failures))
+                }
+            })
+            .buffer_unordered(batch_request_config.max_in_flight)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut failures = vec![];
+        for (chunk_idx, synced_pools, chunk_failures) in synced_chunks {
+            let start = chunk_idx * step;
+            pools[start..start + synced_pools.len()].clone_from_slice(&synced_pools);
+            failures.extend(chunk_failures);
+        }
+
+        progress.finish();
+
+        telemetry::info_event!(failed_pools = failures.len(), "Got pool data");
+
+        Ok(failures)
+    }
+
+    async fn get_pool_data_batch_request<M: Middleware>(
+        &self,
+        pools: &mut [Pool],
         middleware: Arc<M>,
     ) -> Result<(), CFMMError<M>> {
         match self {
             Dex::UniswapV2(_) => {
-                let step = 127; //Max batch size for call
-                for pools in pools.chunks_mut(step) {
-                    request_throttle
-                        .lock()
-                        .expect("Error when acquiring request throttle mutex lock")
-                        .increment_or_sleep(1);
-
-                    batch_requests::uniswap_v2::get_pool_data_batch_request(
-                        pools,
-                        middleware.clone(),
-                    )
-                    .await?;
+                batch_requests::uniswap_v2::get_pool_data_batch_request(pools, middleware).await
+            }
+            Dex::UniswapV3(_) => {
+                batch_requests::uniswap_v3::get_pool_data_batch_request(pools, middleware).await
+            }
+            Dex::Solidly(solidly_dex) => {
+                batch_requests::solidly::get_pool_data_batch_request(
+                    solidly_dex.factory_address,
+                    pools,
+                    middleware,
+                )
+                .await
+            }
+            Dex::Curve(_) => {
+                batch_requests::curve::get_pool_data_batch_request(pools, middleware).await
+            }
+            Dex::BalancerV2(_) => {
+                batch_requests::balancer_v2::get_pool_data_batch_request(pools, middleware).await
+            }
+        }
+    }
+
+    //Measures the gas of the pool data batch request by estimating the gas of a batch with the first pool and a batch
+    //with all of `pools`, and fits the gas of a batch to the number of pools. Dexes that read the pool data in more
+    //than one call use the most expensive call, since the gas budget applies to each call. The pools should be
+    //unpopulated pools of the dex so that every call of the batch request is measured.
+    pub async fn measure_pool_data_gas<M: Middleware>(
+        &self,
+        pools: &[Pool],
+        middleware: Arc<M>,
+    ) -> Result<PoolDataGas, CFMMError<GasEstimator<Arc<M>>>> {
+        if pools.is_empty() {
+            return Err(CFMMError::PoolDataError);
+        }
+
+        let mut measurements = vec![];
+        for batch in [&pools[..1], pools] {
+            let gas_estimator = Arc::new(GasEstimator::new(middleware.clone()));
+
+            self.get_pool_data_batch_request(&mut batch.to_vec(), gas_estimator.clone())
+                .await?;
 
-                    progress_bar.inc(step as u64);
+            let gas = gas_estimator
+                .estimates()
+                .into_iter()
+                .max()
+                .unwrap_or_default();
+            measurements.push(gas.as_u64());
+        }
+
+        telemetry::debug_event!(
+            single_pool_gas = measurements[0],
+            batch_gas = measurements[1],
+            batch_size = pools.len(),
+            "Measured pool data gas"
+        );
+
+        Ok(PoolDataGas::from_measurements(
+            measurements[0],
+            measurements[1],
+            pools.len() as u64,
+        ))
+    }
+
+    //Gets the pool data for a batch of pools, splitting the batch in half when the batch request fails for a reason
+    //that retrying does not fix (ie. a revert caused by a single pool), so that one bad pool does not fail the whole dex.
+    async fn get_pool_data_splitting_failed_batches<M: Middleware>(
+        &self,
+        mut pools: Vec<Pool>,
+        request_throttle: &RequestThrottle,
+        retry_policy: &RetryPolicy,
+        progress: &PhaseProgress,
+        middleware: Arc<M>,
+    ) -> Result<(Vec<Pool>, Vec<PoolSyncFailure<M>>), CFMMError<M>> {
+        let mut pending_batches = vec![(0, pools.len())];
+        let mut failures = vec![];
+
+        while let Some((start, end)) = pending_batches.pop() {
+            //The batch is made on a copy of the pools so that a failed attempt does not leave partially updated pools
+            let result = retry_policy
+                .retry(|| async {
+                    let mut synced_pools = pools[start..end].to_vec();
+                    request_throttle.acquire_eth_call().await;
+
+                    progress
+                        .record_request(
+                            RequestKind::EthCall,
+                            self.get_pool_data_batch_request(&mut synced_pools, middleware.clone()),
+                        )
+                        .await?;
+
+                    Ok(synced_pools)
+                })
+                .await;
+
+            match result {
+                Ok(synced_pools) => {
+                    telemetry::debug_event!(batch_size = end - start, "Got pool data batch");
+                    pools[start..end].clone_from_slice(&synced_pools)
                 }
-            }
 
-            Dex::UniswapV3(_) => {
-                let step = 76; //Max batch size for call
-                for pools in pools.chunks_mut(step) {
-                    request_throttle
-                        .lock()
-                        .expect("Error when acquiring request throttle mutex lock")
-                        .increment_or_sleep(1);
-
-                    batch_requests::uniswap_v3::get_pool_data_batch_request(
-                        pools,
-                        middleware.clone(),
-                    )
-                    .await?;
+                //Network errors are returned since splitting the batch would fail the same way
+                Err(error) if RetryableError::classify_cfmm_error(&error).is_some() => {
+                    return Err(error)
+                }
+
+                Err(_) if end - start > 1 => {
+                    telemetry::warn_event!(
+                        batch_size = end - start,
+                        "Pool data batch failed, splitting"
+                    );
+
+                    let mid = start + (end - start) / 2;
+                    pending_batches.push((mid, end));
+                    pending_batches.push((start, mid));
+                }
 
-                    progress_bar.inc(step as u64);
+                //The pool is left unpopulated and is removed with the other empty pools
+                Err(error) => {
+                    telemetry::warn_event!(
+                        pool = ?pools[start].address(),
+                        %error,
+                        "Could not get pool data"
+                    );
+
+                    failures.push(PoolSyncFailure {
+                        address: pools[start].address(),
+                        error,
+                    })
                 }
             }
         }
 
-        //For each pair in the pairs vec, get the pool data
-        Ok(())
+        Ok((pools, failures))
     }
 
     pub fn new_empty_pool_from_event<M: Middleware>(&self, log: Log) -> Result<Pool, CFMMError<M>> {
         match self {
             Dex::UniswapV2(uniswap_v2_dex) => uniswap_v2_dex.new_empty_pool_from_event(log),
             Dex::UniswapV3(uniswap_v3_dex) => uniswap_v3_dex.new_empty_pool_from_event(log),
+            Dex::Solidly(solidly_dex) => solidly_dex.new_empty_pool_from_event(log),
+            Dex::Curve(curve_dex) => curve_dex.new_empty_pool_from_event(log),
+            Dex::BalancerV2(balancer_v2_dex) => balancer_v2_dex.new_empty_pool_from_event(log),
         }
     }
 
@@ -185,9 +459,14 @@ impl Dex {
                 if pair_address.is_zero() {
                     Ok(None)
                 } else {
-                    Ok(Some(Pool::UniswapV2(
-                        UniswapV2Pool::new_from_address(pair_address, middleware).await?,
-                    )))
+                    let pool = UniswapV2Pool::new_from_address(
+                        pair_address,
+                        uniswap_v2_dex.fee,
+                        middleware,
+                    )
+                    .await?;
+
+                    Ok(Some(Pool::UniswapV2(pool)))
                 }
             }
 
@@ -235,6 +514,35 @@ impl Dex {
                     )))
                 }
             }
+
+            //The stable and volatile pair use different curves, so the pair with the larger reserve of token a is used
+            Dex::Solidly(solidly_dex) => Ok(solidly_dex
+                .get_pairs(token_a, token_b, middleware)
+                .await?
+                .into_iter()
+                .max_by_key(|pool| match pool {
+                    Pool::Solidly(solidly_pool) if solidly_pool.token_a == token_a => {
+                        solidly_pool.reserve_0
+                    }
+                    Pool::Solidly(solidly_pool) => solidly_pool.reserve_1,
+                    _ => 0,
+                })),
+
+            //A pair can be in several Curve pools, so the pool with the largest balance of token a is used
+            Dex::Curve(curve_dex) => Ok(curve_dex
+                .get_pools_for_coins(token_a, token_b, middleware)
+                .await?
+                .into_iter()
+                .max_by_key(|pool| match pool {
+                    Pool::Curve(curve_pool) => curve_pool
+                        .coin_index(token_a)
+                        .map(|i| curve_pool.balances[i])
+                        .unwrap_or_default(),
+                    _ => 0.into(),
+                })),
+
+            //The Vault can not look up pools by their tokens, use the pools from `get_all_pools` instead
+            Dex::BalancerV2(_) => Ok(None),
         }
     }
 
@@ -255,9 +563,14 @@ impl Dex {
                 if pair_address.is_zero() {
                     Ok(None)
                 } else {
-                    Ok(Some(vec![Pool::UniswapV2(
-                        UniswapV2Pool::new_from_address(pair_address, middleware).await?,
-                    )]))
+                    let pool = UniswapV2Pool::new_from_address(
+                        pair_address,
+                        uniswap_v2_dex.fee,
+                        middleware,
+                    )
+                    .await?;
+
+                    Ok(Some(vec![Pool::UniswapV2(pool)]))
                 }
             }
 
@@ -295,16 +608,45 @@ impl Dex {
                     Ok(Some(pools))
                 }
             }
+
+            Dex::Solidly(solidly_dex) => {
+                let pools = solidly_dex.get_pairs(token_a, token_b, middleware).await?;
+
+                if pools.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(pools))
+                }
+            }
+
+            Dex::Curve(curve_dex) => {
+                let pools = curve_dex
+                    .get_pools_for_coins(token_a, token_b, middleware)
+                    .await?;
+
+                if pools.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(pools))
+                }
+            }
+
+            Dex::BalancerV2(_) => Ok(None),
         }
     }
 
     //Function to get all pair created events for a given Dex factory address and sync pool data
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(factory_address = ?self.factory_address(), to_block = ?current_block))
+    )]
     pub async fn get_all_pools_from_logs<M: 'static + Middleware>(
         self,
         current_block: BlockNumber,
         step: usize,
-        request_throttle: Arc<Mutex<RequestThrottle>>,
-        progress_bar: ProgressBar,
+        request_throttle: Arc<RequestThrottle>,
+        retry_policy: Arc<RetryPolicy>,
+        sync_progress: Arc<dyn SyncProgress>,
         middleware: Arc<M>,
     ) -> Result<Vec<Pool>, CFMMError<M>> {
         //Unwrap can be used here because the creation block was verified within `Dex::new()`
@@ -320,56 +662,51 @@ impl Dex {
 
         let mut aggregated_pairs: Vec<Pool> = vec![];
 
-        //Initialize the progress bar message
-        progress_bar.set_length(current_block - from_block);
-
-        //For each block within the range, get all pairs asynchronously
-        for from_block in (from_block..=current_block).step_by(step) {
-            let request_throttle = request_throttle.clone();
-            let provider = middleware.clone();
-            let progress_bar = progress_bar.clone();
-
-            //Get pair created event logs within the block range
-            let to_block = from_block + step as u64;
-
-            //Update the throttle
-            request_throttle
-                .lock()
-                .expect("Error when acquiring request throttle mutex lock")
-                .increment_or_sleep(1);
-
-            let logs = provider
-                .get_logs(
-                    &Filter::new()
-                        .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
-                        .address(self.factory_address())
-                        .from_block(BlockNumber::Number(U64([from_block])))
-                        .to_block(BlockNumber::Number(U64([to_block]))),
-                )
-                .await
-                .map_err(CFMMError::MiddlewareError)?;
+        let progress = PhaseProgress::new(sync_progress, self, SyncPhase::GetPools);
+        progress.start(current_block - from_block + 1);
 
-            //For each pair created log, create a new Pair type and add it to the pairs vec
-            for log in logs {
-                let pool = self.new_empty_pool_from_event(log)?;
-                aggregated_pairs.push(pool);
-            }
+        let filter = Filter::new()
+            .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
+            .address(self.factory_address());
+
+        //Get pair created event logs for multiple block ranges concurrently, the step is split when the provider rejects a range with too many logs
+        let log_ranges = LogFetcher::new(step as u64)
+            .with_parallelism(DEFAULT_LOG_FETCH_PARALLELISM)
+            .with_request_throttle(request_throttle)
+            .with_retry_policy(retry_policy)
+            .with_progress(progress.clone())
+            .get_logs(&filter, from_block, current_block, middleware)
+            .await?;
+
+        progress.finish();
 
-            //Increment the progress bar by the step
-            progress_bar.inc(step as u64);
+        telemetry::debug_event!(log_ranges = log_ranges.len(), "Got pool created logs");
+
+        //For each pair created log, create a new Pair type and add it to the pairs vec
+        for log in log_ranges.into_iter().flat_map(|log_range| log_range.logs) {
+            let pool = self.new_empty_pool_from_event(log)?;
+            aggregated_pairs.push(pool);
         }
 
+        telemetry::info_event!(pools = aggregated_pairs.len(), "Got all pools");
+
         Ok(aggregated_pairs)
     }
 
     //Function to get all pair created events for a given Dex factory address and sync pool data
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(factory_address = ?self.factory_address(), from_block = ?from_block, to_block = ?to_block))
+    )]
     pub async fn get_all_pools_from_logs_within_range<M: 'static + Middleware>(
         self,
         from_block: BlockNumber,
         to_block: BlockNumber,
         step: usize,
-        request_throttle: Arc<Mutex<RequestThrottle>>,
-        progress_bar: ProgressBar,
+        request_throttle: Arc<RequestThrottle>,
+        retry_policy: Arc<RetryPolicy>,
+        sync_progress: Arc<dyn SyncProgress>,
         middleware: Arc<M>,
     ) -> Result<Vec<Pool>, CFMMError<M>> {
         //Unwrap can be used here because the creation block was verified within `Dex::new()`
@@ -384,59 +721,112 @@ impl Dex {
 
         let mut aggregated_pairs: Vec<Pool> = vec![];
 
-        //Initialize the progress bar message
-        progress_bar.set_length(to_block - from_block);
-
-        //For each block within the range, get all pairs asynchronously
-        for from_block in (from_block..=to_block).step_by(step) {
-            let request_throttle = request_throttle.clone();
-            let provider = middleware.clone();
-            let progress_bar = progress_bar.clone();
-
-            //Get pair created event logs within the block range
-            let to_block = from_block + step as u64;
-
-            //Update the throttle
-            request_throttle
-                .lock()
-                .expect("Error when acquiring request throttle mutex lock")
-                .increment_or_sleep(1);
-
-            let logs = provider
-                .get_logs(
-                    &Filter::new()
-                        .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
-                        .address(self.factory_address())
-                        .from_block(BlockNumber::Number(U64([from_block])))
-                        .to_block(BlockNumber::Number(U64([to_block]))),
-                )
-                .await
-                .map_err(CFMMError::MiddlewareError)?;
+        let progress = PhaseProgress::new(sync_progress, self, SyncPhase::GetPools);
+        progress.start(to_block - from_block + 1);
 
-            //For each pair created log, create a new Pair type and add it to the pairs vec
-            for log in logs {
-                let pool = self.new_empty_pool_from_event(log)?;
-                aggregated_pairs.push(pool);
-            }
+        let filter = Filter::new()
+            .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
+            .address(self.factory_address());
+
+        //Get pair created event logs for multiple block ranges concurrently, the step is split when the provider rejects a range with too many logs
+        let log_ranges = LogFetcher::new(step as u64)
+            .with_parallelism(DEFAULT_LOG_FETCH_PARALLELISM)
+            .with_request_throttle(request_throttle)
+            .with_retry_policy(retry_policy)
+            .with_progress(progress.clone())
+            .get_logs(&filter, from_block, to_block, middleware)
+            .await?;
 
-            //Increment the progress bar by the step
-            progress_bar.inc(step as u64);
+        progress.finish();
+
+        telemetry::debug_event!(log_ranges = log_ranges.len(), "Got pool created logs");
+
+        //For each pair created log, create a new Pair type and add it to the pairs vec
+        for log in log_ranges.into_iter().flat_map(|log_range| log_range.logs) {
+            let pool = self.new_empty_pool_from_event(log)?;
+            aggregated_pairs.push(pool);
         }
 
+        telemetry::info_event!(pools = aggregated_pairs.len(), "Got all pools");
+
         Ok(aggregated_pairs)
     }
+
+    //Gets the pools created within the block range until the cancellation token is cancelled. Also returns the last block
+    //that all pool created logs were fetched up to, pools after that block are left out so that the sync can resume from it.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(factory_address = ?self.factory_address(), from_block, to_block))
+    )]
+    pub async fn get_all_pools_from_logs_until_cancelled<M: 'static + Middleware>(
+        self,
+        from_block: u64,
+        to_block: u64,
+        step: usize,
+        request_throttle: Arc<RequestThrottle>,
+        retry_policy: Arc<RetryPolicy>,
+        sync_progress: Arc<dyn SyncProgress>,
+        cancellation_token: CancellationToken,
+        middleware: Arc<M>,
+    ) -> Result<(Vec<Pool>, Option<u64>), CFMMError<M>> {
+        let mut aggregated_pairs: Vec<Pool> = vec![];
+
+        let progress = PhaseProgress::new(sync_progress, self, SyncPhase::GetPools);
+        progress.start(to_block.saturating_sub(from_block) + 1);
+
+        let filter = Filter::new()
+            .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
+            .address(self.factory_address());
+
+        let log_ranges = LogFetcher::new(step as u64)
+            .with_parallelism(DEFAULT_LOG_FETCH_PARALLELISM)
+            .with_request_throttle(request_throttle)
+            .with_retry_policy(retry_policy)
+            .with_progress(progress.clone())
+            .with_cancellation_token(cancellation_token)
+            .get_logs(&filter, from_block, to_block, middleware)
+            .await?;
+
+        progress.finish();
+
+        let completed_to_block = logs::completed_to_block(from_block, &log_ranges);
+
+        telemetry::debug_event!(
+            log_ranges = log_ranges.len(),
+            ?completed_to_block,
+            "Got pool created logs"
+        );
+
+        for log in log_ranges
+            .into_iter()
+            .filter(|log_range| Some(log_range.to_block) <= completed_to_block)
+            .flat_map(|log_range| log_range.logs)
+        {
+            let pool = self.new_empty_pool_from_event(log)?;
+            aggregated_pairs.push(pool);
+        }
+
+        Ok((aggregated_pairs, completed_to_block))
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, Hash)]
 pub enum DexVariant {
     UniswapV2,
     UniswapV3,
+    Solidly,
+    Curve,
+    BalancerV2,
 }
 impl DexVariant {
     pub fn pool_created_event_signature(&self) -> H256 {
         match self {
             DexVariant::UniswapV2 => uniswap_v2::PAIR_CREATED_EVENT_SIGNATURE,
             DexVariant::UniswapV3 => uniswap_v3::POOL_CREATED_EVENT_SIGNATURE,
+            DexVariant::Solidly => solidly::PAIR_CREATED_EVENT_SIGNATURE,
+            DexVariant::Curve => curve::POOL_ADDED_EVENT_SIGNATURE,
+            DexVariant::BalancerV2 => balancer_v2::POOL_REGISTERED_EVENT_SIGNATURE,
         }
     }
 }
@@ -446,11 +836,226 @@ mod tests {
     use std::{env, str::FromStr, sync::Arc};
 
     use ethers::{
+        abi::Token,
         providers::{Http, Provider},
-        types::H160,
+        types::{Bytes, Log, H160, H256, U256},
     };
 
     use super::{Dex, DexVariant};
+    use crate::{
+        batch_requests::{BatchRequestConfig, PoolDataGas},
+        pool::{Pool, UniswapV2Pool},
+        progress::{NoProgress, SyncPhase},
+        retry::RetryPolicy,
+        test_utils::{mock_provider, CountingProgress, MockClient},
+        throttle::RequestThrottle,
+    };
+
+    fn uniswap_v2_pools(count: u64) -> Vec<Pool> {
+        (1..=count)
+            .map(|address| {
+                Pool::UniswapV2(UniswapV2Pool {
+                    address: H160::from_low_u64_be(address),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    //Return data of the uniswap v2 pool data batch request, with the reserves set to the pool address
+    fn uniswap_v2_pool_data(pools: &[Pool]) -> Bytes {
+        ethers::abi::encode(&[Token::Array(
+            pools
+                .iter()
+                .map(|pool| {
+                    let reserve = pool.address().to_low_u64_be();
+                    Token::Tuple(vec![
+                        Token::Address(H160::from_low_u64_be(100)),
+                        Token::Uint(U256::from(18)),
+                        Token::Address(H160::from_low_u64_be(200)),
+                        Token::Uint(U256::from(6)),
+                        Token::Uint(U256::from(reserve)),
+                        Token::Uint(U256::from(reserve)),
+                    ])
+                })
+                .collect(),
+        )])
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_get_all_pool_data_splits_failed_batches() {
+        let (middleware, client) = mock_provider();
+        let dex = Dex::new(H160::zero(), DexVariant::UniswapV2, 0, None);
+        let mut pools = uniswap_v2_pools(4);
+
+        //The last pool reverts every batch that it is in
+        client.push_revert();
+        client.push(uniswap_v2_pool_data(&pools[0..2]));
+        client.push_revert();
+        client.push(uniswap_v2_pool_data(&pools[2..3]));
+        client.push_revert();
+
+        //Fits all four pools in a single batch
+        let batch_request_config = BatchRequestConfig::new(1_040_000, 1);
+
+        let failures = dex
+            .get_all_pool_data_with_config(
+                &mut pools,
+                batch_request_config,
+                Arc::new(RequestThrottle::new(0)),
+                Arc::new(RetryPolicy::no_retry()),
+                Arc::new(NoProgress),
+                middleware,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].address, H160::from_low_u64_be(4));
+
+        for (idx, pool) in pools.iter().enumerate() {
+            match pool {
+                Pool::UniswapV2(pool) if idx < 3 => {
+                    assert_eq!(pool.reserve_0, idx as u128 + 1);
+                    assert_eq!(pool.token_a, H160::from_low_u64_be(100));
+                }
+                Pool::UniswapV2(pool) => assert!(pool.token_a.is_zero()),
+                _ => panic!("Unexpected pool variant"),
+            }
+        }
+
+        assert_eq!(client.remaining_responses(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_all_pool_data_concurrent_batches() {
+        let (middleware, client) = mock_provider();
+        let dex = Dex::new(H160::zero(), DexVariant::UniswapV2, 0, None);
+        let mut pools = uniswap_v2_pools(4);
+
+        //Every batch gets the same response, so the reserves show which batch was written to which pools
+        for _ in 0..4 {
+            client.push(uniswap_v2_pool_data(&pools[0..1]));
+        }
+
+        //One pool per batch, all in flight at the same time
+        let batch_request_config = BatchRequestConfig::new(335_000, 4);
+        let sync_progress = Arc::new(CountingProgress::default());
+
+        dex.get_all_pool_data_with_config(
+            &mut pools,
+            batch_request_config,
+            Arc::new(RequestThrottle::new(0)),
+            Arc::new(RetryPolicy::no_retry()),
+            sync_progress.clone(),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        for (idx, pool) in pools.iter().enumerate() {
+            assert_eq!(pool.address(), H160::from_low_u64_be(idx as u64 + 1));
+            match pool {
+                Pool::UniswapV2(pool) => assert_eq!(pool.reserve_0, 1),
+                _ => panic!("Unexpected pool variant"),
+            }
+        }
+
+        assert_eq!(
+            sync_progress.started_phases(),
+            vec![(SyncPhase::GetPoolData, 4)]
+        );
+        assert_eq!(sync_progress.position(), 4);
+        assert_eq!(
+            sync_progress.finished_phases(),
+            vec![SyncPhase::GetPoolData]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_measure_pool_data_gas() {
+        let (middleware, client) = mock_provider();
+        let dex = Dex::new(H160::zero(), DexVariant::UniswapV2, 0, None);
+        let pools = uniswap_v2_pools(4);
+
+        //Each batch request is estimated before it is made
+        client.push(U256::from(150_000));
+        client.push(uniswap_v2_pool_data(&pools[0..1]));
+        client.push(U256::from(300_000));
+        client.push(uniswap_v2_pool_data(&pools));
+
+        let pool_data_gas = dex.measure_pool_data_gas(&pools, middleware).await.unwrap();
+
+        assert_eq!(pool_data_gas, PoolDataGas::new(100_000, 50_000));
+        assert_eq!(
+            client.requests(),
+            vec!["eth_estimateGas", "eth_call", "eth_estimateGas", "eth_call"]
+        );
+
+        //The measured gas sizes the batches instead of the estimated gas
+        let batch_request_config = BatchRequestConfig::new(1_100_000, 1)
+            .with_pool_data_gas(DexVariant::UniswapV2, pool_data_gas);
+        assert_eq!(
+            batch_request_config.pool_data_batch_size(DexVariant::UniswapV2),
+            20
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_all_pool_data_network_error() {
+        let (middleware, client) = mock_provider();
+        let dex = Dex::new(H160::zero(), DexVariant::UniswapV2, 0, None);
+        let mut pools = uniswap_v2_pools(4);
+
+        client.push_rate_limit_failure();
+
+        //Network errors are not split, since every half would fail the same way
+        let result = dex
+            .get_all_pool_data_with_config(
+                &mut pools,
+                BatchRequestConfig::new(1_040_000, 1),
+                Arc::new(RequestThrottle::new(0)),
+                Arc::new(RetryPolicy::no_retry()),
+                Arc::new(NoProgress),
+                middleware,
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_new_pool_from_event_log_uses_dex_fee() {
+        let (middleware, client) = mock_provider();
+        let dex = Dex::new(H160::zero(), DexVariant::UniswapV2, 0, Some(250));
+        let pair_address = H160::from_low_u64_be(1);
+
+        let log = Log {
+            topics: vec![
+                super::uniswap_v2::PAIR_CREATED_EVENT_SIGNATURE,
+                H256::from(H160::from_low_u64_be(100)),
+                H256::from(H160::from_low_u64_be(200)),
+            ],
+            data: ethers::abi::encode(&[Token::Address(pair_address), Token::Uint(U256::one())])
+                .into(),
+            ..Default::default()
+        };
+
+        let pool = dex
+            .new_empty_pool_from_event::<Provider<MockClient>>(log.clone())
+            .unwrap();
+        assert_eq!(pool.fee(), 250);
+
+        client.push(uniswap_v2_pool_data(&[Pool::UniswapV2(UniswapV2Pool {
+            address: pair_address,
+            ..Default::default()
+        })]));
+        let pool = dex.new_pool_from_event_log(log, middleware).await.unwrap();
+        assert_eq!(pool.address(), pair_address);
+        assert_eq!(pool.fee(), 250);
+    }
 
     #[test]
     fn test_factory_address() {}
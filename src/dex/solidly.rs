@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber, Log, H160, H256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abi,
+    errors::CFMMError,
+    pool::{Pool, SolidlyPool},
+};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash)]
+pub struct SolidlyDex {
+    pub factory_address: H160,
+    pub creation_block: BlockNumber,
+}
+
+//PairCreated(address,address,bool,address,uint256)
+pub const PAIR_CREATED_EVENT_SIGNATURE: H256 = H256([
+    196, 128, 86, 150, 198, 109, 124, 243, 82, 252, 29, 107, 182, 51, 173, 94, 232, 47, 108, 181,
+    119, 196, 83, 2, 75, 110, 14, 184, 48, 108, 111, 201,
+]);
+
+impl SolidlyDex {
+    pub fn new(factory_address: H160, creation_block: BlockNumber) -> SolidlyDex {
+        SolidlyDex {
+            factory_address,
+            creation_block,
+        }
+    }
+
+    pub const fn pool_created_event_signature(&self) -> H256 {
+        PAIR_CREATED_EVENT_SIGNATURE
+    }
+
+    pub async fn new_pool_from_event<M: Middleware>(
+        &self,
+        log: Log,
+        middleware: Arc<M>,
+    ) -> Result<Pool, CFMMError<M>> {
+        Ok(Pool::Solidly(
+            SolidlyPool::new_from_event_log(log, middleware).await?,
+        ))
+    }
+
+    pub fn new_empty_pool_from_event<M: Middleware>(&self, log: Log) -> Result<Pool, CFMMError<M>> {
+        Ok(Pool::Solidly(SolidlyPool::new_empty_pool_from_event_log(
+            log,
+        )?))
+    }
+
+    //Returns the stable and the volatile pair for the tokens, if they exist
+    pub async fn get_pairs<M: Middleware>(
+        &self,
+        token_a: H160,
+        token_b: H160,
+        middleware: Arc<M>,
+    ) -> Result<Vec<Pool>, CFMMError<M>> {
+        let factory = abi::ISolidlyFactory::new(self.factory_address, middleware.clone());
+
+        let mut pools = vec![];
+        for stable in [true, false] {
+            let pair_address = factory.get_pair(token_a, token_b, stable).call().await?;
+
+            if !pair_address.is_zero() {
+                pools.push(Pool::Solidly(
+                    SolidlyPool::new_from_address(pair_address, middleware.clone()).await?,
+                ));
+            }
+        }
+
+        Ok(pools)
+    }
+}
@@ -1,27 +1,27 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use ethers::{
-    abi::ParamType,
     providers::Middleware,
     types::{BlockNumber, Log, H160, H256, U256},
 };
-use indicatif::ProgressBar;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     abi, batch_requests,
     errors::CFMMError,
     pool::{Pool, UniswapV2Pool},
+    progress::PhaseProgress,
+    retry::RetryPolicy,
+    stats::RequestKind,
+    telemetry,
     throttle::RequestThrottle,
 };
 
-use super::DexVariant;
-
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash)]
 pub struct UniswapV2Dex {
     pub factory_address: H160,
     pub creation_block: BlockNumber,
-    pub fee: u64,
+    pub fee: u32,
 }
 
 pub const PAIR_CREATED_EVENT_SIGNATURE: H256 = H256([
@@ -30,7 +30,7 @@ pub const PAIR_CREATED_EVENT_SIGNATURE: H256 = H256([
 ]);
 
 impl UniswapV2Dex {
-    pub fn new(factory_address: H160, creation_block: BlockNumber, fee: u64) -> UniswapV2Dex {
+    pub fn new(factory_address: H160, creation_block: BlockNumber, fee: u32) -> UniswapV2Dex {
         UniswapV2Dex {
             factory_address,
             creation_block,
@@ -47,40 +47,40 @@ impl UniswapV2Dex {
         log: Log,
         middleware: Arc<M>,
     ) -> Result<Pool, CFMMError<M>> {
-        let tokens = ethers::abi::decode(&[ParamType::Address, ParamType::Uint(256)], &log.data)?;
-        let pair_address = tokens[0].to_owned().into_address().unwrap();
-        Pool::new_from_address(pair_address, DexVariant::UniswapV2, middleware).await
+        Ok(Pool::UniswapV2(
+            UniswapV2Pool::new_from_event_log(log, self.fee, middleware).await?,
+        ))
     }
 
     pub fn new_empty_pool_from_event<M: Middleware>(&self, log: Log) -> Result<Pool, CFMMError<M>> {
-        let tokens = ethers::abi::decode(&[ParamType::Address, ParamType::Uint(256)], &log.data)?;
-        let token_a = H160::from(log.topics[0]);
-        let token_b = H160::from(log.topics[1]);
-        let address = tokens[0].to_owned().into_address().unwrap();
-
-        Ok(Pool::UniswapV2(UniswapV2Pool {
-            address,
-            token_a,
-            token_b,
-            token_a_decimals: 0,
-            token_b_decimals: 0,
-            reserve_0: 0,
-            reserve_1: 0,
-            fee: 300,
-        }))
+        Ok(Pool::UniswapV2(
+            UniswapV2Pool::new_empty_pool_from_event_log(log, self.fee)?,
+        ))
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(factory_address = ?self.factory_address))
+    )]
     pub async fn get_all_pairs_via_batched_calls<M: 'static + Middleware>(
         self,
         middleware: Arc<M>,
-        request_throttle: Arc<Mutex<RequestThrottle>>,
-        progress_bar: ProgressBar,
+        request_throttle: Arc<RequestThrottle>,
+        retry_policy: Arc<RetryPolicy>,
+        progress: PhaseProgress,
     ) -> Result<Vec<Pool>, CFMMError<M>> {
         let factory = abi::IUniswapV2Factory::new(self.factory_address, middleware.clone());
 
-        let pairs_length: U256 = factory.all_pairs_length().call().await?;
-        //Initialize the progress bar message
-        progress_bar.set_length(pairs_length.as_u64());
+        let pairs_length: U256 = retry_policy
+            .retry(|| async {
+                Ok(progress
+                    .record_request(RequestKind::EthCall, factory.all_pairs_length().call())
+                    .await?)
+            })
+            .await?;
+        progress.start(pairs_length.as_u64());
+
+        telemetry::debug_event!(pairs = pairs_length.as_u64(), "Getting all pairs");
 
         let mut pairs = vec![];
         let step = 766; //max batch size for this call until codesize is too large
@@ -92,19 +92,24 @@ impl UniswapV2Dex {
         };
 
         for _ in (0..pairs_length.as_u128()).step_by(step) {
-            request_throttle
-                .lock()
-                .expect("Could not acquire mutex")
-                .increment_or_sleep(1);
-
             pairs.append(
-                &mut batch_requests::uniswap_v2::get_pairs_batch_request(
-                    self.factory_address,
-                    idx_from,
-                    idx_to,
-                    middleware.clone(),
-                )
-                .await?,
+                &mut retry_policy
+                    .retry(|| async {
+                        request_throttle.acquire_eth_call().await;
+
+                        progress
+                            .record_request(
+                                RequestKind::EthCall,
+                                batch_requests::uniswap_v2::get_pairs_batch_request(
+                                    self.factory_address,
+                                    idx_from,
+                                    idx_to,
+                                    middleware.clone(),
+                                ),
+                            )
+                            .await
+                    })
+                    .await?,
             );
 
             idx_from = idx_to;
@@ -115,15 +120,18 @@ impl UniswapV2Dex {
                 idx_to = idx_to + step;
             }
 
-            progress_bar.inc(step as u64);
+            progress.inc(step as u64);
         }
 
+        progress.finish();
+
         let mut pools = vec![];
 
         //Create new empty pools for each pair
         for addr in pairs {
             let pool = UniswapV2Pool {
                 address: addr,
+                fee: self.fee,
                 ..Default::default()
             };
 
@@ -1,19 +1,18 @@
-use std::{
-    panic::resume_unwind,
-    sync::{Arc, Mutex},
-};
+use std::sync::Arc;
 
 use ethers::{
     abi::ParamType,
     providers::Middleware,
     types::{BlockNumber, Log, ValueOrArray, H160, H256, U256},
 };
-use indicatif::ProgressBar;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     errors::CFMMError,
+    logs::{LogFetcher, DEFAULT_LOG_FETCH_PARALLELISM},
     pool::{Pool, UniswapV3Pool},
+    progress::PhaseProgress,
+    retry::RetryPolicy,
     throttle::RequestThrottle,
 };
 
@@ -71,15 +70,21 @@ impl UniswapV3Dex {
             tick_spacing: 0,
             tick: 0,
             liquidity_net: 0,
+            tick_map: None,
         }))
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(factory_address = ?self.factory_address, to_block = ?current_block))
+    )]
     pub async fn get_all_pools_from_logs<M: 'static + Middleware>(
         self,
         middleware: Arc<M>,
         current_block: BlockNumber,
-        request_throttle: Arc<Mutex<RequestThrottle>>,
-        progress_bar: ProgressBar,
+        request_throttle: Arc<RequestThrottle>,
+        retry_policy: Arc<RetryPolicy>,
+        progress: PhaseProgress,
     ) -> Result<Vec<Pool>, CFMMError<M>> {
         let mut aggregated_pairs: Vec<Pool> = vec![];
 
@@ -96,70 +101,27 @@ impl UniswapV3Dex {
             .expect("Error using converting current block as number")
             .as_u64();
 
-        //Initialize the progress bar message
-        progress_bar.set_length(current_block - from_block);
-        progress_bar.set_message(format!("Getting all pools from: {}", self.factory_address));
-
-        //Init a new vec to keep track of tasks
-        let mut handles = vec![];
-
-        //For each block within the range, get all pairs asynchronously
-        for from_block in (from_block..=current_block).step_by(step) {
-            let request_throttle = request_throttle.clone();
-            let provider = middleware.clone();
-            let progress_bar = progress_bar.clone();
-
-            //Spawn a new task to get pair created events from the block range
-            handles.push(tokio::spawn(async move {
-                let mut pools = vec![];
-
-                //Get pair created event logs within the block range
-                let to_block = from_block + step as u64;
-
-                //Update the throttle
-                request_throttle
-                    .lock()
-                    .expect("Error when acquiring request throttle mutex lock")
-                    .increment_or_sleep(1);
-
-                let logs = provider
-                    .get_logs(
-                        &ethers::types::Filter::new()
-                            .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
-                            .address(self.factory_address)
-                            .from_block(BlockNumber::Number(ethers::types::U64([from_block])))
-                            .to_block(BlockNumber::Number(ethers::types::U64([to_block]))),
-                    )
-                    .await
-                    .map_err(CFMMError::MiddlewareError)?;
-
-                //For each pair created log, create a new Pair type and add it to the pairs vec
-                for log in logs {
-                    let pool = self.new_empty_pool_from_event(log)?;
-                    pools.push(pool);
-                }
-
-                //Increment the progress bar by the step
-                progress_bar.inc(step as u64);
-
-                Ok::<Vec<Pool>, CFMMError<M>>(pools)
-            }));
-        }
+        progress.start(current_block - from_block + 1);
+
+        let filter = ethers::types::Filter::new()
+            .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
+            .address(self.factory_address);
+
+        //Get pair created event logs for multiple block ranges concurrently
+        let log_ranges = LogFetcher::new(step)
+            .with_parallelism(DEFAULT_LOG_FETCH_PARALLELISM)
+            .with_request_throttle(request_throttle)
+            .with_retry_policy(retry_policy)
+            .with_progress(progress.clone())
+            .get_logs(&filter, from_block, current_block, middleware)
+            .await?;
+
+        progress.finish();
 
-        //Wait for each thread to finish and aggregate the pairs from each Dex into a single aggregated pairs vec
-        for handle in handles {
-            match handle.await {
-                Ok(sync_result) => aggregated_pairs.extend(sync_result?),
-
-                Err(err) => {
-                    {
-                        if err.is_panic() {
-                            // Resume the panic on the main task
-                            resume_unwind(err.into_panic());
-                        }
-                    }
-                }
-            }
+        //For each pair created log, create a new Pair type and add it to the pairs vec
+        for log in log_ranges.into_iter().flat_map(|log_range| log_range.logs) {
+            let pool = self.new_empty_pool_from_event(log)?;
+            aggregated_pairs.push(pool);
         }
 
         Ok(aggregated_pairs)
@@ -1,6 +1,6 @@
 use std::fmt;
 
-use ethers::prelude::{AbiError, ContractError};
+use ethers::prelude::{AbiError, ContractError, MulticallError};
 use ethers::providers::{Middleware, ProviderError};
 use ethers::types::{H160, U256};
 use thiserror::Error;
@@ -18,6 +18,8 @@ where
     ProviderError(#[from] ProviderError),
     #[error("Contract error")]
     ContractError(#[from] ContractError<M>),
+    #[error("Multicall error")]
+    MulticallError(#[from] MulticallError<M>),
     #[error("ABI Codec error")]
     ABICodecError(#[from] AbiError),
     #[error("Eth ABI error")]
@@ -36,10 +38,63 @@ where
     PoolDataError,
     #[error("Arithmetic error")]
     ArithmeticError(#[from] ArithmeticError),
+    #[error("Checkpoint error")]
+    CheckpointError(#[from] CheckpointError),
     #[error("No initialized ticks during v3 swap simulation")]
     NoInitializedTicks,
     #[error("No liquidity net found during v3 swap simulation")]
     NoLiquidityNet,
+    #[error("Tick map has not been populated")]
+    TickMapNotPopulated,
+    #[error("Swap simulation moved past the ticks loaded in the tick map")]
+    TickMapExhausted,
+    #[error("Insufficient liquidity to receive the amount out")]
+    InsufficientLiquidity,
+    #[error("Block not found")]
+    BlockNotFound(u64),
+    #[error("Reorg is deeper than the recorded state history")]
+    ReorgTooDeep,
+    #[error(
+        "Log in block {0} was removed by a reorg, state history is needed to roll back the pools"
+    )]
+    RemovedLog(u64),
+    #[error("Route error")]
+    RouteError(#[from] RouteError),
+}
+
+#[derive(Error, Debug)]
+pub enum RouteError {
+    #[error("Tokens do not match the pool or the previous hop of the route")]
+    TokenMismatch {
+        pool: H160,
+        token_in: H160,
+        token_out: H160,
+    },
+    #[error("Route does not have any hops")]
+    EmptyRoute,
+}
+
+#[derive(Error, Debug)]
+pub enum CheckpointError {
+    #[error("Could not read or write checkpoint")]
+    IOError(#[from] std::io::Error),
+    #[error("Could not parse checkpoint json")]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("Could not decode binary checkpoint")]
+    BincodeError(#[from] bincode::Error),
+    #[error("Unsupported checkpoint version")]
+    UnsupportedVersion(u32),
+    #[error("Invalid checkpoint field at {path}: {reason}")]
+    InvalidField { path: String, reason: String },
+}
+
+impl CheckpointError {
+    pub fn invalid_field(path: &str, reason: impl ToString) -> CheckpointError {
+        CheckpointError::InvalidField {
+            path: path.to_string(),
+            reason: reason.to_string(),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -48,6 +103,13 @@ pub enum ArithmeticError {
     RoundingError,
     YIsZero,
     SqrtPriceOverflow,
+    PowOutOfBounds,
+    MaxInRatioExceeded,
+    MaxOutRatioExceeded,
+    FeeOutOfBounds(u32),
+    ReserveOverflow,
+    Overflow,
+    DivisionByZero,
 }
 
 impl std::fmt::Display for ArithmeticError {
@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+
+use ethers::types::{H160, U256};
+
+use crate::{errors::RouteError, pool::Pool, route::Route};
+
+//Token adjacency graph where each pool is an edge between every pair of its tokens
+#[derive(Debug, Clone, Default)]
+pub struct TokenGraph {
+    pools: Vec<Pool>,
+    //Token -> (token reachable in one swap, index of the pool in `pools`)
+    adjacency: HashMap<H160, Vec<(H160, usize)>>,
+    min_liquidity: HashMap<H160, U256>,
+    excluded_tokens: HashSet<H160>,
+    excluded_pools: HashSet<H160>,
+}
+
+impl TokenGraph {
+    pub fn new(pools: Vec<Pool>) -> TokenGraph {
+        let mut adjacency: HashMap<H160, Vec<(H160, usize)>> = HashMap::new();
+
+        for (pool_index, pool) in pools.iter().enumerate() {
+            let tokens = pool.tokens();
+
+            for token_in in tokens.iter() {
+                for token_out in tokens.iter() {
+                    if token_in != token_out {
+                        adjacency
+                            .entry(*token_in)
+                            .or_default()
+                            .push((*token_out, pool_index));
+                    }
+                }
+            }
+        }
+
+        TokenGraph {
+            pools,
+            adjacency,
+            ..Default::default()
+        }
+    }
+
+    //Skips pools where the reserve of `token` is below `min_reserve`, in the token's own units.
+    //Uniswap v3 pools are measured by their virtual reserves at the current price.
+    pub fn with_min_liquidity(mut self, token: H160, min_reserve: U256) -> TokenGraph {
+        self.min_liquidity.insert(token, min_reserve);
+        self
+    }
+
+    //Routes never pass through these tokens, the token in and token out of a search are still allowed
+    pub fn with_excluded_tokens(mut self, tokens: Vec<H160>) -> TokenGraph {
+        self.excluded_tokens.extend(tokens);
+        self
+    }
+
+    pub fn with_excluded_pools(mut self, pools: Vec<H160>) -> TokenGraph {
+        self.excluded_pools.extend(pools);
+        self
+    }
+
+    pub fn pools(&self) -> &[Pool] {
+        &self.pools
+    }
+
+    //Returns every token that shares a pool with `token`
+    pub fn neighbors(&self, token: H160) -> HashSet<H160> {
+        self.adjacency
+            .get(&token)
+            .map(|edges| edges.iter().map(|(token, _)| *token).collect())
+            .unwrap_or_default()
+    }
+
+    //Returns every simple path from `token_in` to `token_out` with at most `max_hops` hops, as routes ready for `simulate_route`.
+    //No token or pool is used twice in a route, except when `token_in` is `token_out`, which returns the cycles through the token.
+    pub fn find_routes(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        max_hops: usize,
+    ) -> Result<Vec<Route>, RouteError> {
+        let mut paths = vec![];
+        let mut path = vec![];
+        let mut visited_tokens = HashSet::from([token_in]);
+
+        self.find_paths(
+            token_in,
+            token_out,
+            max_hops,
+            &mut path,
+            &mut visited_tokens,
+            &mut paths,
+        );
+
+        paths
+            .into_iter()
+            .map(|path| {
+                Route::new(
+                    path.into_iter()
+                        .map(|(pool_index, token_in, token_out)| {
+                            (self.pools[pool_index].clone(), token_in, token_out)
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn find_paths(
+        &self,
+        token: H160,
+        target: H160,
+        max_hops: usize,
+        path: &mut Vec<(usize, H160, H160)>,
+        visited_tokens: &mut HashSet<H160>,
+        paths: &mut Vec<Vec<(usize, H160, H160)>>,
+    ) {
+        if path.len() == max_hops {
+            return;
+        }
+
+        let edges = match self.adjacency.get(&token) {
+            Some(edges) => edges,
+            None => return,
+        };
+
+        for (next_token, pool_index) in edges {
+            if path.iter().any(|(index, _, _)| index == pool_index)
+                || !self.pool_is_allowed(&self.pools[*pool_index])
+            {
+                continue;
+            }
+
+            if *next_token == target {
+                path.push((*pool_index, token, *next_token));
+                paths.push(path.clone());
+                path.pop();
+                continue;
+            }
+
+            if visited_tokens.contains(next_token) || self.excluded_tokens.contains(next_token) {
+                continue;
+            }
+
+            visited_tokens.insert(*next_token);
+            path.push((*pool_index, token, *next_token));
+
+            self.find_paths(*next_token, target, max_hops, path, visited_tokens, paths);
+
+            path.pop();
+            visited_tokens.remove(next_token);
+        }
+    }
+
+    fn pool_is_allowed(&self, pool: &Pool) -> bool {
+        if self.excluded_pools.contains(&pool.address()) {
+            return false;
+        }
+
+        self.min_liquidity.iter().all(|(token, min_reserve)| {
+            !pool.tokens().contains(token) || token_reserve(pool, *token) >= *min_reserve
+        })
+    }
+}
+
+//Returns the pool's reserve of `token`, or zero if the reserve can not be calculated
+fn token_reserve(pool: &Pool, token: H160) -> U256 {
+    match pool {
+        Pool::UniswapV2(pool) => {
+            if pool.token_a == token {
+                U256::from(pool.reserve_0)
+            } else {
+                U256::from(pool.reserve_1)
+            }
+        }
+        Pool::UniswapV3(pool) => {
+            if pool.sqrt_price.is_zero() {
+                return U256::zero();
+            }
+
+            match pool.calculate_virtual_reserves() {
+                Ok((reserve_0, reserve_1)) => {
+                    if pool.token_a == token {
+                        U256::from(reserve_0)
+                    } else {
+                        U256::from(reserve_1)
+                    }
+                }
+                Err(_) => U256::zero(),
+            }
+        }
+        Pool::Solidly(pool) => {
+            if pool.token_a == token {
+                U256::from(pool.reserve_0)
+            } else {
+                U256::from(pool.reserve_1)
+            }
+        }
+        Pool::Curve(pool) => pool
+            .coin_index(token)
+            .and_then(|index| pool.balances.get(index).copied())
+            .unwrap_or_default(),
+        Pool::BalancerV2(pool) => pool
+            .token_index(token)
+            .and_then(|index| pool.balances.get(index).copied())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{H160, U256};
+
+    use super::TokenGraph;
+    use crate::pool::{CurvePool, Pool, UniswapV2Pool};
+
+    fn v2_pool(address: u64, token_a: H160, token_b: H160, reserve: u128) -> Pool {
+        Pool::UniswapV2(UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            token_a,
+            token_b,
+            reserve_0: reserve,
+            reserve_1: reserve,
+            fee: 300,
+            ..Default::default()
+        })
+    }
+
+    fn route_pools(
+        graph: &TokenGraph,
+        token_in: H160,
+        token_out: H160,
+        max_hops: usize,
+    ) -> Vec<Vec<H160>> {
+        let mut routes: Vec<Vec<H160>> = graph
+            .find_routes(token_in, token_out, max_hops)
+            .unwrap()
+            .iter()
+            .map(|route| route.hops().iter().map(|hop| hop.pool.address()).collect())
+            .collect();
+        routes.sort();
+        routes
+    }
+
+    #[test]
+    fn test_find_routes() {
+        let weth = H160::from_low_u64_be(1);
+        let usdc = H160::from_low_u64_be(2);
+        let dai = H160::from_low_u64_be(3);
+        let usdt = H160::from_low_u64_be(4);
+        let address = H160::from_low_u64_be;
+
+        let graph = TokenGraph::new(vec![
+            v2_pool(100, weth, usdc, 1_000_000),
+            v2_pool(101, weth, dai, 1_000_000),
+            v2_pool(102, weth, usdt, 1_000),
+            Pool::Curve(CurvePool {
+                address: address(103),
+                coins: vec![dai, usdc, usdt],
+                balances: vec![U256::from(1_000_000); 3],
+                ..Default::default()
+            }),
+        ]);
+
+        assert_eq!(route_pools(&graph, weth, usdc, 1), vec![vec![address(100)]]);
+
+        assert_eq!(
+            route_pools(&graph, weth, usdc, 2),
+            vec![
+                vec![address(100)],
+                vec![address(101), address(103)],
+                vec![address(102), address(103)],
+            ]
+        );
+
+        //Routes are continuous and end at the requested token
+        for route in graph.find_routes(weth, usdc, 3).unwrap() {
+            assert_eq!(route.token_in(), weth);
+            assert_eq!(route.token_out(), usdc);
+        }
+
+        //Cycles through weth use each pool at most once, so swapping back through the same pool is not a route
+        assert!(route_pools(&graph, weth, weth, 2).is_empty());
+        assert_eq!(
+            route_pools(&graph, weth, weth, 3),
+            vec![
+                vec![address(100), address(103), address(101)],
+                vec![address(100), address(103), address(102)],
+                vec![address(101), address(103), address(100)],
+                vec![address(101), address(103), address(102)],
+                vec![address(102), address(103), address(100)],
+                vec![address(102), address(103), address(101)],
+            ]
+        );
+
+        let filtered = graph
+            .clone()
+            .with_min_liquidity(usdt, U256::from(10_000))
+            .with_excluded_pools(vec![address(100)]);
+        assert_eq!(
+            route_pools(&filtered, weth, usdc, 3),
+            vec![vec![address(101), address(103)]]
+        );
+
+        let filtered = graph.with_excluded_tokens(vec![dai, usdt]);
+        assert_eq!(
+            route_pools(&filtered, weth, usdc, 3),
+            vec![vec![address(100)]]
+        );
+    }
+}
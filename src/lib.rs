@@ -2,9 +2,20 @@ mod abi;
 pub mod checkpoint;
 pub mod dex;
 pub mod errors;
+pub mod graph;
+pub mod logs;
 pub mod pool;
+pub mod progress;
+pub mod retry;
+pub mod route;
+pub mod state_space;
+pub mod stats;
 pub mod sync;
+mod telemetry;
+#[cfg(test)]
+pub mod test_utils;
 pub mod throttle;
 pub use pool::simulate_route;
+pub use pool::simulate_route_exact_out;
 pub use pool::simulate_route_mut;
 pub mod batch_requests;
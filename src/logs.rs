@@ -0,0 +1,550 @@
+use std::sync::Arc;
+
+use ethers::{
+    providers::{Middleware, MiddlewareError},
+    types::{BlockNumber, Filter, Log, U64},
+};
+use futures::{stream, StreamExt, TryStreamExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    errors::CFMMError, progress::PhaseProgress, retry::RetryPolicy, stats::RequestKind, telemetry,
+    throttle::RequestThrottle,
+};
+
+//Number of consecutive successful requests before the step is doubled again
+pub const SUCCESSES_BEFORE_STEP_GROWTH: u32 = 4;
+
+//Number of block ranges that are requested at the same time when syncing pools from logs
+pub const DEFAULT_LOG_FETCH_PARALLELISM: usize = 8;
+
+//Block range that a get_logs request succeeded for and the logs that were returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRange {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub logs: Vec<Log>,
+}
+
+impl LogRange {
+    pub fn len(&self) -> u64 {
+        self.to_block - self.from_block + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_block < self.from_block
+    }
+}
+
+//Returns true if the provider rejected a get_logs request because the block range or the response was too large
+pub fn is_range_too_large<E: MiddlewareError>(error: &E) -> bool {
+    let message = match error.as_error_response() {
+        Some(error_response) => error_response.message.to_lowercase(),
+        None => error.to_string().to_lowercase(),
+    };
+
+    message.contains("query returned more than")
+        || message.contains("response size exceeded")
+        || message.contains("response size should not")
+        || message.contains("block range")
+        || message.contains("range is too large")
+        || message.contains("range too large")
+        || message.contains("too many logs")
+}
+
+//Last block of the ranges that cover the blocks from `from_block` without a gap. The ranges must be sorted by block.
+pub fn completed_to_block(from_block: u64, log_ranges: &[LogRange]) -> Option<u64> {
+    let mut completed_to_block = None;
+    let mut next_block = from_block;
+
+    for log_range in log_ranges {
+        if log_range.from_block != next_block {
+            break;
+        }
+
+        completed_to_block = Some(log_range.to_block);
+        next_block = log_range.to_block + 1;
+    }
+
+    completed_to_block
+}
+
+pub fn is_range_too_large_error<M: Middleware>(error: &CFMMError<M>) -> bool {
+    match error {
+        CFMMError::MiddlewareError(error) => is_range_too_large(error),
+        CFMMError::ProviderError(error) => is_range_too_large(error),
+        _ => false,
+    }
+}
+
+//Fetches logs over a block range, splitting the range in half when the provider rejects it as too large
+//and growing the step again after consecutive successful requests.
+#[derive(Clone)]
+pub struct LogFetcher {
+    step: u64,
+    min_step: u64,
+    max_step: u64,
+    consecutive_successes: u32,
+    parallelism: usize,
+    request_throttle: Arc<RequestThrottle>,
+    retry_policy: Arc<RetryPolicy>,
+    progress: Option<PhaseProgress>,
+    cancellation_token: Option<CancellationToken>,
+}
+
+impl LogFetcher {
+    //Creates a fetcher that starts with `step` blocks per request and never grows past it
+    pub fn new(step: u64) -> LogFetcher {
+        LogFetcher::new_with_step_bounds(step, 1, step)
+    }
+
+    pub fn new_with_step_bounds(step: u64, min_step: u64, max_step: u64) -> LogFetcher {
+        let min_step = min_step.max(1);
+        let max_step = max_step.max(min_step);
+
+        LogFetcher {
+            step: step.clamp(min_step, max_step),
+            min_step,
+            max_step,
+            consecutive_successes: 0,
+            parallelism: 1,
+            request_throttle: Arc::new(RequestThrottle::new(0)),
+            retry_policy: Arc::new(RetryPolicy::default()),
+            progress: None,
+            cancellation_token: None,
+        }
+    }
+
+    pub fn with_request_throttle(mut self, request_throttle: Arc<RequestThrottle>) -> LogFetcher {
+        self.request_throttle = request_throttle;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: Arc<RetryPolicy>) -> LogFetcher {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    //Sets the number of block ranges that are requested at the same time. The throttle is shared by all requests.
+    pub fn with_parallelism(mut self, parallelism: usize) -> LogFetcher {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    //Reports the number of blocks in each range that was fetched as progress
+    pub fn with_progress(mut self, progress: PhaseProgress) -> LogFetcher {
+        self.progress = Some(progress);
+        self
+    }
+
+    //Stops issuing requests once the token is cancelled, requests in flight are dropped
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> LogFetcher {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    //Number of blocks that the next request will cover
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    //Gets the logs matching the filter from the start of the range, halving the range until the provider accepts it.
+    //Returns the range that the request succeeded for, which ends at or before `to_block`.
+    pub async fn get_next_logs<M: Middleware>(
+        &mut self,
+        filter: &Filter,
+        from_block: u64,
+        to_block: u64,
+        middleware: Arc<M>,
+    ) -> Result<LogRange, CFMMError<M>> {
+        let mut range_size = self.step.min(to_block.saturating_sub(from_block) + 1);
+
+        loop {
+            let range_to_block = from_block + range_size - 1;
+            let filter = filter
+                .clone()
+                .from_block(BlockNumber::Number(U64([from_block])))
+                .to_block(BlockNumber::Number(U64([range_to_block])));
+
+            let result = self
+                .retry_policy
+                .retry(|| async {
+                    //Update the throttle
+                    self.request_throttle.acquire_get_logs().await;
+
+                    let request = middleware.get_logs(&filter);
+                    match &self.progress {
+                        Some(progress) => {
+                            progress.record_request(RequestKind::GetLogs, request).await
+                        }
+                        None => request.await,
+                    }
+                    .map_err(CFMMError::MiddlewareError)
+                })
+                .await;
+
+            match result {
+                Ok(logs) => {
+                    telemetry::debug_event!(
+                        from_block,
+                        to_block = range_to_block,
+                        logs = logs.len(),
+                        "Fetched log range"
+                    );
+
+                    self.record_success(range_size);
+
+                    if let Some(progress) = &self.progress {
+                        progress.inc(range_size);
+                    }
+
+                    return Ok(LogRange {
+                        from_block,
+                        to_block: range_to_block,
+                        logs,
+                    });
+                }
+
+                Err(error) if is_range_too_large_error(&error) && range_size > self.min_step => {
+                    range_size = (range_size / 2).max(self.min_step);
+                    telemetry::warn_event!(
+                        from_block,
+                        to_block = range_to_block,
+                        next_range_size = range_size,
+                        %error,
+                        "Log range rejected as too large, splitting"
+                    );
+
+                    self.step = range_size;
+                    self.consecutive_successes = 0;
+                }
+
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    //Gets all logs matching the filter within the block range, returning the ranges that each request succeeded for.
+    //Up to `parallelism` ranges are requested at the same time, the ranges are returned in block order and the logs
+    //within each range are sorted by block number and log index. If the fetch is cancelled, the ranges that completed
+    //before the cancellation are returned, which can leave gaps between them.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(from_block, to_block, step = self.step, parallelism = self.parallelism))
+    )]
+    pub async fn get_logs<M: Middleware>(
+        &mut self,
+        filter: &Filter,
+        from_block: u64,
+        to_block: u64,
+        middleware: Arc<M>,
+    ) -> Result<Vec<LogRange>, CFMMError<M>> {
+        if from_block > to_block {
+            return Ok(vec![]);
+        }
+
+        let mut log_ranges = if self.parallelism <= 1 {
+            self.get_logs_sequentially(filter, from_block, to_block, middleware)
+                .await?
+        } else {
+            //Each chunk is fetched by its own copy of the fetcher so that a chunk can be split without blocking the others
+            let chunk_size = self.step;
+            let chunks =
+                (from_block..=to_block)
+                    .step_by(chunk_size as usize)
+                    .map(|chunk_from_block| {
+                        (
+                            chunk_from_block,
+                            (chunk_from_block + chunk_size - 1).min(to_block),
+                        )
+                    });
+
+            let results = stream::iter(chunks)
+                .map(|(chunk_from_block, chunk_to_block)| {
+                    let mut log_fetcher = self.clone();
+                    let middleware = middleware.clone();
+
+                    async move {
+                        let log_ranges = log_fetcher
+                            .get_logs_sequentially(
+                                filter,
+                                chunk_from_block,
+                                chunk_to_block,
+                                middleware,
+                            )
+                            .await?;
+
+                        Ok::<_, CFMMError<M>>((log_fetcher.step, log_ranges))
+                    }
+                })
+                .buffer_unordered(self.parallelism)
+                .try_collect::<Vec<(u64, Vec<LogRange>)>>()
+                .await?;
+
+            //Continue with the smallest step that worked for any of the chunks
+            if let Some(step) = results.iter().map(|(step, _)| *step).min() {
+                self.step = step;
+            }
+
+            results
+                .into_iter()
+                .flat_map(|(_, log_ranges)| log_ranges)
+                .collect()
+        };
+
+        log_ranges.sort_by_key(|log_range| log_range.from_block);
+        for log_range in log_ranges.iter_mut() {
+            log_range
+                .logs
+                .sort_by_key(|log| (log.block_number, log.log_index));
+        }
+
+        Ok(log_ranges)
+    }
+
+    async fn get_logs_sequentially<M: Middleware>(
+        &mut self,
+        filter: &Filter,
+        from_block: u64,
+        to_block: u64,
+        middleware: Arc<M>,
+    ) -> Result<Vec<LogRange>, CFMMError<M>> {
+        let mut log_ranges = vec![];
+        let mut from_block = from_block;
+        let cancellation_token = self.cancellation_token.clone();
+
+        while from_block <= to_block {
+            let next_logs = self.get_next_logs(filter, from_block, to_block, middleware.clone());
+
+            let log_range = match &cancellation_token {
+                Some(cancellation_token) => tokio::select! {
+                    biased;
+                    _ = cancellation_token.cancelled() => break,
+                    log_range = next_logs => log_range?,
+                },
+                None => next_logs.await?,
+            };
+
+            from_block = log_range.to_block + 1;
+            log_ranges.push(log_range);
+        }
+
+        Ok(log_ranges)
+    }
+
+    fn record_success(&mut self, range_size: u64) {
+        //Requests that were cut short by the end of the range do not say anything about the step
+        if range_size < self.step {
+            return;
+        }
+
+        self.consecutive_successes += 1;
+
+        if self.consecutive_successes >= SUCCESSES_BEFORE_STEP_GROWTH {
+            self.step = self.step.saturating_mul(2).min(self.max_step);
+            self.consecutive_successes = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use ethers::types::{Filter, Log, H160, U64};
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::{completed_to_block, LogFetcher, LogRange, SUCCESSES_BEFORE_STEP_GROWTH};
+    use crate::{
+        dex::{Dex, DexVariant},
+        progress::{PhaseProgress, SyncPhase},
+        retry::{RetryPolicy, RetryableError},
+        test_utils::{mock_provider, CountingProgress},
+    };
+
+    fn log_fetcher(step: u64, min_step: u64, max_step: u64) -> LogFetcher {
+        LogFetcher::new_with_step_bounds(step, min_step, max_step).with_retry_policy(Arc::new(
+            RetryPolicy::new(
+                3,
+                Duration::from_millis(1),
+                Duration::from_millis(1),
+                0,
+                RetryableError::all(),
+            ),
+        ))
+    }
+
+    fn log(block_number: u64) -> Log {
+        Log {
+            block_number: Some(U64::from(block_number)),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_range() {
+        let (middleware, client) = mock_provider();
+
+        //The full range is rejected, then the first half is rejected, so the fetcher splits the range down to 25 blocks
+        client.push_failure(-32005, "query returned more than 10000 results");
+        client.push_failure(-32005, "query returned more than 10000 results");
+        client.push(vec![log(1), log(20)]);
+        client.push(vec![log(30)]);
+        client.push(vec![log(80)]);
+        client.push::<Vec<Log>>(vec![]);
+
+        let mut log_fetcher = log_fetcher(100, 1, 100);
+        let log_ranges = log_fetcher
+            .get_logs(&Filter::new(), 1, 100, middleware.clone())
+            .await
+            .unwrap();
+
+        let ranges = log_ranges
+            .iter()
+            .map(|log_range| (log_range.from_block, log_range.to_block))
+            .collect::<Vec<(u64, u64)>>();
+
+        assert_eq!(ranges, vec![(1, 25), (26, 50), (51, 75), (76, 100)]);
+        assert_eq!(log_ranges[0].logs.len(), 2);
+        assert_eq!(log_ranges[2].logs.len(), 1);
+
+        //The step grows again after the consecutive successes
+        assert_eq!(log_fetcher.step(), 50);
+        assert_eq!(client.remaining_responses(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_min_step() {
+        let (middleware, client) = mock_provider();
+
+        client.push_failure(-32602, "block range is too large");
+        client.push_failure(-32602, "block range is too large");
+        client.push::<Vec<Log>>(vec![]);
+
+        //The range can not be split below the min step, so the error is returned
+        let mut log_fetcher = log_fetcher(20, 10, 20);
+        let result = log_fetcher
+            .get_next_logs(&Filter::new(), 1, 100, middleware.clone())
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(log_fetcher.step(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_step_growth() {
+        let (middleware, client) = mock_provider();
+
+        for _ in 0..SUCCESSES_BEFORE_STEP_GROWTH + 1 {
+            client.push::<Vec<Log>>(vec![]);
+        }
+
+        let mut log_fetcher = log_fetcher(10, 1, 15);
+
+        for i in 0..SUCCESSES_BEFORE_STEP_GROWTH as u64 {
+            let log_range = log_fetcher
+                .get_next_logs(&Filter::new(), i * 10 + 1, 1000, middleware.clone())
+                .await
+                .unwrap();
+            assert_eq!(log_range.len(), 10);
+        }
+
+        //The step is doubled but capped at the max step
+        assert_eq!(log_fetcher.step(), 15);
+
+        let log_range = log_fetcher
+            .get_next_logs(&Filter::new(), 41, 1000, middleware.clone())
+            .await
+            .unwrap();
+        assert_eq!((log_range.from_block, log_range.to_block), (41, 55));
+    }
+
+    #[tokio::test]
+    async fn test_empty_range() {
+        let (middleware, client) = mock_provider();
+
+        let log_ranges = LogFetcher::new(10)
+            .get_logs(&Filter::new(), 11, 10, middleware)
+            .await
+            .unwrap();
+
+        assert!(log_ranges.is_empty());
+        assert!(client.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_get_logs() {
+        let (middleware, client) = mock_provider();
+
+        for block_number in [1, 11, 21, 31] {
+            //Logs are returned out of order to check that each range is sorted
+            client.push(vec![log(block_number + 5), log(block_number)]);
+        }
+
+        let sync_progress = Arc::new(CountingProgress::default());
+        let progress = PhaseProgress::new(
+            sync_progress.clone(),
+            Dex::new(H160::zero(), DexVariant::UniswapV3, 0, None),
+            SyncPhase::GetPools,
+        );
+
+        let log_ranges = log_fetcher(10, 1, 10)
+            .with_parallelism(4)
+            .with_progress(progress)
+            .get_logs(&Filter::new(), 1, 40, middleware)
+            .await
+            .unwrap();
+
+        let ranges = log_ranges
+            .iter()
+            .map(|log_range| (log_range.from_block, log_range.to_block))
+            .collect::<Vec<(u64, u64)>>();
+
+        assert_eq!(ranges, vec![(1, 10), (11, 20), (21, 30), (31, 40)]);
+        for log_range in log_ranges {
+            let block_numbers = log_range
+                .logs
+                .iter()
+                .map(|log| log.block_number.unwrap().as_u64())
+                .collect::<Vec<u64>>();
+            assert!(block_numbers.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+
+        assert_eq!(sync_progress.position(), 40);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_get_logs() {
+        let (middleware, client) = mock_provider();
+
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+
+        let log_ranges = log_fetcher(10, 1, 10)
+            .with_parallelism(4)
+            .with_cancellation_token(cancellation_token)
+            .get_logs(&Filter::new(), 1, 40, middleware)
+            .await
+            .unwrap();
+
+        assert!(log_ranges.is_empty());
+        assert!(client.requests().is_empty());
+    }
+
+    #[test]
+    fn test_completed_to_block() {
+        let log_range = |from_block, to_block| LogRange {
+            from_block,
+            to_block,
+            logs: vec![],
+        };
+
+        assert_eq!(completed_to_block(1, &[]), None);
+        assert_eq!(completed_to_block(1, &[log_range(11, 20)]), None);
+
+        //The range after the gap was completed, but the blocks before it were not
+        let log_ranges = [log_range(1, 10), log_range(11, 20), log_range(31, 40)];
+        assert_eq!(completed_to_block(1, &log_ranges), Some(20));
+    }
+}
@@ -0,0 +1,646 @@
+use std::sync::Arc;
+
+use ethers::{
+    abi::ParamType,
+    providers::Middleware,
+    types::{Log, H160, H256, I256, U256},
+};
+use serde::{Deserialize, Serialize};
+
+use super::weighted_math;
+use crate::{
+    abi, batch_requests,
+    errors::{ArithmeticError, CFMMError},
+};
+
+//Swap(bytes32,address,address,uint256,uint256), emitted by the Vault
+pub const SWAP_EVENT_SIGNATURE: H256 = H256([
+    33, 112, 199, 65, 196, 21, 49, 174, 194, 14, 124, 16, 124, 36, 238, 207, 221, 21, 230, 156,
+    155, 176, 168, 221, 55, 177, 132, 11, 158, 11, 32, 123,
+]);
+
+//PoolBalanceChanged(bytes32,address,address[],int256[],uint256[]), emitted by the Vault on joins and exits
+pub const POOL_BALANCE_CHANGED_EVENT_SIGNATURE: H256 = H256([
+    229, 206, 36, 144, 135, 206, 4, 240, 90, 149, 113, 146, 67, 84, 0, 253, 151, 134, 141, 186, 14,
+    106, 75, 76, 4, 154, 191, 138, 248, 13, 174, 120,
+]);
+
+//Swap fees are percentages scaled by 1e18, `fee()` converts them to the denomination of the other pools, ie. 300 is a 0.3% fee
+const SWAP_FEE_TO_FEE: U256 = U256([10_000_000_000_000, 0, 0, 0]);
+
+//Balancer V2 weighted pool. The balances are held by the Vault, which identifies the pool by its pool id.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct BalancerV2Pool {
+    pub address: H160,
+    pub pool_id: H256,
+    pub vault: H160,
+    pub tokens: Vec<H160>,
+    pub decimals: Vec<u8>,
+    pub balances: Vec<U256>,
+    //Normalized weights, scaled by 1e18 so that they sum to 1e18
+    pub weights: Vec<U256>,
+    //Swap fee percentage, scaled by 1e18
+    pub swap_fee: U256,
+}
+
+impl BalancerV2Pool {
+    //Creates a new instance of the pool from the pool address, and syncs the pool data
+    pub async fn new_from_address<M: Middleware>(
+        address: H160,
+        middleware: Arc<M>,
+    ) -> Result<Self, CFMMError<M>> {
+        let weighted_pool = abi::IBalancerV2WeightedPool::new(address, middleware.clone());
+
+        let mut pool = BalancerV2Pool {
+            address,
+            pool_id: H256(weighted_pool.get_pool_id().call().await?),
+            vault: weighted_pool.get_vault().call().await?,
+            ..Default::default()
+        };
+
+        pool.get_pool_data(middleware).await?;
+
+        if !pool.data_is_populated() {
+            return Err(CFMMError::PoolDataError);
+        }
+
+        Ok(pool)
+    }
+
+    pub async fn new_from_event_log<M: Middleware>(
+        log: Log,
+        middleware: Arc<M>,
+    ) -> Result<Self, CFMMError<M>> {
+        let mut pool = BalancerV2Pool::new_empty_pool_from_event_log::<M>(log)?;
+        pool.get_pool_data(middleware).await?;
+
+        if !pool.data_is_populated() {
+            return Err(CFMMError::PoolDataError);
+        }
+
+        Ok(pool)
+    }
+
+    //Decodes a Vault `PoolRegistered(pool_id, pool_address, specialization)` log into a pool without pool data
+    pub fn new_empty_pool_from_event_log<M: Middleware>(log: Log) -> Result<Self, CFMMError<M>> {
+        if log.topics.len() < 3 {
+            return Err(CFMMError::UnrecognizedPoolCreatedEventLog);
+        }
+
+        Ok(BalancerV2Pool {
+            address: H160::from(log.topics[2]),
+            pool_id: log.topics[1],
+            vault: log.address,
+            ..Default::default()
+        })
+    }
+
+    pub fn fee(&self) -> u32 {
+        (self.swap_fee / SWAP_FEE_TO_FEE).as_u32()
+    }
+
+    pub fn address(&self) -> H160 {
+        self.address
+    }
+
+    //Gets the tokens and balances from the Vault and the weights and swap fee from the pool
+    pub async fn get_pool_data<M: Middleware>(
+        &mut self,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        let mut pools = [super::Pool::BalancerV2(self.clone())];
+        batch_requests::balancer_v2::get_pool_data_batch_request(&mut pools, middleware).await?;
+
+        if let super::Pool::BalancerV2(pool) = &pools[0] {
+            *self = pool.clone();
+        }
+
+        Ok(())
+    }
+
+    pub async fn sync_pool<M: Middleware>(
+        &mut self,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        self.get_pool_data(middleware).await
+    }
+
+    pub fn data_is_populated(&self) -> bool {
+        self.tokens.len() >= 2
+            && self.decimals.len() == self.tokens.len()
+            && self.balances.len() == self.tokens.len()
+            && self.weights.len() == self.tokens.len()
+            && self.balances.iter().all(|balance| !balance.is_zero())
+    }
+
+    pub fn token_index(&self, token: H160) -> Option<usize> {
+        self.tokens
+            .iter()
+            .position(|pool_token| *pool_token == token)
+    }
+
+    //Returns the first token of the pool that is not `token_in`. Pools with more than two tokens are routed through
+    //the first other token, pass the token out explicitly to swap between any two tokens.
+    pub fn token_out(&self, token_in: H160) -> H160 {
+        self.tokens
+            .iter()
+            .copied()
+            .find(|token| *token != token_in)
+            .unwrap_or_default()
+    }
+
+    //Mirrors `onSwap` of the pool for a given in swap, the swap fee is taken from the amount in before it is scaled
+    pub fn simulate_swap<M: Middleware>(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        amount_in: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let (i, j) = self
+            .token_indices(token_in, token_out)
+            .ok_or(CFMMError::PoolDataError)?;
+
+        let amount_in = amount_in.saturating_sub(weighted_math::mul_up(amount_in, self.swap_fee)?);
+        let amount_out = weighted_math::calc_out_given_in(
+            self.upscale(self.balances[i], i)?,
+            self.weights[i],
+            self.upscale(self.balances[j], j)?,
+            self.weights[j],
+            self.upscale(amount_in, i)?,
+        )?;
+
+        Ok(self.downscale_down(amount_out, j))
+    }
+
+    //The whole amount in is added to the balance of the pool, protocol fees are only charged on joins and exits
+    pub fn simulate_swap_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        token_out: H160,
+        amount_in: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let amount_out = self.simulate_swap(token_in, token_out, amount_in)?;
+        let (i, j) = self
+            .token_indices(token_in, token_out)
+            .ok_or(CFMMError::PoolDataError)?;
+
+        self.update_balances(i, j, amount_in, amount_out)?;
+
+        Ok(amount_out)
+    }
+
+    //Mirrors `onSwap` of the pool for a given out swap, the swap fee is added to the amount in after it is scaled down
+    pub fn simulate_swap_exact_out<M: Middleware>(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let (i, j) = self
+            .token_indices(token_in, token_out)
+            .ok_or(CFMMError::PoolDataError)?;
+
+        let amount_in = weighted_math::calc_in_given_out(
+            self.upscale(self.balances[i], i)?,
+            self.weights[i],
+            self.upscale(self.balances[j], j)?,
+            self.weights[j],
+            self.upscale(amount_out, j)?,
+        )?;
+        let amount_in = self.downscale_up(amount_in, i);
+
+        Ok(weighted_math::div_up(
+            amount_in,
+            weighted_math::complement(self.swap_fee),
+        )?)
+    }
+
+    pub fn simulate_swap_exact_out_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        token_out: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let amount_in = self.simulate_swap_exact_out(token_in, token_out, amount_out)?;
+        let (i, j) = self
+            .token_indices(token_in, token_out)
+            .ok_or(CFMMError::PoolDataError)?;
+
+        self.update_balances(i, j, amount_in, amount_out)?;
+
+        Ok(amount_in)
+    }
+
+    //Spot price of the base token in the token returned by `token_out`, without the swap fee
+    pub fn calculate_price(&self, base_token: H160) -> f64 {
+        let (i, j) = match self.token_indices(base_token, self.token_out(base_token)) {
+            Some(indices) => indices,
+            None => return 0.0,
+        };
+
+        let weighted_balance = |k: usize| {
+            self.upscale(self.balances[k], k)
+                .unwrap_or_default()
+                .as_u128() as f64
+                / self.weights[k].as_u128() as f64
+        };
+
+        weighted_balance(j) / weighted_balance(i)
+    }
+
+    //Updates the balances from a Vault `Swap` log for this pool
+    pub fn update_pool_from_swap_log<M: Middleware>(
+        &mut self,
+        log: &Log,
+    ) -> Result<(), CFMMError<M>> {
+        if log.topics.len() < 4 || log.data.len() < 64 || log.topics[1] != self.pool_id {
+            return Ok(());
+        }
+
+        let token_in = self.token_index(H160::from(log.topics[2]));
+        let token_out = self.token_index(H160::from(log.topics[3]));
+
+        if let (Some(i), Some(j)) = (token_in, token_out) {
+            let amount_in = U256::from_big_endian(&log.data[0..32]);
+            let amount_out = U256::from_big_endian(&log.data[32..64]);
+
+            self.balances[i] = self.balances[i]
+                .checked_add(amount_in)
+                .ok_or(ArithmeticError::Overflow)?;
+            self.balances[j] = self.balances[j].saturating_sub(amount_out);
+        }
+
+        Ok(())
+    }
+
+    //Updates the balances from a Vault `PoolBalanceChanged` log for this pool. Joins have positive deltas and exits have
+    //negative deltas, and the protocol fees that are charged at the same time are taken out of the balances.
+    pub fn update_pool_from_pool_balance_changed_log<M: Middleware>(
+        &mut self,
+        log: &Log,
+    ) -> Result<(), CFMMError<M>> {
+        if log.topics.len() < 2 || log.topics[1] != self.pool_id {
+            return Ok(());
+        }
+
+        let tokens = ethers::abi::decode(
+            &[
+                ParamType::Array(Box::new(ParamType::Address)),
+                ParamType::Array(Box::new(ParamType::Int(256))),
+                ParamType::Array(Box::new(ParamType::Uint(256))),
+            ],
+            &log.data,
+        )?;
+
+        let pool_tokens = tokens[0].to_owned().into_array().unwrap_or_default();
+        let deltas = tokens[1].to_owned().into_array().unwrap_or_default();
+        let protocol_fees = tokens[2].to_owned().into_array().unwrap_or_default();
+
+        for ((token, delta), protocol_fee) in pool_tokens.into_iter().zip(deltas).zip(protocol_fees)
+        {
+            let index = token
+                .into_address()
+                .and_then(|token| self.token_index(token));
+
+            if let (Some(k), Some(delta), Some(protocol_fee)) =
+                (index, delta.into_int(), protocol_fee.into_uint())
+            {
+                let delta = I256::from_raw(delta);
+                let balance = if delta.is_negative() {
+                    self.balances[k].saturating_sub(delta.unsigned_abs())
+                } else {
+                    self.balances[k]
+                        .checked_add(delta.into_raw())
+                        .ok_or(ArithmeticError::Overflow)?
+                };
+
+                self.balances[k] = balance.saturating_sub(protocol_fee);
+            }
+        }
+
+        Ok(())
+    }
+
+    //Adds the amount in to the balance in and subtracts the amount out from the balance out.
+    //The balances are left unchanged if either would overflow or underflow.
+    fn update_balances<M: Middleware>(
+        &mut self,
+        i: usize,
+        j: usize,
+        amount_in: U256,
+        amount_out: U256,
+    ) -> Result<(), CFMMError<M>> {
+        let balance_in = self.balances[i]
+            .checked_add(amount_in)
+            .ok_or(ArithmeticError::Overflow)?;
+        let balance_out = self.balances[j]
+            .checked_sub(amount_out)
+            .ok_or(CFMMError::InsufficientLiquidity)?;
+
+        self.balances[i] = balance_in;
+        self.balances[j] = balance_out;
+
+        Ok(())
+    }
+
+    fn token_indices(&self, token_in: H160, token_out: H160) -> Option<(usize, usize)> {
+        match (self.token_index(token_in), self.token_index(token_out)) {
+            (Some(i), Some(j)) if i != j && self.data_is_populated() => Some((i, j)),
+            _ => None,
+        }
+    }
+
+    //Amounts are scaled to 18 decimals before the weighted math is applied
+    fn scaling_factor(&self, index: usize) -> U256 {
+        U256::exp10(18usize.saturating_sub(self.decimals[index] as usize))
+    }
+
+    fn upscale(&self, amount: U256, index: usize) -> Result<U256, ArithmeticError> {
+        amount
+            .checked_mul(self.scaling_factor(index))
+            .ok_or(ArithmeticError::Overflow)
+    }
+
+    fn downscale_down(&self, amount: U256, index: usize) -> U256 {
+        amount / self.scaling_factor(index)
+    }
+
+    fn downscale_up(&self, amount: U256, index: usize) -> U256 {
+        let scaling_factor = self.scaling_factor(index);
+
+        if amount.is_zero() {
+            U256::zero()
+        } else {
+            (amount - 1) / scaling_factor + 1
+        }
+    }
+}
+
+//The first 20 bytes of a pool id are the address of the pool
+pub fn pool_address_from_pool_id(pool_id: H256) -> H160 {
+    H160::from_slice(&pool_id[0..20])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        abi::Token,
+        providers::{Http, Provider},
+        types::{BlockId, Bytes, Log, H160, H256, I256, U256},
+    };
+
+    use super::{
+        pool_address_from_pool_id, BalancerV2Pool, POOL_BALANCE_CHANGED_EVENT_SIGNATURE,
+        SWAP_EVENT_SIGNATURE,
+    };
+    use crate::{
+        abi::{BatchSwapStep, FundManagement, IBalancerV2Vault, IBalancerV2WeightedPool, IErc20},
+        errors::{ArithmeticError, CFMMError},
+    };
+
+    type M = Provider<Http>;
+
+    fn pool_id() -> H256 {
+        let mut pool_id = H256::zero();
+        pool_id.0[0..20].copy_from_slice(H160::from_low_u64_be(1).as_bytes());
+        pool_id.0[31] = 2;
+        pool_id
+    }
+
+    //80/20 pool with 13M of an 18 decimal token and 9000 of a 6 decimal token
+    fn weighted_pool() -> BalancerV2Pool {
+        BalancerV2Pool {
+            address: H160::from_low_u64_be(1),
+            pool_id: pool_id(),
+            vault: H160::from_low_u64_be(2),
+            tokens: vec![H160::from_low_u64_be(10), H160::from_low_u64_be(11)],
+            decimals: vec![18, 6],
+            balances: vec![
+                U256::from(13_000_000u128) * U256::exp10(18),
+                U256::from(9_000_000_000u128),
+            ],
+            weights: vec![U256::exp10(17) * 8, U256::exp10(17) * 2],
+            swap_fee: U256::exp10(15) * 2,
+        }
+    }
+
+    #[test]
+    fn test_simulate_swap() {
+        let pool = weighted_pool();
+        let (token_80, token_20) = (pool.tokens[0], pool.tokens[1]);
+
+        //Amounts for the synthetic pool, `test_simulate_swap_matches_query_batch_swap` checks the swap math against the Vault
+        assert_eq!(
+            pool.simulate_swap::<M>(token_20, token_80, U256::from(10_000_000u128))
+                .unwrap(),
+            U256::from(3601393268922917000000u128)
+        );
+        assert_eq!(
+            pool.simulate_swap::<M>(token_80, token_20, U256::exp10(21))
+                .unwrap(),
+            U256::from(2763161u128)
+        );
+        assert_eq!(
+            pool.simulate_swap_exact_out::<M>(token_20, token_80, U256::exp10(22))
+                .unwrap(),
+            U256::from(27801248u128)
+        );
+
+        //Swaps that take more than 30% of the balance in revert
+        assert!(pool
+            .simulate_swap::<M>(token_20, token_80, U256::from(3_000_000_000u128))
+            .is_err());
+        assert!(pool
+            .simulate_swap::<M>(token_20, H160::zero(), U256::one())
+            .is_err());
+        assert_eq!(pool.fee(), 200);
+    }
+
+    #[test]
+    fn test_simulate_swap_overflow() {
+        let mut pool = weighted_pool();
+        let (token_80, token_20) = (pool.tokens[0], pool.tokens[1]);
+
+        //The fee and the scaling of the amount in overflow before the max in ratio is checked
+        for amount_in in [U256::MAX / 1000, U256::MAX] {
+            assert!(matches!(
+                pool.simulate_swap::<M>(token_20, token_80, amount_in),
+                Err(CFMMError::ArithmeticError(ArithmeticError::Overflow))
+            ));
+        }
+
+        pool.balances[0] = U256::MAX - 1;
+        assert!(matches!(
+            pool.simulate_swap_mut::<M>(token_80, token_20, U256::from(2)),
+            Err(CFMMError::ArithmeticError(ArithmeticError::Overflow))
+        ));
+        assert_eq!(pool.balances[0], U256::MAX - 1);
+    }
+
+    #[test]
+    fn test_update_pool_from_logs() {
+        let mut pool = weighted_pool();
+        let mut expected_pool = weighted_pool();
+
+        let amount_in = U256::from(10_000_000u128);
+        let amount_out = pool
+            .simulate_swap_mut::<M>(pool.tokens[1], pool.tokens[0], amount_in)
+            .unwrap();
+
+        let swap_log = |amount_in, amount_out| Log {
+            address: pool.vault,
+            topics: vec![
+                SWAP_EVENT_SIGNATURE,
+                pool_id(),
+                H256::from(pool.tokens[1]),
+                H256::from(pool.tokens[0]),
+            ],
+            data: ethers::abi::encode(&[Token::Uint(amount_in), Token::Uint(amount_out)]).into(),
+            ..Default::default()
+        };
+        expected_pool
+            .update_pool_from_swap_log::<M>(&swap_log(amount_in, amount_out))
+            .unwrap();
+        assert_eq!(pool, expected_pool);
+
+        //A swap log that would overflow the balance in leaves the balances unchanged
+        assert!(matches!(
+            expected_pool.update_pool_from_swap_log::<M>(&swap_log(U256::MAX, U256::zero())),
+            Err(CFMMError::ArithmeticError(ArithmeticError::Overflow))
+        ));
+        assert_eq!(pool, expected_pool);
+
+        //Exit with 1000 of the first token while 1 of it is charged as protocol fees
+        pool.update_pool_from_pool_balance_changed_log::<M>(&Log {
+            address: pool.vault,
+            topics: vec![
+                POOL_BALANCE_CHANGED_EVENT_SIGNATURE,
+                pool_id(),
+                H256::from(H160::from_low_u64_be(100)),
+            ],
+            data: ethers::abi::encode(&[
+                Token::Array(vec![
+                    Token::Address(pool.tokens[0]),
+                    Token::Address(pool.tokens[1]),
+                ]),
+                Token::Array(vec![
+                    Token::Int(I256::from(-1000).into_raw()),
+                    Token::Int(I256::from(500).into_raw()),
+                ]),
+                Token::Array(vec![Token::Uint(U256::one()), Token::Uint(U256::zero())]),
+            ])
+            .into(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(pool.balances[0], expected_pool.balances[0] - 1001);
+        assert_eq!(pool.balances[1], expected_pool.balances[1] + 500);
+        assert_eq!(pool_address_from_pool_id(pool_id()), pool.address);
+    }
+
+    //Builds the 80 BAL / 20 WETH pool from its state at block 17000000 and compares swaps in both directions and both
+    //swap kinds to the Vault's queryBatchSwap at the same block
+    #[tokio::test]
+    async fn test_simulate_swap_matches_query_batch_swap() {
+        let rpc_endpoint = std::env::var("ETHEREUM_MAINNET_ENDPOINT")
+            .expect("Could not get ETHEREUM_MAINNET_ENDPOINT");
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint).unwrap());
+
+        let block = BlockId::from(17000000u64);
+        let vault_address = H160::from_str("0xBA12222222228d8Ba445958a75a0704d566BF2C8").unwrap();
+        let pool_id =
+            H256::from_str("0x5c6ee304399dbdb9c8ef030ab642b10820db8f56000200000000000000000014")
+                .unwrap();
+
+        let vault = IBalancerV2Vault::new(vault_address, middleware.clone());
+        let weighted_pool =
+            IBalancerV2WeightedPool::new(pool_address_from_pool_id(pool_id), middleware.clone());
+
+        let (tokens, balances, _) = vault
+            .get_pool_tokens(pool_id.0)
+            .block(block)
+            .call()
+            .await
+            .unwrap();
+
+        let mut decimals = vec![];
+        for token in tokens.iter() {
+            decimals.push(
+                IErc20::new(*token, middleware.clone())
+                    .decimals()
+                    .block(block)
+                    .call()
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        let pool = BalancerV2Pool {
+            address: pool_address_from_pool_id(pool_id),
+            pool_id,
+            vault: vault_address,
+            tokens: tokens.clone(),
+            decimals,
+            balances,
+            weights: weighted_pool
+                .get_normalized_weights()
+                .block(block)
+                .call()
+                .await
+                .unwrap(),
+            swap_fee: weighted_pool
+                .get_swap_fee_percentage()
+                .block(block)
+                .call()
+                .await
+                .unwrap(),
+        };
+
+        //Asset deltas are positive for the amount the Vault receives and negative for the amount it sends
+        let query_batch_swap = |kind: u8, asset_in: usize, asset_out: usize, amount: U256| {
+            let swaps = vec![BatchSwapStep {
+                pool_id: pool_id.0,
+                asset_in_index: U256::from(asset_in),
+                asset_out_index: U256::from(asset_out),
+                amount,
+                user_data: Bytes::new(),
+            }];
+            let funds = FundManagement {
+                sender: H160::zero(),
+                from_internal_balance: false,
+                recipient: H160::zero(),
+                to_internal_balance: false,
+            };
+
+            let call = vault
+                .query_batch_swap(kind, swaps, tokens.clone(), funds)
+                .block(block);
+            async move { call.call().await.unwrap() }
+        };
+
+        for (asset_in, asset_out, amount) in [(0, 1, U256::exp10(20)), (1, 0, U256::exp10(18))] {
+            let (token_in, token_out) = (pool.tokens[asset_in], pool.tokens[asset_out]);
+
+            //Given in, swaps 100 BAL or 1 WETH in
+            let deltas = query_batch_swap(0, asset_in, asset_out, amount).await;
+            assert_eq!(deltas[asset_in], I256::from_raw(amount));
+            assert_eq!(
+                pool.simulate_swap::<M>(token_in, token_out, amount)
+                    .unwrap(),
+                (-deltas[asset_out]).into_raw()
+            );
+
+            //Given out, swaps 100 WETH or 1 BAL out
+            let deltas = query_batch_swap(1, asset_in, asset_out, amount).await;
+            assert_eq!(-deltas[asset_out], I256::from_raw(amount));
+            assert_eq!(
+                pool.simulate_swap_exact_out::<M>(token_in, token_out, amount)
+                    .unwrap(),
+                deltas[asset_in].into_raw()
+            );
+        }
+    }
+}
@@ -0,0 +1,992 @@
+use std::sync::Arc;
+
+use ethers::{
+    providers::Middleware,
+    types::{Log, H160, H256, U256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batch_requests,
+    errors::{ArithmeticError, CFMMError},
+};
+
+pub const TOKEN_EXCHANGE_EVENT_SIGNATURE: H256 = H256([
+    139, 62, 150, 242, 184, 137, 250, 119, 28, 83, 201, 129, 180, 13, 175, 0, 95, 99, 246, 55, 241,
+    134, 159, 112, 112, 82, 209, 90, 61, 217, 113, 64,
+]);
+
+//TokenExchangeUnderlying(address,int128,uint256,int128,uint256)
+pub const TOKEN_EXCHANGE_UNDERLYING_EVENT_SIGNATURE: H256 = H256([
+    208, 19, 202, 35, 231, 122, 101, 0, 60, 44, 101, 156, 84, 66, 192, 12, 128, 83, 113, 183, 252,
+    30, 189, 76, 32, 108, 65, 209, 83, 107, 217, 11,
+]);
+
+//RampA(uint256,uint256,uint256,uint256)
+pub const RAMP_A_EVENT_SIGNATURE: H256 = H256([
+    162, 183, 30, 198, 223, 148, 147, 0, 181, 154, 171, 54, 181, 94, 24, 150, 151, 183, 80, 17,
+    157, 211, 73, 252, 250, 140, 15, 119, 158, 131, 194, 84,
+]);
+
+//StopRampA(uint256,uint256)
+pub const STOP_RAMP_A_EVENT_SIGNATURE: H256 = H256([
+    70, 226, 47, 179, 112, 154, 210, 137, 246, 44, 230, 61, 70, 146, 72, 83, 109, 188, 120, 216,
+    43, 132, 163, 215, 231, 74, 214, 6, 220, 32, 25, 56,
+]);
+
+//Liquidity events of pools with 2 to 4 coins, pools with a dynamic number of coins and the older and newer RemoveLiquidityOne
+pub const LIQUIDITY_EVENT_SIGNATURES: [H256; 15] = [
+    //AddLiquidity(address,uint256[2],uint256[2],uint256,uint256)
+    H256([
+        38, 245, 90, 133, 8, 29, 36, 151, 78, 133, 198, 192, 0, 69, 208, 240, 69, 57, 145, 233, 88,
+        115, 245, 43, 255, 13, 33, 175, 64, 121, 167, 104,
+    ]),
+    //RemoveLiquidity(address,uint256[2],uint256[2],uint256)
+    H256([
+        124, 54, 56, 84, 204, 247, 150, 35, 65, 31, 137, 149, 179, 98, 188, 229, 237, 223, 241,
+        140, 146, 126, 220, 111, 93, 187, 181, 224, 88, 25, 168, 44,
+    ]),
+    //RemoveLiquidityImbalance(address,uint256[2],uint256[2],uint256,uint256)
+    H256([
+        43, 85, 8, 55, 141, 126, 25, 224, 213, 250, 51, 132, 25, 3, 71, 49, 65, 108, 79, 91, 33,
+        154, 16, 55, 153, 86, 247, 100, 49, 127, 212, 126,
+    ]),
+    //AddLiquidity(address,uint256[3],uint256[3],uint256,uint256)
+    H256([
+        66, 63, 100, 149, 160, 143, 198, 82, 66, 92, 244, 237, 13, 31, 158, 55, 229, 113, 217, 185,
+        82, 155, 28, 28, 35, 204, 231, 128, 178, 231, 223, 13,
+    ]),
+    //RemoveLiquidity(address,uint256[3],uint256[3],uint256)
+    H256([
+        164, 157, 76, 240, 38, 86, 174, 191, 140, 119, 31, 90, 133, 133, 99, 138, 42, 21, 238, 108,
+        151, 207, 114, 5, 212, 32, 142, 215, 193, 223, 37, 45,
+    ]),
+    //RemoveLiquidityImbalance(address,uint256[3],uint256[3],uint256,uint256)
+    H256([
+        23, 53, 153, 219, 249, 198, 202, 111, 124, 59, 89, 13, 240, 122, 233, 138, 69, 215, 79,
+        245, 64, 101, 80, 81, 65, 231, 222, 108, 70, 166, 36, 194,
+    ]),
+    //AddLiquidity(address,uint256[4],uint256[4],uint256,uint256)
+    H256([
+        63, 25, 21, 119, 94, 12, 154, 56, 165, 122, 123, 183, 241, 249, 0, 95, 72, 111, 185, 4,
+        225, 248, 74, 162, 21, 54, 77, 86, 115, 25, 165, 141,
+    ]),
+    //RemoveLiquidity(address,uint256[4],uint256[4],uint256)
+    H256([
+        152, 120, 202, 55, 94, 16, 111, 42, 67, 195, 181, 153, 252, 98, 69, 104, 19, 28, 76, 154,
+        75, 166, 106, 20, 86, 55, 21, 118, 59, 233, 213, 157,
+    ]),
+    //RemoveLiquidityImbalance(address,uint256[4],uint256[4],uint256,uint256)
+    H256([
+        185, 100, 183, 47, 115, 245, 239, 91, 240, 253, 197, 89, 178, 250, 185, 167, 177, 42, 57,
+        228, 120, 23, 165, 71, 241, 240, 174, 228, 127, 235, 214, 2,
+    ]),
+    //AddLiquidity(address,uint256[],uint256[],uint256,uint256)
+    H256([
+        24, 156, 98, 59, 102, 107, 27, 69, 184, 61, 113, 120, 243, 155, 140, 8, 124, 176, 151, 116,
+        49, 124, 162, 245, 60, 45, 60, 55, 38, 242, 34, 162,
+    ]),
+    //RemoveLiquidity(address,uint256[],uint256[],uint256)
+    H256([
+        52, 122, 216, 40, 229, 140, 190, 83, 77, 143, 107, 103, 152, 93, 121, 19, 96, 117, 107, 24,
+        240, 217, 95, 217, 241, 151, 166, 108, 196, 100, 128, 234,
+    ]),
+    //RemoveLiquidityImbalance(address,uint256[],uint256[],uint256,uint256)
+    H256([
+        54, 49, 194, 139, 31, 157, 210, 19, 224, 49, 159, 177, 103, 181, 84, 215, 107, 108, 40, 58,
+        65, 20, 62, 180, 0, 160, 209, 173, 177, 175, 23, 85,
+    ]),
+    //RemoveLiquidityOne(address,uint256,uint256)
+    H256([
+        158, 150, 221, 59, 153, 122, 42, 37, 126, 236, 77, 249, 187, 110, 175, 98, 110, 32, 109,
+        245, 245, 67, 189, 150, 54, 130, 209, 67, 48, 11, 227, 16,
+    ]),
+    //RemoveLiquidityOne(address,uint256,uint256,uint256)
+    H256([
+        90, 208, 86, 242, 226, 138, 140, 236, 35, 32, 21, 64, 107, 132, 54, 104, 193, 227, 108,
+        218, 89, 129, 39, 236, 59, 140, 89, 184, 199, 39, 115, 160,
+    ]),
+    //RemoveLiquidityOne(address,int128,uint256,uint256,uint256)
+    H256([
+        111, 72, 18, 157, 177, 243, 124, 203, 156, 197, 221, 126, 17, 156, 179, 39, 80, 202, 189,
+        247, 91, 72, 55, 93, 115, 13, 38, 206, 54, 89, 187, 225,
+    ]),
+];
+
+//Events that change the balances or the amplification coefficient of a pool
+pub fn state_change_event_signatures() -> Vec<H256> {
+    let mut event_signatures = vec![
+        TOKEN_EXCHANGE_EVENT_SIGNATURE,
+        TOKEN_EXCHANGE_UNDERLYING_EVENT_SIGNATURE,
+        RAMP_A_EVENT_SIGNATURE,
+        STOP_RAMP_A_EVENT_SIGNATURE,
+    ];
+    event_signatures.extend(LIQUIDITY_EVENT_SIGNATURES);
+    event_signatures
+}
+
+//Fees are denominated in 1e10, ie. 4000000 is a 0.04% fee
+pub const FEE_DENOMINATOR: u64 = 10_000_000_000;
+//Balances are normalized to 18 decimals with the rates before the invariant is applied
+pub const PRECISION: u128 = 1_000_000_000_000_000_000;
+
+//Max iterations of Newton's method when solving for D and y, matching the Vyper contracts
+const MAX_ITERATIONS: usize = 255;
+
+//Curve StableSwap pool with any number of coins
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct CurvePool {
+    pub address: H160,
+    pub coins: Vec<H160>,
+    pub decimals: Vec<u8>,
+    pub balances: Vec<U256>,
+    //Multiplier that normalizes each balance to 18 decimals, scaled by 1e18, ie. 1e30 for a coin with 6 decimals.
+    //Pools with `stored_rates()` use those instead, which include the price of lending and oracle priced coins.
+    pub rates: Vec<U256>,
+    pub fee: u64,
+    //Share of the fee that is taken out of the pool, denominated in 1e10
+    pub admin_fee: u64,
+    //The amplification coefficient ramps linearly from `initial_a` to `future_a`. Pools that store A with
+    //extra precision report it multiplied by `a_precision`, older pools have an `a_precision` of 1.
+    pub initial_a: u64,
+    pub future_a: u64,
+    pub initial_a_time: u64,
+    pub future_a_time: u64,
+    pub a_precision: u64,
+    //Timestamp of the block that the state was synced at, swaps are simulated with A ramped to this timestamp
+    #[serde(default)]
+    pub timestamp: u64,
+}
+
+impl CurvePool {
+    //Creates a new instance of the pool from the pool address, and syncs the pool data
+    pub async fn new_from_address<M: Middleware>(
+        address: H160,
+        middleware: Arc<M>,
+    ) -> Result<Self, CFMMError<M>> {
+        let mut pool = CurvePool {
+            address,
+            ..Default::default()
+        };
+
+        pool.get_pool_data(middleware).await?;
+
+        if !pool.data_is_populated() {
+            return Err(CFMMError::PoolDataError);
+        }
+
+        Ok(pool)
+    }
+
+    pub async fn new_from_event_log<M: Middleware>(
+        log: Log,
+        middleware: Arc<M>,
+    ) -> Result<Self, CFMMError<M>> {
+        let pool = CurvePool::new_empty_pool_from_event_log::<M>(log)?;
+        CurvePool::new_from_address(pool.address, middleware).await
+    }
+
+    //Decodes a registry `PoolAdded(pool, rate_method_id)` log into a pool without pool data
+    pub fn new_empty_pool_from_event_log<M: Middleware>(log: Log) -> Result<Self, CFMMError<M>> {
+        let address = log
+            .topics
+            .get(1)
+            .map(|topic| H160::from(*topic))
+            .ok_or(CFMMError::UnrecognizedPoolCreatedEventLog)?;
+
+        Ok(CurvePool {
+            address,
+            ..Default::default()
+        })
+    }
+
+    //Returns the fee as a u32 to match the other pool variants, which is only lossy for fees above 42%
+    pub fn fee(&self) -> u32 {
+        u32::try_from(self.fee).unwrap_or(u32::MAX)
+    }
+
+    pub fn address(&self) -> H160 {
+        self.address
+    }
+
+    //Gets the coins, decimals, balances, fees and amplification coefficient of the pool
+    pub async fn get_pool_data<M: Middleware>(
+        &mut self,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        let mut pools = [super::Pool::Curve(self.clone())];
+        batch_requests::curve::get_pool_data_batch_request(&mut pools, middleware).await?;
+
+        if let super::Pool::Curve(pool) = &pools[0] {
+            *self = pool.clone();
+        }
+
+        Ok(())
+    }
+
+    //Updates the balances, fees and amplification coefficient, the coins of a pool never change
+    pub async fn sync_pool<M: Middleware>(
+        &mut self,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        if self.coins.is_empty() {
+            return self.get_pool_data(middleware).await;
+        }
+
+        let mut pools = [super::Pool::Curve(self.clone())];
+        batch_requests::curve::get_pool_state_batch_request(&mut pools, middleware).await?;
+
+        if let super::Pool::Curve(pool) = &pools[0] {
+            *self = pool.clone();
+        }
+
+        Ok(())
+    }
+
+    pub fn data_is_populated(&self) -> bool {
+        self.coins.len() >= 2
+            && self.balances.len() == self.coins.len()
+            && self.rates.len() == self.coins.len()
+            && self.balances.iter().all(|balance| !balance.is_zero())
+    }
+
+    pub fn coin_index(&self, token: H160) -> Option<usize> {
+        self.coins.iter().position(|coin| *coin == token)
+    }
+
+    //Returns the first coin of the pool that is not `token_in`. Pools with more than two coins are routed through
+    //the first other coin, use `get_dy` to swap between any two coins.
+    pub fn token_out(&self, token_in: H160) -> H160 {
+        self.coins
+            .iter()
+            .copied()
+            .find(|coin| *coin != token_in)
+            .unwrap_or_default()
+    }
+
+    //Amplification coefficient at the timestamp, multiplied by `a_precision`
+    pub fn a(&self, timestamp: u64) -> U256 {
+        let initial_a = U256::from(self.initial_a);
+        let future_a = U256::from(self.future_a);
+
+        if timestamp >= self.future_a_time || self.future_a_time <= self.initial_a_time {
+            return future_a;
+        }
+
+        let elapsed = U256::from(timestamp.saturating_sub(self.initial_a_time));
+        let duration = U256::from(self.future_a_time - self.initial_a_time);
+
+        if future_a > initial_a {
+            initial_a + (future_a - initial_a) * elapsed / duration
+        } else {
+            initial_a - (initial_a - future_a) * elapsed / duration
+        }
+    }
+
+    //Balances normalized to 18 decimals
+    pub fn xp(&self) -> Result<Vec<U256>, ArithmeticError> {
+        self.balances
+            .iter()
+            .zip(self.rates.iter())
+            .map(|(balance, rate)| div(mul(*balance, *rate)?, U256::from(PRECISION)))
+            .collect()
+    }
+
+    //Mirrors `get_dy` of the pool contract at the timestamp
+    pub fn get_dy(
+        &self,
+        i: usize,
+        j: usize,
+        dx: U256,
+        timestamp: u64,
+    ) -> Result<U256, ArithmeticError> {
+        let dy = self.get_dy_before_fee(i, j, dx, timestamp)?;
+        Ok(dy - mul(dy, U256::from(self.fee))? / U256::from(FEE_DENOMINATOR))
+    }
+
+    fn get_dy_before_fee(
+        &self,
+        i: usize,
+        j: usize,
+        dx: U256,
+        timestamp: u64,
+    ) -> Result<U256, ArithmeticError> {
+        match self.get_y_after_swap(i, j, dx, timestamp)? {
+            Some((xp, y)) => div(
+                mul(xp[j].saturating_sub(y + 1), U256::from(PRECISION))?,
+                self.rates[j],
+            ),
+            None => Ok(U256::zero()),
+        }
+    }
+
+    //Returns the normalized balances before the swap and the normalized balance of coin j after the swap
+    fn get_y_after_swap(
+        &self,
+        i: usize,
+        j: usize,
+        dx: U256,
+        timestamp: u64,
+    ) -> Result<Option<(Vec<U256>, U256)>, ArithmeticError> {
+        let n_coins = self.coins.len();
+        if i == j || i >= n_coins || j >= n_coins || dx.is_zero() || !self.data_is_populated() {
+            return Ok(None);
+        }
+
+        let xp = self.xp()?;
+        let x = add(xp[i], div(mul(dx, self.rates[i])?, U256::from(PRECISION))?)?;
+        let y = get_y(i, j, x, &xp, self.a(timestamp), self.a_precision())?;
+
+        Ok(Some((xp, y)))
+    }
+
+    //Returns an amount of coin i that receives at least `dy` of coin j
+    pub fn get_dx(
+        &self,
+        i: usize,
+        j: usize,
+        dy: U256,
+        timestamp: u64,
+    ) -> Result<U256, ArithmeticError> {
+        let n_coins = self.coins.len();
+        if i == j || i >= n_coins || j >= n_coins || dy.is_zero() || !self.data_is_populated() {
+            return Ok(U256::zero());
+        }
+
+        let xp = self.xp()?;
+        let fee_denominator = U256::from(FEE_DENOMINATOR);
+        let dy_before_fee = div(
+            mul(dy, fee_denominator)?,
+            sub(fee_denominator, U256::from(self.fee))?,
+        )? + 1;
+        let dy_before_fee_xp = div(
+            mul(add(dy_before_fee, U256::one())?, self.rates[j])?,
+            U256::from(PRECISION),
+        )?;
+
+        //The pool can never give out its entire balance
+        if dy_before_fee_xp >= xp[j] {
+            return Ok(U256::MAX);
+        }
+
+        //The invariant is solved for the balance of coin i that leaves coin j at its balance after the swap
+        let x = get_y(
+            j,
+            i,
+            xp[j] - dy_before_fee_xp,
+            &xp,
+            self.a(timestamp),
+            self.a_precision(),
+        )?;
+        let mut dx = div(
+            mul(x.saturating_sub(xp[i]), U256::from(PRECISION))?,
+            self.rates[i],
+        )? + 1;
+
+        //Newton's method rounds down, so the amount in is increased until it covers the amount out
+        let mut increment = U256::one();
+        while self.get_dy(i, j, dx, timestamp)? < dy {
+            dx = add(dx, increment)?;
+            increment = mul(increment, U256::from(2))?;
+        }
+
+        Ok(dx)
+    }
+
+    //Simulates the swap at the timestamp of the block the pool state was synced at
+    pub fn simulate_swap<M: Middleware>(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        amount_in: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        match (self.coin_index(token_in), self.coin_index(token_out)) {
+            (Some(i), Some(j)) => Ok(self.get_dy(i, j, amount_in, self.timestamp)?),
+            _ => Ok(U256::zero()),
+        }
+    }
+
+    //Mirrors `exchange` of the pool contract, the admin fee is taken out of the balance of coin j.
+    //The balances are left unchanged if the swap fails.
+    pub fn simulate_swap_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        token_out: H160,
+        amount_in: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let (i, j) = match (self.coin_index(token_in), self.coin_index(token_out)) {
+            (Some(i), Some(j)) => (i, j),
+            _ => return Ok(U256::zero()),
+        };
+
+        let (xp, y) = match self.get_y_after_swap(i, j, amount_in, self.timestamp)? {
+            Some(result) => result,
+            None => return Ok(U256::zero()),
+        };
+
+        let precision = U256::from(PRECISION);
+        let fee_denominator = U256::from(FEE_DENOMINATOR);
+
+        let dy = xp[j].saturating_sub(y + 1);
+        let dy_fee = mul(dy, U256::from(self.fee))? / fee_denominator;
+        let dy_admin_fee = div(
+            mul(
+                mul(dy_fee, U256::from(self.admin_fee))? / fee_denominator,
+                precision,
+            )?,
+            self.rates[j],
+        )?;
+        let amount_out = div(mul(dy - dy_fee, precision)?, self.rates[j])?;
+
+        let balance_in = add(self.balances[i], amount_in)?;
+        let balance_out = self.balances[j]
+            .checked_sub(amount_out + dy_admin_fee)
+            .ok_or(CFMMError::InsufficientLiquidity)?;
+
+        self.balances[i] = balance_in;
+        self.balances[j] = balance_out;
+
+        Ok(amount_out)
+    }
+
+    //Returns the amount of token_in needed to receive `amount_out` of token_out
+    pub fn simulate_swap_exact_out<M: Middleware>(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let (i, j) = match (self.coin_index(token_in), self.coin_index(token_out)) {
+            (Some(i), Some(j)) => (i, j),
+            _ => return Err(CFMMError::PoolDataError),
+        };
+
+        let amount_in = self.get_dx(i, j, amount_out, self.timestamp)?;
+        if amount_in == U256::MAX {
+            return Err(CFMMError::InsufficientLiquidity);
+        }
+
+        Ok(amount_in)
+    }
+
+    pub fn simulate_swap_exact_out_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        token_out: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let amount_in = self.simulate_swap_exact_out(token_in, token_out, amount_out)?;
+        self.simulate_swap_mut(token_in, token_out, amount_in)?;
+
+        Ok(amount_in)
+    }
+
+    //Price of the base token in the coin returned by `token_out`, from the amount out of a swap of a thousandth of a base token before fees
+    pub fn calculate_price(&self, base_token: H160) -> f64 {
+        let (i, j) = match (
+            self.coin_index(base_token),
+            self.coin_index(self.token_out(base_token)),
+        ) {
+            (Some(i), Some(j)) => (i, j),
+            _ => return 0.0,
+        };
+
+        let dx = (U256::exp10(self.decimals[i] as usize) / 1000).max(U256::one());
+        let dy = self
+            .get_dy_before_fee(i, j, dx, self.timestamp)
+            .unwrap_or_default();
+
+        (dy.as_u128() as f64 / 10f64.powi(self.decimals[j] as i32))
+            / (dx.as_u128() as f64 / 10f64.powi(self.decimals[i] as i32))
+    }
+
+    fn a_precision(&self) -> U256 {
+        U256::from(self.a_precision.max(1))
+    }
+}
+
+//Multiplier that normalizes a balance of a coin with the decimals to 18 decimals, scaled by 1e18
+pub fn rate_from_decimals(decimals: u8) -> U256 {
+    U256::exp10(36usize.saturating_sub(decimals as usize))
+}
+
+//Solves the StableSwap invariant for D with Newton's method, mirroring `get_D` of the pool contract
+pub fn get_d(xp: &[U256], amp: U256, a_precision: U256) -> Result<U256, ArithmeticError> {
+    let n_coins = U256::from(xp.len());
+    let sum = xp.iter().try_fold(U256::zero(), |sum, x| add(sum, *x))?;
+
+    if sum.is_zero() || xp.iter().any(|x| x.is_zero()) {
+        return Ok(U256::zero());
+    }
+
+    let mut d = sum;
+    let ann = mul(amp, n_coins)?;
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_p = d;
+        for x in xp {
+            d_p = div(mul(d_p, d)?, mul(*x, n_coins)?)?;
+        }
+
+        let d_prev = d;
+        let numerator = mul(
+            add(div(mul(ann, sum)?, a_precision)?, mul(d_p, n_coins)?)?,
+            d,
+        )?;
+        let denominator = add(
+            div(mul(sub(ann, a_precision)?, d)?, a_precision)?,
+            mul(n_coins + 1, d_p)?,
+        )?;
+        d = div(numerator, denominator)?;
+
+        if abs_diff(d, d_prev) <= U256::one() {
+            break;
+        }
+    }
+
+    Ok(d)
+}
+
+//Solves the invariant for the normalized balance of coin j after the balance of coin i is set to x, mirroring `get_y`
+pub fn get_y(
+    i: usize,
+    j: usize,
+    x: U256,
+    xp: &[U256],
+    amp: U256,
+    a_precision: U256,
+) -> Result<U256, ArithmeticError> {
+    let n_coins = U256::from(xp.len());
+    let d = get_d(xp, amp, a_precision)?;
+
+    if d.is_zero() {
+        return Ok(U256::zero());
+    }
+
+    let ann = mul(amp, n_coins)?;
+    let mut c = d;
+    let mut sum = U256::zero();
+
+    for (k, xp_k) in xp.iter().enumerate() {
+        let x_k = if k == i {
+            x
+        } else if k != j {
+            *xp_k
+        } else {
+            continue;
+        };
+
+        sum = add(sum, x_k)?;
+        c = div(mul(c, d)?, mul(x_k, n_coins)?)?;
+    }
+
+    c = div(mul(mul(c, d)?, a_precision)?, mul(ann, n_coins)?)?;
+    let b = add(sum, div(mul(d, a_precision)?, ann)?)?;
+    let mut y = d;
+
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        let numerator = add(mul(y, y)?, c)?;
+        let denominator = sub(add(mul(U256::from(2), y)?, b)?, d)?;
+        y = div(numerator, denominator)?;
+
+        if abs_diff(y, y_prev) <= U256::one() {
+            break;
+        }
+    }
+
+    Ok(y)
+}
+
+//Checked operations that return an error where the Vyper contracts would revert
+fn add(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    a.checked_add(b).ok_or(ArithmeticError::Overflow)
+}
+
+fn sub(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    a.checked_sub(b).ok_or(ArithmeticError::Overflow)
+}
+
+fn mul(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    a.checked_mul(b).ok_or(ArithmeticError::Overflow)
+}
+
+fn div(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    a.checked_div(b).ok_or(ArithmeticError::DivisionByZero)
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Middleware, Provider},
+        types::{BlockId, Log, H160, H256, U256},
+        utils::keccak256,
+    };
+
+    use super::{get_d, rate_from_decimals, CurvePool};
+    use crate::{
+        abi::ICurvePool,
+        errors::{ArithmeticError, CFMMError},
+        pool::Pool,
+        test_utils::mock_provider,
+    };
+
+    //Three coin pool with 18, 6 and 6 decimals, like 3pool
+    fn three_pool() -> CurvePool {
+        CurvePool {
+            address: H160::from_low_u64_be(1),
+            coins: vec![
+                H160::from_low_u64_be(10),
+                H160::from_low_u64_be(11),
+                H160::from_low_u64_be(12),
+            ],
+            decimals: vec![18, 6, 6],
+            balances: vec![
+                U256::from(150_000_000u128) * U256::exp10(18),
+                U256::from(160_000_000_000_000u128),
+                U256::from(70_000_000_000_000u128),
+            ],
+            rates: vec![
+                rate_from_decimals(18),
+                rate_from_decimals(6),
+                rate_from_decimals(6),
+            ],
+            fee: 1_000_000,
+            admin_fee: 5_000_000_000,
+            initial_a: 2000,
+            future_a: 2000,
+            a_precision: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_get_dy() {
+        let pool = three_pool();
+
+        //Regression values for the synthetic pool, `test_get_dy_matches_3pool` compares get_dy to the contract
+        assert_eq!(
+            pool.get_dy(0, 1, U256::from(1_000_000u128) * U256::exp10(18), 0)
+                .unwrap(),
+            U256::from(999_928_698_904u128)
+        );
+        assert_eq!(
+            pool.get_dy(2, 0, U256::from(1_000_000_000u128), 0).unwrap(),
+            U256::from(1_000_483_278_809_529_469_215u128)
+        );
+    }
+
+    #[test]
+    fn test_a_precision() {
+        //Pools that store A with extra precision return the same amounts as pools without it
+        let pool = three_pool();
+        let precise_pool = CurvePool {
+            initial_a: 200000,
+            future_a: 200000,
+            a_precision: 100,
+            ..three_pool()
+        };
+
+        let dx = U256::from(1_000_000u128) * U256::exp10(18);
+        assert_eq!(
+            pool.get_dy(0, 2, dx, 0).unwrap(),
+            precise_pool.get_dy(0, 2, dx, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ramp_a() {
+        let pool = CurvePool {
+            initial_a: 1000,
+            future_a: 2000,
+            initial_a_time: 100,
+            future_a_time: 200,
+            ..three_pool()
+        };
+
+        assert_eq!(pool.a(100), U256::from(1000));
+        assert_eq!(pool.a(150), U256::from(1500));
+        assert_eq!(pool.a(250), U256::from(2000));
+
+        let pool = CurvePool {
+            initial_a: 2000,
+            future_a: 1000,
+            ..pool
+        };
+        assert_eq!(pool.a(175), U256::from(1250));
+    }
+
+    #[test]
+    fn test_get_dx() {
+        let pool = three_pool();
+        let dy = U256::from(500_000_000_000u128);
+
+        let dx = pool.get_dx(0, 1, dy, 0).unwrap();
+
+        assert!(pool.get_dy(0, 1, dx, 0).unwrap() >= dy);
+        assert!(pool.get_dy(0, 1, dx * 9999 / 10000, 0).unwrap() < dy);
+        assert_eq!(pool.get_dx(0, 1, pool.balances[1], 0).unwrap(), U256::MAX);
+    }
+
+    #[test]
+    fn test_simulate_swap_mut() {
+        let mut pool = three_pool();
+        let (token_in, token_out) = (pool.coins[1], pool.coins[2]);
+        let d = get_d(&pool.xp().unwrap(), pool.a(0), U256::one()).unwrap();
+
+        let amount_in = U256::from(10_000_000_000u128);
+        let expected_amount_out = pool
+            .simulate_swap::<Provider<Http>>(token_in, token_out, amount_in)
+            .unwrap();
+        let amount_out = pool
+            .simulate_swap_mut::<Provider<Http>>(token_in, token_out, amount_in)
+            .unwrap();
+
+        //The amount out of exchange differs from get_dy only by rounding
+        assert!(abs_diff(amount_out, expected_amount_out) <= U256::one());
+        assert_eq!(pool.balances[1], U256::from(160_010_000_000_000u128));
+
+        //The fee that stays in the pool grows the invariant
+        assert!(get_d(&pool.xp().unwrap(), pool.a(0), U256::one()).unwrap() > d);
+
+        assert!(pool
+            .simulate_swap_exact_out::<Provider<Http>>(token_in, token_out, pool.balances[2])
+            .is_err());
+    }
+
+    fn abs_diff(a: U256, b: U256) -> U256 {
+        super::abs_diff(a, b)
+    }
+
+    #[test]
+    fn test_simulate_swap_overflow() {
+        let mut pool = CurvePool {
+            coins: vec![H160::from_low_u64_be(10), H160::from_low_u64_be(11)],
+            decimals: vec![18, 6],
+            balances: vec![U256::exp10(24), U256::exp10(12)],
+            rates: vec![rate_from_decimals(18), rate_from_decimals(6)],
+            ..three_pool()
+        };
+        let (token_in, token_out) = (pool.coins[0], pool.coins[1]);
+
+        for amount_in in [U256::exp10(70), U256::MAX] {
+            assert!(matches!(
+                pool.simulate_swap::<Provider<Http>>(token_in, token_out, amount_in),
+                Err(CFMMError::ArithmeticError(ArithmeticError::Overflow))
+            ));
+            assert!(pool
+                .simulate_swap_mut::<Provider<Http>>(token_in, token_out, amount_in)
+                .is_err());
+        }
+        assert_eq!(pool.balances, vec![U256::exp10(24), U256::exp10(12)]);
+    }
+
+    #[test]
+    fn test_simulate_swap_at_synced_timestamp() {
+        let pool = CurvePool {
+            initial_a: 100,
+            future_a: 5000,
+            initial_a_time: 1000,
+            future_a_time: 2000,
+            timestamp: 1500,
+            ..three_pool()
+        };
+        let (token_in, token_out) = (pool.coins[0], pool.coins[1]);
+        let amount_in = U256::from(1_000_000u128) * U256::exp10(18);
+
+        //A is ramped to the timestamp the state was synced at, regardless of the current time
+        assert_eq!(
+            pool.simulate_swap::<Provider<Http>>(token_in, token_out, amount_in)
+                .unwrap(),
+            pool.get_dy(0, 1, amount_in, 1500).unwrap()
+        );
+        assert_ne!(
+            pool.get_dy(0, 1, amount_in, 1500).unwrap(),
+            pool.get_dy(0, 1, amount_in, 2000).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_event_signatures() {
+        let signature = |event: &str| H256::from(keccak256(event));
+
+        assert_eq!(
+            super::LIQUIDITY_EVENT_SIGNATURES.to_vec(),
+            [2, 3, 4]
+                .iter()
+                .map(|n| format!("[{n}]"))
+                .chain(["[]".to_string()])
+                .flat_map(|array| [
+                    signature(&format!(
+                        "AddLiquidity(address,uint256{array},uint256{array},uint256,uint256)"
+                    )),
+                    signature(&format!(
+                        "RemoveLiquidity(address,uint256{array},uint256{array},uint256)"
+                    )),
+                    signature(&format!(
+                        "RemoveLiquidityImbalance(address,uint256{array},uint256{array},uint256,uint256)"
+                    )),
+                ])
+                .chain([
+                    signature("RemoveLiquidityOne(address,uint256,uint256)"),
+                    signature("RemoveLiquidityOne(address,uint256,uint256,uint256)"),
+                    signature("RemoveLiquidityOne(address,int128,uint256,uint256,uint256)"),
+                ])
+                .collect::<Vec<H256>>()
+        );
+        assert_eq!(
+            super::TOKEN_EXCHANGE_UNDERLYING_EVENT_SIGNATURE,
+            signature("TokenExchangeUnderlying(address,int128,uint256,int128,uint256)")
+        );
+        assert_eq!(
+            super::RAMP_A_EVENT_SIGNATURE,
+            signature("RampA(uint256,uint256,uint256,uint256)")
+        );
+        assert_eq!(
+            super::STOP_RAMP_A_EVENT_SIGNATURE,
+            signature("StopRampA(uint256,uint256)")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_liquidity_log_resyncs_pool() {
+        let (middleware, client) = mock_provider();
+        let mut pool = Pool::Curve(three_pool());
+
+        let log = |event_signature| Log {
+            address: H160::from_low_u64_be(1),
+            topics: vec![event_signature],
+            ..Default::default()
+        };
+
+        //Logs of other events do not change the pool
+        pool.update_pool_from_log(&log(H256::zero()), middleware.clone())
+            .await
+            .unwrap();
+        assert!(client.requests().is_empty());
+
+        //Every state changing log requests the pool state again, which fails since the mock provider has no responses
+        for event_signature in super::state_change_event_signatures() {
+            assert!(pool
+                .update_pool_from_log(&log(event_signature), middleware.clone())
+                .await
+                .is_err());
+        }
+        assert_eq!(
+            client.requests(),
+            vec!["eth_call"; super::state_change_event_signatures().len()]
+        );
+    }
+
+    //Builds 3pool from its state at block 17000000 and compares get_dy to the contract's get_dy at the same block
+    #[tokio::test]
+    async fn test_get_dy_matches_3pool() {
+        let rpc_endpoint = std::env::var("ETHEREUM_MAINNET_ENDPOINT")
+            .expect("Could not get ETHEREUM_MAINNET_ENDPOINT");
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint).unwrap());
+
+        let block = BlockId::from(17000000u64);
+        let address = H160::from_str("0xbEbc44782C7dB0a1A60Cb6fe97d0b483032FF1C7").unwrap();
+        let three_pool = ICurvePool::new(address, middleware.clone());
+
+        let (mut coins, mut balances) = (vec![], vec![]);
+        for i in 0..3 {
+            coins.push(
+                three_pool
+                    .coins(U256::from(i))
+                    .block(block)
+                    .call()
+                    .await
+                    .unwrap(),
+            );
+            balances.push(
+                three_pool
+                    .balances(U256::from(i))
+                    .block(block)
+                    .call()
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        //DAI, USDC and USDT
+        let decimals = vec![18, 6, 6];
+        let pool = CurvePool {
+            address,
+            coins,
+            rates: decimals.iter().map(|d| rate_from_decimals(*d)).collect(),
+            decimals,
+            balances,
+            fee: three_pool.fee().block(block).call().await.unwrap().as_u64(),
+            admin_fee: three_pool
+                .admin_fee()
+                .block(block)
+                .call()
+                .await
+                .unwrap()
+                .as_u64(),
+            initial_a: three_pool
+                .initial_a()
+                .block(block)
+                .call()
+                .await
+                .unwrap()
+                .as_u64(),
+            future_a: three_pool
+                .future_a()
+                .block(block)
+                .call()
+                .await
+                .unwrap()
+                .as_u64(),
+            initial_a_time: three_pool
+                .initial_a_time()
+                .block(block)
+                .call()
+                .await
+                .unwrap()
+                .as_u64(),
+            future_a_time: three_pool
+                .future_a_time()
+                .block(block)
+                .call()
+                .await
+                .unwrap()
+                .as_u64(),
+            //3pool stores A without extra precision
+            a_precision: 1,
+            timestamp: middleware
+                .get_block(block)
+                .await
+                .unwrap()
+                .unwrap()
+                .timestamp
+                .as_u64(),
+        };
+
+        for (i, j, dx) in [
+            (0, 1, U256::from(1_000_000u128) * U256::exp10(18)),
+            (1, 2, U256::from(50_000_000_000_000u128)),
+            (2, 0, U256::from(1_000_000_000u128)),
+        ] {
+            let expected_dy = three_pool
+                .get_dy(i as i128, j as i128, dx)
+                .block(block)
+                .call()
+                .await
+                .unwrap();
+
+            assert_eq!(pool.get_dy(i, j, dx, pool.timestamp).unwrap(), expected_dy);
+        }
+    }
+}
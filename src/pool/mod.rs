@@ -7,24 +7,37 @@ use ethers::{
 
 use crate::{
     dex::{self, DexVariant},
-    errors::{ArithmeticError, CFMMError},
+    errors::{ArithmeticError, CFMMError, RouteError},
+    route::Route,
 };
 
+pub mod balancer_v2;
+pub mod curve;
 pub mod fixed_point_math;
+pub mod solidly;
 pub mod uniswap_v2;
 pub mod uniswap_v3;
+pub mod weighted_math;
+pub use balancer_v2::BalancerV2Pool;
+pub use curve::CurvePool;
 use serde::{Deserialize, Serialize};
+pub use solidly::SolidlyPool;
 pub use uniswap_v2::UniswapV2Pool;
 pub use uniswap_v3::UniswapV3Pool;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
+//Pool is not Copy since UniswapV3 pools can hold a tick map and Curve and Balancer V2 pools hold their tokens in Vecs
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub enum Pool {
     UniswapV2(UniswapV2Pool),
     UniswapV3(UniswapV3Pool),
+    Solidly(SolidlyPool),
+    Curve(CurvePool),
+    BalancerV2(BalancerV2Pool),
 }
 
 impl Pool {
-    //Creates a new pool with all pool data populated from the pair address.
+    //Creates a new pool with all pool data populated from the pair address. Uniswap V2 pairs get the default fee,
+    //pairs of forks with other fees are created through their dex.
     pub async fn new_from_address<M: Middleware>(
         pair_address: H160,
         dex_variant: DexVariant,
@@ -32,12 +45,25 @@ impl Pool {
     ) -> Result<Self, CFMMError<M>> {
         match dex_variant {
             DexVariant::UniswapV2 => Ok(Pool::UniswapV2(
-                UniswapV2Pool::new_from_address(pair_address, middleware).await?,
+                UniswapV2Pool::new_from_address(pair_address, uniswap_v2::DEFAULT_FEE, middleware)
+                    .await?,
             )),
 
             DexVariant::UniswapV3 => Ok(Pool::UniswapV3(
                 UniswapV3Pool::new_from_address(pair_address, middleware).await?,
             )),
+
+            DexVariant::Solidly => Ok(Pool::Solidly(
+                SolidlyPool::new_from_address(pair_address, middleware).await?,
+            )),
+
+            DexVariant::Curve => Ok(Pool::Curve(
+                CurvePool::new_from_address(pair_address, middleware).await?,
+            )),
+
+            DexVariant::BalancerV2 => Ok(Pool::BalancerV2(
+                BalancerV2Pool::new_from_address(pair_address, middleware).await?,
+            )),
         }
     }
 
@@ -45,6 +71,9 @@ impl Pool {
         match self {
             Pool::UniswapV2(pool) => pool.fee(),
             Pool::UniswapV3(pool) => pool.fee(),
+            Pool::Solidly(pool) => pool.fee(),
+            Pool::Curve(pool) => pool.fee(),
+            Pool::BalancerV2(pool) => pool.fee(),
         }
     }
 
@@ -57,12 +86,24 @@ impl Pool {
 
         if event_signature == dex::uniswap_v2::PAIR_CREATED_EVENT_SIGNATURE {
             Ok(Pool::UniswapV2(
-                UniswapV2Pool::new_from_event_log(log, middleware).await?,
+                UniswapV2Pool::new_from_event_log(log, uniswap_v2::DEFAULT_FEE, middleware).await?,
             ))
         } else if event_signature == dex::uniswap_v3::POOL_CREATED_EVENT_SIGNATURE {
             Ok(Pool::UniswapV3(
                 UniswapV3Pool::new_from_event_log(log, middleware).await?,
             ))
+        } else if event_signature == dex::solidly::PAIR_CREATED_EVENT_SIGNATURE {
+            Ok(Pool::Solidly(
+                SolidlyPool::new_from_event_log(log, middleware).await?,
+            ))
+        } else if event_signature == dex::curve::POOL_ADDED_EVENT_SIGNATURE {
+            Ok(Pool::Curve(
+                CurvePool::new_from_event_log(log, middleware).await?,
+            ))
+        } else if event_signature == dex::balancer_v2::POOL_REGISTERED_EVENT_SIGNATURE {
+            Ok(Pool::BalancerV2(
+                BalancerV2Pool::new_from_event_log(log, middleware).await?,
+            ))
         } else {
             Err(CFMMError::UnrecognizedPoolCreatedEventLog)
         }
@@ -74,12 +115,22 @@ impl Pool {
 
         if event_signature == dex::uniswap_v2::PAIR_CREATED_EVENT_SIGNATURE {
             Ok(Pool::UniswapV2(
-                UniswapV2Pool::new_empty_pool_from_event_log(log)?,
+                UniswapV2Pool::new_empty_pool_from_event_log(log, uniswap_v2::DEFAULT_FEE)?,
             ))
         } else if event_signature == dex::uniswap_v3::POOL_CREATED_EVENT_SIGNATURE {
             Ok(Pool::UniswapV3(
                 UniswapV3Pool::new_empty_pool_from_event_log(log)?,
             ))
+        } else if event_signature == dex::solidly::PAIR_CREATED_EVENT_SIGNATURE {
+            Ok(Pool::Solidly(SolidlyPool::new_empty_pool_from_event_log(
+                log,
+            )?))
+        } else if event_signature == dex::curve::POOL_ADDED_EVENT_SIGNATURE {
+            Ok(Pool::Curve(CurvePool::new_empty_pool_from_event_log(log)?))
+        } else if event_signature == dex::balancer_v2::POOL_REGISTERED_EVENT_SIGNATURE {
+            Ok(Pool::BalancerV2(
+                BalancerV2Pool::new_empty_pool_from_event_log(log)?,
+            ))
         } else {
             Err(CFMMError::UnrecognizedPoolCreatedEventLog)
         }
@@ -92,6 +143,9 @@ impl Pool {
         match self {
             Pool::UniswapV2(pool) => pool.sync_pool(middleware).await,
             Pool::UniswapV3(pool) => pool.sync_pool(middleware).await,
+            Pool::Solidly(pool) => pool.sync_pool(middleware).await,
+            Pool::Curve(pool) => pool.sync_pool(middleware).await,
+            Pool::BalancerV2(pool) => pool.sync_pool(middleware).await,
         }
     }
 
@@ -100,6 +154,9 @@ impl Pool {
         match self {
             Pool::UniswapV2(pool) => pool.calculate_price(base_token),
             Pool::UniswapV3(pool) => Ok(pool.calculate_price(base_token)),
+            Pool::Solidly(pool) => Ok(pool.calculate_price(base_token)),
+            Pool::Curve(pool) => Ok(pool.calculate_price(base_token)),
+            Pool::BalancerV2(pool) => Ok(pool.calculate_price(base_token)),
         }
     }
 
@@ -110,6 +167,9 @@ impl Pool {
         match self {
             Pool::UniswapV2(pool) => pool.get_pool_data(middleware).await?,
             Pool::UniswapV3(pool) => pool.get_pool_data(middleware).await?,
+            Pool::Solidly(pool) => pool.get_pool_data(middleware).await?,
+            Pool::Curve(pool) => pool.get_pool_data(middleware).await?,
+            Pool::BalancerV2(pool) => pool.get_pool_data(middleware).await?,
         }
         Ok(())
     }
@@ -118,6 +178,41 @@ impl Pool {
         match self {
             Pool::UniswapV2(pool) => pool.address(),
             Pool::UniswapV3(pool) => pool.address(),
+            Pool::Solidly(pool) => pool.address(),
+            Pool::Curve(pool) => pool.address(),
+            Pool::BalancerV2(pool) => pool.address(),
+        }
+    }
+
+    //Returns an unpopulated copy of the pool that keeps only what is needed to request the pool data again,
+    //used to mark pools whose state could not be synced
+    pub fn without_state(&self) -> Pool {
+        match self {
+            Pool::UniswapV2(pool) => Pool::UniswapV2(UniswapV2Pool {
+                address: pool.address,
+                fee: pool.fee,
+                ..Default::default()
+            }),
+            Pool::UniswapV3(pool) => Pool::UniswapV3(UniswapV3Pool {
+                address: pool.address,
+                ..Default::default()
+            }),
+            Pool::Solidly(pool) => Pool::Solidly(SolidlyPool {
+                address: pool.address,
+                stable: pool.stable,
+                fee: pool.fee,
+                ..Default::default()
+            }),
+            Pool::Curve(pool) => Pool::Curve(CurvePool {
+                address: pool.address,
+                ..Default::default()
+            }),
+            Pool::BalancerV2(pool) => Pool::BalancerV2(BalancerV2Pool {
+                address: pool.address,
+                pool_id: pool.pool_id,
+                vault: pool.vault,
+                ..Default::default()
+            }),
         }
     }
 
@@ -128,8 +223,13 @@ impl Pool {
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
         match self {
-            Pool::UniswapV2(pool) => Ok(pool.simulate_swap(token_in, amount_in)),
+            Pool::UniswapV2(pool) => Ok(pool.simulate_swap(token_in, amount_in)?),
             Pool::UniswapV3(pool) => pool.simulate_swap(token_in, amount_in, middleware).await,
+            Pool::Solidly(pool) => pool.simulate_swap(token_in, amount_in),
+            Pool::Curve(pool) => pool.simulate_swap(token_in, pool.token_out(token_in), amount_in),
+            Pool::BalancerV2(pool) => {
+                pool.simulate_swap(token_in, pool.token_out(token_in), amount_in)
+            }
         }
     }
 
@@ -140,12 +240,252 @@ impl Pool {
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
         match self {
-            Pool::UniswapV2(pool) => Ok(pool.simulate_swap_mut(token_in, amount_in)),
+            Pool::UniswapV2(pool) => Ok(pool.simulate_swap_mut(token_in, amount_in)?),
             Pool::UniswapV3(pool) => {
                 pool.simulate_swap_mut(token_in, amount_in, middleware)
                     .await
             }
+            Pool::Solidly(pool) => pool.simulate_swap_mut(token_in, amount_in),
+            Pool::Curve(pool) => {
+                let token_out = pool.token_out(token_in);
+                pool.simulate_swap_mut(token_in, token_out, amount_in)
+            }
+            Pool::BalancerV2(pool) => {
+                let token_out = pool.token_out(token_in);
+                pool.simulate_swap_mut(token_in, token_out, amount_in)
+            }
+        }
+    }
+
+    //Updates the pool state from a state changing log emitted by the pool. Logs for other events are ignored.
+    pub async fn update_pool_from_log<M: Middleware>(
+        &mut self,
+        log: &Log,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        let event_signature = log.topics[0];
+
+        match self {
+            Pool::UniswapV2(pool) => {
+                if event_signature == uniswap_v2::SYNC_EVENT_SIGNATURE {
+                    pool.update_pool_from_sync_log(log);
+                }
+            }
+
+            Pool::UniswapV3(pool) => {
+                if event_signature == uniswap_v3::SWAP_EVENT_SIGNATURE {
+                    pool.update_pool_from_swap_log(log, middleware).await?;
+                } else if event_signature == uniswap_v3::MINT_EVENT_SIGNATURE {
+                    pool.update_pool_from_mint_log(log)?;
+                } else if event_signature == uniswap_v3::BURN_EVENT_SIGNATURE {
+                    pool.update_pool_from_burn_log(log)?;
+                }
+                //Collect only transfers owed tokens to the position owner and does not change the pool's liquidity or price
+            }
+
+            Pool::Solidly(pool) => {
+                if event_signature == solidly::SYNC_EVENT_SIGNATURE {
+                    pool.update_pool_from_sync_log(log);
+                }
+            }
+
+            //Exchanges and liquidity changes move the balances by amounts that depend on the fees and A at the time of the
+            //event, and A ramps are not fully described by their logs, so the pool is synced
+            Pool::Curve(pool) => {
+                if curve::state_change_event_signatures().contains(&event_signature) {
+                    pool.sync_pool(middleware).await?;
+                }
+            }
+
+            //Balancer V2 logs are emitted by the Vault for every pool, the pool id in the log is checked by the pool
+            Pool::BalancerV2(pool) => {
+                if event_signature == balancer_v2::SWAP_EVENT_SIGNATURE {
+                    pool.update_pool_from_swap_log(log)?;
+                } else if event_signature == balancer_v2::POOL_BALANCE_CHANGED_EVENT_SIGNATURE {
+                    pool.update_pool_from_pool_balance_changed_log(log)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    //Returns the amount of token_in needed to receive `amount_out` of the other token
+    pub async fn simulate_swap_exact_out<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_out: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        match self {
+            Pool::UniswapV2(pool) => pool.simulate_swap_exact_out(token_in, amount_out),
+            Pool::UniswapV3(pool) => {
+                pool.simulate_swap_exact_out(token_in, amount_out, middleware)
+                    .await
+            }
+            Pool::Solidly(pool) => pool.simulate_swap_exact_out(token_in, amount_out),
+            Pool::Curve(pool) => {
+                pool.simulate_swap_exact_out(token_in, pool.token_out(token_in), amount_out)
+            }
+            Pool::BalancerV2(pool) => {
+                pool.simulate_swap_exact_out(token_in, pool.token_out(token_in), amount_out)
+            }
+        }
+    }
+
+    pub async fn simulate_swap_exact_out_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        amount_out: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        match self {
+            Pool::UniswapV2(pool) => pool.simulate_swap_exact_out_mut(token_in, amount_out),
+            Pool::UniswapV3(pool) => {
+                pool.simulate_swap_exact_out_mut(token_in, amount_out, middleware)
+                    .await
+            }
+            Pool::Solidly(pool) => pool.simulate_swap_exact_out_mut(token_in, amount_out),
+            Pool::Curve(pool) => {
+                let token_out = pool.token_out(token_in);
+                pool.simulate_swap_exact_out_mut(token_in, token_out, amount_out)
+            }
+            Pool::BalancerV2(pool) => {
+                let token_out = pool.token_out(token_in);
+                pool.simulate_swap_exact_out_mut(token_in, token_out, amount_out)
+            }
+        }
+    }
+
+    //Returns all of the tokens that can be swapped in the pool
+    pub fn tokens(&self) -> Vec<H160> {
+        match self {
+            Pool::UniswapV2(pool) => vec![pool.token_a, pool.token_b],
+            Pool::UniswapV3(pool) => vec![pool.token_a, pool.token_b],
+            Pool::Solidly(pool) => vec![pool.token_a, pool.token_b],
+            Pool::Curve(pool) => pool.coins.clone(),
+            Pool::BalancerV2(pool) => pool.tokens.clone(),
+        }
+    }
+
+    //Returns an error if token_in and token_out are not two different tokens of the pool
+    pub fn check_tokens(&self, token_in: H160, token_out: H160) -> Result<(), RouteError> {
+        let tokens = self.tokens();
+
+        if token_in == token_out || !tokens.contains(&token_in) || !tokens.contains(&token_out) {
+            return Err(RouteError::TokenMismatch {
+                pool: self.address(),
+                token_in,
+                token_out,
+            });
+        }
+
+        Ok(())
+    }
+
+    //Simulates a swap to an explicit token out, which is needed to swap between any two tokens of pools with more than two tokens
+    pub async fn simulate_swap_to_token<M: Middleware>(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        amount_in: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        self.check_tokens(token_in, token_out)?;
+
+        match self {
+            Pool::Curve(pool) => pool.simulate_swap(token_in, token_out, amount_in),
+            Pool::BalancerV2(pool) => pool.simulate_swap(token_in, token_out, amount_in),
+            //Two token pools can only swap to the other token, which was checked above
+            _ => self.simulate_swap(token_in, amount_in, middleware).await,
+        }
+    }
+
+    pub async fn simulate_swap_to_token_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        token_out: H160,
+        amount_in: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        self.check_tokens(token_in, token_out)?;
+
+        match self {
+            Pool::Curve(pool) => pool.simulate_swap_mut(token_in, token_out, amount_in),
+            Pool::BalancerV2(pool) => pool.simulate_swap_mut(token_in, token_out, amount_in),
+            _ => {
+                self.simulate_swap_mut(token_in, amount_in, middleware)
+                    .await
+            }
+        }
+    }
+
+    //Returns the amount of token_in needed to receive `amount_out` of an explicit token out
+    pub async fn simulate_swap_exact_out_to_token<M: Middleware>(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        amount_out: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        self.check_tokens(token_in, token_out)?;
+
+        match self {
+            Pool::Curve(pool) => pool.simulate_swap_exact_out(token_in, token_out, amount_out),
+            Pool::BalancerV2(pool) => pool.simulate_swap_exact_out(token_in, token_out, amount_out),
+            _ => {
+                self.simulate_swap_exact_out(token_in, amount_out, middleware)
+                    .await
+            }
+        }
+    }
+
+    //Returns the other token in the pool
+    pub fn token_out(&self, token_in: H160) -> H160 {
+        match self {
+            Pool::UniswapV2(pool) => {
+                if token_in == pool.token_a {
+                    pool.token_b
+                } else {
+                    pool.token_a
+                }
+            }
+
+            Pool::UniswapV3(pool) => {
+                if token_in == pool.token_a {
+                    pool.token_b
+                } else {
+                    pool.token_a
+                }
+            }
+
+            Pool::Solidly(pool) => {
+                if token_in == pool.token_a {
+                    pool.token_b
+                } else {
+                    pool.token_a
+                }
+            }
+
+            Pool::Curve(pool) => pool.token_out(token_in),
+
+            Pool::BalancerV2(pool) => pool.token_out(token_in),
+        }
+    }
+}
+
+//Returns the address of the pool that a state changing log belongs to. Balancer V2 logs are emitted by the Vault and
+//identify the pool by its pool id, every other log is emitted by the pool itself.
+pub fn pool_address_from_log(log: &Log) -> H160 {
+    match log.topics.first() {
+        Some(event_signature)
+            if (*event_signature == balancer_v2::SWAP_EVENT_SIGNATURE
+                || *event_signature == balancer_v2::POOL_BALANCE_CHANGED_EVENT_SIGNATURE)
+                && log.topics.len() > 1 =>
+        {
+            balancer_v2::pool_address_from_pool_id(log.topics[1])
         }
+        _ => log.address,
     }
 }
 
@@ -176,76 +516,103 @@ pub fn convert_to_common_decimals(
     }
 }
 
+//Returns the amount of the route's token out received for `amount_in` of the route's token in.
+//The amounts of each hop are recorded in the route, the pools are not changed.
 pub async fn simulate_route<M: Middleware>(
-    mut token_in: H160,
-    mut amount_in: U256,
-    route: &[Pool],
+    amount_in: U256,
+    route: &mut Route,
     middleware: Arc<M>,
 ) -> Result<U256, CFMMError<M>> {
-    let mut amount_out = U256::zero();
-
-    for pool in route {
-        amount_out = pool
-            .simulate_swap(token_in, amount_in, middleware.clone())
-            .await?;
-
-        token_in = match pool {
-            Pool::UniswapV2(pool) => {
-                if token_in == pool.token_a {
-                    pool.token_b
-                } else {
-                    pool.token_a
-                }
-            }
-
-            Pool::UniswapV3(pool) => {
-                if token_in == pool.token_a {
-                    pool.token_b
-                } else {
-                    pool.token_a
-                }
-            }
-        };
-
-        amount_in = amount_out
-    }
+    route.simulate(amount_in, middleware).await
+}
 
-    Ok(amount_out)
+//Returns the amount of the route's token in needed to receive `amount_out` from the last hop in the route
+pub async fn simulate_route_exact_out<M: Middleware>(
+    amount_out: U256,
+    route: &mut Route,
+    middleware: Arc<M>,
+) -> Result<U256, CFMMError<M>> {
+    route.simulate_exact_out(amount_out, middleware).await
 }
 
+//Same as `simulate_route`, but the state of each pool in the route is updated by the swap
 pub async fn simulate_route_mut<M: Middleware>(
-    mut token_in: H160,
-    mut amount_in: U256,
-    route: &mut [Pool],
+    amount_in: U256,
+    route: &mut Route,
     middleware: Arc<M>,
 ) -> Result<U256, CFMMError<M>> {
-    let mut amount_out = U256::zero();
+    route.simulate_mut(amount_in, middleware).await
+}
 
-    for pool in route {
-        amount_out = pool
-            .simulate_swap_mut(token_in, amount_in, middleware.clone())
-            .await?;
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
 
-        token_in = match pool {
-            Pool::UniswapV2(pool) => {
-                if token_in == pool.token_a {
-                    pool.token_b
-                } else {
-                    pool.token_a
-                }
-            }
+    use ethers::{
+        providers::{Http, Provider},
+        types::{H160, U256},
+    };
 
-            Pool::UniswapV3(pool) => {
-                if token_in == pool.token_a {
-                    pool.token_b
-                } else {
-                    pool.token_a
-                }
-            }
-        };
+    use super::{simulate_route, simulate_route_exact_out, Pool, UniswapV2Pool};
+    use crate::{
+        errors::{CFMMError, RouteError},
+        route::Route,
+    };
 
-        amount_in = amount_out
-    }
+    #[tokio::test]
+    async fn test_simulate_route_exact_out() {
+        //Uniswap v2 pools do not make any calls to the node, so the provider is never used
+        let middleware = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+
+        let token_a = H160::from_low_u64_be(1);
+        let token_b = H160::from_low_u64_be(2);
+        let token_c = H160::from_low_u64_be(3);
+
+        let pools = [
+            Pool::UniswapV2(UniswapV2Pool {
+                token_a,
+                token_b,
+                reserve_0: 47092140895915,
+                reserve_1: 28396598565590008529300,
+                fee: 300,
+                ..Default::default()
+            }),
+            Pool::UniswapV2(UniswapV2Pool {
+                token_a: token_c,
+                token_b,
+                reserve_0: 1250000000000000000000000,
+                reserve_1: 318000000000000000000000000,
+                fee: 250,
+                ..Default::default()
+            }),
+        ];
 
-    Ok(amount_out)
+        let amount_out = U256::from(1000000000000000000u128);
+
+        let mut route = Route::new(vec![
+            (pools[0].clone(), token_a, token_b),
+            (pools[1].clone(), token_b, token_c),
+        ])
+        .unwrap();
+
+        let amount_in = simulate_route_exact_out(amount_out, &mut route, middleware.clone())
+            .await
+            .unwrap();
+
+        //Swapping the amount in through the route should receive at least the amount out
+        let amount_received = simulate_route(amount_in, &mut route, middleware.clone())
+            .await
+            .unwrap();
+
+        assert!(amount_received >= amount_out);
+        assert_eq!(pools[1].token_out(token_b), token_c);
+
+        //Route errors from a pool simulation are returned as CFMM errors
+        assert!(matches!(
+            pools[0]
+                .simulate_swap_to_token(token_a, token_c, amount_out, middleware)
+                .await,
+            Err(CFMMError::RouteError(RouteError::TokenMismatch { .. }))
+        ));
+    }
 }
@@ -0,0 +1,706 @@
+use std::sync::Arc;
+
+use ethers::{
+    abi::ParamType,
+    providers::Middleware,
+    types::{Log, H160, H256, U256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abi, batch_requests,
+    errors::{ArithmeticError, CFMMError},
+};
+
+pub const SYNC_EVENT_SIGNATURE: H256 = H256([
+    207, 42, 165, 8, 118, 205, 251, 181, 65, 32, 111, 137, 175, 14, 231, 141, 68, 162, 171, 248,
+    211, 40, 227, 127, 164, 145, 127, 152, 33, 73, 132, 138,
+]);
+
+//Fees use the same denomination as Uniswap V2 pools, ie. 300 is a 0.3% fee
+pub const FEE_DENOMINATOR: u32 = 100000;
+
+//Fees that are used when the factory does not report a fee for the pair
+pub const DEFAULT_STABLE_FEE: u32 = 50;
+pub const DEFAULT_VOLATILE_FEE: u32 = 300;
+
+//Max iterations used to solve the stable invariant for the reserve out, matching the pair contract
+const GET_Y_MAX_ITERATIONS: usize = 255;
+
+//Solidly style pair, which uses the x*y=k curve for volatile pairs and the x³y+y³x=k curve for stable pairs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct SolidlyPool {
+    pub address: H160,
+    pub token_a: H160,
+    pub token_a_decimals: u8,
+    pub token_b: H160,
+    pub token_b_decimals: u8,
+    pub stable: bool,
+    pub reserve_0: u128,
+    pub reserve_1: u128,
+    pub fee: u32,
+}
+
+impl SolidlyPool {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        address: H160,
+        token_a: H160,
+        token_a_decimals: u8,
+        token_b: H160,
+        token_b_decimals: u8,
+        stable: bool,
+        reserve_0: u128,
+        reserve_1: u128,
+        fee: u32,
+    ) -> SolidlyPool {
+        SolidlyPool {
+            address,
+            token_a,
+            token_a_decimals,
+            token_b,
+            token_b_decimals,
+            stable,
+            reserve_0,
+            reserve_1,
+            fee,
+        }
+    }
+
+    //Creates a new instance of the pool from the pair address, and syncs the pool data
+    pub async fn new_from_address<M: Middleware>(
+        pair_address: H160,
+        middleware: Arc<M>,
+    ) -> Result<Self, CFMMError<M>> {
+        let mut pool = SolidlyPool {
+            address: pair_address,
+            ..Default::default()
+        };
+
+        pool.get_pool_data(middleware).await?;
+
+        if !pool.data_is_populated() {
+            return Err(CFMMError::PoolDataError);
+        }
+
+        Ok(pool)
+    }
+
+    pub async fn new_from_event_log<M: Middleware>(
+        log: Log,
+        middleware: Arc<M>,
+    ) -> Result<Self, CFMMError<M>> {
+        let pool = SolidlyPool::new_empty_pool_from_event_log::<M>(log)?;
+        SolidlyPool::new_from_address(pool.address, middleware).await
+    }
+
+    //Decodes a `PairCreated(token0, token1, stable, pair, ...)` log into a pool without pool data.
+    //The tokens are left empty so that the pool is recognized as unpopulated until the pool data is fetched.
+    pub fn new_empty_pool_from_event_log<M: Middleware>(log: Log) -> Result<Self, CFMMError<M>> {
+        let tokens = ethers::abi::decode(
+            &[ParamType::Bool, ParamType::Address, ParamType::Uint(256)],
+            &log.data,
+        )?;
+        let stable = tokens[0].to_owned().into_bool().unwrap();
+
+        Ok(SolidlyPool {
+            address: tokens[1].to_owned().into_address().unwrap(),
+            stable,
+            fee: default_fee(stable),
+            ..Default::default()
+        })
+    }
+
+    pub fn fee(&self) -> u32 {
+        self.fee
+    }
+
+    pub fn address(&self) -> H160 {
+        self.address
+    }
+
+    //Gets the tokens, decimals, reserves and stability from the pair and the fee from the factory that created it
+    pub async fn get_pool_data<M: Middleware>(
+        &mut self,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        let pair = abi::ISolidlyPair::new(self.address, middleware.clone());
+        let factory = pair.factory().call().await?;
+
+        //The fee is requested for the pair's stability, so it has to be known before the batch request
+        self.stable = pair.stable().call().await?;
+        if self.fee == 0 {
+            self.fee = default_fee(self.stable);
+        }
+
+        let mut pools = [super::Pool::Solidly(*self)];
+        batch_requests::solidly::get_pool_data_batch_request(factory, &mut pools, middleware)
+            .await?;
+
+        if let super::Pool::Solidly(pool) = pools[0] {
+            *self = pool;
+        }
+
+        Ok(())
+    }
+
+    pub fn data_is_populated(&self) -> bool {
+        !(self.token_a.is_zero()
+            || self.token_b.is_zero()
+            || self.reserve_0 == 0
+            || self.reserve_1 == 0)
+    }
+
+    pub async fn get_reserves<M: Middleware>(
+        &self,
+        middleware: Arc<M>,
+    ) -> Result<(u128, u128), CFMMError<M>> {
+        let pair = abi::ISolidlyPair::new(self.address, middleware);
+        let (reserve_0, reserve_1, _) = pair.get_reserves().call().await?;
+
+        Ok((reserve_0.as_u128(), reserve_1.as_u128()))
+    }
+
+    pub async fn sync_pool<M: Middleware>(
+        &mut self,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        (self.reserve_0, self.reserve_1) = self.get_reserves(middleware).await?;
+
+        Ok(())
+    }
+
+    pub fn update_pool_from_sync_log(&mut self, sync_log: &Log) {
+        (self.reserve_0, self.reserve_1) = self.decode_sync_log(sync_log);
+    }
+
+    //Returns reserve0, reserve1
+    pub fn decode_sync_log(&self, sync_log: &Log) -> (u128, u128) {
+        let data = ethers::abi::decode(
+            &[ParamType::Uint(256), ParamType::Uint(256)],
+            &sync_log.data,
+        )
+        .expect("Could not get log data");
+
+        (
+            data[0]
+                .to_owned()
+                .into_uint()
+                .expect("Could not convert reserve0 in to uint")
+                .as_u128(),
+            data[1]
+                .to_owned()
+                .into_uint()
+                .expect("Could not convert reserve1 in to uint")
+                .as_u128(),
+        )
+    }
+
+    //Calculates the spot price of the base token in the quote token, which for stable pairs is the slope of the stable curve
+    pub fn calculate_price(&self, base_token: H160) -> f64 {
+        let x = self.reserve_0 as f64 / 10f64.powi(self.token_a_decimals as i32);
+        let y = self.reserve_1 as f64 / 10f64.powi(self.token_b_decimals as i32);
+
+        let price_a = if self.stable {
+            (3.0 * x * x * y + y * y * y) / (x * x * x + 3.0 * x * y * y)
+        } else {
+            y / x
+        };
+
+        if base_token == self.token_a {
+            price_a
+        } else {
+            1.0 / price_a
+        }
+    }
+
+    pub fn simulate_swap<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        Ok(self.get_amount_out(amount_in, token_in)?)
+    }
+
+    pub fn simulate_swap_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let amount_out = self.get_amount_out(amount_in, token_in)?;
+
+        self.update_reserves(token_in, amount_in, amount_out)?;
+
+        Ok(amount_out)
+    }
+
+    //Returns the amount of token_in needed to receive `amount_out` of the other token
+    pub fn simulate_swap_exact_out<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        self.get_amount_in(amount_out, token_in)
+    }
+
+    pub fn simulate_swap_exact_out_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let amount_in = self.simulate_swap_exact_out(token_in, amount_out)?;
+
+        self.update_reserves(token_in, amount_in, amount_out)?;
+
+        Ok(amount_in)
+    }
+
+    //Adds the amount in to the reserve in and subtracts the amount out from the reserve out.
+    //The reserves are left unchanged if the reserve in would not fit in a u128.
+    fn update_reserves<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        amount_in: U256,
+        amount_out: U256,
+    ) -> Result<(), CFMMError<M>> {
+        let (reserve_in, reserve_out) = if self.token_a == token_in {
+            (self.reserve_0, self.reserve_1)
+        } else {
+            (self.reserve_1, self.reserve_0)
+        };
+
+        let reserve_in = u128::try_from(amount_in)
+            .ok()
+            .and_then(|amount_in| reserve_in.checked_add(amount_in))
+            .ok_or(ArithmeticError::ReserveOverflow)?;
+        let reserve_out = u128::try_from(amount_out)
+            .ok()
+            .and_then(|amount_out| reserve_out.checked_sub(amount_out))
+            .ok_or(CFMMError::InsufficientLiquidity)?;
+
+        if self.token_a == token_in {
+            (self.reserve_0, self.reserve_1) = (reserve_in, reserve_out);
+        } else {
+            (self.reserve_1, self.reserve_0) = (reserve_in, reserve_out);
+        }
+
+        Ok(())
+    }
+
+    //Mirrors `getAmountOut` of the pair contract, the fee is taken from the amount in before the curve is applied
+    pub fn get_amount_out(&self, amount_in: U256, token_in: H160) -> Result<U256, ArithmeticError> {
+        self.fee_complement()?;
+        let (reserve_in, reserve_out, decimals_in, decimals_out) = self.reserves_for(token_in);
+
+        if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+            return Ok(U256::zero());
+        }
+
+        let amount_in =
+            amount_in - mul(amount_in, U256::from(self.fee))? / U256::from(FEE_DENOMINATOR);
+
+        if self.stable {
+            let xy = self.k()?;
+            let reserve_in = to_wad(reserve_in, decimals_in)?;
+            let reserve_out = to_wad(reserve_out, decimals_out)?;
+            let amount_in = to_wad(amount_in, decimals_in)?;
+
+            let y =
+                reserve_out.saturating_sub(get_y(add(amount_in, reserve_in)?, xy, reserve_out)?);
+            from_wad(y, decimals_out)
+        } else {
+            Ok(mul(amount_in, reserve_out)? / add(reserve_in, amount_in)?)
+        }
+    }
+
+    //Returns an amount in that receives at least `amount_out`
+    pub fn get_amount_in<M: Middleware>(
+        &self,
+        amount_out: U256,
+        token_in: H160,
+    ) -> Result<U256, CFMMError<M>> {
+        let fee_complement = self.fee_complement()?;
+        let (reserve_in, reserve_out, decimals_in, decimals_out) = self.reserves_for(token_in);
+
+        if amount_out.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+            return Ok(U256::zero());
+        }
+
+        //The pool can never give out its entire reserve, and a fee of 100% can never receive an amount out
+        if amount_out >= reserve_out || fee_complement.is_zero() {
+            return Err(CFMMError::InsufficientLiquidity);
+        }
+
+        let amount_in_after_fee = if self.stable {
+            let xy = self.k()?;
+            let reserve_in = to_wad(reserve_in, decimals_in)?;
+            let reserve_out = to_wad(reserve_out - amount_out, decimals_out)?;
+
+            //The invariant is symmetric, so the reserve in is solved the same way as the reserve out
+            let x = get_y(reserve_out, xy, reserve_in)?;
+            add(
+                from_wad(x.saturating_sub(reserve_in), decimals_in)?,
+                U256::one(),
+            )?
+        } else {
+            add(
+                mul(reserve_in, amount_out)? / (reserve_out - amount_out),
+                U256::one(),
+            )?
+        };
+
+        let mut amount_in = add(
+            mul(amount_in_after_fee, U256::from(FEE_DENOMINATOR))? / fee_complement,
+            U256::one(),
+        )?;
+
+        //The stable curve is solved iteratively and rounds down, so the amount in is increased until it covers the amount out
+        let mut increment = U256::one();
+        while self.get_amount_out(amount_in, token_in)? < amount_out {
+            amount_in = add(amount_in, increment)?;
+            increment = mul(increment, U256::from(2))?;
+        }
+
+        Ok(amount_in)
+    }
+
+    //The fee is set from the factory's `getFee` or a checkpoint, so it is checked before every swap
+    fn fee_complement(&self) -> Result<U256, ArithmeticError> {
+        FEE_DENOMINATOR
+            .checked_sub(self.fee)
+            .map(U256::from)
+            .ok_or(ArithmeticError::FeeOutOfBounds(self.fee))
+    }
+
+    //Stable invariant x³y+y³x of the reserves, normalized to 18 decimals
+    pub fn k(&self) -> Result<U256, ArithmeticError> {
+        let x = to_wad(U256::from(self.reserve_0), self.token_a_decimals)?;
+        let y = to_wad(U256::from(self.reserve_1), self.token_b_decimals)?;
+        let wad = wad();
+
+        let a = mul(x, y)? / wad;
+        let b = add(mul(x, x)? / wad, mul(y, y)? / wad)?;
+        Ok(mul(a, b)? / wad)
+    }
+
+    fn reserves_for(&self, token_in: H160) -> (U256, U256, u8, u8) {
+        if self.token_a == token_in {
+            (
+                U256::from(self.reserve_0),
+                U256::from(self.reserve_1),
+                self.token_a_decimals,
+                self.token_b_decimals,
+            )
+        } else {
+            (
+                U256::from(self.reserve_1),
+                U256::from(self.reserve_0),
+                self.token_b_decimals,
+                self.token_a_decimals,
+            )
+        }
+    }
+}
+
+pub fn default_fee(stable: bool) -> u32 {
+    if stable {
+        DEFAULT_STABLE_FEE
+    } else {
+        DEFAULT_VOLATILE_FEE
+    }
+}
+
+fn wad() -> U256 {
+    U256::exp10(18)
+}
+
+fn to_wad(amount: U256, decimals: u8) -> Result<U256, ArithmeticError> {
+    Ok(mul(amount, wad())? / U256::exp10(decimals as usize))
+}
+
+fn from_wad(amount: U256, decimals: u8) -> Result<U256, ArithmeticError> {
+    Ok(mul(amount, U256::exp10(decimals as usize))? / wad())
+}
+
+fn f(x0: U256, y: U256) -> Result<U256, ArithmeticError> {
+    let wad = wad();
+    let y3 = mul(mul(y, y)? / wad, y)? / wad;
+    let x3 = mul(mul(x0, x0)? / wad, x0)? / wad;
+    add(mul(x0, y3)? / wad, mul(x3, y)? / wad)
+}
+
+fn d(x0: U256, y: U256) -> Result<U256, ArithmeticError> {
+    let wad = wad();
+    let x3 = mul(mul(x0, x0)? / wad, x0)? / wad;
+    add(mul(mul(U256::from(3), x0)?, mul(y, y)? / wad)? / wad, x3)
+}
+
+//Solves the stable invariant for the reserve out with Newton's method, starting from the current reserve out
+fn get_y(x0: U256, xy: U256, mut y: U256) -> Result<U256, ArithmeticError> {
+    let wad = wad();
+
+    for _ in 0..GET_Y_MAX_ITERATIONS {
+        let y_prev = y;
+        let k = f(x0, y)?;
+        let derivative = d(x0, y)?;
+
+        if derivative.is_zero() {
+            return Ok(y);
+        }
+
+        if k < xy {
+            y = add(y, mul(xy - k, wad)? / derivative)?;
+        } else {
+            y -= (mul(k - xy, wad)? / derivative).min(y);
+        }
+
+        let difference = if y > y_prev { y - y_prev } else { y_prev - y };
+        if difference <= U256::one() {
+            return Ok(y);
+        }
+    }
+
+    Ok(y)
+}
+
+fn add(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    a.checked_add(b).ok_or(ArithmeticError::Overflow)
+}
+
+fn mul(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    a.checked_mul(b).ok_or(ArithmeticError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        providers::{Http, Provider},
+        types::{Bytes, Log, H160, H256, U256},
+    };
+
+    use super::SolidlyPool;
+    use crate::{
+        dex::solidly::PAIR_CREATED_EVENT_SIGNATURE,
+        errors::{ArithmeticError, CFMMError},
+    };
+
+    fn stable_pool() -> SolidlyPool {
+        SolidlyPool {
+            token_a: H160::from_low_u64_be(1),
+            token_a_decimals: 6,
+            token_b: H160::from_low_u64_be(2),
+            token_b_decimals: 18,
+            stable: true,
+            reserve_0: 1_000_000_000_000,
+            reserve_1: 1_000_000_000_000_000_000_000_000,
+            fee: 50,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_stable_get_amount_out() {
+        let pool = stable_pool();
+
+        //A balanced stable pair trades close to one to one, unlike the constant product curve
+        let amount_out = pool
+            .get_amount_out(U256::from(10_000_000_000u128), pool.token_a)
+            .unwrap();
+        let expected_amount_out = U256::from(10_000u128) * U256::exp10(18);
+
+        assert!(amount_out < expected_amount_out);
+        assert!(amount_out > expected_amount_out * 9990 / 10000);
+
+        let volatile_pool = SolidlyPool {
+            stable: false,
+            ..pool
+        };
+        assert!(
+            volatile_pool
+                .get_amount_out(U256::from(10_000_000_000u128), pool.token_a)
+                .unwrap()
+                < amount_out
+        );
+    }
+
+    #[test]
+    fn test_stable_swap_keeps_invariant() {
+        let mut pool = stable_pool();
+        let k = pool.k().unwrap();
+
+        pool.simulate_swap_mut::<Provider<Http>>(pool.token_b, U256::exp10(21))
+            .unwrap();
+
+        //The fee stays in the pool, so the invariant can only grow
+        assert!(pool.k().unwrap() >= k);
+    }
+
+    #[test]
+    fn test_volatile_get_amount_out() {
+        let pool = SolidlyPool {
+            stable: false,
+            fee: 200,
+            ..stable_pool()
+        };
+
+        let amount_in = U256::from(1_000_000u128);
+        let amount_in_after_fee = amount_in - amount_in * 200 / 100000;
+        let expected_amount_out = amount_in_after_fee * U256::from(pool.reserve_1)
+            / (U256::from(pool.reserve_0) + amount_in_after_fee);
+
+        assert_eq!(
+            pool.get_amount_out(amount_in, pool.token_a).unwrap(),
+            expected_amount_out
+        );
+    }
+
+    #[test]
+    fn test_simulate_swap_exact_out() {
+        for stable in [true, false] {
+            let pool = SolidlyPool {
+                stable,
+                ..stable_pool()
+            };
+
+            let amount_out = U256::from(5_000u128) * U256::exp10(18);
+            let amount_in = pool
+                .simulate_swap_exact_out::<Provider<Http>>(pool.token_a, amount_out)
+                .unwrap();
+
+            assert!(
+                pool.simulate_swap::<Provider<Http>>(pool.token_a, amount_in)
+                    .unwrap()
+                    >= amount_out
+            );
+            //The amount in is within a basis point of the smallest amount in
+            assert!(
+                pool.simulate_swap::<Provider<Http>>(pool.token_a, amount_in * 9999 / 10000)
+                    .unwrap()
+                    < amount_out
+            );
+
+            assert!(pool
+                .simulate_swap_exact_out::<Provider<Http>>(pool.token_a, U256::from(pool.reserve_1))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_fee_out_of_bounds() {
+        for stable in [true, false] {
+            let mut pool = SolidlyPool {
+                stable,
+                fee: 100001,
+                ..stable_pool()
+            };
+
+            assert!(matches!(
+                pool.simulate_swap::<Provider<Http>>(pool.token_a, U256::exp10(6)),
+                Err(CFMMError::ArithmeticError(ArithmeticError::FeeOutOfBounds(
+                    100001
+                )))
+            ));
+            assert!(matches!(
+                pool.simulate_swap_exact_out::<Provider<Http>>(pool.token_a, U256::exp10(18)),
+                Err(CFMMError::ArithmeticError(ArithmeticError::FeeOutOfBounds(
+                    100001
+                )))
+            ));
+            assert!(pool
+                .simulate_swap_mut::<Provider<Http>>(pool.token_a, U256::exp10(6))
+                .is_err());
+            assert_eq!(pool.reserve_0, 1_000_000_000_000);
+        }
+    }
+
+    #[test]
+    fn test_simulate_swap_overflow() {
+        for stable in [true, false] {
+            let pool = SolidlyPool {
+                stable,
+                ..stable_pool()
+            };
+
+            for amount_in in [U256::exp10(60), U256::MAX] {
+                assert!(matches!(
+                    pool.simulate_swap::<Provider<Http>>(pool.token_a, amount_in),
+                    Err(CFMMError::ArithmeticError(ArithmeticError::Overflow))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_simulate_swap_exact_out_with_full_fee() {
+        for stable in [true, false] {
+            let pool = SolidlyPool {
+                stable,
+                fee: 100000,
+                ..stable_pool()
+            };
+
+            //The whole amount in is taken as a fee, so no amount in can receive an amount out
+            assert!(matches!(
+                pool.simulate_swap_exact_out::<Provider<Http>>(pool.token_a, U256::exp10(18)),
+                Err(CFMMError::InsufficientLiquidity)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_simulate_swap_exact_out_mut_overflow() {
+        let mut pool = SolidlyPool {
+            stable: false,
+            reserve_0: u128::MAX / 2,
+            reserve_1: 1000,
+            ..stable_pool()
+        };
+
+        //Receiving all but one wei of the reserve out needs more token in than fits in the reserve
+        assert!(matches!(
+            pool.simulate_swap_exact_out_mut::<Provider<Http>>(pool.token_a, U256::from(999)),
+            Err(CFMMError::ArithmeticError(ArithmeticError::ReserveOverflow))
+        ));
+        assert_eq!(pool.reserve_0, u128::MAX / 2);
+        assert_eq!(pool.reserve_1, 1000);
+
+        let mut pool = SolidlyPool {
+            reserve_0: 1 << 100,
+            ..pool
+        };
+
+        assert!(matches!(
+            pool.simulate_swap_mut::<Provider<Http>>(pool.token_a, U256::from(u128::MAX)),
+            Err(CFMMError::ArithmeticError(ArithmeticError::ReserveOverflow))
+        ));
+        assert_eq!(pool.reserve_0, 1 << 100);
+    }
+
+    #[test]
+    fn test_new_empty_pool_from_event_log() {
+        let token_0 = H160::from_low_u64_be(1);
+        let token_1 = H160::from_low_u64_be(2);
+        let pair = H160::from_low_u64_be(3);
+
+        let log = Log {
+            topics: vec![
+                PAIR_CREATED_EVENT_SIGNATURE,
+                H256::from(token_0),
+                H256::from(token_1),
+            ],
+            data: Bytes::from(ethers::abi::encode(&[
+                ethers::abi::Token::Bool(true),
+                ethers::abi::Token::Address(pair),
+                ethers::abi::Token::Uint(U256::one()),
+            ])),
+            ..Default::default()
+        };
+
+        let pool = SolidlyPool::new_empty_pool_from_event_log::<Provider<Http>>(log).unwrap();
+
+        assert_eq!(pool.address, pair);
+        assert!(pool.token_a.is_zero());
+        assert!(pool.stable);
+        assert_eq!(pool.fee, super::DEFAULT_STABLE_FEE);
+    }
+}
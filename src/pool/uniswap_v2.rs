@@ -19,6 +19,11 @@ pub const SYNC_EVENT_SIGNATURE: H256 = H256([
     199, 139, 229, 14, 6, 43, 3, 169, 255, 251, 186, 209,
 ]);
 
+pub const FEE_DENOMINATOR: u32 = 100000;
+
+//Fee of Uniswap V2 pairs, which is used for pairs that are not created through a dex with a configured fee
+pub const DEFAULT_FEE: u32 = 300;
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct UniswapV2Pool {
     pub address: H160,
@@ -58,6 +63,7 @@ impl UniswapV2Pool {
     //Creates a new instance of the pool from the pair address, and syncs the pool data
     pub async fn new_from_address<M: Middleware>(
         pair_address: H160,
+        fee: u32,
         middleware: Arc<M>,
     ) -> Result<Self, CFMMError<M>> {
         let mut pool = UniswapV2Pool {
@@ -68,7 +74,7 @@ impl UniswapV2Pool {
             token_b_decimals: 0,
             reserve_0: 0,
             reserve_1: 0,
-            fee: 300,
+            fee,
         };
 
         pool.get_pool_data(middleware.clone()).await?;
@@ -81,14 +87,18 @@ impl UniswapV2Pool {
     }
     pub async fn new_from_event_log<M: Middleware>(
         log: Log,
+        fee: u32,
         middleware: Arc<M>,
     ) -> Result<Self, CFMMError<M>> {
         let tokens = ethers::abi::decode(&[ParamType::Address, ParamType::Uint(256)], &log.data)?;
         let pair_address = tokens[0].to_owned().into_address().unwrap();
-        UniswapV2Pool::new_from_address(pair_address, middleware).await
+        UniswapV2Pool::new_from_address(pair_address, fee, middleware).await
     }
 
-    pub fn new_empty_pool_from_event_log<M: Middleware>(log: Log) -> Result<Self, CFMMError<M>> {
+    pub fn new_empty_pool_from_event_log<M: Middleware>(
+        log: Log,
+        fee: u32,
+    ) -> Result<Self, CFMMError<M>> {
         let tokens = ethers::abi::decode(&[ParamType::Address, ParamType::Uint(256)], &log.data)?;
         let token_a = H160::from(log.topics[0]);
         let token_b = H160::from(log.topics[1]);
@@ -102,7 +112,7 @@ impl UniswapV2Pool {
             token_b_decimals: 0,
             reserve_0: 0,
             reserve_1: 0,
-            fee: 300,
+            fee,
         })
     }
 
@@ -261,7 +271,7 @@ impl UniswapV2Pool {
         )
     }
 
-    pub fn simulate_swap(&self, token_in: H160, amount_in: U256) -> U256 {
+    pub fn simulate_swap(&self, token_in: H160, amount_in: U256) -> Result<U256, ArithmeticError> {
         if self.token_a == token_in {
             self.get_amount_out(
                 amount_in,
@@ -277,42 +287,163 @@ impl UniswapV2Pool {
         }
     }
 
-    pub fn simulate_swap_mut(&mut self, token_in: H160, amount_in: U256) -> U256 {
+    pub fn simulate_swap_mut(
+        &mut self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<U256, ArithmeticError> {
         if self.token_a == token_in {
             let amount_out = self.get_amount_out(
                 amount_in,
                 U256::from(self.reserve_0),
                 U256::from(self.reserve_1),
-            );
+            )?;
 
-            self.reserve_0 += amount_in.as_u128();
-            self.reserve_1 -= amount_out.as_u128();
+            self.update_reserves(token_in, amount_in, amount_out)?;
 
-            amount_out
+            Ok(amount_out)
         } else {
             let amount_out = self.get_amount_out(
                 amount_in,
                 U256::from(self.reserve_1),
                 U256::from(self.reserve_0),
-            );
+            )?;
 
-            self.reserve_0 -= amount_out.as_u128();
-            self.reserve_1 += amount_in.as_u128();
+            self.update_reserves(token_in, amount_in, amount_out)?;
 
-            amount_out
+            Ok(amount_out)
         }
     }
 
-    pub fn get_amount_out(&self, amount_in: U256, reserve_in: U256, reserve_out: U256) -> U256 {
+    //Returns the amount of token_in needed to receive `amount_out` of the other token
+    pub fn simulate_swap_exact_out<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let (reserve_in, reserve_out) = if self.token_a == token_in {
+            (self.reserve_0, self.reserve_1)
+        } else {
+            (self.reserve_1, self.reserve_0)
+        };
+
+        if amount_out >= U256::from(reserve_out) {
+            return Err(CFMMError::InsufficientLiquidity);
+        }
+
+        Ok(self.get_amount_in(amount_out, U256::from(reserve_in), U256::from(reserve_out))?)
+    }
+
+    pub fn simulate_swap_exact_out_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let amount_in = self.simulate_swap_exact_out(token_in, amount_out)?;
+
+        self.update_reserves(token_in, amount_in, amount_out)?;
+
+        Ok(amount_in)
+    }
+
+    //Adds the amount in to the reserve in and subtracts the amount out from the reserve out.
+    //The reserves are left unchanged if either reserve would not fit in the pair's uint112 reserves.
+    fn update_reserves(
+        &mut self,
+        token_in: H160,
+        amount_in: U256,
+        amount_out: U256,
+    ) -> Result<(), ArithmeticError> {
+        let (reserve_in, reserve_out) = if self.token_a == token_in {
+            (self.reserve_0, self.reserve_1)
+        } else {
+            (self.reserve_1, self.reserve_0)
+        };
+
+        let reserve_in = u128::try_from(amount_in)
+            .ok()
+            .and_then(|amount_in| reserve_in.checked_add(amount_in))
+            .filter(|reserve_in| *reserve_in < 1 << 112)
+            .ok_or(ArithmeticError::ReserveOverflow)?;
+        //Swaps never take out the whole reserve out, so this only fails if the amount out was not quoted by the pool
+        let reserve_out = u128::try_from(amount_out)
+            .ok()
+            .and_then(|amount_out| reserve_out.checked_sub(amount_out))
+            .ok_or(ArithmeticError::Overflow)?;
+
+        if self.token_a == token_in {
+            (self.reserve_0, self.reserve_1) = (reserve_in, reserve_out);
+        } else {
+            (self.reserve_1, self.reserve_0) = (reserve_in, reserve_out);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_amount_in(
+        &self,
+        amount_out: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+    ) -> Result<U256, ArithmeticError> {
+        let fee_complement = self.fee_complement()?;
+
+        if amount_out.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+            return Ok(U256::zero());
+        }
+
+        //The pool can never give out its entire reserve
+        if amount_out >= reserve_out {
+            return Ok(U256::MAX);
+        }
+
+        //A fee of 100% can never receive an amount out
+        if fee_complement.is_zero() {
+            return Ok(U256::MAX);
+        }
+
+        let numerator = reserve_in
+            .checked_mul(amount_out)
+            .and_then(|product| product.checked_mul(U256::from(FEE_DENOMINATOR)))
+            .ok_or(ArithmeticError::Overflow)?;
+        let denominator = (reserve_out - amount_out) * fee_complement;
+
+        Ok(numerator / denominator + 1)
+    }
+
+    pub fn get_amount_out(
+        &self,
+        amount_in: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+    ) -> Result<U256, ArithmeticError> {
+        let fee_complement = self.fee_complement()?;
+
         if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
-            return U256::zero();
+            return Ok(U256::zero());
         }
 
-        let amount_in_with_fee = amount_in * U256::from(997);
-        let numerator = amount_in_with_fee * reserve_out;
-        let denominator = reserve_in * U256::from(1000) + amount_in_with_fee;
+        //The fee is denominated in hundredths of a basis point, ie. 300 is a 0.3% fee
+        let amount_in_with_fee = amount_in
+            .checked_mul(fee_complement)
+            .ok_or(ArithmeticError::Overflow)?;
+        let numerator = amount_in_with_fee
+            .checked_mul(reserve_out)
+            .ok_or(ArithmeticError::Overflow)?;
+        let denominator = reserve_in
+            .checked_mul(U256::from(FEE_DENOMINATOR))
+            .and_then(|product| product.checked_add(amount_in_with_fee))
+            .ok_or(ArithmeticError::Overflow)?;
+
+        Ok(numerator / denominator)
+    }
 
-        numerator / denominator
+    //The fee is a public field that can be set from any dex config or checkpoint, so it is checked before every swap
+    fn fee_complement(&self) -> Result<U256, ArithmeticError> {
+        FEE_DENOMINATOR
+            .checked_sub(self.fee)
+            .map(U256::from)
+            .ok_or(ArithmeticError::FeeOutOfBounds(self.fee))
     }
 
     pub fn swap_calldata(
@@ -343,10 +474,14 @@ mod tests {
 
     use ethers::{
         providers::{Http, Provider},
-        types::{H160, U256},
+        types::{BlockId, H160, U256},
     };
 
     use super::UniswapV2Pool;
+    use crate::{
+        abi,
+        errors::{ArithmeticError, CFMMError},
+    };
 
     #[test]
     fn test_swap_calldata() {
@@ -360,6 +495,331 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_amount_in_with_fee() {
+        let pool = UniswapV2Pool {
+            fee: 250,
+            ..Default::default()
+        };
+
+        for (reserve_in, reserve_out, amount_out) in [
+            (
+                1250000000000000000000000u128,
+                318000000000000000000000000u128,
+                253763797496489597801u128,
+            ),
+            (
+                318000000000000000000000000,
+                1250000000000000000000000,
+                98017077670206286889,
+            ),
+            (5000000000, 7000000000000000000000, 168263146888044259294),
+        ] {
+            let (reserve_in, reserve_out, amount_out) = (
+                U256::from(reserve_in),
+                U256::from(reserve_out),
+                U256::from(amount_out),
+            );
+
+            let amount_in = pool
+                .get_amount_in(amount_out, reserve_in, reserve_out)
+                .unwrap();
+
+            //The amount in is enough to receive the amount out, and is at most one wei more than needed
+            assert!(
+                pool.get_amount_out(amount_in, reserve_in, reserve_out)
+                    .unwrap()
+                    >= amount_out
+            );
+            assert!(
+                pool.get_amount_out(amount_in - 2, reserve_in, reserve_out)
+                    .unwrap()
+                    < amount_out
+            );
+        }
+    }
+
+    #[test]
+    fn test_fee_out_of_bounds() {
+        let mut pool = UniswapV2Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            reserve_0: 47092140895915,
+            reserve_1: 28396598565590008529300,
+            fee: 100001,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            pool.simulate_swap(pool.token_a, U256::exp10(18)),
+            Err(ArithmeticError::FeeOutOfBounds(100001))
+        ));
+        assert!(pool
+            .simulate_swap_exact_out::<Provider<Http>>(pool.token_a, U256::exp10(18))
+            .is_err());
+        assert!(pool
+            .simulate_swap_mut(pool.token_a, U256::exp10(18))
+            .is_err());
+        assert_eq!(pool.reserve_0, 47092140895915);
+    }
+
+    #[test]
+    fn test_simulate_swap_exact_out_mut() {
+        let mut pool = UniswapV2Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            reserve_0: 47092140895915,
+            reserve_1: 28396598565590008529300,
+            fee: 300,
+            ..Default::default()
+        };
+
+        let amount_out = U256::from(1000000000000000000u128);
+        let amount_in = pool
+            .simulate_swap_exact_out::<Provider<Http>>(pool.token_a, amount_out)
+            .unwrap();
+
+        //The amount in should be the smallest amount that receives at least the amount out
+        assert!(pool.simulate_swap(pool.token_a, amount_in).unwrap() >= amount_out);
+        assert!(pool.simulate_swap(pool.token_a, amount_in - 1).unwrap() < amount_out);
+
+        pool.simulate_swap_exact_out_mut::<Provider<Http>>(pool.token_a, amount_out)
+            .unwrap();
+
+        assert_eq!(pool.reserve_0, 47092140895915 + amount_in.as_u128());
+        assert_eq!(
+            pool.reserve_1,
+            28396598565590008529300 - amount_out.as_u128()
+        );
+
+        //The pool can not give out more than its reserves
+        assert!(pool
+            .simulate_swap_exact_out::<Provider<Http>>(pool.token_a, U256::from(pool.reserve_1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_simulate_swap_exact_out_mut_overflow() {
+        let mut pool = UniswapV2Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            reserve_0: u128::MAX / 2,
+            reserve_1: 1000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        //Receiving all but one wei of the reserve out needs more than a uint112 of token in
+        assert!(matches!(
+            pool.simulate_swap_exact_out_mut::<Provider<Http>>(pool.token_a, U256::from(999)),
+            Err(CFMMError::ArithmeticError(ArithmeticError::ReserveOverflow))
+        ));
+        assert_eq!(pool.reserve_0, u128::MAX / 2);
+        assert_eq!(pool.reserve_1, 1000);
+
+        let mut pool = UniswapV2Pool {
+            reserve_0: 1 << 100,
+            reserve_1: 1000,
+            ..pool
+        };
+
+        assert!(matches!(
+            pool.simulate_swap_mut(pool.token_a, U256::from(u128::MAX)),
+            Err(ArithmeticError::ReserveOverflow)
+        ));
+        assert_eq!(pool.reserve_0, 1 << 100);
+    }
+
+    #[test]
+    fn test_simulate_swap_overflow() {
+        let mut pool = UniswapV2Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            reserve_0: 47092140895915,
+            reserve_1: 28396598565590008529300,
+            fee: 300,
+            ..Default::default()
+        };
+
+        for amount_in in [U256::exp10(60), U256::MAX] {
+            assert!(matches!(
+                pool.simulate_swap(pool.token_a, amount_in),
+                Err(ArithmeticError::Overflow)
+            ));
+            assert!(matches!(
+                pool.simulate_swap_mut(pool.token_a, amount_in),
+                Err(ArithmeticError::Overflow)
+            ));
+        }
+        assert_eq!(pool.reserve_0, 47092140895915);
+    }
+
+    //Compares against PancakeSwap's router on mainnet, which charges a 0.25% fee, at block 18000000
+    #[tokio::test]
+    async fn test_get_amount_out_matches_pancakeswap_router() {
+        let rpc_endpoint = std::env::var("ETHEREUM_MAINNET_ENDPOINT")
+            .expect("Could not get ETHEREUM_MAINNET_ENDPOINT");
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint).unwrap());
+        let block = BlockId::from(18000000u64);
+
+        let factory = abi::IUniswapV2Factory::new(
+            H160::from_str("0x1097053Fd2ea711dad45caCcc45EfF7548fCB362").unwrap(),
+            middleware.clone(),
+        );
+        let router = abi::IUniswapV2Router::new(
+            H160::from_str("0xEfF92A263d31888d860bD50809A8D171709b7b1c").unwrap(),
+            middleware.clone(),
+        );
+        let weth = H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+        let usdt = H160::from_str("0xdAC17F958D2ee523a2206206994597C13D831ec7").unwrap();
+
+        let pair_address = factory
+            .get_pair(weth, usdt)
+            .block(block)
+            .call()
+            .await
+            .unwrap();
+        let pair = abi::IUniswapV2Pair::new(pair_address, middleware.clone());
+        let (reserve_0, reserve_1, _) = pair.get_reserves().block(block).call().await.unwrap();
+        let token_0 = pair.token_0().block(block).call().await.unwrap();
+
+        let pool = UniswapV2Pool {
+            address: pair_address,
+            token_a: token_0,
+            token_b: if token_0 == weth { usdt } else { weth },
+            reserve_0,
+            reserve_1,
+            fee: 250,
+            ..Default::default()
+        };
+
+        for (token_in, token_out, amount_in) in [
+            (weth, usdt, U256::exp10(18)),
+            (weth, usdt, U256::exp10(15) * 37),
+            (usdt, weth, U256::from(2_500_000_000u64)),
+        ] {
+            let amounts = router
+                .get_amounts_out(amount_in, vec![token_in, token_out])
+                .block(block)
+                .call()
+                .await
+                .unwrap();
+
+            assert_eq!(pool.simulate_swap(token_in, amount_in).unwrap(), amounts[1]);
+        }
+    }
+
+    //Records the reserves of PancakeSwap's WBNB/BUSD pair on BNB Smart Chain at block 30000000, along with the amounts
+    //that its router returns for them, and compares them to the pool with PancakeSwap's 0.25% fee
+    #[tokio::test]
+    async fn test_get_amounts_match_pancakeswap_router_on_bsc() {
+        let rpc_endpoint =
+            std::env::var("BSC_MAINNET_ENDPOINT").expect("Could not get BSC_MAINNET_ENDPOINT");
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint).unwrap());
+        let block = BlockId::from(30000000u64);
+
+        let router = abi::IUniswapV2Router::new(
+            H160::from_str("0x10ED43C718714eb63d5aA57B78B54704E256024E").unwrap(),
+            middleware.clone(),
+        );
+        let factory = abi::IUniswapV2Factory::new(
+            H160::from_str("0xcA143Ce32Fe78f1f7019d7d551a6402fC5350c73").unwrap(),
+            middleware.clone(),
+        );
+        let wbnb = H160::from_str("0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c").unwrap();
+        let busd = H160::from_str("0xe9e7CEA3DedcA5984780Bafc599bD69ADd087D56").unwrap();
+
+        let pair_address = factory
+            .get_pair(wbnb, busd)
+            .block(block)
+            .call()
+            .await
+            .unwrap();
+        let pair = abi::IUniswapV2Pair::new(pair_address, middleware.clone());
+        let (reserve_0, reserve_1, _) = pair.get_reserves().block(block).call().await.unwrap();
+        let token_0 = pair.token_0().block(block).call().await.unwrap();
+
+        let pool = UniswapV2Pool {
+            address: pair_address,
+            token_a: token_0,
+            token_b: if token_0 == wbnb { busd } else { wbnb },
+            reserve_0,
+            reserve_1,
+            fee: 250,
+            ..Default::default()
+        };
+
+        for (token_in, token_out, amount) in [
+            (wbnb, busd, U256::exp10(18)),
+            (wbnb, busd, U256::exp10(21) * 5),
+            (busd, wbnb, U256::exp10(15) * 25),
+            (busd, wbnb, U256::one()),
+        ] {
+            let path = vec![token_in, token_out];
+            let amounts_out = router
+                .get_amounts_out(amount, path.clone())
+                .block(block)
+                .call()
+                .await
+                .unwrap();
+            let amounts_in = router
+                .get_amounts_in(amount, path)
+                .block(block)
+                .call()
+                .await
+                .unwrap();
+
+            assert_eq!(
+                pool.simulate_swap(token_in, amount).unwrap(),
+                amounts_out[1]
+            );
+            assert_eq!(
+                pool.simulate_swap_exact_out::<Provider<Http>>(token_in, amount)
+                    .unwrap(),
+                amounts_in[0]
+            );
+
+            //The amount in is added to the reserve in and the amount out is taken from the reserve out
+            let mut swapped_pool = pool;
+            swapped_pool.simulate_swap_mut(token_in, amount).unwrap();
+            let (reserve_in, reserve_out) = if token_in == pool.token_a {
+                (swapped_pool.reserve_0, swapped_pool.reserve_1)
+            } else {
+                (swapped_pool.reserve_1, swapped_pool.reserve_0)
+            };
+            let (prior_reserve_in, prior_reserve_out) = if token_in == pool.token_a {
+                (pool.reserve_0, pool.reserve_1)
+            } else {
+                (pool.reserve_1, pool.reserve_0)
+            };
+            assert_eq!(reserve_in, prior_reserve_in + amount.as_u128());
+            assert_eq!(reserve_out, prior_reserve_out - amounts_out[1].as_u128());
+        }
+    }
+
+    #[test]
+    fn test_get_amount_out_default_fee() {
+        let pool = UniswapV2Pool {
+            fee: 300,
+            ..Default::default()
+        };
+
+        //Uniswap V2 getAmountOut with the 997/1000 fee
+        let amount_out = pool
+            .get_amount_out(
+                U256::from(1000000000000000000u128),
+                U256::from(47092140895915u128),
+                U256::from(28396598565590008529300u128),
+            )
+            .unwrap();
+
+        let amount_in_with_fee = U256::from(1000000000000000000u128) * 997;
+        let expected_amount_out = amount_in_with_fee * U256::from(28396598565590008529300u128)
+            / (U256::from(47092140895915u128) * 1000 + amount_in_with_fee);
+
+        assert_eq!(amount_out, expected_amount_out);
+    }
+
     #[tokio::test]
     async fn test_get_new_from_address() {
         let rpc_endpoint = std::env::var("ETHEREUM_MAINNET_ENDPOINT")
@@ -368,6 +828,7 @@ mod tests {
 
         let pool = UniswapV2Pool::new_from_address(
             H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc").unwrap(),
+            300,
             middleware.clone(),
         )
         .await
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use ethers::{
     abi::{decode, ethabi::Bytes, ParamType, Token},
@@ -6,10 +6,13 @@ use ethers::{
     types::{Log, H160, H256, I256, U256, U64},
 };
 use num_bigfloat::BigFloat;
+use uniswap_v3_math::error::UniswapV3MathError;
 
 use crate::{
-    abi, batch_requests,
+    abi,
+    batch_requests::{self, uniswap_v3::UniswapV3TickData},
     errors::{ArithmeticError, CFMMError},
+    telemetry,
 };
 use serde::{Deserialize, Serialize};
 
@@ -19,11 +22,23 @@ pub const SWAP_EVENT_SIGNATURE: H256 = H256([
     196, 32, 121, 249, 74, 99, 80, 215, 230, 35, 95, 41, 23, 73, 36, 249, 40, 204, 42, 200, 24,
     235, 100, 254, 216, 0, 78, 17, 95, 188, 202, 103,
 ]);
+pub const MINT_EVENT_SIGNATURE: H256 = H256([
+    122, 83, 8, 11, 164, 20, 21, 139, 231, 236, 105, 185, 135, 181, 251, 125, 7, 222, 225, 1, 254,
+    133, 72, 143, 8, 83, 174, 22, 35, 157, 11, 222,
+]);
+pub const BURN_EVENT_SIGNATURE: H256 = H256([
+    12, 57, 108, 217, 137, 163, 159, 68, 89, 181, 250, 26, 237, 106, 154, 141, 205, 188, 69, 144,
+    138, 207, 214, 126, 2, 140, 213, 104, 218, 152, 152, 44,
+]);
+pub const COLLECT_EVENT_SIGNATURE: H256 = H256([
+    112, 147, 83, 56, 230, 151, 117, 69, 106, 133, 221, 239, 34, 108, 57, 95, 182, 104, 182, 63,
+    160, 17, 95, 95, 32, 97, 11, 56, 142, 108, 169, 192,
+]);
 
 pub const U256_TWO: U256 = U256([2, 0, 0, 0]);
 pub const Q128: U256 = U256([0, 0, 1, 0]);
 pub const Q224: U256 = U256([0, 0, 0, 4294967296]);
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct UniswapV3Pool {
     pub address: H160,
     pub token_a: H160,
@@ -36,6 +51,186 @@ pub struct UniswapV3Pool {
     pub tick: i32,
     pub tick_spacing: i32,
     pub liquidity_net: i128,
+    #[serde(default)]
+    pub tick_map: Option<UniswapV3TickMap>,
+}
+
+//Locally held view of the pool's tick bitmap and the liquidity gross and net of each initialized tick.
+//Only the ticks within lower_tick..=upper_tick are known, anything outside of that range must be fetched from the node.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct UniswapV3TickMap {
+    pub tick_bitmap: BTreeMap<i16, U256>,
+    pub liquidity_net: BTreeMap<i32, i128>,
+    //Liquidity gross of the initialized ticks, a tick is cleared from the bitmap once its liquidity gross reaches zero
+    #[serde(default)]
+    pub liquidity_gross: BTreeMap<i32, u128>,
+    pub lower_tick: i32,
+    pub upper_tick: i32,
+    pub block_number: U64,
+}
+
+impl UniswapV3TickMap {
+    //Builds the tick map from the tick data returned by walking the tick bitmap down (zero_for_one) and up (one_for_zero) from the current tick
+    pub fn new(
+        tick: i32,
+        tick_spacing: i32,
+        zero_for_one_tick_data: &[UniswapV3TickData],
+        one_for_zero_tick_data: &[UniswapV3TickData],
+        block_number: U64,
+    ) -> UniswapV3TickMap {
+        let mut tick_map = UniswapV3TickMap {
+            tick_bitmap: BTreeMap::new(),
+            liquidity_net: BTreeMap::new(),
+            liquidity_gross: BTreeMap::new(),
+            lower_tick: tick,
+            upper_tick: tick,
+            block_number,
+        };
+
+        for tick_data in zero_for_one_tick_data {
+            tick_map.lower_tick = tick_map.lower_tick.min(tick_data.tick);
+            tick_map.insert_tick(tick_data, tick_spacing);
+        }
+
+        for tick_data in one_for_zero_tick_data {
+            //The batch contract clamps ticks past the max tick to the min tick, so any tick below the current tick means the walk reached the end of the bitmap
+            if tick_data.tick < tick {
+                tick_map.upper_tick = MAX_TICK;
+            } else {
+                tick_map.upper_tick = tick_map.upper_tick.max(tick_data.tick);
+                tick_map.insert_tick(tick_data, tick_spacing);
+            }
+        }
+
+        tick_map
+    }
+
+    fn insert_tick(&mut self, tick_data: &UniswapV3TickData, tick_spacing: i32) {
+        if tick_data.initialized {
+            let (word_pos, bit_pos) =
+                uniswap_v3_math::tick_bit_map::position(tick_data.tick / tick_spacing);
+
+            *self.tick_bitmap.entry(word_pos).or_default() |= U256::one() << bit_pos;
+            self.liquidity_net
+                .insert(tick_data.tick, tick_data.liquidity_net);
+        }
+    }
+
+    //Applies a change in liquidity for a position to the liquidity gross and net of its lower and upper ticks, flipping
+    //the ticks in the bitmap like `Tick.update` does. Ticks outside of the loaded range are not tracked and are skipped.
+    //Both ticks are checked before either is updated, so the tick map is left unchanged on error.
+    pub fn update_position(
+        &mut self,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity_delta: i128,
+        tick_spacing: i32,
+    ) -> Result<(), ArithmeticError> {
+        let mut updates = vec![];
+
+        for (tick, liquidity_net_delta) in [
+            (tick_lower, liquidity_delta),
+            (tick_upper, -liquidity_delta),
+        ] {
+            if tick < self.lower_tick || tick > self.upper_tick {
+                continue;
+            }
+
+            let liquidity_gross = self
+                .liquidity_gross
+                .get(&tick)
+                .copied()
+                .unwrap_or_default()
+                .checked_add_signed(liquidity_delta)
+                .ok_or(ArithmeticError::Overflow)?;
+            let liquidity_net = self
+                .liquidity_net
+                .get(&tick)
+                .copied()
+                .unwrap_or_default()
+                .checked_add(liquidity_net_delta)
+                .ok_or(ArithmeticError::Overflow)?;
+
+            updates.push((tick, liquidity_gross, liquidity_net));
+        }
+
+        for (tick, liquidity_gross, liquidity_net) in updates {
+            let (word_pos, bit_pos) = uniswap_v3_math::tick_bit_map::position(tick / tick_spacing);
+            let word = self.tick_bitmap.entry(word_pos).or_default();
+
+            if liquidity_gross == 0 {
+                *word &= !(U256::one() << bit_pos);
+                if word.is_zero() {
+                    self.tick_bitmap.remove(&word_pos);
+                }
+
+                self.liquidity_gross.remove(&tick);
+                self.liquidity_net.remove(&tick);
+            } else {
+                *word |= U256::one() << bit_pos;
+
+                self.liquidity_gross.insert(tick, liquidity_gross);
+                self.liquidity_net.insert(tick, liquidity_net);
+            }
+        }
+
+        Ok(())
+    }
+
+    //Returns the next tick in the direction of the swap, or None if the tick is outside of the loaded range
+    pub fn next_initialized_tick_within_one_word(
+        &self,
+        tick: i32,
+        tick_spacing: i32,
+        lte: bool,
+    ) -> Option<UniswapV3TickData> {
+        let compressed = if tick < 0 && tick % tick_spacing != 0 {
+            (tick / tick_spacing) - 1
+        } else {
+            tick / tick_spacing
+        };
+
+        let (word_pos, bit_pos) = if lte {
+            uniswap_v3_math::tick_bit_map::position(compressed)
+        } else {
+            uniswap_v3_math::tick_bit_map::position(compressed + 1)
+        };
+
+        let word = self.tick_bitmap.get(&word_pos).copied().unwrap_or_default();
+
+        let (tick_next, initialized) =
+            uniswap_v3_math::tick_bit_map::next_initialized_tick_within_one_word(
+                tick_spacing,
+                lte,
+                compressed,
+                bit_pos,
+                word,
+            )
+            .ok()?;
+
+        let tick_next = tick_next.clamp(MIN_TICK, MAX_TICK);
+
+        //Make sure that every tick between the current tick and the next tick has been loaded
+        let in_range = if lte {
+            tick <= self.upper_tick && tick_next >= self.lower_tick
+        } else {
+            tick + 1 >= self.lower_tick && tick_next <= self.upper_tick
+        };
+
+        if !in_range {
+            return None;
+        }
+
+        Some(UniswapV3TickData {
+            initialized,
+            tick: tick_next,
+            liquidity_net: self
+                .liquidity_net
+                .get(&tick_next)
+                .copied()
+                .unwrap_or_default(),
+        })
+    }
 }
 
 impl UniswapV3Pool {
@@ -65,6 +260,7 @@ impl UniswapV3Pool {
             tick,
             tick_spacing,
             liquidity_net,
+            tick_map: None,
         }
     }
 
@@ -85,6 +281,7 @@ impl UniswapV3Pool {
             tick_spacing: 0,
             fee: 0,
             liquidity_net: 0,
+            tick_map: None,
         };
 
         pool.get_pool_data(middleware.clone()).await?;
@@ -124,6 +321,7 @@ impl UniswapV3Pool {
             tick_spacing: 0,
             tick: 0,
             liquidity_net: 0,
+            tick_map: None,
         })
     }
 
@@ -258,6 +456,120 @@ impl UniswapV3Pool {
         Ok(())
     }
 
+    pub fn update_pool_from_mint_log(&mut self, mint_log: &Log) -> Result<(), ArithmeticError> {
+        let (tick_lower, tick_upper, amount) = self.decode_mint_log(mint_log);
+        let liquidity_delta = i128::try_from(amount).map_err(|_| ArithmeticError::Overflow)?;
+        self.modify_position(tick_lower, tick_upper, liquidity_delta)
+    }
+
+    pub fn update_pool_from_burn_log(&mut self, burn_log: &Log) -> Result<(), ArithmeticError> {
+        let (tick_lower, tick_upper, amount) = self.decode_burn_log(burn_log);
+        let liquidity_delta = i128::try_from(amount).map_err(|_| ArithmeticError::Overflow)?;
+        self.modify_position(tick_lower, tick_upper, -liquidity_delta)
+    }
+
+    //Updates the in range liquidity, the liquidity net of the current tick and the tick map for a change in a position's liquidity.
+    //The pool is left unchanged if the liquidity would overflow or underflow.
+    pub fn modify_position(
+        &mut self,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity_delta: i128,
+    ) -> Result<(), ArithmeticError> {
+        if liquidity_delta == 0 {
+            return Ok(());
+        }
+
+        let liquidity = if self.tick >= tick_lower && self.tick < tick_upper {
+            self.liquidity
+                .checked_add_signed(liquidity_delta)
+                .ok_or(ArithmeticError::Overflow)?
+        } else {
+            self.liquidity
+        };
+
+        let liquidity_net = if self.tick == tick_lower {
+            self.liquidity_net.checked_add(liquidity_delta)
+        } else if self.tick == tick_upper {
+            self.liquidity_net.checked_sub(liquidity_delta)
+        } else {
+            Some(self.liquidity_net)
+        }
+        .ok_or(ArithmeticError::Overflow)?;
+
+        if let Some(tick_map) = self.tick_map.as_mut() {
+            tick_map.update_position(tick_lower, tick_upper, liquidity_delta, self.tick_spacing)?;
+        }
+
+        self.liquidity = liquidity;
+        self.liquidity_net = liquidity_net;
+
+        Ok(())
+    }
+
+    fn decode_tick_topic(topic: H256) -> i32 {
+        I256::from_raw(U256::from_big_endian(topic.as_bytes())).as_i32()
+    }
+
+    //Returns tick_lower, tick_upper, amount
+    pub fn decode_mint_log(&self, mint_log: &Log) -> (i32, i32, u128) {
+        let log_data = decode(
+            &[
+                ParamType::Address,   //sender
+                ParamType::Uint(128), //amount
+                ParamType::Uint(256), //amount0
+                ParamType::Uint(256), //amount1
+            ],
+            &mint_log.data,
+        )
+        .expect("Could not get log data");
+
+        let tick_lower = Self::decode_tick_topic(mint_log.topics[2]);
+        let tick_upper = Self::decode_tick_topic(mint_log.topics[3]);
+        let amount = log_data[1].to_owned().into_uint().unwrap().as_u128();
+
+        (tick_lower, tick_upper, amount)
+    }
+
+    //Returns tick_lower, tick_upper, amount
+    pub fn decode_burn_log(&self, burn_log: &Log) -> (i32, i32, u128) {
+        let log_data = decode(
+            &[
+                ParamType::Uint(128), //amount
+                ParamType::Uint(256), //amount0
+                ParamType::Uint(256), //amount1
+            ],
+            &burn_log.data,
+        )
+        .expect("Could not get log data");
+
+        let tick_lower = Self::decode_tick_topic(burn_log.topics[2]);
+        let tick_upper = Self::decode_tick_topic(burn_log.topics[3]);
+        let amount = log_data[0].to_owned().into_uint().unwrap().as_u128();
+
+        (tick_lower, tick_upper, amount)
+    }
+
+    //Returns tick_lower, tick_upper, amount0, amount1
+    pub fn decode_collect_log(&self, collect_log: &Log) -> (i32, i32, u128, u128) {
+        let log_data = decode(
+            &[
+                ParamType::Address,   //recipient
+                ParamType::Uint(128), //amount0
+                ParamType::Uint(128), //amount1
+            ],
+            &collect_log.data,
+        )
+        .expect("Could not get log data");
+
+        let tick_lower = Self::decode_tick_topic(collect_log.topics[2]);
+        let tick_upper = Self::decode_tick_topic(collect_log.topics[3]);
+        let amount_0 = log_data[1].to_owned().into_uint().unwrap().as_u128();
+        let amount_1 = log_data[2].to_owned().into_uint().unwrap().as_u128();
+
+        (tick_lower, tick_upper, amount_0, amount_1)
+    }
+
     //Returns reserve0, reserve1
     pub fn decode_swap_log(&self, swap_log: &Log) -> (I256, I256, U256, u128, i32) {
         let log_data = decode(
@@ -272,11 +584,11 @@ impl UniswapV3Pool {
         )
         .expect("Could not get log data");
 
-        let amount_0 = I256::from_raw(log_data[1].to_owned().into_int().unwrap());
+        let amount_0 = I256::from_raw(log_data[0].to_owned().into_int().unwrap());
         let amount_1 = I256::from_raw(log_data[1].to_owned().into_int().unwrap());
         let sqrt_price = log_data[2].to_owned().into_uint().unwrap();
         let liquidity = log_data[3].to_owned().into_uint().unwrap().as_u128();
-        let tick = log_data[4].to_owned().into_uint().unwrap().as_u32() as i32;
+        let tick = I256::from_raw(log_data[4].to_owned().into_int().unwrap()).as_i32();
 
         (amount_0, amount_1, sqrt_price, liquidity, tick)
     }
@@ -351,7 +663,6 @@ impl UniswapV3Pool {
 
         //Sqrt price is stored as a Q64.96 so we need to left shift the liquidity by 96 to be represented as Q64.96
         //We cant right shift sqrt_price because it could move the value to 0, making divison by 0 to get reserve_x
-        let liquidity = liquidity;
 
         let (reserve_0, reserve_1) = if !sqrt_price.is_zero() {
             let reserve_x = liquidity.div(&sqrt_price);
@@ -399,216 +710,276 @@ impl UniswapV3Pool {
         num_ticks: u16,
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
-        if amount_in.is_zero() {
-            return Ok(U256::zero());
-        }
-
-        let zero_for_one = token_in == self.token_a;
-
-        //TODO: make this a queue instead of vec and then an iterator FIXME::
-        let (mut tick_data, block_number) =
-            batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
-                self,
-                self.tick,
-                zero_for_one,
-                num_ticks,
-                None,
-                middleware.clone(),
-            )
+        let (amount_out, current_state, liquidity_net) = self
+            .swap_with_cache(token_in, I256::from_raw(amount_in), num_ticks, middleware)
             .await?;
 
-        let mut tick_data_iter = tick_data.iter();
-
-        //Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
-        let sqrt_price_limit_x_96 = if zero_for_one {
-            MIN_SQRT_RATIO + 1
-        } else {
-            MAX_SQRT_RATIO - 1
-        };
-
-        //Initialize a mutable state state struct to hold the dynamic simulated state of the pool
-        let mut current_state = CurrentState {
-            sqrt_price_x_96: self.sqrt_price, //Active price on the pool
-            amount_calculated: I256::zero(),  //Amount of token_out that has been calculated
-            amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
-            tick: self.tick,                                       //Current i24 tick of the pool
-            liquidity: self.liquidity, //Current available liquidity in the tick range
-        };
-
-        let mut liquidity_net = self.liquidity_net;
-
-        while current_state.amount_specified_remaining != I256::zero()
-            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
-        {
-            //Initialize a new step struct to hold the dynamic state of the pool at each step
-            let mut step = StepComputations {
-                sqrt_price_start_x_96: current_state.sqrt_price_x_96, //Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
-                ..Default::default()
-            };
-
-            let next_tick_data = if let Some(tick_data) = tick_data_iter.next() {
-                tick_data
-            } else {
-                (tick_data, _) =
-                    batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
-                        self,
-                        current_state.tick,
-                        zero_for_one,
-                        num_ticks,
-                        Some(block_number),
-                        middleware.clone(),
-                    )
-                    .await?;
-
-                tick_data_iter = tick_data.iter();
+        self.update_pool_from_current_state(current_state, liquidity_net);
 
-                if let Some(tick_data) = tick_data_iter.next() {
-                    tick_data
-                } else {
-                    //This should never happen, but if it does, we should return an error because something is wrong
-                    return Err(CFMMError::NoInitializedTicks);
-                }
-            };
+        Ok(amount_out)
+    }
 
-            step.tick_next = next_tick_data.tick;
+    pub async fn simulate_swap_with_cache<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        num_ticks: u16,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        let (amount_out, _, _) = self
+            .swap_with_cache(token_in, I256::from_raw(amount_in), num_ticks, middleware)
+            .await?;
 
-            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-            //Note: this could be removed as we are clamping in the batch contract
-            step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
+        Ok(amount_out)
+    }
 
-            //Get the next sqrt price from the input amount
-            step.sqrt_price_next_x96 =
-                uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
+    //Returns the amount of token_in needed to receive `amount_out` of the other token
+    pub async fn simulate_swap_exact_out_with_cache<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_out: U256,
+        num_ticks: u16,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        let (amount_in, _, _) = self
+            .swap_with_cache(token_in, -I256::from_raw(amount_out), num_ticks, middleware)
+            .await?;
 
-            //Target spot price
-            let swap_target_sqrt_ratio = if zero_for_one {
-                if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
-                    sqrt_price_limit_x_96
-                } else {
-                    step.sqrt_price_next_x96
-                }
-            } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
-                sqrt_price_limit_x_96
-            } else {
-                step.sqrt_price_next_x96
-            };
+        Ok(amount_in)
+    }
 
-            //Compute swap step and update the current state
-            (
-                current_state.sqrt_price_x_96,
-                step.amount_in,
-                step.amount_out,
-                step.fee_amount,
-            ) = uniswap_v3_math::swap_math::compute_swap_step(
-                current_state.sqrt_price_x_96,
-                swap_target_sqrt_ratio,
-                current_state.liquidity,
-                current_state.amount_specified_remaining,
-                self.fee,
-            )?;
+    pub async fn simulate_swap_exact_out_mut_with_cache<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        amount_out: U256,
+        num_ticks: u16,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        let (amount_in, current_state, liquidity_net) = self
+            .swap_with_cache(token_in, -I256::from_raw(amount_out), num_ticks, middleware)
+            .await?;
 
-            //Decrement the amount remaining to be swapped and amount received from the step
-            current_state.amount_specified_remaining = current_state
-                .amount_specified_remaining
-                .overflowing_sub(I256::from_raw(
-                    step.amount_in.overflowing_add(step.fee_amount).0,
-                ))
-                .0;
+        self.update_pool_from_current_state(current_state, liquidity_net);
 
-            current_state.amount_calculated -= I256::from_raw(step.amount_out);
+        Ok(amount_in)
+    }
 
-            //If the price moved all the way to the next price, recompute the liquidity change for the next iteration
-            if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
-                if next_tick_data.initialized {
-                    liquidity_net = next_tick_data.liquidity_net;
-
-                    // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
-                    if zero_for_one {
-                        liquidity_net = -liquidity_net;
-                    }
-
-                    current_state.liquidity = if liquidity_net < 0 {
-                        current_state.liquidity - (-liquidity_net as u128)
-                    } else {
-                        current_state.liquidity + (liquidity_net as u128)
-                    };
-                }
-                //Increment the current tick
-                current_state.tick = if zero_for_one {
-                    step.tick_next.wrapping_sub(1)
-                } else {
-                    step.tick_next
+    pub async fn simulate_swap<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        //Simulate against the local tick map if it is populated, only falling back to the node if the swap moves past the loaded ticks
+        if self.tick_map.is_some() {
+            match self.simulate_swap_from_tick_map(token_in, amount_in) {
+                Err(CFMMError::TickMapExhausted) => {
+                    telemetry::debug_event!(
+                        pool = ?self.address,
+                        "Tick map exhausted, fetching ticks from the node"
+                    );
                 }
-                //If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
-                //Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
-            } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
-                current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
-                    current_state.sqrt_price_x_96,
-                )?;
+                result => return result,
             }
         }
 
-        //Update the pool state
-        self.liquidity = current_state.liquidity;
-        self.sqrt_price = current_state.sqrt_price_x_96;
-        self.tick = current_state.tick;
-        self.liquidity_net = liquidity_net;
-
-        Ok((-current_state.amount_calculated).into_raw())
+        self.simulate_swap_with_cache(token_in, amount_in, 150, middleware)
+            .await
     }
 
-    pub async fn simulate_swap_with_cache<M: Middleware>(
+    pub async fn simulate_swap_exact_out<M: Middleware>(
         &self,
         token_in: H160,
-        amount_in: U256,
-        num_ticks: u16,
+        amount_out: U256,
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
-        if amount_in.is_zero() {
-            return Ok(U256::zero());
+        //Simulate against the local tick map if it is populated, only falling back to the node if the swap moves past the loaded ticks
+        if self.tick_map.is_some() {
+            match self.simulate_swap_exact_out_from_tick_map(token_in, amount_out) {
+                Err(CFMMError::TickMapExhausted) => {
+                    telemetry::debug_event!(
+                        pool = ?self.address,
+                        "Tick map exhausted, fetching ticks from the node"
+                    );
+                }
+                result => return result,
+            }
         }
 
-        let zero_for_one = token_in == self.token_a;
+        self.simulate_swap_exact_out_with_cache(token_in, amount_out, 150, middleware)
+            .await
+    }
 
-        //TODO: make this a queue instead of vec and then an iterator FIXME::
-        let (mut tick_data, block_number) =
+    //Populates the local tick map with `num_ticks` ticks on each side of the current tick, pinned to a single block
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(pool = ?self.address, tick = self.tick, num_ticks))
+    )]
+    pub async fn populate_tick_map<M: Middleware>(
+        &mut self,
+        num_ticks: u16,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        let (zero_for_one_tick_data, block_number) =
             batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
                 self,
                 self.tick,
-                zero_for_one,
+                true,
                 num_ticks,
                 None,
                 middleware.clone(),
             )
             .await?;
 
-        let mut tick_data_iter = tick_data.iter();
+        let (one_for_zero_tick_data, _) =
+            batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
+                self,
+                self.tick,
+                false,
+                num_ticks,
+                Some(block_number),
+                middleware.clone(),
+            )
+            .await?;
 
-        //Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
-        let sqrt_price_limit_x_96 = if zero_for_one {
-            MIN_SQRT_RATIO + 1
-        } else {
-            MAX_SQRT_RATIO - 1
-        };
+        let mut tick_map = UniswapV3TickMap::new(
+            self.tick,
+            self.tick_spacing,
+            &zero_for_one_tick_data,
+            &one_for_zero_tick_data,
+            block_number,
+        );
 
-        //Initialize a mutable state state struct to hold the dynamic simulated state of the pool
-        let mut current_state = CurrentState {
-            sqrt_price_x_96: self.sqrt_price, //Active price on the pool
-            amount_calculated: I256::zero(),  //Amount of token_out that has been calculated
-            amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
-            tick: self.tick,                                       //Current i24 tick of the pool
-            liquidity: self.liquidity, //Current available liquidity in the tick range
-        };
+        let ticks = tick_map.liquidity_net.keys().copied().collect::<Vec<i32>>();
+        tick_map.liquidity_gross =
+            batch_requests::uniswap_v3::get_uniswap_v3_liquidity_gross_batch_request(
+                self,
+                &ticks,
+                block_number,
+                middleware,
+            )
+            .await?;
 
-        while current_state.amount_specified_remaining != I256::zero()
-            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
-        {
-            //Initialize a new step struct to hold the dynamic state of the pool at each step
-            let mut step = StepComputations {
-                sqrt_price_start_x_96: current_state.sqrt_price_x_96, //Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
-                ..Default::default()
-            };
+        self.tick_map = Some(tick_map);
+
+        Ok(())
+    }
+
+    //Simulates a swap using only the locally held tick map, without making any calls to the node
+    pub fn simulate_swap_from_tick_map<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let (amount_out, _, _) = self.swap_from_tick_map(token_in, I256::from_raw(amount_in))?;
+        Ok(amount_out)
+    }
+
+    //Simulates a swap using only the locally held tick map and updates the pool state, without making any calls to the node
+    pub fn simulate_swap_mut_from_tick_map<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let (amount_out, current_state, liquidity_net) =
+            self.swap_from_tick_map(token_in, I256::from_raw(amount_in))?;
+
+        self.update_pool_from_current_state(current_state, liquidity_net);
+
+        Ok(amount_out)
+    }
+
+    //Returns the amount of token_in needed to receive `amount_out`, using only the locally held tick map
+    pub fn simulate_swap_exact_out_from_tick_map<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let (amount_in, _, _) = self.swap_from_tick_map(token_in, -I256::from_raw(amount_out))?;
+        Ok(amount_in)
+    }
+
+    pub fn simulate_swap_exact_out_mut_from_tick_map<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        amount_out: U256,
+    ) -> Result<U256, CFMMError<M>> {
+        let (amount_in, current_state, liquidity_net) =
+            self.swap_from_tick_map(token_in, -I256::from_raw(amount_out))?;
+
+        self.update_pool_from_current_state(current_state, liquidity_net);
+
+        Ok(amount_in)
+    }
+
+    fn update_pool_from_current_state(&mut self, current_state: CurrentState, liquidity_net: i128) {
+        self.liquidity = current_state.liquidity;
+        self.sqrt_price = current_state.sqrt_price_x_96;
+        self.tick = current_state.tick;
+        self.liquidity_net = liquidity_net;
+    }
+
+    //Initialize a mutable state state struct to hold the dynamic simulated state of the pool
+    fn initial_swap_state(&self, amount_specified: I256) -> CurrentState {
+        CurrentState {
+            sqrt_price_x_96: self.sqrt_price, //Active price on the pool
+            amount_calculated: I256::zero(),  //Amount of token_out that has been calculated
+            amount_specified_remaining: amount_specified, //Amount of token_in (or token_out if negative) that has not been swapped
+            tick: self.tick,                              //Current i24 tick of the pool
+            liquidity: self.liquidity, //Current available liquidity in the tick range
+        }
+    }
+
+    //Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
+    fn sqrt_price_limit_x_96(zero_for_one: bool) -> U256 {
+        if zero_for_one {
+            MIN_SQRT_RATIO + 1
+        } else {
+            MAX_SQRT_RATIO - 1
+        }
+    }
+
+    //Returns the amount calculated, the state of the pool after the swap and the last liquidity net.
+    //A positive amount_specified is an exact input swap, a negative amount_specified is an exact output swap.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(pool = ?self.address, token_in = ?token_in, num_ticks))
+    )]
+    async fn swap_with_cache<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_specified: I256,
+        num_ticks: u16,
+        middleware: Arc<M>,
+    ) -> Result<(U256, CurrentState, i128), CFMMError<M>> {
+        let mut current_state = self.initial_swap_state(amount_specified);
+        let mut liquidity_net = self.liquidity_net;
+
+        if amount_specified.is_zero() {
+            return Ok((U256::zero(), current_state, liquidity_net));
+        }
+
+        let zero_for_one = token_in == self.token_a;
+
+        //TODO: make this a queue instead of vec and then an iterator FIXME::
+        let (mut tick_data, block_number) =
+            batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
+                self,
+                self.tick,
+                zero_for_one,
+                num_ticks,
+                None,
+                middleware.clone(),
+            )
+            .await?;
+
+        let mut tick_data_iter = tick_data.iter();
+
+        let sqrt_price_limit_x_96 = Self::sqrt_price_limit_x_96(zero_for_one);
 
+        while current_state.amount_specified_remaining != I256::zero()
+            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
+        {
             let next_tick_data = if let Some(tick_data) = tick_data_iter.next() {
                 tick_data
             } else {
@@ -633,43 +1004,140 @@ impl UniswapV3Pool {
                 }
             };
 
-            step.tick_next = next_tick_data.tick;
+            self.swap_step(
+                &mut current_state,
+                &mut liquidity_net,
+                next_tick_data,
+                zero_for_one,
+                sqrt_price_limit_x_96,
+            )?;
+        }
 
-            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-            //Note: this could be removed as we are clamping in the batch contract
-            step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
+        Ok((
+            Self::amount_calculated(amount_specified, &current_state)?,
+            current_state,
+            liquidity_net,
+        ))
+    }
 
-            //Get the next sqrt price from the input amount
-            step.sqrt_price_next_x96 =
-                uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
+    //Returns the amount calculated, the state of the pool after the swap and the last liquidity net.
+    //A positive amount_specified is an exact input swap, a negative amount_specified is an exact output swap.
+    fn swap_from_tick_map<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_specified: I256,
+    ) -> Result<(U256, CurrentState, i128), CFMMError<M>> {
+        let tick_map = self
+            .tick_map
+            .as_ref()
+            .ok_or(CFMMError::TickMapNotPopulated)?;
 
-            //Target spot price
-            let swap_target_sqrt_ratio = if zero_for_one {
-                if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
-                    sqrt_price_limit_x_96
-                } else {
-                    step.sqrt_price_next_x96
-                }
-            } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
+        let mut current_state = self.initial_swap_state(amount_specified);
+        let mut liquidity_net = self.liquidity_net;
+
+        if amount_specified.is_zero() {
+            return Ok((U256::zero(), current_state, liquidity_net));
+        }
+
+        let zero_for_one = token_in == self.token_a;
+        let sqrt_price_limit_x_96 = Self::sqrt_price_limit_x_96(zero_for_one);
+
+        while current_state.amount_specified_remaining != I256::zero()
+            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
+        {
+            //If the next tick is outside of the loaded range, the swap can not be simulated locally
+            let next_tick_data = tick_map
+                .next_initialized_tick_within_one_word(
+                    current_state.tick,
+                    self.tick_spacing,
+                    zero_for_one,
+                )
+                .ok_or(CFMMError::TickMapExhausted)?;
+
+            self.swap_step(
+                &mut current_state,
+                &mut liquidity_net,
+                &next_tick_data,
+                zero_for_one,
+                sqrt_price_limit_x_96,
+            )?;
+        }
+
+        Ok((
+            Self::amount_calculated(amount_specified, &current_state)?,
+            current_state,
+            liquidity_net,
+        ))
+    }
+
+    //Returns the amount out for exact input swaps and the amount in for exact output swaps
+    fn amount_calculated<M: Middleware>(
+        amount_specified: I256,
+        current_state: &CurrentState,
+    ) -> Result<U256, CFMMError<M>> {
+        if amount_specified.is_positive() {
+            Ok((-current_state.amount_calculated).into_raw())
+        } else if current_state.amount_specified_remaining.is_zero() {
+            Ok(current_state.amount_calculated.into_raw())
+        } else {
+            //The price limit was reached before the full amount out could be swapped
+            Err(CFMMError::InsufficientLiquidity)
+        }
+    }
+
+    //Computes a single step of the swap towards the next tick and updates the current state
+    fn swap_step(
+        &self,
+        current_state: &mut CurrentState,
+        liquidity_net: &mut i128,
+        next_tick_data: &UniswapV3TickData,
+        zero_for_one: bool,
+        sqrt_price_limit_x_96: U256,
+    ) -> Result<(), UniswapV3MathError> {
+        //Initialize a new step struct to hold the dynamic state of the pool at each step
+        let mut step = StepComputations {
+            sqrt_price_start_x_96: current_state.sqrt_price_x_96, //Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
+            ..Default::default()
+        };
+
+        step.tick_next = next_tick_data.tick;
+
+        // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
+        //Note: this could be removed as we are clamping in the batch contract
+        step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
+
+        //Get the next sqrt price from the input amount
+        step.sqrt_price_next_x96 =
+            uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
+
+        //Target spot price
+        let swap_target_sqrt_ratio = if zero_for_one {
+            if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
                 sqrt_price_limit_x_96
             } else {
                 step.sqrt_price_next_x96
-            };
-
-            //Compute swap step and update the current state
-            (
-                current_state.sqrt_price_x_96,
-                step.amount_in,
-                step.amount_out,
-                step.fee_amount,
-            ) = uniswap_v3_math::swap_math::compute_swap_step(
-                current_state.sqrt_price_x_96,
-                swap_target_sqrt_ratio,
-                current_state.liquidity,
-                current_state.amount_specified_remaining,
-                self.fee,
-            )?;
+            }
+        } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
+            sqrt_price_limit_x_96
+        } else {
+            step.sqrt_price_next_x96
+        };
 
+        //Compute swap step and update the current state
+        (
+            current_state.sqrt_price_x_96,
+            step.amount_in,
+            step.amount_out,
+            step.fee_amount,
+        ) = uniswap_v3_math::swap_math::compute_swap_step(
+            current_state.sqrt_price_x_96,
+            swap_target_sqrt_ratio,
+            current_state.liquidity,
+            current_state.amount_specified_remaining,
+            self.fee,
+        )?;
+
+        if current_state.amount_specified_remaining.is_positive() {
             //Decrement the amount remaining to be swapped and amount received from the step
             current_state.amount_specified_remaining = current_state
                 .amount_specified_remaining
@@ -679,49 +1147,47 @@ impl UniswapV3Pool {
                 .0;
 
             current_state.amount_calculated -= I256::from_raw(step.amount_out);
+        } else {
+            //Increment the amount remaining to be received and the amount needed to pay for the step
+            current_state.amount_specified_remaining = current_state
+                .amount_specified_remaining
+                .overflowing_add(I256::from_raw(step.amount_out))
+                .0;
 
-            //If the price moved all the way to the next price, recompute the liquidity change for the next iteration
-            if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
-                if next_tick_data.initialized {
-                    let mut liquidity_net = next_tick_data.liquidity_net;
-
-                    // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
-                    if zero_for_one {
-                        liquidity_net = -liquidity_net;
-                    }
-
-                    current_state.liquidity = if liquidity_net < 0 {
-                        current_state.liquidity - (-liquidity_net as u128)
-                    } else {
-                        current_state.liquidity + (liquidity_net as u128)
-                    };
+            current_state.amount_calculated +=
+                I256::from_raw(step.amount_in.overflowing_add(step.fee_amount).0);
+        }
+
+        //If the price moved all the way to the next price, recompute the liquidity change for the next iteration
+        if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
+            if next_tick_data.initialized {
+                *liquidity_net = next_tick_data.liquidity_net;
+
+                // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
+                if zero_for_one {
+                    *liquidity_net = -*liquidity_net;
                 }
-                //Increment the current tick
-                current_state.tick = if zero_for_one {
-                    step.tick_next.wrapping_sub(1)
+
+                current_state.liquidity = if *liquidity_net < 0 {
+                    current_state.liquidity - (-*liquidity_net as u128)
                 } else {
-                    step.tick_next
-                }
-                //If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
-                //Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
-            } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
-                current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
-                    current_state.sqrt_price_x_96,
-                )?;
+                    current_state.liquidity + (*liquidity_net as u128)
+                };
             }
+            //Increment the current tick
+            current_state.tick = if zero_for_one {
+                step.tick_next.wrapping_sub(1)
+            } else {
+                step.tick_next
+            }
+            //If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
+            //Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
+        } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
+            current_state.tick =
+                uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(current_state.sqrt_price_x_96)?;
         }
 
-        Ok((-current_state.amount_calculated).into_raw())
-    }
-
-    pub async fn simulate_swap<M: Middleware>(
-        &self,
-        token_in: H160,
-        amount_in: U256,
-        middleware: Arc<M>,
-    ) -> Result<U256, CFMMError<M>> {
-        self.simulate_swap_with_cache(token_in, amount_in, 150, middleware)
-            .await
+        Ok(())
     }
 
     pub async fn get_word<M: Middleware>(
@@ -730,12 +1196,12 @@ impl UniswapV3Pool {
         block_number: Option<U64>,
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
-        if block_number.is_some() {
+        if let Some(block_number) = block_number {
             //TODO: in the future, create a batch call to get this and liquidity net within the same call
 
             Ok(abi::IUniswapV3Pool::new(self.address, middleware.clone())
                 .tick_bitmap(word_pos)
-                .block(block_number.unwrap())
+                .block(block_number)
                 .call()
                 .await?)
         } else {
@@ -765,10 +1231,46 @@ impl UniswapV3Pool {
         amount_in: U256,
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
+        //Simulate against the local tick map if it is populated, only falling back to the node if the swap moves past the loaded ticks
+        if self.tick_map.is_some() {
+            match self.simulate_swap_mut_from_tick_map(token_in, amount_in) {
+                Err(CFMMError::TickMapExhausted) => {
+                    telemetry::debug_event!(
+                        pool = ?self.address,
+                        "Tick map exhausted, fetching ticks from the node"
+                    );
+                }
+                result => return result,
+            }
+        }
+
         self.simulate_swap_mut_with_cache(token_in, amount_in, 150, middleware)
             .await
     }
 
+    pub async fn simulate_swap_exact_out_mut<M: Middleware>(
+        &mut self,
+        token_in: H160,
+        amount_out: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        //Simulate against the local tick map if it is populated, only falling back to the node if the swap moves past the loaded ticks
+        if self.tick_map.is_some() {
+            match self.simulate_swap_exact_out_mut_from_tick_map(token_in, amount_out) {
+                Err(CFMMError::TickMapExhausted) => {
+                    telemetry::debug_event!(
+                        pool = ?self.address,
+                        "Tick map exhausted, fetching ticks from the node"
+                    );
+                }
+                result => return result,
+            }
+        }
+
+        self.simulate_swap_exact_out_mut_with_cache(token_in, amount_out, 150, middleware)
+            .await
+    }
+
     pub fn swap_calldata(
         &self,
         recipient: H160,
@@ -831,7 +1333,13 @@ mod test {
     use crate::abi::IUniswapV3Pool;
 
     #[allow(unused)]
-    use super::UniswapV3Pool;
+    use super::{UniswapV3Pool, UniswapV3TickMap, MAX_TICK, MIN_TICK};
+    #[allow(unused)]
+    use crate::{
+        batch_requests::uniswap_v3::UniswapV3TickData,
+        errors::{ArithmeticError, CFMMError},
+        pool::Pool,
+    };
     #[allow(unused)]
     use ethers::providers::Middleware;
 
@@ -839,9 +1347,11 @@ mod test {
     use ethers::{
         prelude::abigen,
         providers::{Http, Provider},
-        types::{H160, U256},
+        types::{BlockId, Filter, Log, H160, U256},
     };
     #[allow(unused)]
+    use std::collections::{BTreeMap, BTreeSet};
+    #[allow(unused)]
     use std::error::Error;
     #[allow(unused)]
     use std::{str::FromStr, sync::Arc};
@@ -850,6 +1360,7 @@ mod test {
         IQuoter,
     r#"[
         function quoteExactInputSingle(address tokenIn, address tokenOut,uint24 fee, uint256 amountIn, uint160 sqrtPriceLimitX96) external returns (uint256 amountOut)
+        function quoteExactOutputSingle(address tokenIn, address tokenOut, uint24 fee, uint256 amountOut, uint160 sqrtPriceLimitX96) external returns (uint256 amountIn)
     ]"#;);
 
     #[tokio::test]
@@ -1023,6 +1534,499 @@ mod test {
         assert_eq!(amount_out_3, expected_amount_out_3);
     }
 
+    #[tokio::test]
+    async fn test_simulate_swap_from_tick_map() {
+        let rpc_endpoint = std::env::var("ETHEREUM_MAINNET_ENDPOINT")
+            .expect("Could not get ETHEREUM_MAINNET_ENDPOINT");
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint).unwrap());
+
+        let mut pool = UniswapV3Pool::new_from_address(
+            H160::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640").unwrap(),
+            middleware.clone(),
+        )
+        .await
+        .unwrap();
+
+        pool.populate_tick_map(150, middleware.clone())
+            .await
+            .unwrap();
+        let block_number = pool.tick_map.as_ref().unwrap().block_number;
+
+        let quoter = IQuoter::new(
+            H160::from_str("0xb27308f9f90d607463bb33ea1bebb41c27ce5ab6").unwrap(),
+            middleware.clone(),
+        );
+
+        let amount_in = U256::from_dec_str("10000000000").unwrap(); // 10_000 USDC
+
+        let amount_out = pool
+            .simulate_swap_from_tick_map::<Provider<Http>>(pool.token_a, amount_in)
+            .unwrap();
+
+        let expected_amount_out = quoter
+            .quote_exact_input_single(
+                pool.token_a,
+                pool.token_b,
+                pool.fee,
+                amount_in,
+                U256::zero(),
+            )
+            .block(block_number)
+            .call()
+            .await
+            .unwrap();
+
+        assert_eq!(amount_out, expected_amount_out);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_swap_exact_out() {
+        let rpc_endpoint = std::env::var("ETHEREUM_MAINNET_ENDPOINT")
+            .expect("Could not get ETHEREUM_MAINNET_ENDPOINT");
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint).unwrap());
+
+        let pool = UniswapV3Pool::new_from_address(
+            H160::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640").unwrap(),
+            middleware.clone(),
+        )
+        .await
+        .unwrap();
+
+        let quoter = IQuoter::new(
+            H160::from_str("0xb27308f9f90d607463bb33ea1bebb41c27ce5ab6").unwrap(),
+            middleware.clone(),
+        );
+
+        let amount_out = U256::from_dec_str("1000000000000000000").unwrap(); // 1 WETH
+
+        let current_block = middleware.get_block_number().await.unwrap();
+        let amount_in = pool
+            .simulate_swap_exact_out(pool.token_a, amount_out, middleware.clone())
+            .await
+            .unwrap();
+
+        let expected_amount_in = quoter
+            .quote_exact_output_single(
+                pool.token_a,
+                pool.token_b,
+                pool.fee,
+                amount_out,
+                U256::zero(),
+            )
+            .block(current_block)
+            .call()
+            .await
+            .unwrap();
+
+        assert_eq!(amount_in, expected_amount_in);
+    }
+
+    #[allow(unused)]
+    fn tick_map_test_pool() -> UniswapV3Pool {
+        //Tick data as returned by the tick data batch request when walking 3 ticks down and 2 ticks up from tick 0
+        let zero_for_one_tick_data = vec![
+            UniswapV3TickData {
+                initialized: false,
+                tick: 0,
+                liquidity_net: 0,
+            },
+            UniswapV3TickData {
+                initialized: true,
+                tick: -10,
+                liquidity_net: 500000000000000000,
+            },
+            UniswapV3TickData {
+                initialized: false,
+                tick: -2560,
+                liquidity_net: 0,
+            },
+        ];
+
+        let one_for_zero_tick_data = vec![
+            UniswapV3TickData {
+                initialized: true,
+                tick: 100,
+                liquidity_net: -300000000000000000,
+            },
+            UniswapV3TickData {
+                initialized: false,
+                tick: 2550,
+                liquidity_net: 0,
+            },
+        ];
+
+        let mut tick_map = UniswapV3TickMap::new(
+            0,
+            10,
+            &zero_for_one_tick_data,
+            &one_for_zero_tick_data,
+            0.into(),
+        );
+        tick_map.liquidity_gross = [(-10, 500000000000000000), (100, 300000000000000000)]
+            .into_iter()
+            .collect();
+
+        UniswapV3Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            liquidity: 1000000000000000000,
+            sqrt_price: uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(0).unwrap(),
+            fee: 500,
+            tick: 0,
+            tick_spacing: 10,
+            tick_map: Some(tick_map),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_event_signatures() {
+        use crate::abi::{BurnFilter, CollectFilter, MintFilter, SwapFilter};
+        use ethers::contract::EthEvent;
+
+        assert_eq!(SwapFilter::signature(), super::SWAP_EVENT_SIGNATURE);
+        assert_eq!(MintFilter::signature(), super::MINT_EVENT_SIGNATURE);
+        assert_eq!(BurnFilter::signature(), super::BURN_EVENT_SIGNATURE);
+        assert_eq!(CollectFilter::signature(), super::COLLECT_EVENT_SIGNATURE);
+    }
+
+    //Replays the Swap, Mint, Burn and Collect logs returned by eth_getLogs for the USDC/WETH 0.05% pool over blocks 17000001..=17000500,
+    //starting from the pool state at block 17000000, and compares the result to the pool's liquidity() and ticks() at block 17000500
+    #[tokio::test]
+    async fn test_replay_mainnet_mint_burn_logs() {
+        let rpc_endpoint = std::env::var("ETHEREUM_MAINNET_ENDPOINT")
+            .expect("Could not get ETHEREUM_MAINNET_ENDPOINT");
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint).unwrap());
+
+        let (from_block, to_block) = (17000000u64, 17000500u64);
+        let pool_address = H160::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640").unwrap();
+        let v3_pool = IUniswapV3Pool::new(pool_address, middleware.clone());
+
+        let mut logs = middleware
+            .get_logs(
+                &Filter::new()
+                    .address(pool_address)
+                    .topic0(vec![
+                        super::SWAP_EVENT_SIGNATURE,
+                        super::MINT_EVENT_SIGNATURE,
+                        super::BURN_EVENT_SIGNATURE,
+                        super::COLLECT_EVENT_SIGNATURE,
+                    ])
+                    .from_block(from_block + 1)
+                    .to_block(to_block),
+            )
+            .await
+            .unwrap();
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+
+        let (sqrt_price, tick, ..) = v3_pool
+            .slot_0()
+            .block(BlockId::from(from_block))
+            .call()
+            .await
+            .unwrap();
+        let liquidity = v3_pool
+            .liquidity()
+            .block(BlockId::from(from_block))
+            .call()
+            .await
+            .unwrap();
+
+        let mut pool = UniswapV3Pool {
+            address: pool_address,
+            liquidity,
+            sqrt_price,
+            tick,
+            tick_spacing: 10,
+            fee: 500,
+            ..Default::default()
+        };
+
+        //Load the liquidity gross and net of every tick touched by a position before the logs are replayed
+        let mut touched_ticks = BTreeSet::new();
+        let (mut mints, mut burns) = (0, 0);
+        for log in logs.iter() {
+            if log.topics[0] == super::MINT_EVENT_SIGNATURE {
+                let (tick_lower, tick_upper, _) = pool.decode_mint_log(log);
+                touched_ticks.extend([tick_lower, tick_upper]);
+                mints += 1;
+            } else if log.topics[0] == super::BURN_EVENT_SIGNATURE {
+                let (tick_lower, tick_upper, _) = pool.decode_burn_log(log);
+                touched_ticks.extend([tick_lower, tick_upper]);
+                burns += 1;
+            }
+        }
+        assert!(mints > 0 && burns > 0);
+
+        let (mut liquidity_gross, mut liquidity_net) = (BTreeMap::new(), BTreeMap::new());
+        for tick in touched_ticks.iter() {
+            let (tick_liquidity_gross, tick_liquidity_net, ..) = v3_pool
+                .ticks(*tick)
+                .block(BlockId::from(from_block))
+                .call()
+                .await
+                .unwrap();
+            if tick_liquidity_gross > 0 {
+                liquidity_gross.insert(*tick, tick_liquidity_gross);
+                liquidity_net.insert(*tick, tick_liquidity_net);
+            }
+        }
+
+        pool.tick_map = Some(UniswapV3TickMap {
+            liquidity_net,
+            liquidity_gross,
+            lower_tick: MIN_TICK,
+            upper_tick: MAX_TICK,
+            block_number: from_block.into(),
+            ..Default::default()
+        });
+
+        let mut pool = Pool::UniswapV3(pool);
+        for log in logs.iter() {
+            if log.topics[0] == super::SWAP_EVENT_SIGNATURE {
+                //Swap logs carry the liquidity, price and tick after the swap, the liquidity net is fetched at the latest block so it is skipped here
+                if let Pool::UniswapV3(pool) = &mut pool {
+                    (_, _, pool.sqrt_price, pool.liquidity, pool.tick) = pool.decode_swap_log(log);
+                }
+            } else {
+                pool.update_pool_from_log(log, middleware.clone())
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let pool = match pool {
+            Pool::UniswapV3(pool) => pool,
+            _ => panic!("Unexpected pool variant"),
+        };
+
+        let liquidity = v3_pool
+            .liquidity()
+            .block(BlockId::from(to_block))
+            .call()
+            .await
+            .unwrap();
+        assert_eq!(pool.liquidity, liquidity);
+
+        let tick_map = pool.tick_map.unwrap();
+        for tick in touched_ticks {
+            let (tick_liquidity_gross, tick_liquidity_net, ..) = v3_pool
+                .ticks(tick)
+                .block(BlockId::from(to_block))
+                .call()
+                .await
+                .unwrap();
+            assert_eq!(
+                tick_map
+                    .liquidity_gross
+                    .get(&tick)
+                    .copied()
+                    .unwrap_or_default(),
+                tick_liquidity_gross
+            );
+            assert_eq!(
+                tick_map
+                    .liquidity_net
+                    .get(&tick)
+                    .copied()
+                    .unwrap_or_default(),
+                tick_liquidity_net
+            );
+        }
+    }
+
+    #[test]
+    fn test_modify_position_out_of_range() {
+        let mut pool = tick_map_test_pool();
+
+        pool.modify_position(200, 300, 100000000000000000).unwrap();
+        assert_eq!(pool.liquidity, 1000000000000000000);
+        assert_eq!(
+            pool.tick_map.as_ref().unwrap().liquidity_net[&200],
+            100000000000000000
+        );
+
+        pool.modify_position(0, 300, 100000000000000000).unwrap();
+        assert_eq!(pool.liquidity, 1100000000000000000);
+        assert_eq!(pool.liquidity_net, 100000000000000000);
+
+        //Ticks outside of the loaded range are not tracked
+        pool.modify_position(-5000, 5000, 100000000000000000)
+            .unwrap();
+        assert_eq!(pool.liquidity, 1200000000000000000);
+        let tick_map = pool.tick_map.unwrap();
+        assert!(!tick_map.liquidity_net.contains_key(&-5000));
+        assert!(!tick_map.liquidity_net.contains_key(&5000));
+    }
+
+    #[test]
+    fn test_modify_position_clears_ticks_without_liquidity() {
+        let mut pool = tick_map_test_pool();
+        let initialized = |pool: &UniswapV3Pool, tick: i32| {
+            pool.tick_map
+                .as_ref()
+                .unwrap()
+                .next_initialized_tick_within_one_word(tick + 1, 10, true)
+                .map(|tick_data| tick_data.tick == tick && tick_data.initialized)
+                .unwrap_or_default()
+        };
+
+        //Minting and burning the same position leaves the tick map as it was
+        let tick_map = pool.tick_map.clone();
+        pool.modify_position(200, 300, 100000000000000000).unwrap();
+        assert!(initialized(&pool, 200) && initialized(&pool, 300));
+        pool.modify_position(200, 300, -100000000000000000).unwrap();
+        assert!(!initialized(&pool, 200) && !initialized(&pool, 300));
+        assert_eq!(pool.tick_map, tick_map);
+
+        //Only the ticks that are left without liquidity gross are flipped off
+        pool.modify_position(-10, 100, -300000000000000000).unwrap();
+        assert!(initialized(&pool, -10));
+        assert!(!initialized(&pool, 100));
+        assert_eq!(pool.liquidity, 700000000000000000);
+
+        let tick_map = pool.tick_map.unwrap();
+        assert_eq!(tick_map.liquidity_gross[&-10], 200000000000000000);
+        assert_eq!(tick_map.liquidity_net[&-10], 200000000000000000);
+        assert!(!tick_map.liquidity_net.contains_key(&100));
+    }
+
+    #[test]
+    fn test_modify_position_underflow() {
+        let mut pool = tick_map_test_pool();
+        let expected_pool = pool.clone();
+
+        //Burning more liquidity than tick 100 has leaves the pool unchanged
+        assert!(matches!(
+            pool.modify_position(-10, 100, -400000000000000000),
+            Err(ArithmeticError::Overflow)
+        ));
+        assert_eq!(pool, expected_pool);
+
+        //Burning more than the in range liquidity leaves the pool unchanged
+        let mut pool = UniswapV3Pool {
+            tick_map: None,
+            ..expected_pool
+        };
+        assert!(matches!(
+            pool.modify_position(-100, 100, -2000000000000000000),
+            Err(ArithmeticError::Overflow)
+        ));
+        assert_eq!(pool.liquidity, 1000000000000000000);
+    }
+
+    #[test]
+    fn test_tick_map_next_initialized_tick() {
+        let pool = tick_map_test_pool();
+        let tick_map = pool.tick_map.unwrap();
+
+        assert_eq!(tick_map.lower_tick, -2560);
+        assert_eq!(tick_map.upper_tick, 2550);
+
+        let next = tick_map
+            .next_initialized_tick_within_one_word(-1, 10, true)
+            .unwrap();
+        assert!(next.initialized);
+        assert_eq!(next.tick, -10);
+        assert_eq!(next.liquidity_net, 500000000000000000);
+
+        let next = tick_map
+            .next_initialized_tick_within_one_word(0, 10, false)
+            .unwrap();
+        assert!(next.initialized);
+        assert_eq!(next.tick, 100);
+        assert_eq!(next.liquidity_net, -300000000000000000);
+
+        let next = tick_map
+            .next_initialized_tick_within_one_word(100, 10, false)
+            .unwrap();
+        assert!(!next.initialized);
+        assert_eq!(next.tick, 2550);
+
+        //Walking past the loaded ticks should not return any tick data
+        assert!(tick_map
+            .next_initialized_tick_within_one_word(-2561, 10, true)
+            .is_none());
+        assert!(tick_map
+            .next_initialized_tick_within_one_word(2550, 10, false)
+            .is_none());
+    }
+
+    #[test]
+    fn test_simulate_swap_from_tick_map_within_tick() {
+        let pool = tick_map_test_pool();
+        let amount_in = U256::from(1000000000000u128);
+
+        let amount_out = pool
+            .simulate_swap_from_tick_map::<Provider<Http>>(pool.token_a, amount_in)
+            .unwrap();
+
+        let (_, _, expected_amount_out, _) = uniswap_v3_math::swap_math::compute_swap_step(
+            pool.sqrt_price,
+            uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(-10).unwrap(),
+            pool.liquidity,
+            ethers::types::I256::from_raw(amount_in),
+            pool.fee,
+        )
+        .unwrap();
+
+        assert_eq!(amount_out, expected_amount_out);
+    }
+
+    #[test]
+    fn test_simulate_swap_mut_from_tick_map_crosses_tick() {
+        let mut pool = tick_map_test_pool();
+        let amount_in = U256::from(1000000000000000u128);
+
+        pool.simulate_swap_mut_from_tick_map::<Provider<Http>>(pool.token_a, amount_in)
+            .unwrap();
+
+        //Crossing tick -10 from above removes its liquidity net from the active liquidity
+        assert!(pool.tick < -10);
+        assert_eq!(pool.liquidity, 500000000000000000);
+    }
+
+    #[test]
+    fn test_simulate_swap_exact_out_from_tick_map() {
+        let mut pool = tick_map_test_pool();
+
+        //Large enough to cross the initialized tick at -10
+        let amount_out = U256::from(1000000000000000u128);
+
+        let amount_in = pool
+            .simulate_swap_exact_out_from_tick_map::<Provider<Http>>(pool.token_a, amount_out)
+            .unwrap();
+
+        //Swapping the amount in should receive at least the amount out
+        let amount_received = pool
+            .simulate_swap_from_tick_map::<Provider<Http>>(pool.token_a, amount_in)
+            .unwrap();
+        assert!(amount_received >= amount_out);
+
+        let expected_pool = {
+            let mut pool = pool.clone();
+            pool.simulate_swap_mut_from_tick_map::<Provider<Http>>(pool.token_a, amount_in)
+                .unwrap();
+            pool
+        };
+
+        pool.simulate_swap_exact_out_mut_from_tick_map::<Provider<Http>>(pool.token_a, amount_out)
+            .unwrap();
+
+        assert_eq!(pool.liquidity, expected_pool.liquidity);
+        assert_eq!(pool.tick, expected_pool.tick);
+    }
+
+    #[test]
+    fn test_simulate_swap_from_tick_map_exhausted() {
+        let pool = tick_map_test_pool();
+        let amount_in = U256::from(1000000000000000000000u128);
+
+        let result = pool.simulate_swap_from_tick_map::<Provider<Http>>(pool.token_b, amount_in);
+
+        assert!(matches!(result, Err(CFMMError::TickMapExhausted)));
+    }
+
     #[tokio::test]
     async fn test_get_new_from_address() {
         let rpc_endpoint = std::env::var("ETHEREUM_MAINNET_ENDPOINT")
@@ -0,0 +1,413 @@
+//Port of Balancer V2's `LogExpMath`, `FixedPoint` and `WeightedMath` libraries. Every operation rounds the same way as
+//the Solidity libraries so that simulated swaps match the amounts returned by the Vault.
+use ethers::types::{I256, U256};
+
+use crate::errors::ArithmeticError;
+
+pub const ONE: U256 = U256([1_000_000_000_000_000_000, 0, 0, 0]);
+
+//Weighted pools revert when a swap takes more than 30% of the balance of a token in or out
+pub const MAX_IN_RATIO: U256 = U256([300_000_000_000_000_000, 0, 0, 0]);
+pub const MAX_OUT_RATIO: U256 = U256([300_000_000_000_000_000, 0, 0, 0]);
+
+//Relative error of `LogExpMath::pow`, which `pow_up` and `pow_down` round away
+const MAX_POW_RELATIVE_ERROR: U256 = U256([10000, 0, 0, 0]);
+
+//`FixedPoint` reverts on overflow and division by zero, which are returned as errors here
+pub fn mul_down(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    Ok(mul(a, b)? / ONE)
+}
+
+pub fn mul_up(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    let product = mul(a, b)?;
+
+    if product.is_zero() {
+        Ok(U256::zero())
+    } else {
+        Ok((product - 1) / ONE + 1)
+    }
+}
+
+pub fn div_down(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    if b.is_zero() {
+        return Err(ArithmeticError::DivisionByZero);
+    }
+
+    Ok(mul(a, ONE)? / b)
+}
+
+pub fn div_up(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    if b.is_zero() {
+        return Err(ArithmeticError::DivisionByZero);
+    }
+
+    if a.is_zero() {
+        Ok(U256::zero())
+    } else {
+        Ok((mul(a, ONE)? - 1) / b + 1)
+    }
+}
+
+fn mul(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    a.checked_mul(b).ok_or(ArithmeticError::Overflow)
+}
+
+fn add(a: U256, b: U256) -> Result<U256, ArithmeticError> {
+    a.checked_add(b).ok_or(ArithmeticError::Overflow)
+}
+
+pub fn complement(x: U256) -> U256 {
+    if x < ONE {
+        ONE - x
+    } else {
+        U256::zero()
+    }
+}
+
+pub fn pow_up(x: U256, y: U256) -> Result<U256, ArithmeticError> {
+    //Integer exponents are computed exactly since they are the most common weights
+    if y == ONE {
+        Ok(x)
+    } else if y == ONE * 2 {
+        mul_up(x, x)
+    } else if y == ONE * 4 {
+        let square = mul_up(x, x)?;
+        mul_up(square, square)
+    } else {
+        let raw = pow(x, y)?;
+        add(add(raw, mul_up(raw, MAX_POW_RELATIVE_ERROR)?)?, U256::one())
+    }
+}
+
+pub fn pow_down(x: U256, y: U256) -> Result<U256, ArithmeticError> {
+    if y == ONE {
+        Ok(x)
+    } else if y == ONE * 2 {
+        mul_down(x, x)
+    } else if y == ONE * 4 {
+        let square = mul_down(x, x)?;
+        mul_down(square, square)
+    } else {
+        let raw = pow(x, y)?;
+        Ok(raw.saturating_sub(add(mul_up(raw, MAX_POW_RELATIVE_ERROR)?, U256::one())?))
+    }
+}
+
+//Amount of token out for an amount in after fees, with all amounts scaled to 18 decimals
+pub fn calc_out_given_in(
+    balance_in: U256,
+    weight_in: U256,
+    balance_out: U256,
+    weight_out: U256,
+    amount_in: U256,
+) -> Result<U256, ArithmeticError> {
+    if amount_in > mul_down(balance_in, MAX_IN_RATIO)? {
+        return Err(ArithmeticError::MaxInRatioExceeded);
+    }
+
+    let base = div_up(balance_in, add(balance_in, amount_in)?)?;
+    let exponent = div_down(weight_in, weight_out)?;
+    let power = pow_up(base, exponent)?;
+
+    mul_down(balance_out, complement(power))
+}
+
+//Amount of token in before fees for an amount out, with all amounts scaled to 18 decimals
+pub fn calc_in_given_out(
+    balance_in: U256,
+    weight_in: U256,
+    balance_out: U256,
+    weight_out: U256,
+    amount_out: U256,
+) -> Result<U256, ArithmeticError> {
+    if amount_out > mul_down(balance_out, MAX_OUT_RATIO)? {
+        return Err(ArithmeticError::MaxOutRatioExceeded);
+    }
+
+    let base = div_up(balance_out, balance_out - amount_out)?;
+    let exponent = div_up(weight_out, weight_in)?;
+    let power = pow_up(base, exponent)?;
+
+    mul_up(balance_in, power.saturating_sub(ONE))
+}
+
+fn int(value: u128) -> I256 {
+    I256::from_raw(U256::from(value))
+}
+
+fn one_18() -> I256 {
+    int(1_000_000_000_000_000_000)
+}
+
+fn one_20() -> I256 {
+    int(100_000_000_000_000_000_000)
+}
+
+fn one_36() -> I256 {
+    int(1_000_000_000_000_000_000_000_000_000_000_000_000)
+}
+
+//Bounds of the natural exponent that `exp` accepts, scaled by 1e18
+fn max_natural_exponent() -> I256 {
+    int(130) * one_18()
+}
+
+fn min_natural_exponent() -> I256 {
+    -(int(41) * one_18())
+}
+
+//x0 = 2^7 and x1 = 2^6 with 18 decimals, a0 = e^x0 and a1 = e^x1 without decimals
+fn x0() -> I256 {
+    int(128_000_000_000_000_000_000)
+}
+
+fn a0() -> I256 {
+    int(388_770_840_599_459_509_222) * I256::exp10(35)
+}
+
+fn x1() -> I256 {
+    int(64_000_000_000_000_000_000)
+}
+
+fn a1() -> I256 {
+    int(6_235_149_080_811_616_882_910_000_000)
+}
+
+//x2 = 2^5 through x11 = 2^-4 with 20 decimals, and e^x with 20 decimals
+const X_20: [u128; 10] = [
+    3_200_000_000_000_000_000_000,
+    1_600_000_000_000_000_000_000,
+    800_000_000_000_000_000_000,
+    400_000_000_000_000_000_000,
+    200_000_000_000_000_000_000,
+    100_000_000_000_000_000_000,
+    50_000_000_000_000_000_000,
+    25_000_000_000_000_000_000,
+    12_500_000_000_000_000_000,
+    6_250_000_000_000_000_000,
+];
+
+const A_20: [u128; 10] = [
+    7_896_296_018_268_069_516_100_000_000_000_000,
+    888_611_052_050_787_263_676_000_000,
+    298_095_798_704_172_827_474_000,
+    5_459_815_003_314_423_907_810,
+    738_905_609_893_065_022_723,
+    271_828_182_845_904_523_536,
+    164_872_127_070_012_814_685,
+    128_402_541_668_774_148_407,
+    113_314_845_306_682_631_683,
+    106_449_445_891_785_942_956,
+];
+
+//x^y with 18 decimals, mirroring `LogExpMath.pow`
+pub fn pow(x: U256, y: U256) -> Result<U256, ArithmeticError> {
+    if y.is_zero() {
+        return Ok(ONE);
+    }
+
+    if x.is_zero() {
+        return Ok(U256::zero());
+    }
+
+    //x must fit in an int256 and y must be small enough that ln(x) * y can not overflow
+    let mild_exponent_bound = (U256::one() << 254) / U256::exp10(20);
+    if x.bit(255) || y >= mild_exponent_bound {
+        return Err(ArithmeticError::PowOutOfBounds);
+    }
+
+    let x = I256::from_raw(x);
+    let y = I256::from_raw(y);
+
+    //ln is computed with 36 decimals when x is close to one, where the extra precision matters
+    let ln_36_lower_bound = one_18() - I256::exp10(17);
+    let ln_36_upper_bound = one_18() + I256::exp10(17);
+
+    let logx_times_y = if ln_36_lower_bound < x && x < ln_36_upper_bound {
+        let ln_36_x = ln_36(x);
+        (ln_36_x / one_18()) * y + ((ln_36_x % one_18()) * y) / one_18()
+    } else {
+        ln(x) * y
+    } / one_18();
+
+    if logx_times_y < min_natural_exponent() || logx_times_y > max_natural_exponent() {
+        return Err(ArithmeticError::PowOutOfBounds);
+    }
+
+    Ok(exp(logx_times_y)?.into_raw())
+}
+
+//e^x with 18 decimals, mirroring `LogExpMath.exp`
+pub fn exp(mut x: I256) -> Result<I256, ArithmeticError> {
+    if x < min_natural_exponent() || x > max_natural_exponent() {
+        return Err(ArithmeticError::PowOutOfBounds);
+    }
+
+    if x.is_negative() {
+        return Ok(one_18() * one_18() / exp(-x)?);
+    }
+
+    let first_an = if x >= x0() {
+        x -= x0();
+        a0()
+    } else if x >= x1() {
+        x -= x1();
+        a1()
+    } else {
+        I256::one()
+    };
+
+    //The rest of the exponent is decomposed with 20 decimals for extra precision, x10 and x11 are not needed
+    x *= int(100);
+
+    let mut product = one_20();
+    for (x_n, a_n) in X_20.iter().zip(A_20.iter()).take(8) {
+        if x >= int(*x_n) {
+            x -= int(*x_n);
+            product = product * int(*a_n) / one_20();
+        }
+    }
+
+    //Taylor series of the remaining exponent, which is smaller than 2^-3
+    let mut series_sum = one_20();
+    let mut term = x;
+    series_sum += term;
+    for n in 2..=12 {
+        term = term * x / one_20() / int(n);
+        series_sum += term;
+    }
+
+    Ok(product * series_sum / one_20() * first_an / int(100))
+}
+
+//Natural logarithm with 18 decimals, mirroring `LogExpMath._ln`
+fn ln(mut a: I256) -> I256 {
+    if a < one_18() {
+        return -ln(one_18() * one_18() / a);
+    }
+
+    let mut sum = I256::zero();
+    if a >= a0() * one_18() {
+        a /= a0();
+        sum += x0();
+    }
+
+    if a >= a1() * one_18() {
+        a /= a1();
+        sum += x1();
+    }
+
+    sum *= int(100);
+    a *= int(100);
+
+    for (x_n, a_n) in X_20.iter().zip(A_20.iter()) {
+        if a >= int(*a_n) {
+            a = a * one_20() / int(*a_n);
+            sum += int(*x_n);
+        }
+    }
+
+    //ln(a) = 2 * artanh(z) with z = (a - 1) / (a + 1), which converges quickly since a is now close to one
+    let z = (a - one_20()) * one_20() / (a + one_20());
+    let z_squared = z * z / one_20();
+
+    let mut num = z;
+    let mut series_sum = num;
+    for n in [3, 5, 7, 9, 11] {
+        num = num * z_squared / one_20();
+        series_sum += num / int(n);
+    }
+
+    (sum + series_sum * int(2)) / int(100)
+}
+
+//Natural logarithm with 36 decimals for x close to one, mirroring `LogExpMath._ln_36`
+fn ln_36(mut x: I256) -> I256 {
+    x *= one_18();
+
+    let z = (x - one_36()) * one_36() / (x + one_36());
+    let z_squared = z * z / one_36();
+
+    let mut num = z;
+    let mut series_sum = num;
+    for n in [3, 5, 7, 9, 11, 13, 15] {
+        num = num * z_squared / one_36();
+        series_sum += num / int(n);
+    }
+
+    series_sum * int(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{I256, U256};
+
+    use super::{calc_in_given_out, calc_out_given_in, exp, pow, ONE};
+
+    fn e18(value: u128) -> U256 {
+        U256::from(value) * ONE
+    }
+
+    //Regression values for the fixed point functions. Swaps through this math are compared to the Vault's
+    //queryBatchSwap in the Balancer V2 pool tests.
+    #[test]
+    fn test_pow() {
+        assert_eq!(
+            pow(U256::exp10(17) * 5, U256::exp10(16) * 25).unwrap(),
+            U256::from(840896415253714543u128)
+        );
+        assert_eq!(
+            pow(U256::exp10(16) * 95, e18(3)).unwrap(),
+            U256::from(857375000000000000u128)
+        );
+        assert_eq!(
+            pow(e18(2), U256::exp10(17) * 5).unwrap(),
+            U256::from(1414213562373095047u128)
+        );
+        assert_eq!(
+            exp(I256::exp10(18)).unwrap(),
+            I256::from(2718281828459045235u128 as i128)
+        );
+        assert_eq!(
+            exp(-(I256::exp10(18) * I256::from(3))).unwrap(),
+            I256::from(49787068367863942i128)
+        );
+        assert!(pow(e18(10), e18(1000)).is_err());
+    }
+
+    #[test]
+    fn test_calc_out_given_in() {
+        //80/20 pool with 13M of the 80% token and 9000 of the 20% token
+        let balance_80 = e18(13_000_000);
+        let balance_20 = e18(9_000);
+        let weight_80 = U256::exp10(17) * 8;
+        let weight_20 = U256::exp10(17) * 2;
+
+        assert_eq!(
+            calc_out_given_in(balance_20, weight_20, balance_80, weight_80, e18(10)).unwrap(),
+            U256::from(3608605482810289000000u128)
+        );
+        assert!(
+            calc_out_given_in(balance_20, weight_20, balance_80, weight_80, e18(3_000)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_calc_in_given_out() {
+        let balance_80 = e18(13_000_000);
+        let balance_20 = e18(9_000);
+        let weight_80 = U256::exp10(17) * 8;
+        let weight_20 = U256::exp10(17) * 2;
+
+        let amount_in =
+            calc_in_given_out(balance_20, weight_20, balance_80, weight_80, e18(10_000)).unwrap();
+        let amount_out =
+            calc_out_given_in(balance_20, weight_20, balance_80, weight_80, amount_in).unwrap();
+
+        assert_eq!(amount_in, U256::from(27745644170508120000u128));
+
+        //Both directions round in favor of the pool, so swapping the amount in back out receives slightly less
+        assert!(amount_out <= e18(10_000));
+        assert!(e18(10_000) - amount_out < e18(1) / 1000);
+    }
+}
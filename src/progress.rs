@@ -0,0 +1,287 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[cfg(any(feature = "indicatif", feature = "tracing"))]
+use std::{collections::HashMap, sync::Mutex};
+
+#[cfg(any(feature = "indicatif", feature = "tracing"))]
+use ethers::types::H160;
+
+#[cfg(any(feature = "indicatif", feature = "tracing"))]
+use crate::dex::DexVariant;
+use crate::{
+    dex::Dex,
+    stats::{RequestKind, SyncStats},
+};
+
+//Phases that each dex goes through while syncing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyncPhase {
+    //Getting the addresses of all pools from the factory, counted in blocks or pairs
+    GetPools,
+    //Getting the pool data via batched calls, counted in pools
+    GetPoolData,
+}
+
+//Receives the progress of each dex while syncing. Phases of different dexes are reported concurrently.
+pub trait SyncProgress: Send + Sync {
+    fn phase_started(&self, dex: &Dex, phase: SyncPhase, total: u64);
+    fn progressed(&self, dex: &Dex, phase: SyncPhase, amount: u64);
+    fn phase_finished(&self, dex: &Dex, phase: SyncPhase);
+
+    //Called after every attempt of a request that was made for the dex
+    fn request_completed(
+        &self,
+        _dex: &Dex,
+        _request: RequestKind,
+        _latency: Duration,
+        _success: bool,
+    ) {
+    }
+
+    //Called once with the stats of the whole sync, ie. to push them to a metrics backend
+    fn sync_finished(&self, _sync_stats: &SyncStats) {}
+}
+
+//Progress reporter that ignores all progress
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl SyncProgress for NoProgress {
+    fn phase_started(&self, _dex: &Dex, _phase: SyncPhase, _total: u64) {}
+    fn progressed(&self, _dex: &Dex, _phase: SyncPhase, _amount: u64) {}
+    fn phase_finished(&self, _dex: &Dex, _phase: SyncPhase) {}
+}
+
+//Progress reporter that is used when no reporter is passed, which draws progress bars when the `indicatif` feature is enabled
+pub fn default_sync_progress() -> Arc<dyn SyncProgress> {
+    #[cfg(feature = "indicatif")]
+    {
+        Arc::new(IndicatifProgress::new())
+    }
+
+    #[cfg(not(feature = "indicatif"))]
+    {
+        Arc::new(NoProgress)
+    }
+}
+
+//Reports the progress of a single phase of a dex, so that the phase can be passed to functions that do not know about the dex
+#[derive(Clone)]
+pub struct PhaseProgress {
+    sync_progress: Arc<dyn SyncProgress>,
+    dex: Dex,
+    phase: SyncPhase,
+}
+
+impl PhaseProgress {
+    pub fn new(sync_progress: Arc<dyn SyncProgress>, dex: Dex, phase: SyncPhase) -> PhaseProgress {
+        PhaseProgress {
+            sync_progress,
+            dex,
+            phase,
+        }
+    }
+
+    pub fn start(&self, total: u64) {
+        self.sync_progress
+            .phase_started(&self.dex, self.phase, total);
+    }
+
+    pub fn inc(&self, amount: u64) {
+        self.sync_progress.progressed(&self.dex, self.phase, amount);
+    }
+
+    pub fn finish(&self) {
+        self.sync_progress.phase_finished(&self.dex, self.phase);
+    }
+
+    //Awaits the request and reports its latency and whether it succeeded
+    pub async fn record_request<T, E, Fut>(
+        &self,
+        request: RequestKind,
+        request_future: Fut,
+    ) -> Result<T, E>
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let started_at = Instant::now();
+        let result = request_future.await;
+
+        self.sync_progress.request_completed(
+            &self.dex,
+            request,
+            started_at.elapsed(),
+            result.is_ok(),
+        );
+
+        result
+    }
+}
+
+#[cfg(any(feature = "indicatif", feature = "tracing"))]
+fn dex_variant_name(dex_variant: DexVariant) -> &'static str {
+    match dex_variant {
+        DexVariant::UniswapV2 => "Uniswap V2",
+        DexVariant::UniswapV3 => "Uniswap V3",
+        DexVariant::Solidly => "Solidly",
+        DexVariant::Curve => "Curve",
+        DexVariant::BalancerV2 => "Balancer V2",
+    }
+}
+
+//Draws a progress bar for each dex
+#[cfg(feature = "indicatif")]
+pub struct IndicatifProgress {
+    multi_progress: indicatif::MultiProgress,
+    progress_bars: Mutex<HashMap<(H160, DexVariant), indicatif::ProgressBar>>,
+}
+
+#[cfg(feature = "indicatif")]
+impl Default for IndicatifProgress {
+    fn default() -> Self {
+        IndicatifProgress {
+            multi_progress: indicatif::MultiProgress::new(),
+            progress_bars: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl IndicatifProgress {
+    pub fn new() -> IndicatifProgress {
+        IndicatifProgress::default()
+    }
+
+    fn progress_bar(&self, dex: &Dex) -> indicatif::ProgressBar {
+        self.progress_bars
+            .lock()
+            .unwrap()
+            .entry((dex.factory_address(), dex.dex_variant()))
+            .or_insert_with(|| self.multi_progress.add(indicatif::ProgressBar::new(0)))
+            .clone()
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl SyncProgress for IndicatifProgress {
+    fn phase_started(&self, dex: &Dex, phase: SyncPhase, total: u64) {
+        let progress_bar = self.progress_bar(dex);
+
+        progress_bar.reset();
+        progress_bar.set_style(
+            indicatif::ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
+                .expect("Error when setting progress bar style")
+                .progress_chars("##-"),
+        );
+        progress_bar.set_length(total);
+
+        //Pools from a checkpoint are synced with a dex that has no factory address
+        let message = match phase {
+            SyncPhase::GetPools => format!("Getting all pools from: {}", dex.factory_address()),
+            SyncPhase::GetPoolData if dex.factory_address().is_zero() => format!(
+                "Syncing all {} pool variants from checkpoint",
+                dex_variant_name(dex.dex_variant())
+            ),
+            SyncPhase::GetPoolData => {
+                format!("Getting all pool data for: {}", dex.factory_address())
+            }
+        };
+        progress_bar.set_message(message);
+    }
+
+    fn progressed(&self, dex: &Dex, _phase: SyncPhase, amount: u64) {
+        self.progress_bar(dex).inc(amount);
+    }
+
+    fn phase_finished(&self, dex: &Dex, phase: SyncPhase) {
+        let progress_bar = self.progress_bar(dex);
+
+        if phase == SyncPhase::GetPoolData {
+            progress_bar.set_message(format!(
+                "Finished syncing {} pools for {}",
+                dex_variant_name(dex.dex_variant()),
+                dex.factory_address()
+            ));
+        }
+
+        progress_bar.finish();
+    }
+}
+
+//Emits a tracing event when a phase starts or finishes and for each step of progress
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+pub struct TracingProgress {
+    //Position and total of each phase that is in progress
+    positions: Mutex<HashMap<PhaseKey, (u64, u64)>>,
+}
+
+#[cfg(feature = "tracing")]
+type PhaseKey = (H160, DexVariant, SyncPhase);
+
+#[cfg(feature = "tracing")]
+impl TracingProgress {
+    pub fn new() -> TracingProgress {
+        TracingProgress::default()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl SyncProgress for TracingProgress {
+    fn phase_started(&self, dex: &Dex, phase: SyncPhase, total: u64) {
+        self.positions.lock().unwrap().insert(
+            (dex.factory_address(), dex.dex_variant(), phase),
+            (0, total),
+        );
+
+        tracing::info!(
+            factory_address = ?dex.factory_address(),
+            dex_variant = dex_variant_name(dex.dex_variant()),
+            ?phase,
+            total,
+            "Sync phase started"
+        );
+    }
+
+    fn progressed(&self, dex: &Dex, phase: SyncPhase, amount: u64) {
+        let (position, total) = {
+            let mut positions = self.positions.lock().unwrap();
+            let (position, total) = positions
+                .entry((dex.factory_address(), dex.dex_variant(), phase))
+                .or_default();
+            *position += amount;
+            (*position, *total)
+        };
+
+        tracing::debug!(
+            factory_address = ?dex.factory_address(),
+            dex_variant = dex_variant_name(dex.dex_variant()),
+            ?phase,
+            position,
+            total,
+            "Sync progressed"
+        );
+    }
+
+    fn phase_finished(&self, dex: &Dex, phase: SyncPhase) {
+        let (position, total) = self
+            .positions
+            .lock()
+            .unwrap()
+            .remove(&(dex.factory_address(), dex.dex_variant(), phase))
+            .unwrap_or_default();
+
+        tracing::info!(
+            factory_address = ?dex.factory_address(),
+            dex_variant = dex_variant_name(dex.dex_variant()),
+            ?phase,
+            position,
+            total,
+            "Sync phase finished"
+        );
+    }
+}
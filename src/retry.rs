@@ -0,0 +1,432 @@
+use std::{
+    collections::{hash_map::RandomState, HashSet},
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use ethers::providers::{Middleware, MiddlewareError};
+
+use crate::{errors::CFMMError, logs, telemetry};
+
+//Classes of errors that are caused by the node or the network rather than the request itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryableError {
+    RateLimited,
+    Timeout,
+    Connection,
+    ServerError,
+}
+
+impl RetryableError {
+    pub fn all() -> HashSet<RetryableError> {
+        HashSet::from([
+            RetryableError::RateLimited,
+            RetryableError::Timeout,
+            RetryableError::Connection,
+            RetryableError::ServerError,
+        ])
+    }
+
+    //Classifies an error from the middleware or provider, returning None if retrying the request would not change the result
+    pub fn classify<E: MiddlewareError>(error: &E) -> Option<RetryableError> {
+        //Requesting the same log range again fails the same way, the range has to be split instead
+        if logs::is_range_too_large(error) {
+            return None;
+        }
+
+        if let Some(error_response) = error.as_error_response() {
+            let message = error_response.message.to_lowercase();
+
+            //-32005 is the limit exceeded code used by most providers, 429 is forwarded from the http status.
+            //Only request limits are matched by message, "gas limit exceeded" and similar execution errors fail the same way every time.
+            if error_response.code == 429
+                || error_response.code == -32005
+                || message.contains("rate limit")
+                || message.contains("too many requests")
+                || message.contains("quota exceeded")
+                || message.contains("request limit exceeded")
+                || message.contains("compute units exceeded")
+            {
+                return Some(RetryableError::RateLimited);
+            }
+
+            if message.contains("timeout") || message.contains("timed out") {
+                return Some(RetryableError::Timeout);
+            }
+
+            if error_response.code == -32603 || message.contains("header not found") {
+                return Some(RetryableError::ServerError);
+            }
+
+            return None;
+        }
+
+        let message = error.to_string().to_lowercase();
+
+        if message.contains("429") || message.contains("too many requests") {
+            Some(RetryableError::RateLimited)
+        } else if message.contains("timeout") || message.contains("timed out") {
+            Some(RetryableError::Timeout)
+        } else if message.contains("connection")
+            || message.contains("error sending request")
+            || message.contains("broken pipe")
+        {
+            Some(RetryableError::Connection)
+        } else if message.contains("502")
+            || message.contains("503")
+            || message.contains("504")
+            || message.contains("bad gateway")
+            || message.contains("service unavailable")
+        {
+            Some(RetryableError::ServerError)
+        } else {
+            None
+        }
+    }
+
+    pub fn classify_cfmm_error<M: Middleware>(error: &CFMMError<M>) -> Option<RetryableError> {
+        match error {
+            CFMMError::MiddlewareError(error) => RetryableError::classify(error),
+            CFMMError::ProviderError(error) => RetryableError::classify(error),
+            CFMMError::ContractError(error) => {
+                if let Some(error) = error.as_middleware_error() {
+                    RetryableError::classify(error)
+                } else if let Some(error) = error.as_provider_error() {
+                    RetryableError::classify(error)
+                } else {
+                    None
+                }
+            }
+            CFMMError::MulticallError(error) => {
+                if let Some(error) = error.as_middleware_error() {
+                    RetryableError::classify(error)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    //Total number of attempts, including the first request. 1 disables retries.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    //Percentage of the backoff that is randomized so that concurrent tasks do not retry at the same time
+    pub jitter_percent: u8,
+    pub retryable_errors: HashSet<RetryableError>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter_percent: 50,
+            retryable_errors: RetryableError::all(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(
+        max_attempts: u32,
+        initial_backoff: Duration,
+        max_backoff: Duration,
+        jitter_percent: u8,
+        retryable_errors: HashSet<RetryableError>,
+    ) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff,
+            max_backoff,
+            jitter_percent: jitter_percent.min(100),
+            retryable_errors,
+        }
+    }
+
+    //Policy that makes every request exactly once
+    pub fn no_retry() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_retryable<M: Middleware>(&self, error: &CFMMError<M>) -> bool {
+        match RetryableError::classify_cfmm_error(error) {
+            Some(retryable_error) => self.retryable_errors.contains(&retryable_error),
+            None => false,
+        }
+    }
+
+    //Exponential backoff before the retry following the `attempt`th failed attempt, starting at 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+
+        let jitter = backoff.as_nanos() as u64 * self.jitter_percent.min(100) as u64 / 100;
+        if jitter == 0 {
+            return backoff;
+        }
+
+        //Randomly removes up to `jitter` from the backoff
+        let random = RandomState::new().build_hasher().finish();
+        backoff - Duration::from_nanos(random % (jitter + 1))
+    }
+
+    //Runs the request until it succeeds, fails with an error that is not retryable or runs out of attempts
+    pub async fn retry<T, M, F, Fut>(&self, mut request: F) -> Result<T, CFMMError<M>>
+    where
+        M: Middleware,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CFMMError<M>>>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            match request().await {
+                Ok(result) => return Ok(result),
+                Err(error) if attempt < self.max_attempts && self.is_retryable(&error) => {
+                    let backoff = self.backoff(attempt);
+                    telemetry::warn_event!(
+                        attempt,
+                        max_attempts = self.max_attempts,
+                        backoff_ms = backoff.as_millis() as u64,
+                        error_class = ?RetryableError::classify_cfmm_error(&error),
+                        %error,
+                        "Retrying request"
+                    );
+
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use ethers::{
+        providers::Middleware,
+        types::{BlockNumber, Filter, Log, H160, U64},
+    };
+
+    use super::{RetryPolicy, RetryableError};
+    use crate::{
+        dex::{Dex, DexVariant},
+        errors::CFMMError,
+        progress::NoProgress,
+        test_utils::mock_provider,
+        throttle::RequestThrottle,
+    };
+
+    fn fast_retry_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(
+            max_attempts,
+            Duration::from_millis(1),
+            Duration::from_millis(4),
+            0,
+            RetryableError::all(),
+        )
+    }
+
+    async fn get_block_number<M: Middleware>(
+        retry_policy: &RetryPolicy,
+        middleware: Arc<M>,
+    ) -> Result<U64, CFMMError<M>> {
+        retry_policy
+            .retry(|| async {
+                middleware
+                    .get_block_number()
+                    .await
+                    .map_err(CFMMError::MiddlewareError)
+            })
+            .await
+    }
+
+    async fn get_logs<M: Middleware>(
+        retry_policy: &RetryPolicy,
+        middleware: Arc<M>,
+    ) -> Result<Vec<Log>, CFMMError<M>> {
+        retry_policy
+            .retry(|| async {
+                middleware
+                    .get_logs(&Filter::new())
+                    .await
+                    .map_err(CFMMError::MiddlewareError)
+            })
+            .await
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new(
+            10,
+            Duration::from_millis(100),
+            Duration::from_millis(1000),
+            0,
+            RetryableError::all(),
+        );
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(100), Duration::from_millis(1000));
+
+        let policy = RetryPolicy {
+            jitter_percent: 50,
+            ..policy
+        };
+
+        for _ in 0..100 {
+            let backoff = policy.backoff(2);
+            assert!(backoff >= Duration::from_millis(100) && backoff <= Duration::from_millis(200));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let (middleware, client) = mock_provider();
+
+        client.push_rate_limit_failure();
+        client.push_failure(-32603, "Internal error");
+        client.push(U64::from(17000000));
+
+        let block_number = get_block_number(&fast_retry_policy(3), middleware.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(block_number, U64::from(17000000));
+        assert_eq!(client.remaining_responses(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_retry_attempts_exhausted() {
+        let (middleware, client) = mock_provider();
+
+        client.push_rate_limit_failure();
+        client.push_rate_limit_failure();
+        client.push::<Vec<Log>>(vec![]);
+
+        let result = get_logs(&fast_retry_policy(2), middleware.clone()).await;
+
+        assert!(matches!(result, Err(CFMMError::MiddlewareError(_))));
+        assert_eq!(client.remaining_responses(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_non_retryable_error() {
+        let (middleware, client) = mock_provider();
+
+        client.push_failure(-32602, "invalid params");
+        client.push_rate_limit_failure();
+        client.push(U64::from(17000000));
+
+        let result = get_block_number(&fast_retry_policy(5), middleware.clone()).await;
+
+        assert!(result.is_err());
+        //The request is not retried, so the rate limit failure and the block number are never returned
+        assert_eq!(client.remaining_responses(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retryable_error_classes() {
+        let (middleware, client) = mock_provider();
+
+        //Only timeouts are retried, so the rate limit error is returned
+        let policy = RetryPolicy {
+            retryable_errors: [RetryableError::Timeout].into_iter().collect(),
+            ..fast_retry_policy(5)
+        };
+
+        client.push_failure(-32000, "request timed out");
+        client.push_rate_limit_failure();
+        client.push(U64::from(17000000));
+
+        match get_block_number(&policy, middleware.clone()).await {
+            Err(error) => assert_eq!(
+                RetryableError::classify_cfmm_error(&error),
+                Some(RetryableError::RateLimited)
+            ),
+            Ok(_) => panic!("Expected the rate limit error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execution_errors_not_rate_limited() {
+        let (middleware, client) = mock_provider();
+
+        for message in [
+            "max code size exceeded",
+            "gas limit exceeded",
+            "max initcode size exceeded",
+        ] {
+            client.push_failure(-32000, message);
+            client.push(U64::from(17000000));
+
+            match get_block_number(&fast_retry_policy(3), middleware.clone()).await {
+                Err(error) => assert_eq!(RetryableError::classify_cfmm_error(&error), None),
+                Ok(_) => panic!("Expected {message} to not be retried"),
+            }
+
+            //Pop the response that a retry would have received
+            get_block_number(&fast_retry_policy(1), middleware.clone())
+                .await
+                .unwrap();
+        }
+
+        for message in [
+            "daily request limit exceeded",
+            "Quota exceeded for this project",
+            "rate limit exceeded",
+        ] {
+            client.push_failure(-32000, message);
+            client.push(U64::from(17000000));
+
+            get_block_number(&fast_retry_policy(2), middleware.clone())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(client.remaining_responses(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dex_get_logs_retried() {
+        let (middleware, client) = mock_provider();
+
+        client.push_rate_limit_failure();
+        client.push_failure(-32000, "request timed out");
+        client.push::<Vec<Log>>(vec![]);
+
+        let dex = Dex::new(H160::zero(), DexVariant::UniswapV3, 0, None);
+        let pools = dex
+            .get_all_pools_from_logs_within_range(
+                BlockNumber::Number(0.into()),
+                BlockNumber::Number(10.into()),
+                100,
+                Arc::new(RequestThrottle::new(0)),
+                Arc::new(fast_retry_policy(3)),
+                Arc::new(NoProgress),
+                middleware.clone(),
+            )
+            .await
+            .unwrap();
+
+        assert!(pools.is_empty());
+        assert_eq!(client.remaining_responses(), 0);
+    }
+}
@@ -0,0 +1,281 @@
+use std::sync::Arc;
+
+use ethers::{
+    providers::Middleware,
+    types::{H160, U256},
+};
+
+use crate::{
+    errors::{CFMMError, RouteError},
+    pool::Pool,
+};
+
+//A single swap in a route, the amounts are recorded by the last simulation of the route
+#[derive(Debug, Clone)]
+pub struct Hop {
+    pub pool: Pool,
+    pub token_in: H160,
+    pub token_out: H160,
+    pub amount_in: U256,
+    pub amount_out: U256,
+}
+
+//A sequence of hops where the token out of each hop is the token in of the next hop
+#[derive(Debug, Clone)]
+pub struct Route {
+    hops: Vec<Hop>,
+}
+
+impl Route {
+    //Creates a route from (pool, token_in, token_out) hops, checking that each pool contains
+    //its tokens and that each hop starts with the token out of the previous hop
+    pub fn new(hops: Vec<(Pool, H160, H160)>) -> Result<Route, RouteError> {
+        if hops.is_empty() {
+            return Err(RouteError::EmptyRoute);
+        }
+
+        let mut previous_token_out: Option<H160> = None;
+        for (pool, token_in, token_out) in hops.iter() {
+            pool.check_tokens(*token_in, *token_out)?;
+
+            if let Some(previous_token_out) = previous_token_out {
+                if previous_token_out != *token_in {
+                    return Err(RouteError::TokenMismatch {
+                        pool: pool.address(),
+                        token_in: *token_in,
+                        token_out: *token_out,
+                    });
+                }
+            }
+
+            previous_token_out = Some(*token_out);
+        }
+
+        Ok(Route {
+            hops: hops
+                .into_iter()
+                .map(|(pool, token_in, token_out)| Hop {
+                    pool,
+                    token_in,
+                    token_out,
+                    amount_in: U256::zero(),
+                    amount_out: U256::zero(),
+                })
+                .collect(),
+        })
+    }
+
+    pub fn hops(&self) -> &[Hop] {
+        &self.hops
+    }
+
+    pub fn pools(&self) -> Vec<&Pool> {
+        self.hops.iter().map(|hop| &hop.pool).collect()
+    }
+
+    pub fn token_in(&self) -> H160 {
+        self.hops[0].token_in
+    }
+
+    pub fn token_out(&self) -> H160 {
+        self.hops[self.hops.len() - 1].token_out
+    }
+
+    pub fn len(&self) -> usize {
+        self.hops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hops.is_empty()
+    }
+
+    //Simulates swapping `amount_in` through the route without changing the pools, recording the amounts of each hop
+    pub async fn simulate<M: Middleware>(
+        &mut self,
+        amount_in: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        let mut amount = amount_in;
+
+        for hop in self.hops.iter_mut() {
+            hop.amount_in = amount;
+            hop.amount_out = hop
+                .pool
+                .simulate_swap_to_token(hop.token_in, hop.token_out, amount, middleware.clone())
+                .await?;
+
+            amount = hop.amount_out;
+        }
+
+        Ok(amount)
+    }
+
+    //Same as `simulate`, but the state of each pool in the route is updated by the swap
+    pub async fn simulate_mut<M: Middleware>(
+        &mut self,
+        amount_in: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        let mut amount = amount_in;
+
+        for hop in self.hops.iter_mut() {
+            hop.amount_in = amount;
+            hop.amount_out = hop
+                .pool
+                .simulate_swap_to_token_mut(hop.token_in, hop.token_out, amount, middleware.clone())
+                .await?;
+
+            amount = hop.amount_out;
+        }
+
+        Ok(amount)
+    }
+
+    //Returns the amount of token in needed to receive `amount_out` from the last hop, walking the route in reverse
+    pub async fn simulate_exact_out<M: Middleware>(
+        &mut self,
+        amount_out: U256,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        let mut amount = amount_out;
+
+        for hop in self.hops.iter_mut().rev() {
+            hop.amount_out = amount;
+            hop.amount_in = hop
+                .pool
+                .simulate_swap_exact_out_to_token(
+                    hop.token_in,
+                    hop.token_out,
+                    amount,
+                    middleware.clone(),
+                )
+                .await?;
+
+            amount = hop.amount_in;
+        }
+
+        Ok(amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::{H160, U256},
+    };
+
+    use super::Route;
+    use crate::{
+        errors::RouteError,
+        pool::{curve::rate_from_decimals, CurvePool, Pool, UniswapV2Pool},
+    };
+
+    fn v2_pool(address: u64, token_a: H160, token_b: H160) -> Pool {
+        Pool::UniswapV2(UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            token_a,
+            token_b,
+            reserve_0: 1_000_000_000_000_000_000_000,
+            reserve_1: 2_000_000_000_000_000_000_000,
+            fee: 300,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_route_validation() {
+        let token_a = H160::from_low_u64_be(1);
+        let token_b = H160::from_low_u64_be(2);
+        let token_c = H160::from_low_u64_be(3);
+
+        let pool_ab = v2_pool(100, token_a, token_b);
+        let pool_bc = v2_pool(101, token_b, token_c);
+
+        assert!(matches!(Route::new(vec![]), Err(RouteError::EmptyRoute)));
+
+        //Token out is not in the pool
+        assert!(matches!(
+            Route::new(vec![(pool_ab.clone(), token_a, token_c)]),
+            Err(RouteError::TokenMismatch { .. })
+        ));
+
+        //Second hop does not start with the token out of the first hop
+        assert!(matches!(
+            Route::new(vec![
+                (pool_ab.clone(), token_b, token_a),
+                (pool_bc.clone(), token_b, token_c),
+            ]),
+            Err(RouteError::TokenMismatch { .. })
+        ));
+
+        let route = Route::new(vec![
+            (pool_ab, token_a, token_b),
+            (pool_bc, token_b, token_c),
+        ])
+        .unwrap();
+
+        assert_eq!(route.len(), 2);
+        assert_eq!(route.token_in(), token_a);
+        assert_eq!(route.token_out(), token_c);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_route_with_explicit_token_out() {
+        //Neither pool makes any calls to the node, so the provider is never used
+        let middleware = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+
+        let token_a = H160::from_low_u64_be(1);
+        let dai = H160::from_low_u64_be(10);
+        let usdc = H160::from_low_u64_be(11);
+        let usdt = H160::from_low_u64_be(12);
+
+        let curve_pool = Pool::Curve(CurvePool {
+            address: H160::from_low_u64_be(200),
+            coins: vec![dai, usdc, usdt],
+            decimals: vec![18, 6, 6],
+            balances: vec![
+                U256::from(150_000_000u128) * U256::exp10(18),
+                U256::from(160_000_000_000_000u128),
+                U256::from(70_000_000_000_000u128),
+            ],
+            rates: vec![
+                rate_from_decimals(18),
+                rate_from_decimals(6),
+                rate_from_decimals(6),
+            ],
+            fee: 1_000_000,
+            admin_fee: 5_000_000_000,
+            initial_a: 2000,
+            future_a: 2000,
+            a_precision: 1,
+            ..Default::default()
+        });
+
+        //Swapping dai to usdt skips usdc, which is the coin token_out would infer
+        let mut route = Route::new(vec![
+            (v2_pool(100, token_a, dai), token_a, dai),
+            (curve_pool.clone(), dai, usdt),
+        ])
+        .unwrap();
+
+        let amount_in = U256::exp10(18);
+        let amount_out = route.simulate(amount_in, middleware.clone()).await.unwrap();
+
+        let hops = route.hops();
+        assert_eq!(hops[0].amount_in, amount_in);
+        assert_eq!(hops[1].amount_in, hops[0].amount_out);
+        assert_eq!(hops[1].amount_out, amount_out);
+
+        let Pool::Curve(pool) = &curve_pool else {
+            unreachable!()
+        };
+        assert_eq!(
+            amount_out,
+            pool.simulate_swap::<Provider<Http>>(dai, usdt, hops[0].amount_out)
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,759 @@
+pub mod state_history;
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use ethers::{
+    providers::Middleware,
+    types::{Filter, Log, ValueOrArray, H160, H256},
+};
+use tokio::{
+    sync::{
+        mpsc::{self, Receiver},
+        RwLock,
+    },
+    task::JoinHandle,
+};
+
+use crate::{
+    errors::CFMMError,
+    logs::LogFetcher,
+    pool::{self, balancer_v2, curve, solidly, uniswap_v2, uniswap_v3, Pool},
+};
+
+use self::state_history::StateHistory;
+
+//Buffer size of the channel used to send state changes to the subscriber
+pub const STATE_CHANGE_CHANNEL_BUFFER: usize = 100;
+
+pub type StateSpace = HashMap<H160, Pool>;
+
+//Set of pools that were updated by the logs within a single block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChange {
+    pub block_number: u64,
+    pub pools: HashSet<H160>,
+}
+
+pub struct StateSpaceManager<M: Middleware> {
+    pub state: Arc<RwLock<StateSpace>>,
+    pub last_synced_block: u64,
+    pub middleware: Arc<M>,
+    //When set, the state is synced block by block and rolled back on reorgs
+    pub state_history: Option<StateHistory>,
+}
+
+impl<M: Middleware> StateSpaceManager<M> {
+    //Creates a new state space from pools that were synced at `last_synced_block`
+    pub fn new(pools: Vec<Pool>, last_synced_block: u64, middleware: Arc<M>) -> Self {
+        let state = pools
+            .into_iter()
+            .map(|pool| (pool.address(), pool))
+            .collect::<StateSpace>();
+
+        StateSpaceManager {
+            state: Arc::new(RwLock::new(state)),
+            last_synced_block,
+            middleware,
+            state_history: None,
+        }
+    }
+
+    //Creates a new state space that keeps the last `max_depth` blocks of state diffs to recover from reorgs
+    pub fn new_with_state_history(
+        pools: Vec<Pool>,
+        last_synced_block: u64,
+        max_depth: usize,
+        middleware: Arc<M>,
+    ) -> Self {
+        StateSpaceManager {
+            state_history: Some(StateHistory::new(max_depth)),
+            ..StateSpaceManager::new(pools, last_synced_block, middleware)
+        }
+    }
+
+    pub fn state_change_event_signatures() -> Vec<H256> {
+        let mut event_signatures = vec![
+            uniswap_v2::SYNC_EVENT_SIGNATURE,
+            uniswap_v3::SWAP_EVENT_SIGNATURE,
+            uniswap_v3::MINT_EVENT_SIGNATURE,
+            uniswap_v3::BURN_EVENT_SIGNATURE,
+            solidly::SYNC_EVENT_SIGNATURE,
+            balancer_v2::SWAP_EVENT_SIGNATURE,
+            balancer_v2::POOL_BALANCE_CHANGED_EVENT_SIGNATURE,
+        ];
+        event_signatures.extend(curve::state_change_event_signatures());
+        event_signatures
+    }
+
+    fn state_change_filter() -> Filter {
+        //The filter is not restricted to the pool addresses since providers limit the number of addresses in a filter
+        Filter::new().topic0(ValueOrArray::Array(
+            Self::state_change_event_signatures()
+                .into_iter()
+                .map(Some)
+                .collect(),
+        ))
+    }
+
+    async fn filter_tracked_logs(&self, logs: Vec<Log>) -> Vec<Log> {
+        let state = self.state.read().await;
+
+        logs.into_iter()
+            .filter(|log| state.contains_key(&pool::pool_address_from_log(log)))
+            .collect()
+    }
+
+    //Gets all state changing logs within the block range that were emitted by pools in the state space.
+    //The range is split if the provider rejects it for returning too many logs.
+    pub async fn get_state_change_logs(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<Log>, CFMMError<M>> {
+        let log_ranges = LogFetcher::new(to_block.saturating_sub(from_block) + 1)
+            .get_logs(
+                &Self::state_change_filter(),
+                from_block,
+                to_block,
+                self.middleware.clone(),
+            )
+            .await?;
+
+        Ok(self
+            .filter_tracked_logs(
+                log_ranges
+                    .into_iter()
+                    .flat_map(|log_range| log_range.logs)
+                    .collect(),
+            )
+            .await)
+    }
+
+    //Gets all state changing logs within the block that were emitted by pools in the state space
+    pub async fn get_state_change_logs_at_block_hash(
+        &self,
+        block_hash: H256,
+    ) -> Result<Vec<Log>, CFMMError<M>> {
+        let logs = self
+            .middleware
+            .get_logs(&Self::state_change_filter().at_block_hash(block_hash))
+            .await
+            .map_err(CFMMError::MiddlewareError)?;
+
+        Ok(self.filter_tracked_logs(logs).await)
+    }
+
+    //Clones the pools in the state space that emitted the logs. Logs are applied to the copies, which are written back
+    //once every log is applied, so that the state is not locked while pools make requests to the node and a log that
+    //fails to apply leaves the state unchanged.
+    async fn tracked_pools(&self, logs: &[Log]) -> HashMap<H160, Pool> {
+        let state = self.state.read().await;
+
+        logs.iter()
+            .filter(|log| !log.topics.is_empty())
+            .filter_map(|log| {
+                let pool_address = pool::pool_address_from_log(log);
+                state
+                    .get(&pool_address)
+                    .map(|pool| (pool_address, pool.clone()))
+            })
+            .collect()
+    }
+
+    //Applies the logs to the pools in the state space, returning the pools that changed in each block.
+    //Removed logs are rejected before any log is applied, since the pools can not be rolled back without state history.
+    //If a log fails to apply, none of the logs are applied.
+    pub async fn handle_state_changes_from_logs(
+        &self,
+        mut logs: Vec<Log>,
+    ) -> Result<Vec<StateChange>, CFMMError<M>> {
+        if let Some(log) = logs.iter().find(|log| log.removed == Some(true)) {
+            return Err(CFMMError::RemovedLog(
+                log.block_number.unwrap_or_default().as_u64(),
+            ));
+        }
+
+        //Make sure logs are applied in the order that they were emitted
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+
+        let mut state_changes: BTreeMap<u64, HashSet<H160>> = BTreeMap::new();
+        let mut updated_pools = self.tracked_pools(&logs).await;
+
+        for log in logs {
+            if log.topics.is_empty() {
+                continue;
+            }
+
+            let pool_address = pool::pool_address_from_log(&log);
+
+            if let Some(pool) = updated_pools.get_mut(&pool_address) {
+                pool.update_pool_from_log(&log, self.middleware.clone())
+                    .await?;
+
+                let block_number = log.block_number.unwrap_or_default().as_u64();
+                state_changes
+                    .entry(block_number)
+                    .or_default()
+                    .insert(pool_address);
+            }
+        }
+
+        self.state.write().await.extend(updated_pools);
+
+        Ok(state_changes
+            .into_iter()
+            .map(|(block_number, pools)| StateChange {
+                block_number,
+                pools,
+            })
+            .collect())
+    }
+
+    //Applies all state changes from the last synced block up to and including `to_block`
+    pub async fn sync_to_block(&mut self, to_block: u64) -> Result<Vec<StateChange>, CFMMError<M>> {
+        if to_block <= self.last_synced_block {
+            return Ok(vec![]);
+        }
+
+        if self.state_history.is_some() {
+            return self.sync_to_block_with_state_history(to_block).await;
+        }
+
+        let logs = self
+            .get_state_change_logs(self.last_synced_block + 1, to_block)
+            .await?;
+
+        let state_changes = self.handle_state_changes_from_logs(logs).await?;
+        self.last_synced_block = to_block;
+
+        Ok(state_changes)
+    }
+
+    //Applies state changes one block at a time, checking each block's parent hash against the recorded history.
+    //On a reorg, the touched pools are rolled back to the common ancestor and the new canonical blocks are re-applied.
+    async fn sync_to_block_with_state_history(
+        &mut self,
+        to_block: u64,
+    ) -> Result<Vec<StateChange>, CFMMError<M>> {
+        let mut state_changes = vec![];
+        let mut unwound_pools = HashSet::new();
+        let mut block_number = self.last_synced_block + 1;
+
+        while block_number <= to_block {
+            let block = self
+                .middleware
+                .get_block(block_number)
+                .await
+                .map_err(CFMMError::MiddlewareError)?
+                .ok_or(CFMMError::BlockNotFound(block_number))?;
+            let block_hash = block.hash.ok_or(CFMMError::BlockNotFound(block_number))?;
+
+            let is_reorg = self
+                .state_history
+                .as_ref()
+                .expect("State history is not enabled")
+                .is_reorg(block_number, block.parent_hash);
+
+            if is_reorg {
+                let common_ancestor = self.find_common_ancestor().await?;
+
+                let mut state = self.state.write().await;
+                unwound_pools.extend(
+                    self.state_history
+                        .as_mut()
+                        .expect("State history is not enabled")
+                        .unwind_to(common_ancestor, &mut state),
+                );
+
+                self.last_synced_block = common_ancestor;
+                block_number = common_ancestor + 1;
+                continue;
+            }
+
+            let logs = self.get_state_change_logs_at_block_hash(block_hash).await?;
+
+            let mut updated_pools = self.tracked_pools(&logs).await;
+            let mut pools = self
+                .state_history
+                .as_mut()
+                .expect("State history is not enabled")
+                .apply_block_logs(
+                    &mut updated_pools,
+                    block_number,
+                    block_hash,
+                    block.parent_hash,
+                    &logs,
+                    self.middleware.clone(),
+                )
+                .await?;
+            self.state.write().await.extend(updated_pools);
+
+            //Pools that were rolled back are reported with the next block so the subscriber can refresh them
+            pools.extend(unwound_pools.drain());
+
+            if !pools.is_empty() {
+                state_changes.push(StateChange {
+                    block_number,
+                    pools,
+                });
+            }
+
+            self.last_synced_block = block_number;
+            block_number += 1;
+        }
+
+        Ok(state_changes)
+    }
+
+    //Walks the state history from newest to oldest and returns the latest block that is still on the canonical chain
+    async fn find_common_ancestor(&self) -> Result<u64, CFMMError<M>> {
+        let state_history = self
+            .state_history
+            .as_ref()
+            .expect("State history is not enabled");
+        let block_hashes = state_history.block_hashes();
+
+        for (block_number, block_hash) in block_hashes {
+            let canonical_block = self
+                .middleware
+                .get_block(block_number)
+                .await
+                .map_err(CFMMError::MiddlewareError)?
+                .ok_or(CFMMError::BlockNotFound(block_number))?;
+
+            if canonical_block.hash == Some(block_hash) {
+                return Ok(block_number);
+            }
+        }
+
+        //If every recorded block was reorged, the prior state of the oldest block restores its parent
+        if let Some(oldest) = state_history.oldest() {
+            if let Some(parent_number) = oldest.block_number.checked_sub(1) {
+                let canonical_parent = self
+                    .middleware
+                    .get_block(parent_number)
+                    .await
+                    .map_err(CFMMError::MiddlewareError)?
+                    .ok_or(CFMMError::BlockNotFound(parent_number))?;
+
+                if canonical_parent.hash == Some(oldest.parent_hash) {
+                    return Ok(parent_number);
+                }
+            }
+        }
+
+        Err(CFMMError::ReorgTooDeep)
+    }
+
+    //Polls for new blocks and sends the pools that changed in each block through the returned channel.
+    //The task exits when the receiver is dropped or when a request to the node fails.
+    pub fn subscribe_state_changes(
+        mut self,
+        poll_interval: Duration,
+    ) -> (Receiver<StateChange>, JoinHandle<Result<(), CFMMError<M>>>)
+    where
+        M: 'static,
+    {
+        let (tx, rx) = mpsc::channel(STATE_CHANGE_CHANNEL_BUFFER);
+
+        let handle = tokio::spawn(async move {
+            loop {
+                let block_number = self
+                    .middleware
+                    .get_block_number()
+                    .await
+                    .map_err(CFMMError::MiddlewareError)?
+                    .as_u64();
+
+                for state_change in self.sync_to_block(block_number).await? {
+                    if tx.send(state_change).await.is_err() {
+                        return Ok(());
+                    }
+                }
+
+                tokio::time::sleep(poll_interval).await;
+            }
+        });
+
+        (rx, handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc, time::Duration};
+
+    use ethers::{
+        abi::Token,
+        providers::Provider,
+        types::{Block, Log, H160, H256, U256, U64},
+    };
+
+    use super::{StateChange, StateSpaceManager};
+    use crate::{
+        errors::CFMMError,
+        pool::{
+            curve::TOKEN_EXCHANGE_EVENT_SIGNATURE, uniswap_v2::SYNC_EVENT_SIGNATURE, CurvePool,
+            Pool, UniswapV2Pool,
+        },
+    };
+
+    fn sync_log(address: H160, reserve_0: u128, reserve_1: u128, block_number: u64) -> Log {
+        Log {
+            address,
+            topics: vec![SYNC_EVENT_SIGNATURE],
+            data: ethers::abi::encode(&[
+                Token::Uint(U256::from(reserve_0)),
+                Token::Uint(U256::from(reserve_1)),
+            ])
+            .into(),
+            block_number: Some(U64::from(block_number)),
+            log_index: Some(U256::zero()),
+            ..Default::default()
+        }
+    }
+
+    fn block(block_number: u64, block_hash: u64, parent_hash: u64) -> Block<H256> {
+        Block {
+            number: Some(U64::from(block_number)),
+            hash: Some(H256::from_low_u64_be(block_hash)),
+            parent_hash: H256::from_low_u64_be(parent_hash),
+            ..Default::default()
+        }
+    }
+
+    fn uniswap_v2_pool(address: H160) -> Pool {
+        Pool::UniswapV2(UniswapV2Pool {
+            address,
+            reserve_0: 1,
+            reserve_1: 1,
+            fee: 300,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_sync_to_block() {
+        let (provider, mock) = Provider::mocked();
+
+        let pool_a = H160::from_low_u64_be(1);
+        let pool_b = H160::from_low_u64_be(2);
+        let untracked_pool = H160::from_low_u64_be(3);
+
+        let mut state_space_manager = StateSpaceManager::new(
+            vec![uniswap_v2_pool(pool_a), uniswap_v2_pool(pool_b)],
+            10,
+            Arc::new(provider),
+        );
+
+        mock.push::<Vec<Log>, _>(vec![
+            sync_log(pool_a, 100, 200, 11),
+            sync_log(untracked_pool, 1, 1, 11),
+            sync_log(pool_a, 300, 400, 12),
+            sync_log(pool_b, 500, 600, 12),
+        ])
+        .unwrap();
+
+        let state_changes = state_space_manager.sync_to_block(12).await.unwrap();
+
+        assert_eq!(
+            state_changes,
+            vec![
+                StateChange {
+                    block_number: 11,
+                    pools: HashSet::from([pool_a]),
+                },
+                StateChange {
+                    block_number: 12,
+                    pools: HashSet::from([pool_a, pool_b]),
+                },
+            ]
+        );
+        assert_eq!(state_space_manager.last_synced_block, 12);
+
+        let state = state_space_manager.state.read().await;
+        match state.get(&pool_a).unwrap() {
+            Pool::UniswapV2(pool) => assert_eq!((pool.reserve_0, pool.reserve_1), (300, 400)),
+            _ => panic!("Unexpected pool variant"),
+        }
+        match state.get(&pool_b).unwrap() {
+            Pool::UniswapV2(pool) => assert_eq!((pool.reserve_0, pool.reserve_1), (500, 600)),
+            _ => panic!("Unexpected pool variant"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_removed_logs_rejected() {
+        let (provider, mock) = Provider::mocked();
+
+        let pool_a = H160::from_low_u64_be(1);
+
+        let mut state_space_manager =
+            StateSpaceManager::new(vec![uniswap_v2_pool(pool_a)], 10, Arc::new(provider));
+
+        let mut removed_log = sync_log(pool_a, 300, 400, 12);
+        removed_log.removed = Some(true);
+
+        mock.push::<Vec<Log>, _>(vec![sync_log(pool_a, 100, 200, 11), removed_log])
+            .unwrap();
+
+        assert!(matches!(
+            state_space_manager.sync_to_block(12).await,
+            Err(CFMMError::RemovedLog(12))
+        ));
+        assert_eq!(state_space_manager.last_synced_block, 10);
+
+        //None of the logs are applied, including the canonical log before the removed log
+        let state = state_space_manager.state.read().await;
+        match state.get(&pool_a).unwrap() {
+            Pool::UniswapV2(pool) => assert_eq!((pool.reserve_0, pool.reserve_1), (1, 1)),
+            _ => panic!("Unexpected pool variant"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_log_leaves_state_unchanged() {
+        let (provider, mock) = Provider::mocked();
+
+        let pool_a = H160::from_low_u64_be(1);
+        let curve_pool = H160::from_low_u64_be(2);
+
+        let mut state_space_manager = StateSpaceManager::new(
+            vec![
+                uniswap_v2_pool(pool_a),
+                Pool::Curve(CurvePool {
+                    address: curve_pool,
+                    coins: vec![H160::from_low_u64_be(10), H160::from_low_u64_be(11)],
+                    ..Default::default()
+                }),
+            ],
+            10,
+            Arc::new(provider),
+        );
+
+        //The exchange log resyncs the curve pool, which fails since the mock provider has no response for the call
+        mock.push::<Vec<Log>, _>(vec![
+            sync_log(pool_a, 100, 200, 11),
+            Log {
+                address: curve_pool,
+                topics: vec![TOKEN_EXCHANGE_EVENT_SIGNATURE],
+                block_number: Some(U64::from(12)),
+                log_index: Some(U256::zero()),
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+
+        assert!(state_space_manager.sync_to_block(12).await.is_err());
+        assert_eq!(state_space_manager.last_synced_block, 10);
+
+        //The sync log before the failed log is not applied, so syncing again does not apply it twice
+        let state = state_space_manager.state.read().await;
+        match state.get(&pool_a).unwrap() {
+            Pool::UniswapV2(pool) => assert_eq!((pool.reserve_0, pool.reserve_1), (1, 1)),
+            _ => panic!("Unexpected pool variant"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_state_changes() {
+        let (provider, mock) = Provider::mocked();
+
+        let pool_a = H160::from_low_u64_be(1);
+
+        let state_space_manager =
+            StateSpaceManager::new(vec![uniswap_v2_pool(pool_a)], 10, Arc::new(provider));
+        let state = state_space_manager.state.clone();
+
+        //Responses are returned in reverse order, first the block number and then the logs
+        mock.push::<Vec<Log>, _>(vec![sync_log(pool_a, 100, 200, 11)])
+            .unwrap();
+        mock.push(U64::from(11)).unwrap();
+
+        let (mut rx, _handle) =
+            state_space_manager.subscribe_state_changes(Duration::from_millis(10));
+
+        let state_change = rx.recv().await.unwrap();
+
+        assert_eq!(
+            state_change,
+            StateChange {
+                block_number: 11,
+                pools: HashSet::from([pool_a]),
+            }
+        );
+
+        let state = state.read().await;
+        match state.get(&pool_a).unwrap() {
+            Pool::UniswapV2(pool) => assert_eq!((pool.reserve_0, pool.reserve_1), (100, 200)),
+            _ => panic!("Unexpected pool variant"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_to_block_with_reorg() {
+        let (provider, mock) = Provider::mocked();
+
+        let pool_a = H160::from_low_u64_be(1);
+        let pool_b = H160::from_low_u64_be(2);
+
+        let mut state_space_manager = StateSpaceManager::new_with_state_history(
+            vec![uniswap_v2_pool(pool_a), uniswap_v2_pool(pool_b)],
+            10,
+            10,
+            Arc::new(provider),
+        );
+
+        //Responses are returned in reverse order, each block is followed by the logs at its block hash
+        mock.push::<Vec<Log>, _>(vec![sync_log(pool_a, 300, 400, 12)])
+            .unwrap();
+        mock.push(block(12, 12, 11)).unwrap();
+        mock.push::<Vec<Log>, _>(vec![sync_log(pool_a, 100, 200, 11)])
+            .unwrap();
+        mock.push(block(11, 11, 10)).unwrap();
+
+        state_space_manager.sync_to_block(12).await.unwrap();
+        assert_eq!(state_space_manager.last_synced_block, 12);
+
+        //Block 12 is replaced by 1012, so the new block 13 does not build on the recorded block 12
+        mock.push::<Vec<Log>, _>(vec![sync_log(pool_b, 700, 800, 13)])
+            .unwrap();
+        mock.push(block(13, 1013, 1012)).unwrap();
+        mock.push::<Vec<Log>, _>(vec![sync_log(pool_a, 500, 600, 12)])
+            .unwrap();
+        mock.push(block(12, 1012, 11)).unwrap();
+        mock.push(block(11, 11, 10)).unwrap();
+        mock.push(block(12, 1012, 11)).unwrap();
+        mock.push(block(13, 1013, 1012)).unwrap();
+
+        let state_changes = state_space_manager.sync_to_block(13).await.unwrap();
+
+        assert_eq!(
+            state_changes,
+            vec![
+                StateChange {
+                    block_number: 12,
+                    pools: HashSet::from([pool_a]),
+                },
+                StateChange {
+                    block_number: 13,
+                    pools: HashSet::from([pool_b]),
+                },
+            ]
+        );
+        assert_eq!(state_space_manager.last_synced_block, 13);
+        assert_eq!(
+            state_space_manager
+                .state_history
+                .as_ref()
+                .unwrap()
+                .block_hash(12),
+            Some(H256::from_low_u64_be(1012))
+        );
+
+        let state = state_space_manager.state.read().await;
+        match state.get(&pool_a).unwrap() {
+            Pool::UniswapV2(pool) => assert_eq!((pool.reserve_0, pool.reserve_1), (500, 600)),
+            _ => panic!("Unexpected pool variant"),
+        }
+        match state.get(&pool_b).unwrap() {
+            Pool::UniswapV2(pool) => assert_eq!((pool.reserve_0, pool.reserve_1), (700, 800)),
+            _ => panic!("Unexpected pool variant"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_to_block_reorg_too_deep() {
+        let (provider, mock) = Provider::mocked();
+
+        let pool_a = H160::from_low_u64_be(1);
+
+        let mut state_space_manager = StateSpaceManager::new_with_state_history(
+            vec![uniswap_v2_pool(pool_a)],
+            10,
+            1,
+            Arc::new(provider),
+        );
+
+        mock.push::<Vec<Log>, _>(vec![sync_log(pool_a, 100, 200, 11)])
+            .unwrap();
+        mock.push(block(11, 11, 10)).unwrap();
+
+        state_space_manager.sync_to_block(11).await.unwrap();
+
+        //The parent of the oldest recorded block was also reorged
+        mock.push(block(10, 1010, 9)).unwrap();
+        mock.push(block(11, 1011, 1010)).unwrap();
+        mock.push(block(12, 1012, 1011)).unwrap();
+
+        assert!(matches!(
+            state_space_manager.sync_to_block(12).await,
+            Err(CFMMError::ReorgTooDeep)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sync_to_block_reorg_to_parent_of_oldest_block() {
+        let (provider, mock) = Provider::mocked();
+
+        let pool_a = H160::from_low_u64_be(1);
+
+        let mut state_space_manager = StateSpaceManager::new_with_state_history(
+            vec![uniswap_v2_pool(pool_a)],
+            10,
+            1,
+            Arc::new(provider),
+        );
+
+        mock.push::<Vec<Log>, _>(vec![sync_log(pool_a, 100, 200, 11)])
+            .unwrap();
+        mock.push(block(11, 11, 10)).unwrap();
+
+        state_space_manager.sync_to_block(11).await.unwrap();
+
+        //Block 11 is replaced by 1011, which still builds on the canonical block 10
+        mock.push::<Vec<Log>, _>(vec![sync_log(pool_a, 500, 600, 12)])
+            .unwrap();
+        mock.push(block(12, 1012, 1011)).unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(block(11, 1011, 10)).unwrap();
+        mock.push(block(10, 10, 9)).unwrap();
+        mock.push(block(11, 1011, 10)).unwrap();
+        mock.push(block(12, 1012, 1011)).unwrap();
+
+        let state_changes = state_space_manager.sync_to_block(12).await.unwrap();
+
+        assert_eq!(
+            state_changes,
+            vec![
+                StateChange {
+                    block_number: 11,
+                    pools: HashSet::from([pool_a]),
+                },
+                StateChange {
+                    block_number: 12,
+                    pools: HashSet::from([pool_a]),
+                },
+            ]
+        );
+        assert_eq!(state_space_manager.last_synced_block, 12);
+        assert_eq!(
+            state_space_manager
+                .state_history
+                .as_ref()
+                .unwrap()
+                .block_hash(12),
+            Some(H256::from_low_u64_be(1012))
+        );
+
+        let state = state_space_manager.state.read().await;
+        match state.get(&pool_a).unwrap() {
+            Pool::UniswapV2(pool) => assert_eq!((pool.reserve_0, pool.reserve_1), (500, 600)),
+            _ => panic!("Unexpected pool variant"),
+        }
+    }
+}
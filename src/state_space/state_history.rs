@@ -0,0 +1,351 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
+
+use ethers::{
+    providers::Middleware,
+    types::{Log, H160, H256},
+};
+
+use crate::{
+    errors::CFMMError,
+    pool::{self, Pool},
+};
+
+//State of the pools touched within a block, before any of the logs in the block were applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStateDiff {
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub parent_hash: H256,
+    pub prior_state: HashMap<H160, Pool>,
+}
+
+//Bounded history of per block state diffs, used to roll pools back to a common ancestor after a reorg
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateHistory {
+    pub max_depth: usize,
+    blocks: VecDeque<BlockStateDiff>,
+}
+
+impl StateHistory {
+    pub fn new(max_depth: usize) -> StateHistory {
+        StateHistory {
+            max_depth,
+            blocks: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn latest(&self) -> Option<&BlockStateDiff> {
+        self.blocks.back()
+    }
+
+    pub fn oldest(&self) -> Option<&BlockStateDiff> {
+        self.blocks.front()
+    }
+
+    pub fn block_hash(&self, block_number: u64) -> Option<H256> {
+        self.blocks
+            .iter()
+            .rev()
+            .find(|block| block.block_number == block_number)
+            .map(|block| block.block_hash)
+    }
+
+    //Returns the recorded block numbers and hashes from newest to oldest
+    pub fn block_hashes(&self) -> Vec<(u64, H256)> {
+        self.blocks
+            .iter()
+            .rev()
+            .map(|block| (block.block_number, block.block_hash))
+            .collect()
+    }
+
+    //Returns true if the parent hash of a new block does not match the hash recorded for the previous block
+    pub fn is_reorg(&self, block_number: u64, parent_hash: H256) -> bool {
+        match self.block_hash(block_number.saturating_sub(1)) {
+            Some(block_hash) => block_hash != parent_hash,
+            None => false,
+        }
+    }
+
+    pub fn push(&mut self, block_state_diff: BlockStateDiff) {
+        self.blocks.push_back(block_state_diff);
+
+        while self.blocks.len() > self.max_depth {
+            self.blocks.pop_front();
+        }
+    }
+
+    //Applies the logs from a block to the state, recording the prior state of every pool that was touched.
+    //If a log fails to apply, the touched pools are restored so that the block is either applied and recorded or not applied at all.
+    pub async fn apply_block_logs<M: Middleware>(
+        &mut self,
+        state: &mut HashMap<H160, Pool>,
+        block_number: u64,
+        block_hash: H256,
+        parent_hash: H256,
+        logs: &[Log],
+        middleware: Arc<M>,
+    ) -> Result<HashSet<H160>, CFMMError<M>> {
+        let mut prior_state = HashMap::new();
+
+        for log in logs {
+            if log.topics.is_empty() || log.removed == Some(true) {
+                continue;
+            }
+
+            let pool_address = pool::pool_address_from_log(log);
+
+            if let Some(pool) = state.get_mut(&pool_address) {
+                prior_state
+                    .entry(pool_address)
+                    .or_insert_with(|| pool.clone());
+
+                if let Err(error) = pool.update_pool_from_log(log, middleware.clone()).await {
+                    state.extend(prior_state);
+                    return Err(error);
+                }
+            }
+        }
+
+        let touched_pools = prior_state.keys().copied().collect();
+
+        self.push(BlockStateDiff {
+            block_number,
+            block_hash,
+            parent_hash,
+            prior_state,
+        });
+
+        Ok(touched_pools)
+    }
+
+    //Rolls back every block after `block_number`, restoring the state of the pools touched in those blocks.
+    //Returns the addresses of the pools that were rolled back.
+    pub fn unwind_to(
+        &mut self,
+        block_number: u64,
+        state: &mut HashMap<H160, Pool>,
+    ) -> HashSet<H160> {
+        let mut unwound_pools = HashSet::new();
+
+        while let Some(block) = self.blocks.back() {
+            if block.block_number <= block_number {
+                break;
+            }
+
+            let block = self.blocks.pop_back().expect("Block history is empty");
+
+            //Blocks are unwound from newest to oldest, so the last restored state is the state at `block_number`
+            for (address, pool) in block.prior_state {
+                state.insert(address, pool);
+                unwound_pools.insert(address);
+            }
+        }
+
+        unwound_pools
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use ethers::{
+        abi::Token,
+        providers::{Http, Provider},
+        types::{Log, H160, H256, U256, U64},
+    };
+
+    use super::StateHistory;
+    use crate::{
+        pool::{
+            curve::TOKEN_EXCHANGE_EVENT_SIGNATURE, uniswap_v2::SYNC_EVENT_SIGNATURE, CurvePool,
+            Pool, UniswapV2Pool,
+        },
+        test_utils::mock_provider,
+    };
+
+    fn sync_log(address: H160, reserve_0: u128, reserve_1: u128, block_number: u64) -> Log {
+        Log {
+            address,
+            topics: vec![SYNC_EVENT_SIGNATURE],
+            data: ethers::abi::encode(&[
+                Token::Uint(U256::from(reserve_0)),
+                Token::Uint(U256::from(reserve_1)),
+            ])
+            .into(),
+            block_number: Some(U64::from(block_number)),
+            ..Default::default()
+        }
+    }
+
+    fn reserves(state: &HashMap<H160, Pool>, address: H160) -> (u128, u128) {
+        match state.get(&address).unwrap() {
+            Pool::UniswapV2(pool) => (pool.reserve_0, pool.reserve_1),
+            _ => panic!("Unexpected pool variant"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_and_unwind() {
+        let middleware = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+
+        let pool_a = H160::from_low_u64_be(1);
+        let pool_b = H160::from_low_u64_be(2);
+
+        let mut state = HashMap::from([
+            (
+                pool_a,
+                Pool::UniswapV2(UniswapV2Pool {
+                    address: pool_a,
+                    reserve_0: 1,
+                    reserve_1: 1,
+                    fee: 300,
+                    ..Default::default()
+                }),
+            ),
+            (
+                pool_b,
+                Pool::UniswapV2(UniswapV2Pool {
+                    address: pool_b,
+                    reserve_0: 1,
+                    reserve_1: 1,
+                    fee: 300,
+                    ..Default::default()
+                }),
+            ),
+        ]);
+
+        let mut state_history = StateHistory::new(10);
+
+        state_history
+            .apply_block_logs(
+                &mut state,
+                11,
+                H256::from_low_u64_be(11),
+                H256::from_low_u64_be(10),
+                &[sync_log(pool_a, 100, 200, 11)],
+                middleware.clone(),
+            )
+            .await
+            .unwrap();
+
+        let touched_pools = state_history
+            .apply_block_logs(
+                &mut state,
+                12,
+                H256::from_low_u64_be(12),
+                H256::from_low_u64_be(11),
+                &[
+                    sync_log(pool_a, 300, 400, 12),
+                    sync_log(pool_a, 500, 600, 12),
+                    sync_log(pool_b, 700, 800, 12),
+                ],
+                middleware.clone(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(touched_pools.len(), 2);
+        assert_eq!(reserves(&state, pool_a), (500, 600));
+        assert_eq!(reserves(&state, pool_b), (700, 800));
+
+        assert!(!state_history.is_reorg(13, H256::from_low_u64_be(12)));
+        assert!(state_history.is_reorg(13, H256::from_low_u64_be(1312)));
+
+        let unwound_pools = state_history.unwind_to(11, &mut state);
+
+        assert_eq!(unwound_pools.len(), 2);
+        assert_eq!(reserves(&state, pool_a), (100, 200));
+        assert_eq!(reserves(&state, pool_b), (1, 1));
+        assert_eq!(state_history.latest().unwrap().block_number, 11);
+
+        state_history.unwind_to(10, &mut state);
+
+        assert_eq!(reserves(&state, pool_a), (1, 1));
+        assert!(state_history.is_empty());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut state_history = StateHistory::new(2);
+
+        for block_number in 1..=3 {
+            state_history.push(super::BlockStateDiff {
+                block_number,
+                block_hash: H256::from_low_u64_be(block_number),
+                parent_hash: H256::from_low_u64_be(block_number - 1),
+                prior_state: HashMap::new(),
+            });
+        }
+
+        assert_eq!(state_history.len(), 2);
+        assert_eq!(state_history.oldest().unwrap().block_number, 2);
+        assert_eq!(state_history.block_hash(1), None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_block_logs_error_restores_state() {
+        let (middleware, _client) = mock_provider();
+
+        let pool_a = H160::from_low_u64_be(1);
+        let curve_pool = H160::from_low_u64_be(2);
+        let mut state = HashMap::from([
+            (
+                pool_a,
+                Pool::UniswapV2(UniswapV2Pool {
+                    address: pool_a,
+                    reserve_0: 1,
+                    reserve_1: 1,
+                    fee: 300,
+                    ..Default::default()
+                }),
+            ),
+            (
+                curve_pool,
+                Pool::Curve(CurvePool {
+                    address: curve_pool,
+                    coins: vec![H160::from_low_u64_be(10), H160::from_low_u64_be(11)],
+                    ..Default::default()
+                }),
+            ),
+        ]);
+
+        let mut state_history = StateHistory::new(10);
+
+        //The exchange log resyncs the curve pool, which fails because the mock provider has no responses
+        let result = state_history
+            .apply_block_logs(
+                &mut state,
+                11,
+                H256::from_low_u64_be(11),
+                H256::from_low_u64_be(10),
+                &[
+                    sync_log(pool_a, 100, 200, 11),
+                    Log {
+                        address: curve_pool,
+                        topics: vec![TOKEN_EXCHANGE_EVENT_SIGNATURE],
+                        block_number: Some(U64::from(11)),
+                        ..Default::default()
+                    },
+                ],
+                middleware.clone(),
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(reserves(&state, pool_a), (1, 1));
+        assert!(state_history.is_empty());
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ethers::{providers::Middleware, types::H160};
+
+use crate::{
+    dex::{Dex, DexVariant},
+    progress::{SyncPhase, SyncProgress},
+    sync::SyncReport,
+};
+
+//Upper bounds of the latency histogram buckets in milliseconds, latencies above the last bound are counted in an overflow bucket
+pub const LATENCY_BUCKETS_MS: [u64; 10] = [10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+//Kinds of requests to the node that are counted while syncing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestKind {
+    EthCall,
+    GetLogs,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    bucket_counts: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|upper_bound| latency <= Duration::from_millis(*upper_bound))
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+
+        self.bucket_counts[bucket] += 1;
+        self.count += 1;
+        self.sum += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+
+        self.sum / self.count as u32
+    }
+
+    //Number of latencies in each bucket with the upper bound of the bucket, the overflow bucket has no upper bound
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        LATENCY_BUCKETS_MS
+            .iter()
+            .map(|upper_bound| Some(Duration::from_millis(*upper_bound)))
+            .chain([None])
+            .zip(self.bucket_counts)
+            .collect()
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (bucket_count, other_count) in self.bucket_counts.iter_mut().zip(other.bucket_counts) {
+            *bucket_count += other_count;
+        }
+
+        self.count += other.count;
+        self.sum += other.sum;
+        self.max = self.max.max(other.max);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestStats {
+    //Every attempt is counted, including attempts that were retried
+    pub requests: u64,
+    pub failed_requests: u64,
+    pub latency: LatencyHistogram,
+}
+
+impl RequestStats {
+    pub fn record(&mut self, latency: Duration, success: bool) {
+        self.requests += 1;
+        if !success {
+            self.failed_requests += 1;
+        }
+
+        self.latency.record(latency);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DexSyncStats {
+    pub factory_address: H160,
+    pub dex_variant: DexVariant,
+    pub eth_calls: RequestStats,
+    pub get_logs: RequestStats,
+    //Pools that were found for the dex, or loaded from the checkpoint
+    pub pools_discovered: u64,
+    pub pools_populated: u64,
+    //Pools that no data was returned for and that were dropped as empty
+    pub pools_dropped: u64,
+    pub pools_failed: u64,
+}
+
+impl DexSyncStats {
+    pub fn new(factory_address: H160, dex_variant: DexVariant) -> DexSyncStats {
+        DexSyncStats {
+            factory_address,
+            dex_variant,
+            eth_calls: RequestStats::default(),
+            get_logs: RequestStats::default(),
+            pools_discovered: 0,
+            pools_populated: 0,
+            pools_dropped: 0,
+            pools_failed: 0,
+        }
+    }
+
+    pub fn request_stats(&self, request: RequestKind) -> &RequestStats {
+        match request {
+            RequestKind::EthCall => &self.eth_calls,
+            RequestKind::GetLogs => &self.get_logs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncStats {
+    //Stats of each dex, ordered by factory address. Pools that are synced from a checkpoint are counted under a dex with a zero factory address.
+    pub dexes: Vec<DexSyncStats>,
+    pub duration: Duration,
+}
+
+impl SyncStats {
+    pub fn dex_stats(&self, dex: &Dex) -> Option<&DexSyncStats> {
+        self.dexes.iter().find(|dex_stats| {
+            dex_stats.factory_address == dex.factory_address()
+                && dex_stats.dex_variant == dex.dex_variant()
+        })
+    }
+
+    pub fn extend(&mut self, other: SyncStats) {
+        self.dexes.extend(other.dexes);
+        self.dexes
+            .sort_by_key(|dex_stats| dex_stats.factory_address);
+        self.duration = self.duration.max(other.duration);
+    }
+
+    pub fn total_requests(&self, request: RequestKind) -> u64 {
+        self.dexes
+            .iter()
+            .map(|dex_stats| dex_stats.request_stats(request).requests)
+            .sum()
+    }
+}
+
+//Collects the stats of a sync while forwarding the progress to another reporter
+pub struct SyncStatsCollector {
+    sync_progress: Arc<dyn SyncProgress>,
+    started_at: Instant,
+    dexes: Mutex<HashMap<(H160, DexVariant), DexSyncStats>>,
+}
+
+impl SyncStatsCollector {
+    pub fn new(sync_progress: Arc<dyn SyncProgress>) -> SyncStatsCollector {
+        SyncStatsCollector {
+            sync_progress,
+            started_at: Instant::now(),
+            dexes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn update_dex_stats(&self, dex: &Dex, update: impl FnOnce(&mut DexSyncStats)) {
+        let mut dexes = self.dexes.lock().unwrap();
+        let dex_stats = dexes
+            .entry((dex.factory_address(), dex.dex_variant()))
+            .or_insert_with(|| DexSyncStats::new(dex.factory_address(), dex.dex_variant()));
+
+        update(dex_stats);
+    }
+
+    //Records the number of pools that were discovered for the dex and how they ended up in the sync report
+    pub fn record_pools<M: Middleware>(
+        &self,
+        dex: &Dex,
+        pools_discovered: usize,
+        sync_report: &SyncReport<M>,
+    ) {
+        self.update_dex_stats(dex, |dex_stats| {
+            dex_stats.pools_discovered += pools_discovered as u64;
+            dex_stats.pools_populated += sync_report.synced_pools.len() as u64;
+            dex_stats.pools_dropped += sync_report.skipped_pools.len() as u64;
+            dex_stats.pools_failed += sync_report.failed_pools.len() as u64;
+        });
+    }
+
+    //Returns the stats collected so far and passes them to the forwarded reporter
+    pub fn finish(&self) -> SyncStats {
+        let mut dexes = self
+            .dexes
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<DexSyncStats>>();
+        dexes.sort_by_key(|dex_stats| dex_stats.factory_address);
+
+        let sync_stats = SyncStats {
+            dexes,
+            duration: self.started_at.elapsed(),
+        };
+
+        self.sync_progress.sync_finished(&sync_stats);
+
+        sync_stats
+    }
+}
+
+impl SyncProgress for SyncStatsCollector {
+    fn phase_started(&self, dex: &Dex, phase: SyncPhase, total: u64) {
+        self.sync_progress.phase_started(dex, phase, total);
+    }
+
+    fn progressed(&self, dex: &Dex, phase: SyncPhase, amount: u64) {
+        self.sync_progress.progressed(dex, phase, amount);
+    }
+
+    fn phase_finished(&self, dex: &Dex, phase: SyncPhase) {
+        self.sync_progress.phase_finished(dex, phase);
+    }
+
+    fn request_completed(&self, dex: &Dex, request: RequestKind, latency: Duration, success: bool) {
+        self.update_dex_stats(dex, |dex_stats| match request {
+            RequestKind::EthCall => dex_stats.eth_calls.record(latency, success),
+            RequestKind::GetLogs => dex_stats.get_logs.record(latency, success),
+        });
+
+        self.sync_progress
+            .request_completed(dex, request, latency, success);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use ethers::{
+        abi::Token,
+        types::{Bytes, Filter, Log, H160, U256},
+    };
+
+    use super::{LatencyHistogram, RequestKind, SyncStatsCollector};
+    use crate::{
+        batch_requests::BatchRequestConfig,
+        dex::{Dex, DexVariant},
+        logs::LogFetcher,
+        pool::{Pool, UniswapV2Pool},
+        progress::{NoProgress, PhaseProgress, SyncPhase},
+        retry::RetryPolicy,
+        sync::SyncReport,
+        test_utils::mock_provider,
+        throttle::RequestThrottle,
+    };
+
+    #[test]
+    fn test_latency_histogram() {
+        let mut histogram = LatencyHistogram::default();
+
+        histogram.record(Duration::from_millis(5));
+        histogram.record(Duration::from_millis(10));
+        histogram.record(Duration::from_millis(300));
+        histogram.record(Duration::from_secs(60));
+
+        let buckets = histogram.buckets();
+        assert_eq!(buckets[0], (Some(Duration::from_millis(10)), 2));
+        assert_eq!(buckets[5], (Some(Duration::from_millis(500)), 1));
+        assert_eq!(buckets[buckets.len() - 1], (None, 1));
+
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(histogram.max(), Duration::from_secs(60));
+        assert_eq!(histogram.sum(), Duration::from_millis(60_315));
+
+        let mut merged = LatencyHistogram::default();
+        merged.merge(&histogram);
+        merged.merge(&histogram);
+        assert_eq!(merged.count(), 8);
+        assert_eq!(merged.buckets()[0].1, 4);
+    }
+
+    #[tokio::test]
+    async fn test_sync_stats_collector() {
+        let (middleware, client) = mock_provider();
+        let dex = Dex::new(H160::from_low_u64_be(1), DexVariant::UniswapV2, 0, None);
+        let sync_stats = Arc::new(SyncStatsCollector::new(Arc::new(NoProgress)));
+
+        //Two log ranges
+        client.push::<Vec<Log>>(vec![]);
+        client.push::<Vec<Log>>(vec![]);
+
+        LogFetcher::new(10)
+            .with_progress(PhaseProgress::new(
+                sync_stats.clone(),
+                dex,
+                SyncPhase::GetPools,
+            ))
+            .get_logs(&Filter::new(), 1, 20, middleware.clone())
+            .await
+            .unwrap();
+
+        //The batch with both pools reverts, the first pool is populated and the second pool reverts again
+        let mut pools = vec![
+            Pool::UniswapV2(UniswapV2Pool {
+                address: H160::from_low_u64_be(10),
+                ..Default::default()
+            }),
+            Pool::UniswapV2(UniswapV2Pool {
+                address: H160::from_low_u64_be(11),
+                ..Default::default()
+            }),
+        ];
+        client.push_revert();
+        client.push::<Bytes>(
+            ethers::abi::encode(&[Token::Array(vec![Token::Tuple(vec![
+                Token::Address(H160::from_low_u64_be(100)),
+                Token::Uint(U256::from(18)),
+                Token::Address(H160::from_low_u64_be(200)),
+                Token::Uint(U256::from(6)),
+                Token::Uint(U256::from(1000)),
+                Token::Uint(U256::from(1000)),
+            ])])])
+            .into(),
+        );
+        client.push_revert();
+
+        let failures = dex
+            .get_all_pool_data_with_config(
+                &mut pools,
+                BatchRequestConfig::new(1_040_000, 1),
+                Arc::new(RequestThrottle::new(0)),
+                Arc::new(RetryPolicy::no_retry()),
+                sync_stats.clone(),
+                middleware,
+            )
+            .await
+            .unwrap();
+
+        let sync_report = SyncReport::from_pools(pools, failures);
+        sync_stats.record_pools(&dex, 2, &sync_report);
+
+        let stats = sync_stats.finish();
+        let dex_stats = stats.dex_stats(&dex).unwrap();
+
+        assert_eq!(dex_stats.get_logs.requests, 2);
+        assert_eq!(dex_stats.get_logs.failed_requests, 0);
+        assert_eq!(dex_stats.eth_calls.requests, 3);
+        assert_eq!(dex_stats.eth_calls.failed_requests, 2);
+        assert_eq!(dex_stats.eth_calls.latency.count(), 3);
+        assert_eq!(dex_stats.pools_discovered, 2);
+        assert_eq!(dex_stats.pools_populated, 1);
+        assert_eq!(dex_stats.pools_failed, 1);
+        assert_eq!(stats.total_requests(RequestKind::EthCall), 3);
+    }
+}
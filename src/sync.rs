@@ -1,14 +1,178 @@
-use crate::{checkpoint, errors::CFMMError};
+use crate::{
+    checkpoint::{self, DexSyncState, PartialCheckpoint},
+    errors::CFMMError,
+    telemetry,
+};
 
+use super::batch_requests::BatchRequestConfig;
 use super::dex::Dex;
 use super::pool::Pool;
+use super::progress::{self, SyncProgress};
+use super::retry::RetryPolicy;
+use super::stats::{SyncStats, SyncStatsCollector};
 use super::throttle::RequestThrottle;
-use ethers::providers::Middleware;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::{
-    panic::resume_unwind,
-    sync::{Arc, Mutex},
-};
+use ethers::{providers::Middleware, types::H160};
+use futures::{stream, StreamExt};
+use std::{collections::HashSet, panic::resume_unwind, sync::Arc};
+
+pub use tokio_util::sync::CancellationToken;
+
+//Pool that could not be synced and the error that was returned for it
+#[derive(Debug)]
+pub struct PoolSyncFailure<M: Middleware> {
+    pub address: H160,
+    pub error: CFMMError<M>,
+}
+
+//Outcome of a sync that continues past pools that could not be synced
+#[derive(Debug)]
+pub struct SyncReport<M: Middleware> {
+    pub synced_pools: Vec<Pool>,
+    pub failed_pools: Vec<PoolSyncFailure<M>>,
+    //Pools that no data was returned for, ie. pools with a token that does not implement `decimals()`
+    pub skipped_pools: Vec<H160>,
+    pub stats: SyncStats,
+    //Set if the sync was cancelled before all dexes were synced
+    pub cancelled: bool,
+    //Pools that were found before the sync was cancelled but whose data was not synced
+    pub unsynced_pools: Vec<Pool>,
+}
+
+impl<M: Middleware> Default for SyncReport<M> {
+    fn default() -> Self {
+        SyncReport {
+            synced_pools: vec![],
+            failed_pools: vec![],
+            skipped_pools: vec![],
+            stats: SyncStats::default(),
+            cancelled: false,
+            unsynced_pools: vec![],
+        }
+    }
+}
+
+impl<M: Middleware> SyncReport<M> {
+    pub fn new() -> SyncReport<M> {
+        SyncReport::default()
+    }
+
+    //Sorts the pools returned by a batch sync into synced and skipped pools, leaving out the pools that failed
+    pub fn from_pools(pools: Vec<Pool>, failed_pools: Vec<PoolSyncFailure<M>>) -> SyncReport<M> {
+        let failed_addresses = failed_pools
+            .iter()
+            .map(|failure| failure.address)
+            .collect::<HashSet<H160>>();
+
+        let mut sync_report = SyncReport {
+            failed_pools,
+            ..SyncReport::default()
+        };
+
+        for pool in pools {
+            if failed_addresses.contains(&pool.address()) {
+                continue;
+            }
+
+            if is_empty_pool(&pool) {
+                telemetry::debug_event!(pool = ?pool.address(), "Skipping empty pool");
+                sync_report.skipped_pools.push(pool.address());
+            } else {
+                sync_report.synced_pools.push(pool);
+            }
+        }
+
+        sync_report
+    }
+
+    pub fn extend(&mut self, other: SyncReport<M>) {
+        self.synced_pools.extend(other.synced_pools);
+        self.failed_pools.extend(other.failed_pools);
+        self.skipped_pools.extend(other.skipped_pools);
+        self.stats.extend(other.stats);
+        self.cancelled |= other.cancelled;
+        self.unsynced_pools.extend(other.unsynced_pools);
+    }
+
+    //Returns true if every pool was synced
+    pub fn is_complete(&self) -> bool {
+        !self.cancelled && self.failed_pools.is_empty() && self.skipped_pools.is_empty()
+    }
+}
+
+//Default block range of each log request when pools are found from logs
+pub const DEFAULT_SYNC_STEP: usize = 100000;
+
+//Settings of a sync. The request throttle and retry policy are shared by every request of the sync,
+//and by every sync that the config is cloned into.
+#[derive(Clone)]
+pub struct SyncConfig {
+    //Block range of each log request when pools are found from logs
+    pub step: usize,
+    pub request_throttle: Arc<RequestThrottle>,
+    pub retry_policy: Arc<RetryPolicy>,
+    pub batch_config: BatchRequestConfig,
+    pub sync_progress: Arc<dyn SyncProgress>,
+    //Set to make the sync stop when the token is cancelled. Pools are then found from logs so that the sync can be resumed by block.
+    pub cancellation_token: Option<CancellationToken>,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig {
+            step: DEFAULT_SYNC_STEP,
+            request_throttle: Arc::new(RequestThrottle::new(0)),
+            retry_policy: Arc::new(RetryPolicy::default()),
+            batch_config: BatchRequestConfig::default(),
+            sync_progress: progress::default_sync_progress(),
+            cancellation_token: None,
+        }
+    }
+}
+
+impl SyncConfig {
+    pub fn new() -> SyncConfig {
+        SyncConfig::default()
+    }
+
+    pub fn with_step(mut self, step: usize) -> SyncConfig {
+        self.step = step;
+        self
+    }
+
+    //A limit of 0 disables the throttle
+    pub fn with_requests_per_second_limit(
+        mut self,
+        requests_per_second_limit: usize,
+    ) -> SyncConfig {
+        self.request_throttle = Arc::new(RequestThrottle::new(requests_per_second_limit));
+        self
+    }
+
+    pub fn with_request_throttle(mut self, request_throttle: Arc<RequestThrottle>) -> SyncConfig {
+        self.request_throttle = request_throttle;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> SyncConfig {
+        self.retry_policy = Arc::new(retry_policy);
+        self
+    }
+
+    pub fn with_batch_config(mut self, batch_config: BatchRequestConfig) -> SyncConfig {
+        self.batch_config = batch_config;
+        self
+    }
+
+    pub fn with_sync_progress(mut self, sync_progress: Arc<dyn SyncProgress>) -> SyncConfig {
+        self.sync_progress = sync_progress;
+        self
+    }
+
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> SyncConfig {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+}
 
 //Get all pairs and sync reserve values for each Dex in the `dexes` vec.
 pub async fn sync_pairs<M: 'static + Middleware>(
@@ -17,7 +181,7 @@ pub async fn sync_pairs<M: 'static + Middleware>(
     checkpoint_path: Option<&str>,
 ) -> Result<Vec<Pool>, CFMMError<M>> {
     //Sync pairs with throttle but set the requests per second limit to 0, disabling the throttle.
-    sync_pairs_with_throttle(dexes, 100000, middleware, 0, checkpoint_path).await
+    sync_pairs_with_throttle(dexes, DEFAULT_SYNC_STEP, middleware, 0, checkpoint_path).await
 }
 
 //Get all pairs and sync reserve values for each Dex in the `dexes` vec.
@@ -32,6 +196,7 @@ pub async fn sync_pairs_with_step<M: 'static + Middleware>(
 }
 
 //Get all pairs and sync reserve values for each Dex in the `dexes` vec.
+//Use `sync_pairs_with_config` to set the retry policy, the progress or to stop a sync part way through and resume it later.
 pub async fn sync_pairs_with_throttle<M: 'static + Middleware>(
     dexes: Vec<Dex>,
     step: usize, //TODO: Add docs on step. Step is the block range used to get all pools from a dex if syncing from event logs
@@ -39,79 +204,103 @@ pub async fn sync_pairs_with_throttle<M: 'static + Middleware>(
     requests_per_second_limit: usize,
     checkpoint_path: Option<&str>,
 ) -> Result<Vec<Pool>, CFMMError<M>> {
-    let current_block = middleware
-        .get_block_number()
-        .await
-        .map_err(CFMMError::MiddlewareError)?;
+    let config = SyncConfig::new()
+        .with_step(step)
+        .with_requests_per_second_limit(requests_per_second_limit);
+
+    Ok(
+        sync_pairs_with_config(dexes, checkpoint_path, config, middleware)
+            .await?
+            .synced_pools,
+    )
+}
+
+//Get all pairs and sync reserve values for each Dex in the `dexes` vec. Pools that could not be synced do not fail the sync,
+//they are listed in the report instead. The progress of each dex is reported to the `sync_progress` of the config.
+//A checkpoint of the synced pools is written to `checkpoint_path` if one is provided.
+//
+//If the config has a cancellation token, pools are found from logs so that the sync can be resumed by block. When cancelled,
+//the last completed block range of each dex is saved as a partial checkpoint next to `checkpoint_path` instead of the checkpoint,
+//and the pools that were found so far are listed as unsynced pools. The next sync with the same `checkpoint_path` resumes from it.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(dexes = dexes.len(), step = config.step, checkpoint_path))
+)]
+pub async fn sync_pairs_with_config<M: 'static + Middleware>(
+    dexes: Vec<Dex>,
+    checkpoint_path: Option<&str>,
+    config: SyncConfig,
+    middleware: Arc<M>,
+) -> Result<SyncReport<M>, CFMMError<M>> {
+    if let Some(cancellation_token) = config.cancellation_token.clone() {
+        return sync_pairs_until_cancelled(
+            dexes,
+            checkpoint_path,
+            config,
+            cancellation_token,
+            middleware,
+        )
+        .await;
+    }
 
-    //Initialize a new request throttle
-    let request_throttle = Arc::new(Mutex::new(RequestThrottle::new(requests_per_second_limit)));
+    let current_block = config
+        .retry_policy
+        .retry(|| async {
+            middleware
+                .get_block_number()
+                .await
+                .map_err(CFMMError::MiddlewareError)
+        })
+        .await?;
 
-    //Aggregate the populated pools from each thread
-    let mut aggregated_pools: Vec<Pool> = vec![];
+    //Aggregate the sync reports from each thread
+    let mut sync_report = SyncReport::new();
     let mut handles = vec![];
 
-    //Initialize multi progress bar
-    let multi_progress_bar = MultiProgress::new();
+    let sync_stats = Arc::new(SyncStatsCollector::new(config.sync_progress.clone()));
 
     //For each dex supplied, get all pair created events and get reserve values
     for dex in dexes.clone() {
         let middleware = middleware.clone();
-        let request_throttle = request_throttle.clone();
-        let progress_bar = multi_progress_bar.add(ProgressBar::new(0));
+        let config = config.clone();
+        let sync_stats = sync_stats.clone();
 
         //Spawn a new thread to get all pools and sync data for each dex
         handles.push(tokio::spawn(async move {
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                    .expect("Error when setting progress bar style")
-                    .progress_chars("##-"),
-            );
-
             //Get all of the pools from the dex
-            progress_bar.set_message(format!("Getting all pools from: {}", dex.factory_address()));
-
             let mut pools = dex
                 .get_all_pools(
-                    request_throttle.clone(),
-                    step,
-                    progress_bar.clone(),
+                    config.request_throttle.clone(),
+                    config.retry_policy.clone(),
+                    config.step,
+                    sync_stats.clone(),
                     middleware.clone(),
                 )
                 .await?;
-
-            progress_bar.reset();
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                    .expect("Error when setting progress bar style")
-                    .progress_chars("##-"),
-            );
+            let pools_discovered = pools.len();
 
             //Get all of the pool data and sync the pool
-            progress_bar.set_message(format!(
-                "Getting all pool data for: {}",
-                dex.factory_address()
-            ));
-            progress_bar.set_length(pools.len() as u64);
-
-            dex.get_all_pool_data(
-                &mut pools,
-                request_throttle.clone(),
-                progress_bar.clone(),
-                middleware.clone(),
-            )
-            .await?;
+            let failures = dex
+                .get_all_pool_data_with_config(
+                    &mut pools,
+                    config.batch_config,
+                    config.request_throttle,
+                    config.retry_policy,
+                    sync_stats.clone(),
+                    middleware,
+                )
+                .await?;
 
-            //Clean empty pools
-            pools = remove_empty_pools(pools);
+            let sync_report = SyncReport::from_pools(pools, failures);
+            sync_stats.record_pools(&dex, pools_discovered, &sync_report);
 
-            Ok::<_, CFMMError<M>>(pools)
+            Ok::<_, CFMMError<M>>(sync_report)
         }));
     }
 
     for handle in handles {
         match handle.await {
-            Ok(sync_result) => aggregated_pools.extend(sync_result?),
+            Ok(sync_result) => sync_report.extend(sync_result?),
             Err(err) => {
                 {
                     if err.is_panic() {
@@ -124,38 +313,575 @@ pub async fn sync_pairs_with_throttle<M: 'static + Middleware>(
     }
 
     //Save a checkpoint if a path is provided
-    if checkpoint_path.is_some() {
-        let checkpoint_path = checkpoint_path.unwrap();
-
+    if let Some(checkpoint_path) = checkpoint_path {
         checkpoint::construct_checkpoint(
             dexes,
-            &aggregated_pools,
+            &sync_report.synced_pools,
             current_block.as_u64(),
             checkpoint_path,
-        )
+        )?;
     }
 
-    //Return the populated aggregated pools vec
-    Ok(aggregated_pools)
+    sync_report.stats = sync_stats.finish();
+
+    telemetry::info_event!(
+        synced_pools = sync_report.synced_pools.len(),
+        failed_pools = sync_report.failed_pools.len(),
+        skipped_pools = sync_report.skipped_pools.len(),
+        "Synced pairs"
+    );
+
+    Ok(sync_report)
 }
 
-pub fn remove_empty_pools(pools: Vec<Pool>) -> Vec<Pool> {
-    let mut cleaned_pools = vec![];
+//Finds the pools of each dex from logs until the `cancellation_token` is cancelled, resuming from the partial checkpoint
+//next to `checkpoint_path` if the last sync was cancelled
+async fn sync_pairs_until_cancelled<M: 'static + Middleware>(
+    dexes: Vec<Dex>,
+    checkpoint_path: Option<&str>,
+    config: SyncConfig,
+    cancellation_token: CancellationToken,
+    middleware: Arc<M>,
+) -> Result<SyncReport<M>, CFMMError<M>> {
+    let partial_checkpoint = match checkpoint_path {
+        Some(checkpoint_path) => PartialCheckpoint::load(checkpoint_path)?,
+        None => None,
+    };
 
-    for pool in pools {
-        match pool {
-            Pool::UniswapV2(uniswap_v2_pool) => {
-                if !uniswap_v2_pool.token_a.is_zero() {
-                    cleaned_pools.push(pool)
-                }
+    let current_block = config
+        .retry_policy
+        .retry(|| async {
+            middleware
+                .get_block_number()
+                .await
+                .map_err(CFMMError::MiddlewareError)
+        })
+        .await?
+        .as_u64();
+
+    let sync_stats = Arc::new(SyncStatsCollector::new(config.sync_progress.clone()));
+
+    let mut handles = vec![];
+    for dex in dexes.clone() {
+        //Resume each dex from the partial checkpoint if the last sync was cancelled
+        let dex_sync_state = partial_checkpoint
+            .as_ref()
+            .and_then(|partial_checkpoint| partial_checkpoint.dex_sync_state(&dex))
+            .cloned()
+            .unwrap_or_else(|| DexSyncState::new(dex));
+
+        handles.push(tokio::spawn(sync_dex_until_cancelled(
+            dex_sync_state,
+            current_block,
+            config.clone(),
+            cancellation_token.clone(),
+            sync_stats.clone(),
+            middleware.clone(),
+        )));
+    }
+
+    let mut sync_report = SyncReport::new();
+    let mut dex_sync_states = vec![];
+    for handle in handles {
+        match handle.await {
+            Ok(sync_result) => {
+                let (dex_sync_state, dex_sync_report) = sync_result?;
+                dex_sync_states.push(dex_sync_state);
+                sync_report.extend(dex_sync_report);
             }
-            Pool::UniswapV3(uniswap_v3_pool) => {
-                if !uniswap_v3_pool.token_a.is_zero() {
-                    cleaned_pools.push(pool)
+            Err(err) => {
+                if err.is_panic() {
+                    // Resume the panic on the main task
+                    resume_unwind(err.into_panic());
                 }
             }
         }
     }
 
-    cleaned_pools
+    if let Some(checkpoint_path) = checkpoint_path {
+        if sync_report.cancelled {
+            PartialCheckpoint::new(current_block, dex_sync_states).save(checkpoint_path)?;
+        } else {
+            checkpoint::construct_checkpoint(
+                dexes,
+                &sync_report.synced_pools,
+                current_block,
+                checkpoint_path,
+            )?;
+            checkpoint::remove_partial_checkpoint(checkpoint_path)?;
+        }
+    }
+
+    sync_report.stats = sync_stats.finish();
+
+    telemetry::info_event!(
+        synced_pools = sync_report.synced_pools.len(),
+        failed_pools = sync_report.failed_pools.len(),
+        skipped_pools = sync_report.skipped_pools.len(),
+        unsynced_pools = sync_report.unsynced_pools.len(),
+        cancelled = sync_report.cancelled,
+        "Synced pairs"
+    );
+
+    Ok(sync_report)
+}
+
+//Gets the pools of the dex from where its sync state left off, then syncs the data of all of its pools.
+//Returns the sync state that the dex can be resumed from along with the report.
+pub async fn sync_dex_until_cancelled<M: 'static + Middleware>(
+    mut dex_sync_state: DexSyncState,
+    current_block: u64,
+    config: SyncConfig,
+    cancellation_token: CancellationToken,
+    sync_stats: Arc<SyncStatsCollector>,
+    middleware: Arc<M>,
+) -> Result<(DexSyncState, SyncReport<M>), CFMMError<M>> {
+    let dex = dex_sync_state.dex;
+
+    let from_block = match dex_sync_state.synced_to_block {
+        Some(synced_to_block) => synced_to_block + 1,
+        None => dex
+            .creation_block()
+            .as_number()
+            .expect("Error converting creation block as number")
+            .as_u64(),
+    };
+
+    if from_block <= current_block {
+        let (pools, synced_to_block) = dex
+            .get_all_pools_from_logs_until_cancelled(
+                from_block,
+                current_block,
+                config.step,
+                config.request_throttle.clone(),
+                config.retry_policy.clone(),
+                sync_stats.clone(),
+                cancellation_token.clone(),
+                middleware.clone(),
+            )
+            .await?;
+
+        dex_sync_state.pools.extend(pools);
+        if synced_to_block.is_some() {
+            dex_sync_state.synced_to_block = synced_to_block;
+        }
+    }
+
+    let cancelled_report = |dex_sync_state: &DexSyncState| SyncReport {
+        cancelled: true,
+        unsynced_pools: dex_sync_state.pools.clone(),
+        ..SyncReport::default()
+    };
+
+    //A dex created after the current block has no completed block range, so only a cancelled token stops the sync here
+    if cancellation_token.is_cancelled() {
+        telemetry::info_event!(
+            factory_address = ?dex.factory_address(),
+            synced_to_block = ?dex_sync_state.synced_to_block,
+            "Sync cancelled while getting pools"
+        );
+
+        let sync_report = cancelled_report(&dex_sync_state);
+        return Ok((dex_sync_state, sync_report));
+    }
+
+    //Pools from before a resume are synced again so that all pools are synced at the current block
+    let mut pools = dex_sync_state.pools.clone();
+    let pools_discovered = pools.len();
+
+    let failures = tokio::select! {
+        biased;
+        _ = cancellation_token.cancelled() => {
+            telemetry::info_event!(
+                factory_address = ?dex.factory_address(),
+                "Sync cancelled while getting pool data"
+            );
+
+            let sync_report = cancelled_report(&dex_sync_state);
+            return Ok((dex_sync_state, sync_report));
+        }
+        failures = dex.get_all_pool_data_with_config(
+            &mut pools,
+            config.batch_config,
+            config.request_throttle,
+            config.retry_policy,
+            sync_stats.clone(),
+            middleware,
+        ) => failures?,
+    };
+
+    let sync_report = SyncReport::from_pools(pools, failures);
+    sync_stats.record_pools(&dex, pools_discovered, &sync_report);
+
+    Ok((dex_sync_state, sync_report))
+}
+
+//Syncs the state of each pool, up to `max_in_flight` pools at the same time.
+//A pool that can not be synced is listed in the report instead of failing the other pools.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(pools = pools.len(), max_in_flight))
+)]
+pub async fn sync_pools<M: Middleware>(
+    pools: Vec<Pool>,
+    max_in_flight: usize,
+    retry_policy: &RetryPolicy,
+    middleware: Arc<M>,
+) -> SyncReport<M> {
+    let results = stream::iter(pools)
+        .map(|pool| {
+            let middleware = middleware.clone();
+
+            async move {
+                let address = pool.address();
+                let result = retry_policy
+                    .retry(|| async {
+                        let mut synced_pool = pool.clone();
+                        synced_pool.sync_pool(middleware.clone()).await?;
+                        Ok(synced_pool)
+                    })
+                    .await;
+
+                (address, result)
+            }
+        })
+        .buffered(max_in_flight.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut sync_report = SyncReport::new();
+    for (address, result) in results {
+        match result {
+            Ok(pool) => sync_report.synced_pools.push(pool),
+            Err(error) => {
+                telemetry::warn_event!(pool = ?address, %error, "Could not sync pool");
+
+                sync_report
+                    .failed_pools
+                    .push(PoolSyncFailure { address, error })
+            }
+        }
+    }
+
+    sync_report
+}
+
+pub fn remove_empty_pools(pools: Vec<Pool>) -> Vec<Pool> {
+    pools
+        .into_iter()
+        .filter(|pool| {
+            if is_empty_pool(pool) {
+                telemetry::debug_event!(pool = ?pool.address(), "Removing empty pool");
+                return false;
+            }
+
+            true
+        })
+        .collect()
+}
+
+//Pools that were not populated by a batch request have a zero address as token_a
+pub fn is_empty_pool(pool: &Pool) -> bool {
+    match pool {
+        Pool::UniswapV2(uniswap_v2_pool) => uniswap_v2_pool.token_a.is_zero(),
+        Pool::UniswapV3(uniswap_v3_pool) => uniswap_v3_pool.token_a.is_zero(),
+        Pool::Solidly(solidly_pool) => solidly_pool.token_a.is_zero(),
+        //Pools with coins that have no rates are pools whose rates can not be read, which are not supported
+        Pool::Curve(curve_pool) => {
+            curve_pool.coins.is_empty() || curve_pool.rates.len() != curve_pool.coins.len()
+        }
+        Pool::BalancerV2(balancer_v2_pool) => balancer_v2_pool.tokens.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::{
+        abi::Token,
+        providers::Provider,
+        types::{Bytes, Log, H160, U256, U64},
+    };
+
+    use super::{
+        sync_pairs_with_config, sync_pools, CancellationToken, PoolSyncFailure, SyncConfig,
+        SyncReport,
+    };
+    use crate::{
+        checkpoint::{self, Checkpoint, PartialCheckpoint},
+        dex::{Dex, DexVariant},
+        errors::CFMMError,
+        pool::{Pool, UniswapV2Pool, UniswapV3Pool},
+        progress::NoProgress,
+        retry::RetryPolicy,
+        test_utils::{mock_provider, pair_created_log, CancellingProgress, MockClient},
+    };
+
+    fn uniswap_v3_pool(address: u64) -> Pool {
+        Pool::UniswapV3(UniswapV3Pool {
+            address: H160::from_low_u64_be(address),
+            token_a: H160::from_low_u64_be(100),
+            token_b: H160::from_low_u64_be(200),
+            ..Default::default()
+        })
+    }
+
+    //Return data of the uniswap v3 sync batch request
+    fn uniswap_v3_sync_data(liquidity: u128, sqrt_price: U256) -> Bytes {
+        ethers::abi::encode(&[Token::Tuple(vec![
+            Token::Uint(U256::from(liquidity)),
+            Token::Uint(sqrt_price),
+            Token::Int(U256::zero()),
+            Token::Int(U256::zero()),
+        ])])
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_sync_pools_reports_failed_pools() {
+        let (middleware, client) = mock_provider();
+        let pools = vec![uniswap_v3_pool(1), uniswap_v3_pool(2), uniswap_v3_pool(3)];
+
+        client.push(uniswap_v3_sync_data(1000, U256::from(2).pow(96.into())));
+        client.push_revert();
+        //A zero sqrt price signals that the pool data was not populated
+        client.push(uniswap_v3_sync_data(0, U256::zero()));
+
+        let sync_report = sync_pools(pools, 1, &RetryPolicy::no_retry(), middleware).await;
+
+        assert_eq!(sync_report.synced_pools.len(), 1);
+        assert_eq!(
+            sync_report.synced_pools[0].address(),
+            H160::from_low_u64_be(1)
+        );
+        if let Pool::UniswapV3(pool) = &sync_report.synced_pools[0] {
+            assert_eq!(pool.liquidity, 1000);
+        }
+
+        let failed_addresses = sync_report
+            .failed_pools
+            .iter()
+            .map(|failure| failure.address)
+            .collect::<Vec<H160>>();
+        assert_eq!(
+            failed_addresses,
+            vec![H160::from_low_u64_be(2), H160::from_low_u64_be(3)]
+        );
+        assert!(matches!(
+            sync_report.failed_pools[1].error,
+            CFMMError::SyncError(_)
+        ));
+        assert!(!sync_report.is_complete());
+    }
+
+    //Return data of the uniswap v2 pool data batch request for a single pool
+    fn uniswap_v2_pool_data(reserve: u128) -> Bytes {
+        ethers::abi::encode(&[Token::Array(vec![Token::Tuple(vec![
+            Token::Address(H160::from_low_u64_be(100)),
+            Token::Uint(U256::from(18)),
+            Token::Address(H160::from_low_u64_be(200)),
+            Token::Uint(U256::from(18)),
+            Token::Uint(U256::from(reserve)),
+            Token::Uint(U256::from(reserve)),
+        ])])])
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_sync_pairs_resumes_after_cancellation() {
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_sync_cancellation.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+        let dex = Dex::new(H160::from_low_u64_be(1), DexVariant::UniswapV2, 100, None);
+        let _ = checkpoint::remove_partial_checkpoint(checkpoint_path);
+
+        //The sync is cancelled once the first block range of pair created logs is done, so the rest of the range is not requested
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(399));
+        client.push(vec![pair_created_log(10, 150)]);
+
+        let cancellation_token = CancellationToken::new();
+
+        let sync_report = sync_pairs_with_config(
+            vec![dex],
+            Some(checkpoint_path),
+            SyncConfig::new()
+                .with_step(100)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(CancellingProgress::new(
+                    cancellation_token.clone(),
+                    100,
+                )))
+                .with_cancellation_token(cancellation_token),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert!(sync_report.cancelled);
+        assert!(!sync_report.is_complete());
+        assert!(sync_report.synced_pools.is_empty());
+        assert_eq!(
+            sync_report
+                .unsynced_pools
+                .iter()
+                .map(|pool| pool.address())
+                .collect::<Vec<H160>>(),
+            vec![H160::from_low_u64_be(10)]
+        );
+        assert_eq!(client.requests(), vec!["eth_blockNumber", "eth_getLogs"]);
+        assert!(Checkpoint::load(checkpoint_path).is_err());
+
+        //The partial checkpoint records the pools found in the completed block range
+        let partial_checkpoint = PartialCheckpoint::load(checkpoint_path).unwrap().unwrap();
+        assert_eq!(partial_checkpoint.block_number, 399);
+        assert_eq!(partial_checkpoint.dexes.len(), 1);
+        assert_eq!(partial_checkpoint.dexes[0].synced_to_block, Some(199));
+        assert_eq!(
+            partial_checkpoint.dexes[0].pools,
+            sync_report.unsynced_pools
+        );
+
+        //The next sync only requests the logs after the completed block range, then syncs the pools found before and after the resume
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(399));
+        client.push(Vec::<Log>::new());
+        client.push(Vec::<Log>::new());
+        client.push(uniswap_v2_pool_data(1000));
+
+        let sync_report = sync_pairs_with_config(
+            vec![dex],
+            Some(checkpoint_path),
+            SyncConfig::new()
+                .with_step(100)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(NoProgress))
+                .with_cancellation_token(CancellationToken::new()),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert!(sync_report.is_complete());
+        assert_eq!(
+            client.requests(),
+            vec!["eth_blockNumber", "eth_getLogs", "eth_getLogs", "eth_call"]
+        );
+        assert!(PartialCheckpoint::load(checkpoint_path).unwrap().is_none());
+
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        assert_eq!(checkpoint.block_number, 399);
+        assert_eq!(checkpoint.pools, sync_report.synced_pools);
+        match &checkpoint.pools[..] {
+            [Pool::UniswapV2(pool)] => {
+                assert_eq!(pool.address, H160::from_low_u64_be(10));
+                assert_eq!(pool.reserve_0, 1000);
+            }
+            _ => panic!("Unexpected checkpoint pools"),
+        }
+
+        std::fs::remove_file(checkpoint_path).unwrap();
+        assert!(!checkpoint::partial_checkpoint_path(checkpoint_path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_sync_pairs_with_dex_created_after_current_block() {
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_sync_future_dex.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+        let dex = Dex::new(H160::from_low_u64_be(1), DexVariant::UniswapV2, 500, None);
+        let _ = checkpoint::remove_partial_checkpoint(checkpoint_path);
+
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(399));
+
+        let sync_report = sync_pairs_with_config(
+            vec![dex],
+            Some(checkpoint_path),
+            SyncConfig::new()
+                .with_step(100)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(NoProgress))
+                .with_cancellation_token(CancellationToken::new()),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert!(sync_report.is_complete());
+        assert_eq!(client.requests(), vec!["eth_blockNumber"]);
+        assert!(PartialCheckpoint::load(checkpoint_path).unwrap().is_none());
+
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        std::fs::remove_file(checkpoint_path).unwrap();
+
+        assert_eq!(checkpoint.block_number, 399);
+        assert!(checkpoint.pools.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_sync_pairs_without_checkpoint_path() {
+        let dex = Dex::new(H160::from_low_u64_be(1), DexVariant::UniswapV2, 100, None);
+
+        let (middleware, client) = mock_provider();
+        client.push(U64::from(399));
+        client.push(vec![pair_created_log(10, 150)]);
+
+        let cancellation_token = CancellationToken::new();
+
+        let sync_report = sync_pairs_with_config(
+            vec![dex],
+            None,
+            SyncConfig::new()
+                .with_step(100)
+                .with_retry_policy(RetryPolicy::no_retry())
+                .with_sync_progress(Arc::new(CancellingProgress::new(
+                    cancellation_token.clone(),
+                    100,
+                )))
+                .with_cancellation_token(cancellation_token),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert!(sync_report.cancelled);
+        assert_eq!(client.requests(), vec!["eth_blockNumber", "eth_getLogs"]);
+        assert_eq!(
+            sync_report
+                .unsynced_pools
+                .iter()
+                .map(|pool| pool.address())
+                .collect::<Vec<H160>>(),
+            vec![H160::from_low_u64_be(10)]
+        );
+    }
+
+    #[test]
+    fn test_sync_report_from_pools() {
+        let populated_pool = Pool::UniswapV2(UniswapV2Pool {
+            address: H160::from_low_u64_be(1),
+            token_a: H160::from_low_u64_be(100),
+            ..Default::default()
+        });
+        let empty_pool = Pool::UniswapV2(UniswapV2Pool {
+            address: H160::from_low_u64_be(2),
+            ..Default::default()
+        });
+        let failed_pool = Pool::UniswapV2(UniswapV2Pool {
+            address: H160::from_low_u64_be(3),
+            ..Default::default()
+        });
+
+        let sync_report = SyncReport::<Provider<MockClient>>::from_pools(
+            vec![populated_pool.clone(), empty_pool, failed_pool],
+            vec![PoolSyncFailure {
+                address: H160::from_low_u64_be(3),
+                error: CFMMError::SyncError(H160::from_low_u64_be(3)),
+            }],
+        );
+
+        assert_eq!(sync_report.synced_pools, vec![populated_pool]);
+        assert_eq!(sync_report.skipped_pools, vec![H160::from_low_u64_be(2)]);
+        assert_eq!(sync_report.failed_pools.len(), 1);
+    }
 }
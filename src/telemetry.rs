@@ -0,0 +1,27 @@
+//Forward to the `tracing` event macros when the `tracing` feature is enabled and expand to nothing otherwise,
+//so that instrumented code does not need a cfg attribute on every event.
+
+macro_rules! debug_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!($($arg)*);
+    };
+}
+
+macro_rules! info_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::info!($($arg)*);
+    };
+}
+
+macro_rules! warn_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::warn!($($arg)*);
+    };
+}
+
+pub(crate) use debug_event;
+pub(crate) use info_event;
+pub(crate) use warn_event;
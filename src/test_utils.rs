@@ -0,0 +1,218 @@
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use ethers::{
+    abi::Token,
+    providers::{JsonRpcClient, JsonRpcError, Provider, ProviderError, RpcError},
+    types::{Log, H160, H256, U256, U64},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    dex::{uniswap_v2::PAIR_CREATED_EVENT_SIGNATURE, Dex},
+    progress::{SyncPhase, SyncProgress},
+    sync::CancellationToken,
+};
+
+#[derive(Error, Debug)]
+pub enum MockClientError {
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("Injected failure {0}")]
+    JsonRpcError(JsonRpcError),
+    #[error("No response queued for {0}")]
+    EmptyResponses(String),
+}
+
+impl RpcError for MockClientError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            MockClientError::JsonRpcError(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            MockClientError::SerdeJson(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<MockClientError> for ProviderError {
+    fn from(error: MockClientError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(error))
+    }
+}
+
+//JSON-RPC client that answers every request with the next queued response or failure, in the order they were pushed.
+//Failures are injected at the transport, so they also reach calls that bypass the middleware stack, like batch request deployments.
+#[derive(Debug, Clone, Default)]
+pub struct MockClient {
+    responses: Arc<Mutex<VecDeque<Result<Value, JsonRpcError>>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockClient {
+    pub fn new() -> MockClient {
+        MockClient::default()
+    }
+
+    pub fn push<T: Serialize>(&self, response: T) {
+        self.responses
+            .lock()
+            .unwrap()
+            .push_back(Ok(serde_json::to_value(response).unwrap()));
+    }
+
+    pub fn push_failure(&self, code: i64, message: &str) {
+        self.responses.lock().unwrap().push_back(Err(JsonRpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        }));
+    }
+
+    pub fn push_rate_limit_failure(&self) {
+        self.push_failure(429, "Too Many Requests");
+    }
+
+    pub fn push_revert(&self) {
+        self.push_failure(3, "execution reverted");
+    }
+
+    pub fn remaining_responses(&self) -> usize {
+        self.responses.lock().unwrap().len()
+    }
+
+    //Methods of the requests that were made, in the order they were made
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for MockClient {
+    type Error = MockClientError;
+
+    async fn request<T, R>(&self, method: &str, _params: T) -> Result<R, MockClientError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        self.requests.lock().unwrap().push(method.to_string());
+
+        let response = self
+            .responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| MockClientError::EmptyResponses(method.to_string()))?;
+
+        match response {
+            Ok(value) => Ok(serde_json::from_value(value)?),
+            Err(error) => Err(MockClientError::JsonRpcError(error)),
+        }
+    }
+}
+
+//Creates a provider backed by a mock client, the client is returned to queue responses
+pub fn mock_provider() -> (Arc<Provider<MockClient>>, MockClient) {
+    let client = MockClient::new();
+    (Arc::new(Provider::new(client.clone())), client)
+}
+
+//Progress reporter that records the phases that were started and finished and the total progress
+#[derive(Debug, Default)]
+pub struct CountingProgress {
+    position: AtomicU64,
+    started_phases: Mutex<Vec<(SyncPhase, u64)>>,
+    finished_phases: Mutex<Vec<SyncPhase>>,
+}
+
+impl CountingProgress {
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::SeqCst)
+    }
+
+    //Phases that were started with their totals, in the order they were started
+    pub fn started_phases(&self) -> Vec<(SyncPhase, u64)> {
+        self.started_phases.lock().unwrap().clone()
+    }
+
+    pub fn finished_phases(&self) -> Vec<SyncPhase> {
+        self.finished_phases.lock().unwrap().clone()
+    }
+}
+
+impl SyncProgress for CountingProgress {
+    fn phase_started(&self, _dex: &Dex, phase: SyncPhase, total: u64) {
+        self.started_phases.lock().unwrap().push((phase, total));
+    }
+
+    fn progressed(&self, _dex: &Dex, _phase: SyncPhase, amount: u64) {
+        self.position.fetch_add(amount, Ordering::SeqCst);
+    }
+
+    fn phase_finished(&self, _dex: &Dex, phase: SyncPhase) {
+        self.finished_phases.lock().unwrap().push(phase);
+    }
+}
+
+//Progress reporter that cancels the token once the progress reaches `cancel_at`, to cancel a sync part way through
+#[derive(Debug)]
+pub struct CancellingProgress {
+    cancellation_token: CancellationToken,
+    cancel_at: u64,
+    position: AtomicU64,
+}
+
+impl CancellingProgress {
+    pub fn new(cancellation_token: CancellationToken, cancel_at: u64) -> CancellingProgress {
+        CancellingProgress {
+            cancellation_token,
+            cancel_at,
+            position: AtomicU64::new(0),
+        }
+    }
+}
+
+impl SyncProgress for CancellingProgress {
+    fn phase_started(&self, _dex: &Dex, _phase: SyncPhase, _total: u64) {}
+
+    fn progressed(&self, _dex: &Dex, _phase: SyncPhase, amount: u64) {
+        if self.position.fetch_add(amount, Ordering::SeqCst) + amount >= self.cancel_at {
+            self.cancellation_token.cancel();
+        }
+    }
+
+    fn phase_finished(&self, _dex: &Dex, _phase: SyncPhase) {}
+}
+
+//Pair created log of the uniswap v2 factory for the pair at `pair`
+pub fn pair_created_log(pair: u64, block_number: u64) -> Log {
+    Log {
+        topics: vec![
+            PAIR_CREATED_EVENT_SIGNATURE,
+            H256::from_low_u64_be(100),
+            H256::from_low_u64_be(200),
+        ],
+        data: ethers::abi::encode(&[
+            Token::Address(H160::from_low_u64_be(pair)),
+            Token::Uint(U256::one()),
+        ])
+        .into(),
+        block_number: Some(U64::from(block_number)),
+        ..Default::default()
+    }
+}
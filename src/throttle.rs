@@ -1,49 +1,197 @@
 use std::{
-    thread::sleep,
-    time::{Duration, SystemTime},
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 
+//Relative cost of each request type, taken from the tokens in the bucket before the request is made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestWeights {
+    pub eth_call: u32,
+    pub get_logs: u32,
+}
+
+impl Default for RequestWeights {
+    fn default() -> Self {
+        //Log queries scan a range of blocks and are priced higher than calls by most providers
+        RequestWeights {
+            eth_call: 1,
+            get_logs: 3,
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+//Token bucket rate limiter that can be shared across tasks. Waiting for tokens sleeps the task instead of blocking the runtime worker.
 pub struct RequestThrottle {
     enabled: bool,
-    last_request_timestamp: SystemTime,
     requests_per_second_limit: usize,
-    requests_per_second: usize,
+    burst_capacity: usize,
+    weights: RequestWeights,
+    bucket: Mutex<TokenBucket>,
 }
 
 impl RequestThrottle {
+    //Creates a throttle that allows `requests_per_second_limit` requests per second, with a burst capacity of one second of requests.
+    //A limit of 0 disables the throttle.
     pub fn new(requests_per_second_limit: usize) -> RequestThrottle {
-        if requests_per_second_limit > 0 {
-            RequestThrottle {
-                enabled: true,
-                last_request_timestamp: SystemTime::now(),
-                requests_per_second_limit,
-                requests_per_second: 0,
-            }
+        RequestThrottle::new_with_burst_capacity(
+            requests_per_second_limit,
+            requests_per_second_limit,
+        )
+    }
+
+    pub fn new_with_burst_capacity(
+        requests_per_second_limit: usize,
+        burst_capacity: usize,
+    ) -> RequestThrottle {
+        let burst_capacity = burst_capacity.max(1);
+
+        RequestThrottle {
+            enabled: requests_per_second_limit > 0,
+            requests_per_second_limit,
+            burst_capacity,
+            weights: RequestWeights::default(),
+            bucket: Mutex::new(TokenBucket {
+                tokens: burst_capacity as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn with_weights(mut self, weights: RequestWeights) -> RequestThrottle {
+        self.weights = weights;
+        self
+    }
+
+    pub fn weights(&self) -> RequestWeights {
+        self.weights
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    //Takes `weight` tokens from the bucket, waiting until the bucket has refilled enough to cover them
+    pub async fn acquire(&self, weight: u32) {
+        if let Some(wait) = self.reserve(weight) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    pub async fn acquire_eth_call(&self) {
+        self.acquire(self.weights.eth_call).await
+    }
+
+    pub async fn acquire_get_logs(&self) {
+        self.acquire(self.weights.get_logs).await
+    }
+
+    //Reserves the tokens for a request and returns how long the caller has to wait before making it.
+    //Tokens are reserved up front, letting the balance go negative, so that waiting tasks are served in the order they arrived.
+    fn reserve(&self, weight: u32) -> Option<Duration> {
+        if !self.enabled {
+            return None;
+        }
+
+        let mut bucket = self
+            .bucket
+            .lock()
+            .expect("Error when acquiring request throttle mutex lock");
+
+        let now = Instant::now();
+        let refill_rate = self.requests_per_second_limit as f64;
+
+        bucket.tokens = (bucket.tokens
+            + now.duration_since(bucket.last_refill).as_secs_f64() * refill_rate)
+            .min(self.burst_capacity as f64);
+        bucket.last_refill = now;
+
+        //A request heavier than the burst capacity could otherwise never be served
+        bucket.tokens -= weight.min(self.burst_capacity as u32) as f64;
+
+        if bucket.tokens >= 0.0 {
+            None
         } else {
-            RequestThrottle {
-                enabled: false,
-                last_request_timestamp: SystemTime::now(),
-                requests_per_second_limit,
-                requests_per_second: 0,
-            }
-        }
-    }
-
-    pub fn increment_or_sleep(&mut self, inc: usize) {
-        let time_elapsed = self
-            .last_request_timestamp
-            .elapsed()
-            .expect("Could not get time elapsed from last request timestamp")
-            .as_millis();
-
-        if self.enabled && time_elapsed < 1000 {
-            if self.requests_per_second >= self.requests_per_second_limit {
-                sleep(Duration::from_secs(1));
-                self.requests_per_second = 0;
-                self.last_request_timestamp = SystemTime::now();
-            } else {
-                self.requests_per_second += inc;
-            }
+            Some(Duration::from_secs_f64(-bucket.tokens / refill_rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{RequestThrottle, RequestWeights};
+
+    #[test]
+    fn test_disabled_throttle() {
+        let throttle = RequestThrottle::new(0);
+
+        assert!(!throttle.is_enabled());
+        for _ in 0..1000 {
+            assert_eq!(throttle.reserve(100), None);
+        }
+    }
+
+    #[test]
+    fn test_burst_capacity() {
+        let throttle = RequestThrottle::new_with_burst_capacity(10, 5);
+
+        for _ in 0..5 {
+            assert_eq!(throttle.reserve(1), None);
         }
+
+        //The bucket is empty, so the next request waits for one token at 10 tokens per second
+        let wait = throttle.reserve(1).unwrap();
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100));
+
+        //The following request is queued behind the previous one
+        let wait = throttle.reserve(1).unwrap();
+        assert!(wait > Duration::from_millis(190) && wait <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_request_weights() {
+        let throttle = RequestThrottle::new(10).with_weights(RequestWeights {
+            eth_call: 1,
+            get_logs: 4,
+        });
+
+        assert_eq!(throttle.reserve(throttle.weights().get_logs), None);
+        assert_eq!(throttle.reserve(throttle.weights().get_logs), None);
+
+        //Only 2 tokens are left, a log request needs to wait for 2 more
+        let wait = throttle.reserve(throttle.weights().get_logs).unwrap();
+        assert!(wait > Duration::from_millis(190) && wait <= Duration::from_millis(200));
+
+        //Requests heavier than the burst capacity are capped at the capacity
+        let throttle = RequestThrottle::new(2);
+        assert_eq!(throttle.reserve(100), None);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_refills_over_time() {
+        let throttle = Arc::new(RequestThrottle::new_with_burst_capacity(50, 5));
+        let start = std::time::Instant::now();
+
+        let mut handles = vec![];
+        for _ in 0..15 {
+            let throttle = throttle.clone();
+            handles.push(tokio::spawn(async move {
+                throttle.acquire_eth_call().await;
+            }));
+        }
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        //5 requests are served from the initial burst, the other 10 are served at 50 per second
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(190) && elapsed < Duration::from_secs(1));
     }
 }